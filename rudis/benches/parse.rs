@@ -0,0 +1,120 @@
+//! 对比 `Frame::check` + `Frame::parse` 与单遍的 `Parser`
+//!
+//! 输入按照固定大小分块写入缓冲区, 模拟 `read_buf` 每次只读取到一部分数据的情况
+//!
+//! ```text
+//! cargo bench --bench parse
+//! ```
+
+use std::io::Cursor;
+
+use bytes::{Buf, BytesMut};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rudis::{frame, Frame, Parser};
+
+/// 与 read_buf 默认的读取大小相近
+const CHUNK: usize = 4096;
+
+fn command(args: &[&[u8]]) -> Vec<u8> {
+    let mut out = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        out.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        out.extend_from_slice(arg);
+        out.extend_from_slice(b"\r\n");
+    }
+
+    out
+}
+
+/// 1000 个 pipeline 的 SET 命令
+fn pipeline() -> Vec<u8> {
+    (0..1000)
+        .flat_map(|i| command(&[b"SET", format!("key:{}", i).as_bytes(), &[b'x'; 64]]))
+        .collect()
+}
+
+/// 一个带有 10000 对参数的 MSET, 数据不完整时 check 每次都要从头扫描整个 Array
+fn big_array() -> Vec<u8> {
+    let keys: Vec<String> = (0..10000).map(|i| format!("key:{}", i)).collect();
+    let mut args: Vec<&[u8]> = vec![b"MSET"];
+    for key in &keys {
+        args.push(key.as_bytes());
+        args.push(b"value");
+    }
+
+    command(&args)
+}
+
+/// 一个 1MB 的 bulk
+fn big_bulk() -> Vec<u8> {
+    command(&[b"SET", b"big", &vec![b'x'; 1024 * 1024]])
+}
+
+/// 原来 `Connection::parse_frame` 的实现: 先 check 再 parse, 数据不完整时下次从头开始
+fn check_then_parse(buf: &mut BytesMut, frames: &mut usize) {
+    loop {
+        let mut cursor = Cursor::new(&buf[..]);
+        match Frame::check(&mut cursor) {
+            Ok(()) => {
+                let len = cursor.position() as usize;
+                cursor.set_position(0);
+                black_box(Frame::parse(&mut cursor).unwrap());
+                buf.advance(len);
+                *frames += 1;
+            }
+            Err(frame::Error::Incomplete) => return,
+            Err(err) => panic!("{}", err),
+        }
+    }
+}
+
+fn single_pass(parser: &mut Parser, buf: &mut BytesMut, frames: &mut usize) {
+    while let Some(frame) = parser.parse(buf).unwrap() {
+        black_box(frame);
+        *frames += 1;
+    }
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+
+    for (name, input) in [
+        ("pipeline", pipeline()),
+        ("big_array", big_array()),
+        ("big_bulk", big_bulk()),
+    ] {
+        group.throughput(Throughput::Bytes(input.len() as u64));
+
+        group.bench_with_input(BenchmarkId::new("check_then_parse", name), &input, |b, input| {
+            b.iter(|| {
+                let mut buf = BytesMut::with_capacity(CHUNK);
+                let mut frames = 0;
+                for chunk in input.chunks(CHUNK) {
+                    buf.extend_from_slice(chunk);
+                    check_then_parse(&mut buf, &mut frames);
+                }
+
+                frames
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("single_pass", name), &input, |b, input| {
+            b.iter(|| {
+                let mut parser = Parser::new();
+                let mut buf = BytesMut::with_capacity(CHUNK);
+                let mut frames = 0;
+                for chunk in input.chunks(CHUNK) {
+                    buf.extend_from_slice(chunk);
+                    single_pass(&mut parser, &mut buf, &mut frames);
+                }
+
+                frames
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse);
+criterion_main!(benches);
@@ -0,0 +1,441 @@
+use std::{
+    collections::HashMap,
+    env, process,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use hdrhistogram::Histogram;
+use rudis::{Connection, Frame, Result};
+
+/// rudis 的压测工具, 可以对任意 RESP 服务进行压测
+///
+/// ```text
+/// rudis-benchmark [-h host] [-p port] [-c clients] [-n requests] [-P pipeline]
+///                 [-r keyspace] [-d size] [-t set:1,get:4] [--csv | --json]
+/// ```
+///
+/// 例如对比不同的分片数量下 SET/GET 混合负载的表现:
+///
+/// ```text
+/// rudis-benchmark -c 50 -n 1000000 -P 16 -r 100000 -t set:1,get:4
+/// ```
+#[tokio::main]
+async fn main() {
+    let config = Config::build(env::args()).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+
+    if let Err(err) = run(config).await {
+        eprintln!("benchmark failed: {}", err);
+        process::exit(1);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Output {
+    Text,
+    Csv,
+    Json,
+}
+
+#[derive(Clone)]
+struct Config {
+    host: String,
+    port: u16,
+    clients: usize,
+    requests: usize,
+    pipeline: usize,
+    /// key 的取值范围, 为 1 时所有的请求都访问同一个 key(热点 key)
+    keyspace: u64,
+    data_size: usize,
+    /// 命令以及对应的权重
+    mix: Vec<(Kind, u32)>,
+    output: Output,
+}
+
+impl Config {
+    fn build(mut args: impl Iterator<Item = String>) -> std::result::Result<Self, &'static str> {
+        args.next();
+
+        let mut config = Config {
+            host: "127.0.0.1".to_string(),
+            port: 6379,
+            clients: 50,
+            requests: 100_000,
+            pipeline: 1,
+            keyspace: 1,
+            data_size: 3,
+            mix: vec![(Kind::Set, 1), (Kind::Get, 1)],
+            output: Output::Text,
+        };
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or("missing option value");
+
+            match arg.as_str() {
+                "-h" => config.host = value()?,
+                "-p" => config.port = value()?.parse().map_err(|_| "-p requires a valid port")?,
+                "-c" => config.clients = parse_positive(value()?)?,
+                "-n" => config.requests = parse_positive(value()?)?,
+                "-P" => config.pipeline = parse_positive(value()?)?,
+                "-r" => config.keyspace = parse_positive(value()?)? as u64,
+                "-d" => config.data_size = value()?.parse().map_err(|_| "-d requires a size")?,
+                "-t" => config.mix = parse_mix(&value()?)?,
+                "--csv" => config.output = Output::Csv,
+                "--json" => config.output = Output::Json,
+                _ => Err("unknown option")?,
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+fn parse_positive(val: String) -> std::result::Result<usize, &'static str> {
+    match val.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err("option requires a positive number"),
+    }
+}
+
+/// 解析命令组合, 格式为 `name[:weight],...`, 例如 `set:1,get:4`
+fn parse_mix(val: &str) -> std::result::Result<Vec<(Kind, u32)>, &'static str> {
+    let mut mix = vec![];
+
+    for part in val.split(',').filter(|part| !part.is_empty()) {
+        let (name, weight) = match part.split_once(':') {
+            Some((name, weight)) => (name, weight.parse().map_err(|_| "invalid weight")?),
+            None => (part, 1),
+        };
+
+        let kind = Kind::from_name(name).ok_or("unsupported command in -t")?;
+        if weight > 0 {
+            mix.push((kind, weight));
+        }
+    }
+
+    if mix.is_empty() {
+        return Err("-t requires at least one command");
+    }
+
+    Ok(mix)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Kind {
+    Ping,
+    Set,
+    Get,
+    Incr,
+    Lpush,
+    Rpush,
+    Lpop,
+    Rpop,
+    Sadd,
+    Hset,
+    Mset,
+}
+
+impl Kind {
+    const ALL: [Kind; 11] = [
+        Kind::Ping,
+        Kind::Set,
+        Kind::Get,
+        Kind::Incr,
+        Kind::Lpush,
+        Kind::Rpush,
+        Kind::Lpop,
+        Kind::Rpop,
+        Kind::Sadd,
+        Kind::Hset,
+        Kind::Mset,
+    ];
+
+    fn from_name(name: &str) -> Option<Kind> {
+        Kind::ALL
+            .into_iter()
+            .find(|kind| kind.name().eq_ignore_ascii_case(name))
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Kind::Ping => "PING",
+            Kind::Set => "SET",
+            Kind::Get => "GET",
+            Kind::Incr => "INCR",
+            Kind::Lpush => "LPUSH",
+            Kind::Rpush => "RPUSH",
+            Kind::Lpop => "LPOP",
+            Kind::Rpop => "RPOP",
+            Kind::Sadd => "SADD",
+            Kind::Hset => "HSET",
+            Kind::Mset => "MSET",
+        }
+    }
+
+    /// 构造命令, 不同的命令类型使用不同的 key 前缀, 避免类型冲突
+    fn command(&self, rng: &mut Rng, keyspace: u64, value: &Bytes) -> Frame {
+        let mut frame = Frame::array();
+        frame.push_bulk(Bytes::from_static(self.name().as_bytes()));
+
+        let mut key = |prefix: &str| Bytes::from(format!("{}:{}", prefix, rng.next() % keyspace));
+
+        match self {
+            Kind::Ping => {}
+            Kind::Set => {
+                frame.push_bulk(key("key"));
+                frame.push_bulk(value.clone());
+            }
+            Kind::Get => frame.push_bulk(key("key")),
+            Kind::Incr => frame.push_bulk(key("counter")),
+            Kind::Lpush | Kind::Rpush => {
+                frame.push_bulk(key("list"));
+                frame.push_bulk(value.clone());
+            }
+            Kind::Lpop | Kind::Rpop => frame.push_bulk(key("list")),
+            Kind::Sadd => {
+                frame.push_bulk(key("set"));
+                frame.push_bulk(key("element"));
+            }
+            Kind::Hset => {
+                frame.push_bulk(key("hash"));
+                frame.push_bulk(key("field"));
+                frame.push_bulk(value.clone());
+            }
+            Kind::Mset => {
+                // 与 redis-benchmark 一致, 每次 MSET 写入 10 个 key
+                for _ in 0..10 {
+                    frame.push_bulk(key("key"));
+                    frame.push_bulk(value.clone());
+                }
+            }
+        }
+
+        frame
+    }
+}
+
+/// xorshift 随机数生成器, 压测只需要足够快且分布均匀的随机数
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+/// 每种命令的延迟直方图, 单位为微秒
+type Histograms = HashMap<Kind, Histogram<u64>>;
+
+fn new_histogram() -> Histogram<u64> {
+    // 1us ~ 60s, 3 位有效数字
+    Histogram::new_with_bounds(1, 60_000_000, 3).unwrap()
+}
+
+async fn run(config: Config) -> Result<()> {
+    let addr = format!("{}:{}", config.host, config.port);
+    let value = Bytes::from(vec![b'x'; config.data_size]);
+
+    // 先建立所有的连接, 避免将建立连接的耗时计入压测结果
+    let mut conns = Vec::with_capacity(config.clients);
+    for _ in 0..config.clients {
+        conns.push(Connection::connect(&addr).await?);
+    }
+
+    let start = Instant::now();
+    let mut tasks = Vec::with_capacity(config.clients);
+    for (i, conn) in conns.into_iter().enumerate() {
+        // 将请求数平均分配给每个客户端
+        let requests = config.requests / config.clients + usize::from(i < config.requests % config.clients);
+        let config = config.clone();
+        let value = value.clone();
+
+        tasks.push(tokio::spawn(async move {
+            client(conn, config, requests, value, i as u64 + 1).await
+        }));
+    }
+
+    let mut histograms = Histograms::new();
+    let mut errors = 0;
+    for task in tasks {
+        let (hists, errs) = task.await??;
+        errors += errs;
+        for (kind, hist) in hists {
+            histograms.entry(kind).or_insert_with(new_histogram).add(hist)?;
+        }
+    }
+
+    report(&config, start.elapsed(), &histograms, errors);
+
+    Ok(())
+}
+
+/// 单个客户端: 每次发送 `pipeline` 个命令, 然后依次读取回复
+///
+/// 每个命令的延迟从这一批命令发送开始计算, 到读取到该命令的回复为止
+async fn client(
+    mut conn: Connection,
+    config: Config,
+    mut requests: usize,
+    value: Bytes,
+    seed: u64,
+) -> Result<(Histograms, u64)> {
+    let mut rng = Rng::new(seed);
+    let total_weight: u32 = config.mix.iter().map(|(_, weight)| weight).sum();
+    let mut histograms = Histograms::new();
+    let mut errors = 0;
+
+    let mut kinds = Vec::with_capacity(config.pipeline);
+    let mut frames = Vec::with_capacity(config.pipeline);
+
+    while requests > 0 {
+        let batch = requests.min(config.pipeline);
+        requests -= batch;
+
+        kinds.clear();
+        frames.clear();
+        for _ in 0..batch {
+            let kind = pick(&config.mix, total_weight, &mut rng);
+            frames.push(kind.command(&mut rng, config.keyspace, &value));
+            kinds.push(kind);
+        }
+
+        let sent = Instant::now();
+        conn.write_frames(&frames).await?;
+
+        for kind in &kinds {
+            let reply = conn
+                .read_frame()
+                .await?
+                .ok_or("connection reset by peer")?;
+            if let Frame::Error(_) = reply {
+                errors += 1;
+            }
+
+            let micros = sent.elapsed().as_micros() as u64;
+            histograms
+                .entry(*kind)
+                .or_insert_with(new_histogram)
+                .saturating_record(micros.max(1));
+        }
+    }
+
+    Ok((histograms, errors))
+}
+
+fn pick(mix: &[(Kind, u32)], total_weight: u32, rng: &mut Rng) -> Kind {
+    let mut n = (rng.next() % total_weight as u64) as u32;
+    for (kind, weight) in mix {
+        if n < *weight {
+            return *kind;
+        }
+        n -= weight;
+    }
+
+    mix[mix.len() - 1].0
+}
+
+struct Row {
+    name: &'static str,
+    requests: u64,
+    rps: f64,
+    avg: f64,
+    min: f64,
+    p50: f64,
+    p99: f64,
+    p999: f64,
+    max: f64,
+}
+
+impl Row {
+    fn new(name: &'static str, hist: &Histogram<u64>, elapsed: Duration) -> Row {
+        let ms = |micros: u64| micros as f64 / 1000.0;
+
+        Row {
+            name,
+            requests: hist.len(),
+            rps: hist.len() as f64 / elapsed.as_secs_f64(),
+            avg: hist.mean() / 1000.0,
+            min: ms(hist.min()),
+            p50: ms(hist.value_at_quantile(0.5)),
+            p99: ms(hist.value_at_quantile(0.99)),
+            p999: ms(hist.value_at_quantile(0.999)),
+            max: ms(hist.max()),
+        }
+    }
+}
+
+fn report(config: &Config, elapsed: Duration, histograms: &Histograms, errors: u64) {
+    let mut kinds: Vec<_> = histograms.keys().copied().collect();
+    kinds.sort();
+
+    let mut total = new_histogram();
+    let mut rows = vec![];
+    for kind in kinds {
+        let hist = &histograms[&kind];
+        total.add(hist).unwrap();
+        rows.push(Row::new(kind.name(), hist, elapsed));
+    }
+    rows.push(Row::new("ALL", &total, elapsed));
+
+    match config.output {
+        Output::Text => {
+            println!(
+                "{} requests completed in {:.2} seconds ({} clients, pipeline {}, keyspace {}, {} bytes payload, {} errors)",
+                total.len(),
+                elapsed.as_secs_f64(),
+                config.clients,
+                config.pipeline,
+                config.keyspace,
+                config.data_size,
+                errors,
+            );
+            for row in rows {
+                println!(
+                    "{:>6}: {:>12.2} requests per second, latency (msec) avg={:.3} min={:.3} p50={:.3} p99={:.3} p999={:.3} max={:.3}",
+                    row.name, row.rps, row.avg, row.min, row.p50, row.p99, row.p999, row.max
+                );
+            }
+        }
+        Output::Csv => {
+            println!("\"test\",\"requests\",\"rps\",\"avg_latency_ms\",\"min_latency_ms\",\"p50_latency_ms\",\"p99_latency_ms\",\"p999_latency_ms\",\"max_latency_ms\"");
+            for row in rows {
+                println!(
+                    "\"{}\",\"{}\",\"{:.2}\",\"{:.3}\",\"{:.3}\",\"{:.3}\",\"{:.3}\",\"{:.3}\",\"{:.3}\"",
+                    row.name, row.requests, row.rps, row.avg, row.min, row.p50, row.p99, row.p999, row.max
+                );
+            }
+        }
+        Output::Json => {
+            let rows: Vec<String> = rows
+                .into_iter()
+                .map(|row| {
+                    format!(
+                        "{{\"test\":\"{}\",\"requests\":{},\"rps\":{:.2},\"avg_latency_ms\":{:.3},\"min_latency_ms\":{:.3},\"p50_latency_ms\":{:.3},\"p99_latency_ms\":{:.3},\"p999_latency_ms\":{:.3},\"max_latency_ms\":{:.3}}}",
+                        row.name, row.requests, row.rps, row.avg, row.min, row.p50, row.p99, row.p999, row.max
+                    )
+                })
+                .collect();
+
+            println!(
+                "{{\"elapsed_secs\":{:.3},\"clients\":{},\"pipeline\":{},\"keyspace\":{},\"data_size\":{},\"errors\":{},\"results\":[{}]}}",
+                elapsed.as_secs_f64(),
+                config.clients,
+                config.pipeline,
+                config.keyspace,
+                config.data_size,
+                errors,
+                rows.join(",")
+            );
+        }
+    }
+}
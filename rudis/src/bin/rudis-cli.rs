@@ -0,0 +1,738 @@
+use std::{
+    env,
+    io::{self, IsTerminal, Write},
+    process,
+    time::Duration,
+};
+
+use bytes::{Buf, Bytes, BytesMut};
+use rudis::{inline::split_args, Connection, Frame, Result};
+use rustyline::{error::ReadlineError, DefaultEditor};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+/// rudis 的交互式命令行, 用法与 redis-cli 保持一致, 见 [`USAGE`]
+#[tokio::main]
+async fn main() {
+    let config = Config::build(env::args()).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+    if config.help {
+        print!("{}", USAGE);
+        return;
+    }
+
+    if let Err(err) = run(config).await {
+        eprintln!("Could not connect to rudis: {}", err);
+        process::exit(1);
+    }
+}
+
+const USAGE: &str = "\
+Usage: rudis-cli [OPTIONS] [cmd [arg ...]]
+  -h <hostname>          Server hostname (default: 127.0.0.1).
+  -p <port>              Server port (default: 6379).
+  -n <db>                Database number.
+  -r <repeat>            Execute specified command N times, -1 to run forever.
+  -i <interval>          When -r is used, waits <interval> seconds per command.
+  --raw                  Use raw formatting for replies.
+  --no-raw               Force formatted output even when STDOUT is not a tty.
+  --pipe                 Transfer raw Redis protocol from stdin to server.
+  --scan                 List all keys using the SCAN command.
+  --pattern <pat>        Keys pattern when using --scan.
+  --bigkeys              Sample keys looking for keys with many elements.
+  --memkeys              Sample keys looking for keys consuming a lot of memory.
+  --memkeys-samples <n>  Like --memkeys, with the SAMPLES argument of MEMORY USAGE.
+  --help                 Output this help and exit.
+";
+
+struct Config {
+    host: String,
+    port: u16,
+    /// 连接之后通过 SELECT 选择的数据库
+    db: usize,
+    /// 命令重复执行的次数, 负数代表一直执行
+    repeat: i64,
+    interval: Duration,
+    raw: bool,
+    pipe: bool,
+    scan: bool,
+    pattern: Option<String>,
+    bigkeys: bool,
+    memkeys: bool,
+    /// 传给 MEMORY USAGE 的 SAMPLES 参数, 未指定时使用服务端的默认值
+    memkeys_samples: Option<usize>,
+    command: Vec<String>,
+    /// --help: 只打印用法, 不连接服务端
+    help: bool,
+}
+
+impl Config {
+    fn build(mut args: impl Iterator<Item = String>) -> std::result::Result<Self, &'static str> {
+        args.next();
+
+        let mut config = Config {
+            host: "127.0.0.1".to_string(),
+            port: 6379,
+            db: 0,
+            repeat: 1,
+            interval: Duration::ZERO,
+            // 输出不是终端(例如重定向到文件)时, 与 redis-cli 一样使用原始格式
+            raw: !io::stdout().is_terminal(),
+            pipe: false,
+            scan: false,
+            pattern: None,
+            bigkeys: false,
+            memkeys: false,
+            memkeys_samples: None,
+            command: vec![],
+            help: false,
+        };
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" => config.host = args.next().ok_or("-h requires a host")?,
+                "-p" => {
+                    config.port = match args.next().map(|v| v.parse()) {
+                        Some(Ok(port)) => port,
+                        _ => Err("-p requires a valid port")?,
+                    }
+                }
+                "-n" => {
+                    config.db = match args.next().map(|v| v.parse()) {
+                        Some(Ok(db)) => db,
+                        _ => Err("-n requires a database number")?,
+                    }
+                }
+                "-r" => {
+                    config.repeat = match args.next().map(|v| v.parse()) {
+                        Some(Ok(n)) => n,
+                        _ => Err("-r requires a number")?,
+                    }
+                }
+                "-i" => {
+                    config.interval = match args.next().map(|v| v.parse::<f64>()) {
+                        Some(Ok(secs)) if secs >= 0.0 => Duration::from_secs_f64(secs),
+                        _ => Err("-i requires a non-negative number of seconds")?,
+                    }
+                }
+                "--raw" => config.raw = true,
+                "--no-raw" => config.raw = false,
+                "--pipe" => config.pipe = true,
+                "--scan" => config.scan = true,
+                "--pattern" => config.pattern = Some(args.next().ok_or("--pattern requires a value")?),
+                "--help" => config.help = true,
+                "--bigkeys" => config.bigkeys = true,
+                "--memkeys" => config.memkeys = true,
+                "--memkeys-samples" => {
+                    config.memkeys = true;
+                    config.memkeys_samples = match args.next().map(|v| v.parse()) {
+                        Some(Ok(n)) => Some(n),
+                        _ => Err("--memkeys-samples requires a number")?,
+                    }
+                }
+                _ => {
+                    // 第一个非选项参数之后的内容全部作为命令
+                    config.command.push(arg);
+                    config.command.extend(args.by_ref());
+                }
+            }
+        }
+
+        Ok(config)
+    }
+
+    fn addr(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+}
+
+async fn run(config: Config) -> Result<()> {
+    if config.pipe {
+        return pipe(&config).await;
+    }
+
+    let mut conn = Connection::connect(config.addr()).await?;
+    if config.db != 0 {
+        if let Frame::Error(err) = conn.request(["SELECT".to_string(), config.db.to_string()]).await? {
+            return Err(err.into());
+        }
+    }
+
+    if config.scan {
+        return scan(&mut conn, config.pattern.as_deref()).await;
+    }
+
+    if config.bigkeys || config.memkeys {
+        return find_big_keys(&mut conn, &config, &mut io::stdout()).await;
+    }
+
+    if !config.command.is_empty() {
+        let args = config.command.iter().map(|arg| Bytes::from(arg.clone())).collect();
+        return repeat(&mut conn, &config, args).await;
+    }
+
+    repl(&mut conn, &config).await
+}
+
+/// 交互模式, 通过 rustyline 提供行编辑以及历史记录
+async fn repl(conn: &mut Connection, config: &Config) -> Result<()> {
+    let mut editor = DefaultEditor::new()?;
+    let history = env::var("HOME")
+        .map(|home| format!("{}/.rudiscli_history", home))
+        .ok();
+    if let Some(history) = &history {
+        // 第一次运行时历史文件还不存在, 忽略该错误
+        let _ = editor.load_history(history);
+    }
+
+    let mut db = config.db;
+    loop {
+        // 与 redis-cli 一样, 选择了非 0 的数据库时在提示符中显示数据库编号
+        let prompt = match db {
+            0 => format!("{}> ", config.addr()),
+            db => format!("{}[{}]> ", config.addr(), db),
+        };
+
+        // readline 会阻塞当前线程, 通过 block_in_place 告知运行时将其他任务转移到别的线程
+        let line = match tokio::task::block_in_place(|| editor.readline(&prompt)) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err.into()),
+        };
+
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line)?;
+
+        let args = match split_args(line.as_bytes()) {
+            Ok(args) if args.is_empty() => continue,
+            Ok(args) => args,
+            Err(err) => {
+                println!("Invalid argument(s): {}", err);
+                continue;
+            }
+        };
+
+        let name = String::from_utf8_lossy(&args[0]).to_lowercase();
+        if name == "quit" || name == "exit" {
+            break;
+        }
+
+        // 与 redis-cli 一样支持在命令前加上重复次数, 例如 `3 PING`
+        let (times, args) = match name.parse::<i64>() {
+            Ok(times) if args.len() > 1 => (times, args[1..].to_vec()),
+            _ => (1, args),
+        };
+
+        for _ in 0..times {
+            let reply = conn.request(args.iter().cloned()).await?;
+            print_reply(&reply, config.raw);
+
+            if name == "select" && matches!(reply, Frame::Simple(_)) {
+                db = String::from_utf8_lossy(&args[1]).parse().unwrap_or(db);
+            }
+        }
+
+        if is_subscribe(&args) {
+            return subscribed(conn, config).await;
+        }
+    }
+
+    if let Some(history) = &history {
+        editor.save_history(history)?;
+    }
+
+    Ok(())
+}
+
+/// 执行命令行中给出的命令, 通过 `-r`/`-i` 控制重复次数与间隔
+async fn repeat(conn: &mut Connection, config: &Config, args: Vec<Bytes>) -> Result<()> {
+    let mut n = 0;
+    while config.repeat < 0 || n < config.repeat {
+        let reply = conn.request(args.iter().cloned()).await?;
+        print_reply(&reply, config.raw);
+
+        if is_subscribe(&args) {
+            return subscribed(conn, config).await;
+        }
+
+        n += 1;
+        if !config.interval.is_zero() {
+            tokio::time::sleep(config.interval).await;
+        }
+    }
+
+    Ok(())
+}
+
+fn is_subscribe(args: &[Bytes]) -> bool {
+    args[0].eq_ignore_ascii_case(b"subscribe") || args[0].eq_ignore_ascii_case(b"psubscribe")
+}
+
+/// 订阅模式: 服务端会持续推送消息, 一直读取并输出, 直到连接关闭
+async fn subscribed(conn: &mut Connection, config: &Config) -> Result<()> {
+    if !config.raw {
+        println!("Reading messages... (press Ctrl-C to quit)");
+    }
+
+    while let Some(frame) = conn.read_frame().await? {
+        print_reply(&frame, config.raw);
+    }
+
+    Ok(())
+}
+
+/// 通过 SCAN 遍历所有匹配的 key, 每行输出一个 key
+async fn scan(conn: &mut Connection, pattern: Option<&str>) -> Result<()> {
+    let mut cursor = Bytes::from_static(b"0");
+    let mut stdout = io::stdout().lock();
+
+    loop {
+        let (next, keys) = scan_page(conn, cursor, pattern).await?;
+        for key in keys {
+            stdout.write_all(&key)?;
+            stdout.write_all(b"\n")?;
+        }
+
+        if &next[..] == b"0" {
+            return Ok(());
+        }
+        cursor = next;
+    }
+}
+
+/// 执行一次 SCAN, 返回下一次的游标以及这一批 key
+async fn scan_page(
+    conn: &mut Connection,
+    cursor: Bytes,
+    pattern: Option<&str>,
+) -> Result<(Bytes, Vec<Bytes>)> {
+    let mut args = vec![Bytes::from_static(b"SCAN"), cursor];
+    if let Some(pattern) = pattern {
+        args.push(Bytes::from_static(b"MATCH"));
+        args.push(Bytes::from(pattern.to_string()));
+    }
+
+    match conn.request(args).await? {
+        Frame::Array(mut parts) if parts.len() == 2 => match (parts.remove(0), parts.remove(0)) {
+            (Frame::Bulk(next), Frame::Array(keys)) => {
+                let keys = keys
+                    .into_iter()
+                    .filter_map(|key| match key {
+                        Frame::Bulk(key) => Some(key),
+                        _ => None,
+                    })
+                    .collect();
+                Ok((next, keys))
+            }
+            _ => Err("unexpected SCAN reply".into()),
+        },
+        Frame::Error(err) => Err(err.into()),
+        _ => Err("unexpected SCAN reply".into()),
+    }
+}
+
+/// 每种类型获取大小的命令以及大小的单位, 其他类型(例如模块类型)只统计个数
+const TYPE_SIZES: &[(&str, &str, &str)] = &[
+    ("string", "STRLEN", "bytes"),
+    ("list", "LLEN", "items"),
+    ("set", "SCARD", "members"),
+    ("zset", "ZCARD", "members"),
+    ("hash", "HLEN", "fields"),
+];
+
+/// 一种类型的统计结果
+struct TypeStats {
+    name: String,
+    unit: &'static str,
+    count: usize,
+    total: u64,
+    biggest: Option<(Bytes, u64)>,
+}
+
+/// --bigkeys/--memkeys: 通过 SCAN 遍历整个数据库, 找出每种类型中最大的 key
+///
+/// --bigkeys 按照元素个数(字符串为字节数)比较, --memkeys 按照 MEMORY USAGE 返回的字节数比较.
+/// 与 redis-cli 相同, 指定 -i 时每执行 100 次 SCAN 暂停一次, 以减少对服务端的影响
+async fn find_big_keys(
+    conn: &mut Connection,
+    config: &Config,
+    out: &mut impl Write,
+) -> Result<()> {
+    let total = match conn.request(["DBSIZE"]).await? {
+        Frame::Integer(n) => n.max(0) as u64,
+        Frame::Error(err) => return Err(err.into()),
+        _ => return Err("unexpected DBSIZE reply".into()),
+    };
+
+    writeln!(out)?;
+    writeln!(out, "# Scanning the entire keyspace to find biggest keys as well as")?;
+    writeln!(out, "# average sizes per key type.  You can use -i 0.1 to sleep 0.1 sec")?;
+    writeln!(out, "# per 100 SCAN commands (not usually needed).")?;
+    writeln!(out)?;
+
+    let mut stats: Vec<TypeStats> = TYPE_SIZES
+        .iter()
+        .map(|&(name, _, unit)| TypeStats {
+            name: name.to_string(),
+            unit: if config.memkeys { "bytes" } else { unit },
+            count: 0,
+            total: 0,
+            biggest: None,
+        })
+        .collect();
+    let (mut sampled, mut key_bytes, mut scans) = (0u64, 0u64, 0u64);
+    let mut cursor = Bytes::from_static(b"0");
+
+    loop {
+        let (next, keys) = scan_page(conn, cursor, None).await?;
+        scans += 1;
+
+        for key in keys {
+            let type_name = match conn.request([Bytes::from_static(b"TYPE"), key.clone()]).await? {
+                Frame::Simple(name) => name,
+                Frame::Error(err) => return Err(err.into()),
+                _ => return Err("unexpected TYPE reply".into()),
+            };
+            // 在 SCAN 与 TYPE 之间被删除了
+            if type_name == "none" {
+                continue;
+            }
+
+            let size = key_size(conn, config, &type_name, &key).await?;
+            let index = match stats.iter().position(|stats| stats.name == type_name) {
+                Some(index) => index,
+                None => {
+                    stats.push(TypeStats {
+                        name: type_name,
+                        unit: if config.memkeys { "bytes" } else { "?" },
+                        count: 0,
+                        total: 0,
+                        biggest: None,
+                    });
+                    stats.len() - 1
+                }
+            };
+
+            sampled += 1;
+            key_bytes += key.len() as u64;
+            let stats = &mut stats[index];
+            stats.count += 1;
+
+            let Some(size) = size else {
+                continue;
+            };
+            stats.total += size;
+            if stats.biggest.as_ref().is_none_or(|(_, biggest)| size > *biggest) {
+                let pct = if total == 0 { 0.0 } else { sampled as f64 * 100.0 / total as f64 };
+                writeln!(
+                    out,
+                    "[{:05.2}%] Biggest {:<6} found so far '{}' with {} {}",
+                    pct.min(100.0),
+                    stats.name,
+                    quote(&key),
+                    size,
+                    stats.unit
+                )?;
+                stats.biggest = Some((key, size));
+            }
+        }
+
+        if &next[..] == b"0" {
+            break;
+        }
+        cursor = next;
+
+        if !config.interval.is_zero() && scans.is_multiple_of(100) {
+            tokio::time::sleep(config.interval).await;
+        }
+    }
+
+    writeln!(out)?;
+    writeln!(out, "-------- summary -------")?;
+    writeln!(out)?;
+    writeln!(out, "Sampled {} keys in the keyspace!", sampled)?;
+    let avg = if sampled == 0 { 0.0 } else { key_bytes as f64 / sampled as f64 };
+    writeln!(out, "Total key length in bytes is {} (avg len {:.2})", key_bytes, avg)?;
+    writeln!(out)?;
+
+    for stats in &stats {
+        if let Some((key, size)) = &stats.biggest {
+            let key = quote(key);
+            let (name, unit) = (&stats.name, stats.unit);
+            writeln!(out, "Biggest {:>6} found '{}' has {} {}", name, key, size, unit)?;
+        }
+    }
+    writeln!(out)?;
+
+    for stats in &stats {
+        let pct = if sampled == 0 { 0.0 } else { stats.count as f64 * 100.0 / sampled as f64 };
+        let avg = if stats.count == 0 { 0.0 } else { stats.total as f64 / stats.count as f64 };
+        writeln!(
+            out,
+            "{} {}s with {} {} ({:05.2}% of keys, avg size {:.2})",
+            stats.count, stats.name, stats.total, stats.unit, pct, avg
+        )?;
+    }
+
+    Ok(())
+}
+
+/// key 的大小, 不知道如何获取大小的类型返回 None
+async fn key_size(
+    conn: &mut Connection,
+    config: &Config,
+    type_name: &str,
+    key: &Bytes,
+) -> Result<Option<u64>> {
+    let args = if config.memkeys {
+        let mut args = vec![Bytes::from("MEMORY"), Bytes::from("USAGE"), key.clone()];
+        if let Some(samples) = config.memkeys_samples {
+            args.push(Bytes::from_static(b"SAMPLES"));
+            args.push(Bytes::from(samples.to_string()));
+        }
+        args
+    } else {
+        match TYPE_SIZES.iter().find(|(name, _, _)| *name == type_name) {
+            Some((_, cmd, _)) => vec![Bytes::from_static(cmd.as_bytes()), key.clone()],
+            None => return Ok(None),
+        }
+    };
+
+    match conn.request(args).await? {
+        Frame::Integer(n) => Ok(Some(n.max(0) as u64)),
+        // 在 SCAN 之后被删除了
+        Frame::Null => Ok(Some(0)),
+        Frame::Error(err) => Err(err.into()),
+        _ => Err("unexpected reply while sizing a key".into()),
+    }
+}
+
+/// pipe 模式: 将标准输入中的原始协议数据原样发送给服务端
+///
+/// 与 redis-cli 相同, 数据发送完毕之后追加一个携带随机标记的 ECHO 命令,
+/// 读取到该标记的回复时, 说明所有的回复都已经收到
+async fn pipe(config: &Config) -> Result<()> {
+    let stream = TcpStream::connect(config.addr()).await?;
+    stream.set_nodelay(true)?;
+    let (mut reader, mut writer) = stream.into_split();
+
+    let marker = format!(
+        "{:x}{:x}",
+        process::id(),
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos()
+    );
+    let echo = format!("*2\r\n$4\r\nECHO\r\n${}\r\n{}\r\n", marker.len(), marker);
+
+    let write_task = tokio::spawn(async move {
+        let mut stdin = tokio::io::stdin();
+        tokio::io::copy(&mut stdin, &mut writer).await?;
+        writer.write_all(echo.as_bytes()).await?;
+        eprintln!("All data transferred. Waiting for the last reply...");
+
+        Ok::<_, io::Error>(())
+    });
+
+    let (mut replies, mut errors) = (0u64, 0u64);
+    let mut buffer = BytesMut::with_capacity(16 * 1024);
+    'read: loop {
+        if reader.read_buf(&mut buffer).await? == 0 {
+            return Err("connection closed before the last reply".into());
+        }
+
+        loop {
+            let mut cursor = io::Cursor::new(&buffer[..]);
+            match Frame::check(&mut cursor) {
+                Ok(()) => {
+                    let len = cursor.position() as usize;
+                    cursor.set_position(0);
+                    let frame = Frame::parse(&mut cursor)?;
+                    buffer.advance(len);
+
+                    match frame {
+                        Frame::Bulk(data) if data == marker.as_bytes() => break 'read,
+                        Frame::Error(err) => {
+                            errors += 1;
+                            eprintln!("{}", err);
+                        }
+                        _ => {}
+                    }
+                    replies += 1;
+                }
+                Err(rudis::frame::Error::Incomplete) => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    write_task.await??;
+    eprintln!("errors: {}, replies: {}", errors, replies);
+
+    Ok(())
+}
+
+fn print_reply(reply: &Frame, raw: bool) {
+    if raw {
+        let mut out = Vec::new();
+        format_raw(reply, &mut out);
+        out.push(b'\n');
+        let _ = io::stdout().write_all(&out);
+    } else {
+        println!("{}", format_tty(reply, 0));
+    }
+}
+
+/// 按照 Frame 的类型美化输出, 格式与 redis-cli 在终端中的输出一致
+///
+/// ```text
+/// 1) 1) "a"
+///    2) (integer) 1
+/// 2) (nil)
+/// ```
+fn format_tty(frame: &Frame, indent: usize) -> String {
+    match frame {
+        Frame::Simple(val) => val.clone(),
+        Frame::Error(err) => format!("(error) {}", err),
+        Frame::Integer(val) => format!("(integer) {}", val),
+        Frame::Bulk(val) => quote(val),
+        Frame::Null => "(nil)".to_string(),
+        Frame::Array(items) if items.is_empty() => "(empty array)".to_string(),
+        Frame::Array(items) | Frame::Push(items) => {
+            let width = items.len().to_string().len();
+            let mut out = String::new();
+
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push('\n');
+                    out.push_str(&" ".repeat(indent));
+                }
+
+                let label = format!("{:>width$}) ", i + 1);
+                out.push_str(&label);
+                out.push_str(&format_tty(item, indent + label.len()));
+            }
+
+            out
+        }
+    }
+}
+
+/// 原始格式: 不带类型提示, bulk 数据原样输出, 数组中每个元素占一行
+fn format_raw(frame: &Frame, out: &mut Vec<u8>) {
+    match frame {
+        Frame::Simple(val) | Frame::Error(val) => out.extend_from_slice(val.as_bytes()),
+        Frame::Integer(val) => out.extend_from_slice(val.to_string().as_bytes()),
+        Frame::Bulk(val) => out.extend_from_slice(val),
+        Frame::Null => {}
+        Frame::Array(items) | Frame::Push(items) => {
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(b'\n');
+                }
+                format_raw(item, out);
+            }
+        }
+    }
+}
+
+/// 将二进制数据转义为带双引号的字符串, 不可打印的字符使用 `\xHH` 表示
+fn quote(val: &[u8]) -> String {
+    let mut out = String::with_capacity(val.len() + 2);
+    out.push('"');
+
+    for &b in val {
+        match b {
+            b'\\' => out.push_str("\\\\"),
+            b'"' => out.push_str("\\\""),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            0x07 => out.push_str("\\a"),
+            0x08 => out.push_str("\\b"),
+            b if b.is_ascii_graphic() || b == b' ' => out.push(b as char),
+            b => out.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_nested_array() {
+        let reply = Frame::Array(vec![
+            Frame::Array(vec![Frame::Bulk("a".into()), Frame::Integer(1)]),
+            Frame::Null,
+        ]);
+
+        assert_eq!("1) 1) \"a\"\n   2) (integer) 1\n2) (nil)", format_tty(&reply, 0));
+    }
+
+    #[test]
+    fn help() {
+        let build = |args: &[&str]| Config::build(args.iter().map(|arg| arg.to_string())).unwrap();
+
+        // --help 之后的参数仍然正常解析, 不会被当作命令
+        let config = build(&["rudis-cli", "-p", "7000", "--help"]);
+        assert!(config.help);
+        assert!(config.command.is_empty());
+        assert!(!build(&["rudis-cli", "get", "--help"]).help);
+    }
+
+    #[tokio::test]
+    async fn big_keys() {
+        let server = rudis::server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+        conn.request(["RPUSH", "short", "a"]).await.unwrap();
+        conn.request(["RPUSH", "long", "a", "b", "c"]).await.unwrap();
+        conn.request(["SET", "str", "hello"]).await.unwrap();
+        conn.request(["HSET", "hash", "f", "v"]).await.unwrap();
+        conn.request(["BF.ADD", "bloom", "x"]).await.unwrap();
+
+        let port = server.addr().port().to_string();
+        let config = |args: &'static [&'static str]| {
+            let args = ["rudis-cli", "-p", &port].into_iter().chain(args.iter().copied());
+            Config::build(args.map(String::from)).unwrap()
+        };
+
+        let mut out = vec![];
+        find_big_keys(&mut conn, &config(&["--bigkeys"]), &mut out).await.unwrap();
+        let out = String::from_utf8(out).unwrap();
+        for line in [
+            "Sampled 5 keys in the keyspace!",
+            "Biggest   list found '\"long\"' has 3 items",
+            "Biggest string found '\"str\"' has 5 bytes",
+            "Biggest   hash found '\"hash\"' has 1 fields",
+            "2 lists with 4 items (40.00% of keys, avg size 2.00)",
+            // 模块类型只统计个数
+            "1 MBbloom--s with 0 ? (20.00% of keys, avg size 0.00)",
+        ] {
+            assert!(out.contains(line), "{} not found in:\n{}", line, out);
+        }
+
+        // --memkeys 使用 MEMORY USAGE 返回的字节数, 包括模块类型
+        let reply = conn.request(["MEMORY", "USAGE", "bloom"]).await.unwrap();
+        let Frame::Integer(usage) = reply else {
+            panic!("MEMORY USAGE should reply an integer");
+        };
+        let mut out = vec![];
+        let memkeys = config(&["--memkeys-samples", "0"]);
+        find_big_keys(&mut conn, &memkeys, &mut out).await.unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let line = format!("Biggest MBbloom-- found '\"bloom\"' has {} bytes", usage);
+        assert!(out.contains(&line), "{} not found in:\n{}", line, out);
+        assert!(out.contains("2 lists with "), "{}", out);
+    }
+}
@@ -0,0 +1,426 @@
+//! rudis-sentinel: 监控主节点的可用性, 不进行故障转移
+//!
+//! ```text
+//! rudis-sentinel --monitor name host port quorum [--bind addr] [--port 26379]
+//!                [--down-after-milliseconds 5000] [--peer host:port ...]
+//! ```
+//!
+//! 与 redis sentinel 相同:
+//! + 每秒(down-after-milliseconds 小于 1 秒时按该值) PING 一次主节点,
+//!   超过 down-after-milliseconds 没有收到 PONG 时, 认为主节点主观下线(sdown)
+//! + 主观下线之后通过 `SENTINEL is-master-down-by-addr` 询问其他的 sentinel,
+//!   包括自己在内认为主节点下线的 sentinel 达到 quorum 时, 主节点客观下线(odown)
+//! + 客户端通过 `SENTINEL get-master-addr-by-name` 查询主节点的地址
+//!
+//! 与 redis 不同, sentinel 之间不会通过主节点的 pub/sub 自动发现彼此, 需要通过 --peer 指定.
+//!
+//! rudis-sentinel 只负责监控. 自动故障转移(提升最合适的从节点, 并让其他从节点复制新的主节点)
+//! 依赖主从复制, rudis 没有实现复制, 也就没有可以提升的从节点, 因此不在 rudis-sentinel 的范围内:
+//! 客观下线只会记录 `+odown` 日志, `get-master-addr-by-name` 始终返回启动时指定的地址
+
+use std::{
+    env, process,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use log::{info, warn};
+use rudis::{Connection, Frame};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    time::{self, Instant},
+};
+
+/// PING 主节点的间隔, 与 redis sentinel 相同
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 连接主节点或者其他 sentinel 时, 单个请求的超时时间
+const REQUEST_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+
+    let args = Args::build(env::args()).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+
+    let listener = match TcpListener::bind((args.bind.as_str(), args.port)).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("failed to listen on {}:{}: {}", args.bind, args.port, err);
+            process::exit(1);
+        }
+    };
+
+    run(listener, args.master, args.peers).await;
+}
+
+/// 在后台监控主节点, 同时处理 listener 上的请求
+async fn run(listener: TcpListener, master: Master, peers: Vec<String>) {
+    info!(
+        "monitoring master {} {}:{} quorum {}",
+        master.name, master.host, master.port, master.quorum
+    );
+
+    let master = Arc::new(Mutex::new(master));
+    tokio::spawn(monitor(master.clone(), peers));
+
+    loop {
+        let (socket, _) = listener.accept().await.unwrap();
+        let _ = socket.set_nodelay(true);
+        tokio::spawn(serve(socket, master.clone()));
+    }
+}
+
+/// 被监控的主节点
+struct Master {
+    name: String,
+    host: String,
+    port: u16,
+    quorum: usize,
+    down_after: Duration,
+
+    /// 最近一次收到 PONG 的时间
+    last_ok: Instant,
+
+    /// 主观下线
+    sdown: bool,
+
+    /// 客观下线
+    odown: bool,
+}
+
+impl Master {
+    fn addr(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    fn flags(&self) -> String {
+        let mut flags = "master".to_string();
+        if self.sdown {
+            flags.push_str(",s_down");
+        }
+        if self.odown {
+            flags.push_str(",o_down");
+        }
+
+        flags
+    }
+}
+
+struct Args {
+    bind: String,
+    port: u16,
+    master: Master,
+
+    /// 其他 sentinel 的地址
+    peers: Vec<String>,
+}
+
+impl Args {
+    fn build(mut args: impl Iterator<Item = String>) -> Result<Self, &'static str> {
+        const USAGE: &str = "Usage: rudis-sentinel --monitor name host port quorum [--bind addr] \
+                             [--port port] [--down-after-milliseconds ms] [--peer host:port ...]";
+
+        args.next();
+
+        let (mut bind, mut port) = ("127.0.0.1".to_string(), 26379);
+        let (mut monitor, mut down_after, mut peers) = (None, 5000, vec![]);
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--bind" => bind = args.next().ok_or("--bind requires an address")?,
+                "--port" => {
+                    port = match args.next().map(|v| v.parse()) {
+                        Some(Ok(port)) => port,
+                        _ => Err("--port requires a valid port")?,
+                    }
+                }
+                "--monitor" => {
+                    let (Some(name), Some(host), Some(port), Some(quorum)) =
+                        (args.next(), args.next(), args.next(), args.next())
+                    else {
+                        return Err("--monitor requires name, host, port and quorum");
+                    };
+                    match (port.parse::<u16>(), quorum.parse::<usize>()) {
+                        (Ok(port), Ok(quorum)) if quorum > 0 => {
+                            monitor = Some((name, host, port, quorum))
+                        }
+                        _ => Err("--monitor requires a valid port and a positive quorum")?,
+                    }
+                }
+                "--down-after-milliseconds" => {
+                    down_after = match args.next().map(|v| v.parse()) {
+                        Some(Ok(ms)) => ms,
+                        _ => Err("--down-after-milliseconds requires a number")?,
+                    }
+                }
+                "--peer" => peers.push(args.next().ok_or("--peer requires an address")?),
+                _ => return Err(USAGE),
+            }
+        }
+
+        let (name, host, master_port, quorum) = monitor.ok_or(USAGE)?;
+        Ok(Args {
+            bind,
+            port,
+            master: Master {
+                name,
+                host,
+                port: master_port,
+                quorum,
+                down_after: Duration::from_millis(down_after),
+                last_ok: Instant::now(),
+                sdown: false,
+                odown: false,
+            },
+            peers,
+        })
+    }
+}
+
+/// 定期检查主节点的状态
+async fn monitor(master: Arc<Mutex<Master>>, peers: Vec<String>) {
+    let down_after = master.lock().unwrap().down_after;
+    let mut interval = time::interval(PING_INTERVAL.min(down_after));
+    let mut connection = None;
+
+    loop {
+        interval.tick().await;
+
+        let addr = master.lock().unwrap().addr();
+        let alive = ping(&mut connection, &addr).await;
+
+        let (sdown, host, port) = {
+            let mut master = master.lock().unwrap();
+            if alive {
+                if master.sdown {
+                    info!("-sdown master {} {}", master.name, addr);
+                }
+                master.last_ok = Instant::now();
+                master.sdown = false;
+                master.odown = false;
+            } else if !master.sdown && master.last_ok.elapsed() > master.down_after {
+                warn!("+sdown master {} {}", master.name, addr);
+                master.sdown = true;
+            }
+
+            (master.sdown, master.host.clone(), master.port)
+        };
+        if !sdown {
+            continue;
+        }
+
+        // 自己的一票加上其他 sentinel 的投票
+        let mut votes = 1;
+        for peer in &peers {
+            if is_down_by_peer(peer, &host, port).await {
+                votes += 1;
+            }
+        }
+
+        let mut master = master.lock().unwrap();
+        let odown = votes >= master.quorum;
+        if odown && !master.odown {
+            warn!(
+                "+odown master {} {} #quorum {}/{}",
+                master.name, addr, votes, master.quorum
+            );
+        } else if !odown && master.odown {
+            info!("-odown master {} {}", master.name, addr);
+        }
+        master.odown = odown;
+    }
+}
+
+/// PING 主节点, 连接断开时下一次重新建立连接
+async fn ping(connection: &mut Option<Connection>, addr: &str) -> bool {
+    if connection.is_none() {
+        match time::timeout(REQUEST_TIMEOUT, Connection::connect(addr)).await {
+            Ok(Ok(conn)) => *connection = Some(conn),
+            _ => return false,
+        }
+    }
+
+    let conn = connection.as_mut().unwrap();
+    match time::timeout(REQUEST_TIMEOUT, conn.request(["PING"])).await {
+        Ok(Ok(Frame::Simple(pong))) if pong == "PONG" => true,
+        _ => {
+            // 超时之后回复可能还在路上, 继续使用该连接会读到错位的回复
+            *connection = None;
+            false
+        }
+    }
+}
+
+/// 询问另一个 sentinel 是否认为主节点已经下线
+async fn is_down_by_peer(peer: &str, host: &str, port: u16) -> bool {
+    let ask = async {
+        let mut conn = Connection::connect(peer).await?;
+        let args = ["SENTINEL", "is-master-down-by-addr", host, &port.to_string(), "0", "*"]
+            .map(|arg| Bytes::from(arg.to_string()));
+        conn.request(args).await
+    };
+
+    match time::timeout(REQUEST_TIMEOUT, ask).await {
+        Ok(Ok(Frame::Array(reply))) => reply.first() == Some(&Frame::Integer(1)),
+        _ => false,
+    }
+}
+
+/// 处理客户端以及其他 sentinel 的请求
+async fn serve(socket: TcpStream, master: Arc<Mutex<Master>>) {
+    let mut connection = Connection::new(socket);
+    connection.enable_inline();
+
+    while let Ok(Some(frame)) = connection.read_frame().await {
+        let reply = match args(frame) {
+            Some(args) => execute(&args, &master.lock().unwrap()),
+            None => Frame::Error("ERR Protocol error: expected array of bulk strings".to_string()),
+        };
+
+        if connection.write_frame(&reply).await.is_err() {
+            break;
+        }
+    }
+}
+
+fn args(frame: Frame) -> Option<Vec<String>> {
+    let Frame::Array(parts) = frame else {
+        return None;
+    };
+
+    parts
+        .into_iter()
+        .map(|part| match part {
+            Frame::Bulk(bytes) => Some(String::from_utf8_lossy(&bytes).into_owned()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn execute(args: &[String], master: &Master) -> Frame {
+    let bulk = |s: &str| Frame::Bulk(Bytes::from(s.to_string()));
+    let lower = |i: usize| args.get(i).map(|arg| arg.to_ascii_lowercase()).unwrap_or_default();
+
+    match (lower(0).as_str(), lower(1).as_str(), args.len()) {
+        ("ping", _, 1) => Frame::Simple("PONG".to_string()),
+        ("sentinel", "get-master-addr-by-name", 3) if args[2] == master.name => {
+            Frame::Array(vec![bulk(&master.host), bulk(&master.port.to_string())])
+        }
+        ("sentinel", "get-master-addr-by-name", 3) => Frame::Null,
+        // 回复的格式与 redis 相同: [是否下线, leader 的 runid, leader 的 epoch], rudis 不进行 leader 选举
+        ("sentinel", "is-master-down-by-addr", 6) => {
+            let down =
+                args[2] == master.host && args[3] == master.port.to_string() && master.sdown;
+            Frame::Array(vec![Frame::Integer(down as i64), bulk("*"), Frame::Integer(0)])
+        }
+        ("sentinel", "masters", 2) => Frame::Array(vec![master_info(master)]),
+        ("sentinel", "master", 3) if args[2] == master.name => master_info(master),
+        ("sentinel", "master", 3) => Frame::Error("ERR No such master with that name".to_string()),
+        _ => Frame::Error(format!("ERR unknown command '{}'", args.join(" "))),
+    }
+}
+
+fn master_info(master: &Master) -> Frame {
+    let fields = [
+        ("name", master.name.clone()),
+        ("ip", master.host.clone()),
+        ("port", master.port.to_string()),
+        ("flags", master.flags()),
+        ("last-ok-ping-reply", master.last_ok.elapsed().as_millis().to_string()),
+        ("down-after-milliseconds", master.down_after.as_millis().to_string()),
+        ("quorum", master.quorum.to_string()),
+    ];
+
+    Frame::Array(
+        fields
+            .into_iter()
+            .flat_map(|(name, value)| [Frame::Bulk(Bytes::from(name)), Frame::Bulk(value.into())])
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 在 listener 上启动一个 sentinel, args 与命令行参数相同
+    async fn sentinel(listener: TcpListener, args: &[&str]) {
+        let args = ["rudis-sentinel"].iter().chain(args).map(|arg| arg.to_string());
+        let args = Args::build(args).unwrap();
+        tokio::spawn(run(listener, args.master, args.peers));
+    }
+
+    async fn flags(addr: &str) -> String {
+        let mut conn = Connection::connect(addr).await.unwrap();
+        let Frame::Array(fields) = conn.request(["SENTINEL", "master", "mymaster"]).await.unwrap()
+        else {
+            panic!("SENTINEL master should reply an array");
+        };
+
+        let i = fields.iter().position(|field| *field == Frame::Bulk("flags".into())).unwrap();
+        match &fields[i + 1] {
+            Frame::Bulk(flags) => String::from_utf8(flags.to_vec()).unwrap(),
+            frame => panic!("unexpected flags {}", frame),
+        }
+    }
+
+    /// 等待 sentinel 的状态变为 expected, 最多等待 5 秒
+    async fn wait_flags(addr: &str, expected: &str) {
+        let wait = async {
+            while flags(addr).await != expected {
+                time::sleep(Duration::from_millis(20)).await;
+            }
+        };
+        time::timeout(Duration::from_secs(5), wait)
+            .await
+            .unwrap_or_else(|_| panic!("{} should become {}", addr, expected));
+    }
+
+    #[tokio::test]
+    async fn sdown_and_odown() {
+        let server = rudis::server::isolated().await;
+        let port = server.addr().port().to_string();
+
+        let mut listeners = vec![];
+        let mut addrs = vec![];
+        for _ in 0..3 {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            addrs.push(listener.local_addr().unwrap().to_string());
+            listeners.push(listener);
+        }
+
+        // a 与 b 互为 peer, quorum 为 2; c 没有 peer, 只能得到自己的一票
+        let monitor = ["--monitor", "mymaster", "127.0.0.1", &port, "2"];
+        let down_after = ["--down-after-milliseconds", "100"];
+        let mut listeners = listeners.into_iter();
+        for peer in [&addrs[1], &addrs[0]] {
+            let args = [&monitor[..], &down_after, &["--peer", peer]].concat();
+            sentinel(listeners.next().unwrap(), &args).await;
+        }
+        sentinel(listeners.next().unwrap(), &[&monitor[..], &down_after].concat()).await;
+
+        let mut conn = Connection::connect(&addrs[0]).await.unwrap();
+        let reply = conn.request(["SENTINEL", "get-master-addr-by-name", "mymaster"]).await;
+        let expected = [Bytes::from("127.0.0.1"), Bytes::from(port.clone())].map(Frame::Bulk);
+        assert_eq!(Frame::Array(expected.to_vec()), reply.unwrap());
+        for addr in &addrs {
+            assert_eq!("master", flags(addr).await);
+        }
+
+        server.shutdown().await;
+
+        wait_flags(&addrs[0], "master,s_down,o_down").await;
+        wait_flags(&addrs[1], "master,s_down,o_down").await;
+        // c 得不到足够的投票, 只会主观下线
+        wait_flags(&addrs[2], "master,s_down").await;
+
+        // 不进行故障转移, 主节点的地址保持不变
+        let reply = conn.request(["SENTINEL", "get-master-addr-by-name", "mymaster"]).await;
+        assert_eq!(Frame::Array(expected.to_vec()), reply.unwrap());
+    }
+}
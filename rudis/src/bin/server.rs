@@ -1,23 +1,7 @@
-use bytes::Bytes;
-use log::{debug, info};
-use mini_redis::{Command, Connection, Frame};
-use std::{
-    collections::HashMap,
-    sync::{Arc, Mutex},
-};
-use tokio::net::{TcpListener, TcpStream};
+use std::{env, process};
 
-/// Tokio 提供的异步锁只应该在跨多个 `.await` 调用时使用
-/// 在 `.await` 执行期间, 任务可能会在线程间转移. 理解了这个很多时候就明白错误了
-///
-/// Example:
-///
-/// 如果我们在 `async` 中跨 `.await` 使用了 Mutex, 此时会可能导致死锁的问题
-/// 因为 `.await` 期间如果调度了另外一个 Future, 并且该 Future 也需要获取锁, 此时就导致死锁啦
-///
-/// 或者我们可以使用 Tokio 提供的锁.
-/// 最大的优点就是：它可以在 `.await` 执行期间被持有，而且不会有任何问题。但是代价就是，这种异步锁的性能开销会更高
-type Database = Arc<Vec<Mutex<HashMap<String, Bytes>>>>;
+use log::{error, info};
+use rudis::{config::Config, server::Server};
 
 /// 我们将 `.await` 理解为就是: **一步走两步判读**
 /// * 一步走: 推动执行一个 Future 的 poll()
@@ -33,76 +17,96 @@ type Database = Arc<Vec<Mutex<HashMap<String, Bytes>>>>;
 async fn main() {
     // init logger
     env_logger::init();
-    info!("rudis is starting");
 
-    let tcp_listener = TcpListener::bind("127.0.0.1:6379").await.unwrap();
-    let db = new_shared_db(3);
-
-    loop {
-        let (tcp_stream, _) = tcp_listener.accept().await.unwrap();
-
-        // 一个 Tokio 任务是一个异步的绿色线程, 它们通过 `tokio::spawn` 进行创建
-        // 该函数会返回一个 `JoinHandle` 类型的句柄, 调用者可以使用该句柄跟创建的任务进行交互
-        // 任务是调度器管理的执行单元. spawn生成的任务会首先提交给'调度器', 然后由它负责调度执行.
-        // 需要注意的是, 执行任务的线程未必是创建任务的线程, 任务'完全有可能运行在另一个不同的线程'上, 而且任务在生成后, 它还可能会在线程间被移动.
-        // 类似于启动一个 "Golang的协程" :)
+    let args = Args::build(env::args()).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+    info!("rudis is starting");
 
-        let db = db.clone();
-        tokio::spawn(async {
-            process(tcp_stream, db).await;
-        });
+    let builder = Server::builder()
+        .bind(args.bind)
+        .port(args.port)
+        .config(Config::default().with_debug_command(args.enable_debug_command));
+    #[cfg(unix)]
+    let builder = match (args.unixsocket, args.unixsocketperm) {
+        (Some(path), Some(perm)) => builder.unixsocket(path).unixsocketperm(perm),
+        (Some(path), None) => builder.unixsocket(path),
+        (None, _) => builder,
+    };
+    #[cfg(not(unix))]
+    if args.unixsocket.is_some() || args.unixsocketperm.is_some() {
+        eprintln!("--unixsocket is only supported on unix platforms");
+        process::exit(1);
     }
-}
-
-async fn process(socket: TcpStream, db: Database) {
-    // Connection 对 redis 的读写进行了封装
-    // Frame(数据帧 = redis命令 + 数据)
-    let mut connection = Connection::new(socket);
-
-    // 我们需要使用循环 (while) 的方式在同一个客户端连接中处理多次连续的请求
-    while let Some(frame) = connection.read_frame().await.unwrap() {
-        let response: Frame = match Command::from_frame(frame).unwrap() {
-            Command::Set(set) => {
-                let key = set.key().to_string();
-                let val = set.value().clone();
 
-                debug!("Set command: key={}, val={:?}", &key, &val);
+    // 监听端口以及处理连接的逻辑都在 `rudis::server` 中, 测试也可以在进程内启动服务端
+    let server = builder.spawn().await.unwrap_or_else(|err| {
+        error!("failed to start server: {}", err);
+        process::exit(1);
+    });
+    server.wait().await;
+}
 
-                let mut db = db[key.len() % db.len()].lock().unwrap();
-                db.insert(key, val);
-                Frame::Simple("OK".to_string())
-            }
-            Command::Get(get) => {
-                let key = get.key();
-                let db = db[key.len() % db.len()].lock().unwrap();
+/// 服务端的启动参数, 与 redis.conf 中的同名配置项含义相同
+///
+/// ```text
+/// server [--bind addr] [--port port] [--unixsocket path] [--unixsocketperm 700]
+///        [--enable-debug-command yes|no]
+/// ```
+struct Args {
+    bind: String,
+    port: u16,
+    unixsocket: Option<String>,
+
+    /// socket 文件的权限, 使用八进制表示, 不指定时由 umask 决定
+    unixsocketperm: Option<u32>,
+
+    /// 是否允许执行 DEBUG 命令, 默认关闭
+    enable_debug_command: bool,
+}
 
-                if let Some(val) = db.get(key) {
-                    debug!("Get command: key={},val={:?}", key, val);
+impl Args {
+    fn build(mut args: impl Iterator<Item = String>) -> Result<Self, &'static str> {
+        args.next();
 
-                    Frame::Bulk(val.clone().into())
-                } else {
-                    info!("Get command not found val by key={}", key);
+        let mut config = Args {
+            bind: "127.0.0.1".to_string(),
+            port: 6379,
+            unixsocket: None,
+            unixsocketperm: None,
+            enable_debug_command: false,
+        };
 
-                    Frame::Null
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--bind" => config.bind = args.next().ok_or("--bind requires an address")?,
+                "--port" => {
+                    config.port = match args.next().map(|v| v.parse()) {
+                        Some(Ok(port)) => port,
+                        _ => Err("--port requires a valid port")?,
+                    }
+                }
+                "--unixsocket" => {
+                    config.unixsocket = Some(args.next().ok_or("--unixsocket requires a path")?)
                 }
+                "--unixsocketperm" => {
+                    config.unixsocketperm = match args.next().map(|v| u32::from_str_radix(&v, 8)) {
+                        Some(Ok(perm)) if perm <= 0o777 => Some(perm),
+                        _ => Err("--unixsocketperm requires an octal permission, e.g. 700")?,
+                    }
+                }
+                "--enable-debug-command" => {
+                    config.enable_debug_command = match args.next().as_deref() {
+                        Some("yes") => true,
+                        Some("no") => false,
+                        _ => Err("--enable-debug-command requires yes or no")?,
+                    }
+                }
+                _ => return Err("Usage: server [--bind addr] [--port port] [--unixsocket path] [--unixsocketperm perm] [--enable-debug-command yes|no]"),
             }
-            cmd => panic!("unimmplementd command: {:?}", cmd),
-        };
+        }
 
-        // reply
-        connection.write_frame(&response).await.unwrap();
+        Ok(config)
     }
 }
-
-fn new_shared_db(mut shards_num: usize) -> Database {
-    if shards_num == 0 {
-        shards_num = 1;
-    }
-
-    let mut db = Vec::with_capacity(3);
-    for _ in 0..shards_num {
-        db.push(Mutex::new(HashMap::new()));
-    }
-
-    Arc::new(db)
-}
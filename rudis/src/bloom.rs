@@ -0,0 +1,250 @@
+//! 可扩展的布隆过滤器
+//!
+//! 与 RedisBloom 相同, 一个过滤器由多个子过滤器组成: 当前子过滤器中的元素达到容量上限时,
+//! 再添加一个容量为 `capacity * expansion` 的子过滤器. 为了让整体的误判率不超过设定值,
+//! 每个新的子过滤器的误判率都是上一个的一半, 整体误判率的上界为 `error_rate * 2`.
+//!
+//! 查询时需要检查所有的子过滤器, 添加时只写入最后一个
+
+use crate::{
+    dump::{self, Reader},
+    hll::murmurhash64a,
+};
+
+/// 每个新的子过滤器的误判率相对于上一个的比例
+const TIGHTENING_RATIO: f64 = 0.5;
+
+pub const DEFAULT_ERROR_RATE: f64 = 0.01;
+pub const DEFAULT_CAPACITY: u64 = 100;
+pub const DEFAULT_EXPANSION: u64 = 2;
+
+/// 与 redis 的字符串相同, 一个过滤器所有子过滤器的位数组加起来最多 512MB
+pub const MAX_BYTES: u64 = 512 * 1024 * 1024;
+
+pub const FULL_ERR: &str = "ERR non scaling filter is full";
+pub const TOO_LARGE_ERR: &str = "ERR filter exceeds the maximum size of 512MB";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bloom {
+    filters: Vec<Filter>,
+    /// 为 0 时代表不可扩展
+    expansion: u64,
+}
+
+/// 一个固定大小的布隆过滤器
+#[derive(Debug, Clone, PartialEq)]
+struct Filter {
+    bits: Vec<u64>,
+    /// 位数组的长度
+    nbits: u64,
+    /// 哈希函数的个数
+    hashes: u32,
+    capacity: u64,
+    error_rate: f64,
+    /// 已经添加的元素个数
+    items: u64,
+}
+
+impl Filter {
+    /// 位数组超过 `max_bytes` 时返回 None, 必须在分配内存之前检查
+    fn new(capacity: u64, error_rate: f64, max_bytes: u64) -> Option<Filter> {
+        // 最优的位数 m = -n * ln(p) / ln(2)^2, 哈希函数个数 k = -log2(p).
+        // 误判率很小时 m 可能是无穷大, 转换为整数之前先在浮点数上比较
+        let ln2 = std::f64::consts::LN_2;
+        let nbits = (-(capacity as f64) * error_rate.ln() / (ln2 * ln2)).ceil().max(64.0);
+        if nbits > max_bytes as f64 * 8.0 {
+            return None;
+        }
+        let nbits = nbits as u64;
+        let hashes = (-error_rate.log2()).ceil().max(1.0) as u32;
+
+        Some(Filter {
+            bits: vec![0; nbits.div_ceil(64) as usize],
+            nbits,
+            hashes,
+            capacity,
+            error_rate,
+            items: 0,
+        })
+    }
+
+    /// 使用 double hashing 从两个哈希值生成 k 个下标
+    fn positions(&self, (h1, h2): (u64, u64)) -> impl Iterator<Item = u64> + '_ {
+        (0..self.hashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % self.nbits)
+    }
+
+    fn contains(&self, hash: (u64, u64)) -> bool {
+        self.positions(hash)
+            .all(|pos| self.bits[(pos / 64) as usize] & (1 << (pos % 64)) != 0)
+    }
+
+    /// 返回是否有新的位被设置, 没有时说明元素(可能)已经存在
+    fn insert(&mut self, hash: (u64, u64)) -> bool {
+        let mut changed = false;
+        for pos in self.positions(hash).collect::<Vec<_>>() {
+            let word = &mut self.bits[(pos / 64) as usize];
+            changed |= *word & (1 << (pos % 64)) == 0;
+            *word |= 1 << (pos % 64);
+        }
+
+        if changed {
+            self.items += 1;
+        }
+        changed
+    }
+}
+
+impl Bloom {
+    /// 创建过滤器, expansion 为 0 时不可扩展. 参数的合法性由调用者检查,
+    /// 位数组超过 [`MAX_BYTES`] 时返回错误
+    pub fn new(error_rate: f64, capacity: u64, expansion: u64) -> Result<Bloom, &'static str> {
+        Ok(Bloom {
+            filters: vec![Filter::new(capacity, error_rate, MAX_BYTES).ok_or(TOO_LARGE_ERR)?],
+            expansion,
+        })
+    }
+
+    pub fn contains(&self, item: &[u8]) -> bool {
+        let hash = hash(item);
+        self.filters.iter().any(|filter| filter.contains(hash))
+    }
+
+    /// 添加元素, 返回元素之前是否不存在
+    pub fn insert(&mut self, item: &[u8]) -> Result<bool, &'static str> {
+        let hash = hash(item);
+        if self.filters.iter().any(|filter| filter.contains(hash)) {
+            return Ok(false);
+        }
+
+        let last = self.filters.last().unwrap();
+        if last.items >= last.capacity {
+            if self.expansion == 0 {
+                return Err(FULL_ERR);
+            }
+
+            // 子过滤器的容量按指数增长, 扩展之后的总大小同样不能超过上限
+            let filter = Filter::new(
+                last.capacity.saturating_mul(self.expansion),
+                last.error_rate * TIGHTENING_RATIO,
+                MAX_BYTES.saturating_sub(self.size() as u64),
+            );
+            self.filters.push(filter.ok_or(TOO_LARGE_ERR)?);
+        }
+
+        Ok(self.filters.last_mut().unwrap().insert(hash))
+    }
+
+    /// 所有子过滤器的总容量
+    pub fn capacity(&self) -> u64 {
+        self.filters.iter().map(|filter| filter.capacity).sum()
+    }
+
+    /// 已经添加的元素个数
+    pub fn len(&self) -> u64 {
+        self.filters.iter().map(|filter| filter.items).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 位数组占用的字节数
+    pub fn size(&self) -> usize {
+        self.filters.iter().map(|filter| filter.bits.len() * 8).sum()
+    }
+
+    /// 序列化, 见 `crate::dump`
+    pub(crate) fn encode(&self, out: &mut Vec<u8>) {
+        dump::put_u64(out, self.expansion);
+        dump::put_u64(out, self.filters.len() as u64);
+        for filter in &self.filters {
+            dump::put_u64(out, filter.nbits);
+            dump::put_u64(out, filter.hashes as u64);
+            dump::put_u64(out, filter.capacity);
+            dump::put_f64(out, filter.error_rate);
+            dump::put_u64(out, filter.items);
+            for word in &filter.bits {
+                dump::put_u64(out, *word);
+            }
+        }
+    }
+
+    pub(crate) fn decode(reader: &mut Reader<'_>) -> Option<Bloom> {
+        let expansion = reader.u64()?;
+        let mut filters = Vec::with_capacity(reader.len()?);
+        for _ in 0..filters.capacity() {
+            let nbits = reader.u64()?;
+            let hashes = u32::try_from(reader.u64()?).ok()?;
+            let (capacity, error_rate, items) = (reader.u64()?, reader.f64()?, reader.u64()?);
+            if nbits == 0 || hashes == 0 {
+                return None;
+            }
+
+            let bits = (0..nbits.div_ceil(64))
+                .map(|_| reader.u64())
+                .collect::<Option<_>>()?;
+            filters.push(Filter {
+                bits,
+                nbits,
+                hashes,
+                capacity,
+                error_rate,
+                items,
+            });
+        }
+
+        (!filters.is_empty()).then_some(Bloom { filters, expansion })
+    }
+}
+
+fn hash(item: &[u8]) -> (u64, u64) {
+    let h1 = murmurhash64a(item, 0xc6a4_a793_5bd1_e995);
+    // h2 为 0 时 k 个下标都相同, 设置最低位保证不为 0
+    let h2 = murmurhash64a(item, h1) | 1;
+
+    (h1, h2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scaling() {
+        let mut bloom = Bloom::new(0.01, 100, 2).unwrap();
+        let mut added = 0;
+        for i in 0..1000 {
+            if bloom.insert(format!("item:{}", i).as_bytes()).unwrap() {
+                added += 1;
+            }
+        }
+
+        // 添加时的误判会让少量新元素被当作已存在
+        assert!(added > 970);
+        assert!(bloom.capacity() >= 1000);
+        assert!((0..1000).all(|i| bloom.contains(format!("item:{}", i).as_bytes())));
+
+        // 误判率的上界约为设定值的两倍, 容量很小的子过滤器误差较大, 这里留一些余量
+        let false_positives = (0..10000)
+            .filter(|i| bloom.contains(format!("other:{}", i).as_bytes()))
+            .count();
+        assert!(false_positives < 250, "{}", false_positives);
+
+        let mut fixed = Bloom::new(0.01, 1, 0).unwrap();
+        assert_eq!(Ok(true), fixed.insert(b"a"));
+        assert_eq!(Err(FULL_ERR), fixed.insert(b"b"));
+    }
+
+    #[test]
+    fn too_large() {
+        assert_eq!(Err(TOO_LARGE_ERR), Bloom::new(0.0001, i64::MAX as u64, 2));
+        assert_eq!(Err(TOO_LARGE_ERR), Bloom::new(f64::MIN_POSITIVE, 1_000_000_000, 2));
+
+        // 扩展出的子过滤器超过上限时添加失败, 已有的元素不受影响
+        let mut bloom = Bloom::new(0.01, 1, i64::MAX as u64).unwrap();
+        assert_eq!(Ok(true), bloom.insert(b"a"));
+        assert_eq!(Err(TOO_LARGE_ERR), bloom.insert(b"b"));
+        assert!(bloom.contains(b"a"));
+        assert_eq!(8, bloom.size());
+    }
+}
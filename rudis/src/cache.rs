@@ -0,0 +1,223 @@
+//! 客户端缓存
+//!
+//! [`CachedConnection`] 在本地缓存 GET 的结果, 命中时不需要访问服务端.
+//! 缓存的正确性依赖服务端的 `CLIENT TRACKING`(见 `crate::tracking`):
+//! + 建立连接时额外建立一条订阅了 `__redis__:invalidate` 的连接, 数据连接通过 REDIRECT
+//!   将失效消息转发给它, 因此使用 RESP2 即可
+//! + 后台任务读取失效消息并删除对应的缓存, 收到 nil 时(例如 FLUSHDB)清空所有的缓存
+//! + 订阅连接断开之后无法再得知 key 的修改, 缓存随之停用, 所有的读取都会访问服务端
+//!
+//! GET 的回复与失效消息从两条连接到达, 先后顺序是不确定的. 发送 GET 之前先在缓存中放入一个占位,
+//! 回复到达之前收到失效消息时占位被删除, 此时回复的值不会被缓存
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use bytes::Bytes;
+use log::warn;
+use tokio::task::JoinHandle;
+
+use crate::{tracking::INVALIDATE_CHANNEL, Connection, Frame, Result};
+
+/// 缓存的 key 的数量上限, 超过之后随机删除一个
+const MAX_ENTRIES: usize = 10_000;
+
+/// 带有本地缓存的连接, 只有 GET 会使用缓存, 其他命令通过 `request` 直接发送
+pub struct CachedConnection {
+    connection: Connection,
+    cache: Arc<Mutex<Cache>>,
+
+    /// 读取失效消息的后台任务, 连接被 drop 时终止
+    invalidator: JoinHandle<()>,
+}
+
+#[derive(Default)]
+struct Cache {
+    entries: HashMap<String, Entry>,
+
+    /// 订阅连接断开之后缓存不再可信
+    disabled: bool,
+}
+
+enum Entry {
+    /// 已经发送了 GET, 尚未收到回复
+    Pending,
+    Ready(Option<Bytes>),
+}
+
+impl CachedConnection {
+    pub async fn connect(addr: &str) -> Result<CachedConnection> {
+        let mut listener = Connection::connect(addr).await?;
+        let id = match listener.request(["CLIENT", "ID"]).await? {
+            Frame::Integer(id) => id,
+            frame => return Err(format!("unexpected CLIENT ID reply: {}", frame).into()),
+        };
+        match listener.request(["SUBSCRIBE", INVALIDATE_CHANNEL]).await? {
+            Frame::Array(_) => {}
+            frame => return Err(format!("unexpected SUBSCRIBE reply: {}", frame).into()),
+        }
+
+        let mut connection = Connection::connect(addr).await?;
+        let tracking = ["CLIENT", "TRACKING", "ON", "REDIRECT"].map(String::from);
+        match connection.request(tracking.into_iter().chain([id.to_string()])).await? {
+            Frame::Simple(_) => {}
+            frame => return Err(format!("failed to enable tracking: {}", frame).into()),
+        }
+
+        let cache = Arc::new(Mutex::new(Cache::default()));
+        let invalidator = tokio::spawn(invalidate(listener, cache.clone()));
+
+        Ok(CachedConnection {
+            connection,
+            cache,
+            invalidator,
+        })
+    }
+
+    /// 读取 key 的值, 缓存命中时直接返回
+    pub async fn get(&mut self, key: &str) -> Result<Option<Bytes>> {
+        {
+            let mut cache = self.cache.lock().unwrap();
+            if !cache.disabled {
+                if let Some(Entry::Ready(value)) = cache.entries.get(key) {
+                    return Ok(value.clone());
+                }
+
+                if cache.entries.len() >= MAX_ENTRIES {
+                    let victim = cache.entries.keys().next().cloned().unwrap();
+                    cache.entries.remove(&victim);
+                }
+                cache.entries.insert(key.to_string(), Entry::Pending);
+            }
+        }
+
+        let reply = self.connection.request(["GET", key].map(str::to_string)).await;
+        let value = match reply {
+            Ok(Frame::Bulk(value)) => Ok(Some(value)),
+            Ok(Frame::Null) => Ok(None),
+            Ok(Frame::Error(err)) => Err(err.into()),
+            Ok(frame) => Err(format!("unexpected GET reply: {}", frame).into()),
+            Err(err) => Err(err),
+        };
+
+        let mut cache = self.cache.lock().unwrap();
+        match (&value, cache.entries.get_mut(key)) {
+            (Ok(value), Some(entry @ Entry::Pending)) => *entry = Entry::Ready(value.clone()),
+            (Err(_), Some(Entry::Pending)) => {
+                cache.entries.remove(key);
+            }
+            _ => {}
+        }
+
+        value
+    }
+
+    /// 发送任意命令, 修改 key 的命令会由服务端发送失效消息
+    pub async fn request<I, A>(&mut self, args: I) -> Result<Frame>
+    where
+        I: IntoIterator<Item = A>,
+        A: Into<Bytes>,
+    {
+        self.connection.request(args).await
+    }
+
+    /// key 当前是否在本地缓存中
+    pub fn is_cached(&self, key: &str) -> bool {
+        let cache = self.cache.lock().unwrap();
+        matches!(cache.entries.get(key), Some(Entry::Ready(_)))
+    }
+}
+
+impl Drop for CachedConnection {
+    fn drop(&mut self) {
+        self.invalidator.abort();
+    }
+}
+
+/// 读取失效消息: `["message", "__redis__:invalidate", [key ...] | nil]`
+async fn invalidate(mut listener: Connection, cache: Arc<Mutex<Cache>>) {
+    loop {
+        let frame = match listener.read_frame().await {
+            Ok(Some(frame)) => frame,
+            res => {
+                if let Err(err) = res {
+                    warn!("invalidation connection broken: {}", err);
+                }
+
+                let mut cache = cache.lock().unwrap();
+                cache.disabled = true;
+                cache.entries.clear();
+                return;
+            }
+        };
+
+        let Frame::Array(mut parts) = frame else {
+            continue;
+        };
+        let mut cache = cache.lock().unwrap();
+        match parts.pop() {
+            Some(Frame::Array(keys)) => {
+                for key in keys {
+                    if let Frame::Bulk(key) = key {
+                        cache.entries.remove(&*String::from_utf8_lossy(&key));
+                    }
+                }
+            }
+            Some(Frame::Null) => cache.entries.clear(),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time;
+
+    use super::*;
+    use crate::server;
+
+    /// 失效消息是异步到达的, 等待 key 被移出缓存
+    async fn wait_evicted(conn: &CachedConnection, key: &str) {
+        time::timeout(Duration::from_secs(2), async {
+            while conn.is_cached(key) {
+                time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .expect("cache was not invalidated");
+    }
+
+    #[tokio::test]
+    async fn invalidated_by_other_client() {
+        let server = server::isolated().await;
+        let mut cached = CachedConnection::connect(&server.addr().to_string())
+            .await
+            .unwrap();
+        let mut other = server.connect().await.unwrap();
+
+        other.request(["SET", "foo", "1"]).await.unwrap();
+        assert_eq!(Some(Bytes::from("1")), cached.get("foo").await.unwrap());
+        assert!(cached.is_cached("foo"));
+
+        // 其他连接的写入通过 REDIRECT 的订阅连接让本地缓存失效
+        other.request(["SET", "foo", "2"]).await.unwrap();
+        wait_evicted(&cached, "foo").await;
+        assert_eq!(Some(Bytes::from("2")), cached.get("foo").await.unwrap());
+
+        // 不存在的 key 同样会被缓存, 被创建之后失效
+        assert_eq!(None, cached.get("bar").await.unwrap());
+        assert!(cached.is_cached("bar"));
+        other.request(["SET", "bar", "1"]).await.unwrap();
+        wait_evicted(&cached, "bar").await;
+
+        // FLUSHALL 让所有的缓存失效
+        cached.get("foo").await.unwrap();
+        other.request(["FLUSHALL"]).await.unwrap();
+        wait_evicted(&cached, "foo").await;
+        assert_eq!(None, cached.get("foo").await.unwrap());
+    }
+}
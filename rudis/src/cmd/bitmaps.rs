@@ -0,0 +1,624 @@
+//! 位图命令
+//!
+//! 位图就是普通的字符串, 每个字节的最高位是第 0 位. 写入超过字符串长度的位时, 字符串会用 0 补齐
+
+use bytes::Bytes;
+
+use super::{
+    int, is, key, string, strings::update_string, CmdResult, Context, SYNTAX_ERR,
+    WRONGTYPE_ERR,
+};
+use crate::{db::Value, notify::Class, Frame};
+
+/// 字符串的最大长度为 512MB, 与 redis 的 `proto-max-bulk-len` 默认值相同
+const MAX_BITS: u64 = 512 * 1024 * 1024 * 8;
+
+const OFFSET_ERR: &str = "ERR bit offset is not an integer or out of range";
+
+/// GETBIT key offset
+pub fn getbit(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let offset = bit_offset(&args[2], false, 1)?;
+    let mut guard = ctx.lock(&[key]);
+
+    let bit = match guard.get(key) {
+        Some(Value::String(val)) => get_bit(val, offset),
+        Some(_) => return Err(WRONGTYPE_ERR.to_string()),
+        None => false,
+    };
+
+    Ok(Frame::Integer(bit as i64).into())
+}
+
+/// SETBIT key offset value
+pub fn setbit(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let offset = bit_offset(&args[2], false, 1)?;
+    let on = match &args[3][..] {
+        b"1" => true,
+        b"0" => false,
+        _ => return Err("ERR bit is not an integer or out of range".to_string()),
+    };
+    let mut guard = ctx.lock(&[key]);
+
+    let old = update_string(&mut guard, key, |buf| {
+        grow(buf, offset, 1);
+        let old = get_bit(buf, offset);
+        set_bit(buf, offset, on);
+
+        Ok(old)
+    })?;
+    guard.notify(Class::String, "setbit", key);
+
+    Ok(Frame::Integer(old as i64).into())
+}
+
+/// BITCOUNT key [start end [BYTE | BIT]]
+pub fn bitcount(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let range = match args.len() {
+        2 => None,
+        4 | 5 => Some(Range::parse(&args[2], &args[3], args.get(4))?),
+        _ => return Err(SYNTAX_ERR.to_string()),
+    };
+    let mut guard = ctx.lock(&[key]);
+
+    let val = match guard.get(key) {
+        Some(Value::String(val)) => val,
+        Some(_) => return Err(WRONGTYPE_ERR.to_string()),
+        None => return Ok(Frame::Integer(0).into()),
+    };
+
+    let count = match range {
+        None => val.iter().map(|b| b.count_ones() as u64).sum(),
+        Some(range) => match range.bits(val.len()) {
+            Some((start, end)) => bytes_in(val, start, end)
+                .map(|(b, mask)| (b & mask).count_ones() as u64)
+                .sum(),
+            None => 0,
+        },
+    };
+
+    Ok(Frame::Integer(count as i64).into())
+}
+
+/// BITPOS key bit [start [end [BYTE | BIT]]]
+pub fn bitpos(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let bit = match &args[2][..] {
+        b"1" => true,
+        b"0" => false,
+        _ => return Err("ERR The bit argument must be 1 or 0.".to_string()),
+    };
+
+    let end_given = args.len() >= 5;
+    let range = match args.len() {
+        3 => None,
+        4 => Some(Range::parse(&args[3], &Bytes::from_static(b"-1"), None)?),
+        5 | 6 => Some(Range::parse(&args[3], &args[4], args.get(5))?),
+        _ => return Err(SYNTAX_ERR.to_string()),
+    };
+    let mut guard = ctx.lock(&[key]);
+
+    // key 不存在时视为全 0 的字符串
+    let val = match guard.get(key) {
+        Some(Value::String(val)) => val,
+        Some(_) => return Err(WRONGTYPE_ERR.to_string()),
+        None => return Ok(Frame::Integer(if bit { -1 } else { 0 }).into()),
+    };
+
+    let range = range.unwrap_or(Range::ALL).bits(val.len());
+    let Some((start, end)) = range else {
+        return Ok(Frame::Integer(-1).into());
+    };
+
+    let pos = bytes_in(val, start, end)
+        .enumerate()
+        .find_map(|(i, (b, mask))| {
+            let b = if bit { b & mask } else { !b & mask };
+            (b != 0).then(|| (start / 8 + i as u64) * 8 + b.leading_zeros() as u64)
+        });
+
+    // 查找 0 并且没有指定 end 时, 字符串的右侧视为用 0 补齐
+    let pos = match pos {
+        Some(pos) => pos as i64,
+        None if !bit && !end_given => (end / 8 + 1) as i64 * 8,
+        None => -1,
+    };
+
+    Ok(Frame::Integer(pos).into())
+}
+
+/// BITOP AND | OR | XOR | NOT destkey key [key ...]
+pub fn bitop(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let op = string(&args[1])?.to_ascii_lowercase();
+    if !matches!(op.as_str(), "and" | "or" | "xor" | "not") {
+        return Err(SYNTAX_ERR.to_string());
+    }
+    if op == "not" && args.len() != 4 {
+        return Err("ERR BITOP NOT must be called with a single source key.".to_string());
+    }
+
+    let keys = args[2..].iter().map(key).collect::<Result<Vec<_>, _>>()?;
+    let (dest, sources) = (keys[0], &keys[1..]);
+    let mut guard = ctx.lock(&keys);
+
+    // 不存在的 key 视为空字符串
+    let mut values = vec![];
+    for source in sources {
+        match guard.get(source) {
+            Some(Value::String(val)) => values.push(val.clone()),
+            Some(_) => return Err(WRONGTYPE_ERR.to_string()),
+            None => values.push(Bytes::new()),
+        }
+    }
+
+    // 较短的字符串用 0 补齐
+    let len = values.iter().map(|val| val.len()).max().unwrap_or(0);
+    let byte = |val: &Bytes, i: usize| val.get(i).copied().unwrap_or(0);
+    let result: Vec<u8> = (0..len)
+        .map(|i| {
+            let mut bytes = values.iter().map(|val| byte(val, i));
+            let first = bytes.next().unwrap();
+            match op.as_str() {
+                "and" => bytes.fold(first, |acc, b| acc & b),
+                "or" => bytes.fold(first, |acc, b| acc | b),
+                "xor" => bytes.fold(first, |acc, b| acc ^ b),
+                _ => !first,
+            }
+        })
+        .collect();
+
+    // 结果为空字符串时删除目标 key
+    if result.is_empty() {
+        if guard.remove(dest).is_some() {
+            guard.notify(Class::Generic, "del", dest);
+        }
+    } else {
+        guard.insert(dest, Value::String(Bytes::from(result)), None);
+        guard.notify(Class::String, "set", dest);
+    }
+
+    Ok(Frame::Integer(len as i64).into())
+}
+
+/// BITFIELD key [GET encoding offset | [OVERFLOW WRAP | SAT | FAIL]
+///   SET encoding offset value | INCRBY encoding offset increment ...]
+pub fn bitfield(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    bitfield_generic(ctx, args, false)
+}
+
+/// BITFIELD_RO key [GET encoding offset [GET encoding offset ...]]
+pub fn bitfield_ro(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    bitfield_generic(ctx, args, true)
+}
+
+/// BITFIELD 中的一个操作
+struct Field {
+    op: FieldOp,
+    signed: bool,
+    bits: u32,
+    offset: u64,
+}
+
+enum FieldOp {
+    Get,
+    Set(i64),
+    IncrBy(i64),
+}
+
+/// 写入的值超出类型范围时的处理方式
+#[derive(Clone, Copy)]
+enum Overflow {
+    Wrap,
+    Sat,
+    Fail,
+}
+
+fn bitfield_generic(ctx: &mut Context<'_>, args: &[Bytes], readonly: bool) -> CmdResult {
+    let key = key(&args[1])?;
+
+    // 先解析并检查所有的操作, 出错时不执行任何操作
+    let mut fields = vec![];
+    let mut overflow = Overflow::Wrap;
+    let mut options = args[2..].iter();
+    while let Some(option) = options.next() {
+        if is(option, "overflow") {
+            let kind = options.next().ok_or(SYNTAX_ERR)?;
+            overflow = if is(kind, "wrap") {
+                Overflow::Wrap
+            } else if is(kind, "sat") {
+                Overflow::Sat
+            } else if is(kind, "fail") {
+                Overflow::Fail
+            } else {
+                return Err("ERR Invalid OVERFLOW type specified".to_string());
+            };
+            continue;
+        }
+
+        let is_get = is(option, "get");
+        if !is_get && !is(option, "set") && !is(option, "incrby") {
+            return Err(SYNTAX_ERR.to_string());
+        }
+        if readonly && !is_get {
+            return Err("ERR BITFIELD_RO only supports the GET subcommand".to_string());
+        }
+
+        let (signed, bits) = encoding(options.next().ok_or(SYNTAX_ERR)?)?;
+        let offset = bit_offset(options.next().ok_or(SYNTAX_ERR)?, true, bits)?;
+        let op = if is_get {
+            FieldOp::Get
+        } else {
+            let value = int(options.next().ok_or(SYNTAX_ERR)?)?;
+            if is(option, "set") {
+                FieldOp::Set(value)
+            } else {
+                FieldOp::IncrBy(value)
+            }
+        };
+
+        fields.push((
+            Field {
+                op,
+                signed,
+                bits,
+                offset,
+            },
+            overflow,
+        ));
+    }
+
+    let mut guard = ctx.lock(&[key]);
+
+    // 只有 GET 时不需要创建 key
+    if fields.iter().all(|(field, _)| matches!(field.op, FieldOp::Get)) {
+        let val = match guard.get(key) {
+            Some(Value::String(val)) => val.clone(),
+            Some(_) => return Err(WRONGTYPE_ERR.to_string()),
+            None => Bytes::new(),
+        };
+
+        let out = fields
+            .iter()
+            .map(|(field, _)| Frame::Integer(field.get(&val)))
+            .collect();
+        return Ok(Frame::Array(out).into());
+    }
+
+    let out = update_string(&mut guard, key, |buf| {
+        // 与 redis 相同, 先按照最大的写入位置扩展字符串
+        for (field, _) in &fields {
+            if !matches!(field.op, FieldOp::Get) {
+                grow(buf, field.offset, field.bits);
+            }
+        }
+
+        let out = fields
+            .iter()
+            .map(|(field, overflow)| field.apply(buf, *overflow))
+            .collect();
+        Ok(out)
+    })?;
+    guard.notify(Class::String, "setbit", key);
+
+    Ok(Frame::Array(out).into())
+}
+
+impl Field {
+    fn get(&self, buf: &[u8]) -> i64 {
+        let mut value: u64 = 0;
+        for i in 0..self.bits as u64 {
+            value = (value << 1) | get_bit(buf, self.offset + i) as u64;
+        }
+
+        // 有符号整数需要符号扩展
+        if self.signed && self.bits < 64 && value >> (self.bits - 1) & 1 == 1 {
+            value |= u64::MAX << self.bits;
+        }
+
+        value as i64
+    }
+
+    fn set(&self, buf: &mut [u8], value: i64) {
+        for i in 0..self.bits as u64 {
+            let on = (value as u64) >> (self.bits as u64 - 1 - i) & 1 == 1;
+            set_bit(buf, self.offset + i, on);
+        }
+    }
+
+    /// 执行一个操作, 溢出并且处理方式为 FAIL 时返回 Null, 不修改字符串
+    fn apply(&self, buf: &mut [u8], overflow: Overflow) -> Frame {
+        let old = self.get(buf);
+        let (value, incr) = match self.op {
+            FieldOp::Get => return Frame::Integer(old),
+            // 无符号类型中的负数按照补码视为很大的正数, 与 redis 相同
+            FieldOp::Set(value) if !self.signed => (value as u64 as i128, 0),
+            FieldOp::Set(value) => (value as i128, 0),
+            FieldOp::IncrBy(incr) => (old as i128, incr as i128),
+        };
+
+        let Some(new) = self.check_overflow(value + incr, overflow) else {
+            return Frame::Null;
+        };
+        self.set(buf, new);
+
+        match self.op {
+            FieldOp::Set(_) => Frame::Integer(old),
+            _ => Frame::Integer(new),
+        }
+    }
+
+    fn check_overflow(&self, value: i128, overflow: Overflow) -> Option<i64> {
+        let (min, max) = if self.signed {
+            (-(1i128 << (self.bits - 1)), (1i128 << (self.bits - 1)) - 1)
+        } else {
+            (0, (1i128 << self.bits) - 1)
+        };
+        if (min..=max).contains(&value) {
+            return Some(value as i64);
+        }
+
+        match overflow {
+            Overflow::Wrap => {
+                let mut wrapped = value.rem_euclid(1i128 << self.bits);
+                if wrapped > max {
+                    wrapped -= 1i128 << self.bits;
+                }
+                Some(wrapped as i64)
+            }
+            Overflow::Sat => Some(value.clamp(min, max) as i64),
+            Overflow::Fail => None,
+        }
+    }
+}
+
+/// 解析 BITFIELD 的类型, 例如 `i8`、`u16`. 与 redis 相同, 不支持 u64
+fn encoding(arg: &Bytes) -> Result<(bool, u32), String> {
+    const ERR: &str = "ERR Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is.";
+
+    let (signed, max) = match arg.first() {
+        Some(b'i' | b'I') => (true, 64),
+        Some(b'u' | b'U') => (false, 63),
+        _ => return Err(ERR.to_string()),
+    };
+    let bits = std::str::from_utf8(&arg[1..])
+        .ok()
+        .and_then(|s| s.parse::<u32>().ok())
+        .filter(|bits| (1..=max).contains(bits))
+        .ok_or(ERR)?;
+
+    Ok((signed, bits))
+}
+
+/// 解析位的偏移量, BITFIELD 中 `#N` 代表第 N 个 `bits` 宽度的整数
+fn bit_offset(arg: &Bytes, allow_hash: bool, bits: u32) -> Result<u64, String> {
+    let (multiplier, arg) = match arg.strip_prefix(b"#") {
+        Some(rest) if allow_hash => (bits as u64, rest),
+        _ => (1, &arg[..]),
+    };
+
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .and_then(|offset| offset.checked_mul(multiplier))
+        .filter(|offset| offset + bits as u64 <= MAX_BITS)
+        .ok_or_else(|| OFFSET_ERR.to_string())
+}
+
+fn get_bit(buf: &[u8], offset: u64) -> bool {
+    let byte = (offset / 8) as usize;
+    buf.get(byte)
+        .is_some_and(|b| b >> (7 - offset % 8) & 1 == 1)
+}
+
+fn set_bit(buf: &mut [u8], offset: u64, on: bool) {
+    let byte = (offset / 8) as usize;
+    let mask = 1 << (7 - offset % 8);
+    if on {
+        buf[byte] |= mask;
+    } else {
+        buf[byte] &= !mask;
+    }
+}
+
+/// 扩展字符串, 保证可以写入 [offset, offset + bits) 范围内的位
+fn grow(buf: &mut Vec<u8>, offset: u64, bits: u32) {
+    let len = ((offset + bits as u64).div_ceil(8)) as usize;
+    if buf.len() < len {
+        buf.resize(len, 0);
+    }
+}
+
+/// 依次返回 [start, end] 范围内的位所在的每个字节, 以及字节中在范围内的位的掩码
+fn bytes_in(val: &[u8], start: u64, end: u64) -> impl Iterator<Item = (u8, u8)> + '_ {
+    let (first, last) = (start / 8, end / 8);
+
+    (first..=last).map(move |i| {
+        let mut mask = 0xffu8;
+        if i == first {
+            mask &= 0xff >> (start % 8);
+        }
+        if i == last {
+            mask &= 0xff << (7 - end % 8);
+        }
+
+        (val[i as usize], mask)
+    })
+}
+
+/// BITCOUNT、BITPOS 的范围, 负数代表从末尾开始计算
+struct Range {
+    start: i64,
+    end: i64,
+
+    /// 范围的单位是位而不是字节
+    bit: bool,
+}
+
+impl Range {
+    const ALL: Range = Range {
+        start: 0,
+        end: -1,
+        bit: false,
+    };
+
+    fn parse(start: &Bytes, end: &Bytes, unit: Option<&Bytes>) -> Result<Range, String> {
+        let (start, end) = (int(start)?, int(end)?);
+        let bit = match unit {
+            None => false,
+            Some(unit) if is(unit, "byte") => false,
+            Some(unit) if is(unit, "bit") => true,
+            Some(_) => return Err(SYNTAX_ERR.to_string()),
+        };
+
+        Ok(Range { start, end, bit })
+    }
+
+    /// 转换为位的范围 [start, end], 范围为空时返回 None
+    fn bits(&self, len: usize) -> Option<(u64, u64)> {
+        let total = if self.bit { len as i64 * 8 } else { len as i64 };
+
+        // 与 redis 相同, 超出范围的下标先截断, 再判断范围是否为空
+        let start = if self.start < 0 { total + self.start } else { self.start }.max(0);
+        let end = if self.end < 0 { total + self.end } else { self.end }
+            .max(0)
+            .min(total - 1);
+        if start > end {
+            return None;
+        }
+
+        let (start, end) = (start as u64, end as u64);
+        match self.bit {
+            true => Some((start, end)),
+            false => Some((start * 8, end * 8 + 7)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{server, Frame};
+
+    fn is_err(frame: &Frame, msg: &str) -> bool {
+        matches!(frame, Frame::Error(err) if err.contains(msg))
+    }
+
+    fn ints(items: &[i64]) -> Frame {
+        Frame::Array(items.iter().map(|n| Frame::Integer(*n)).collect())
+    }
+
+    #[tokio::test]
+    async fn bits_and_ranges() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+
+        assert_eq!(Frame::Integer(0), conn.request(["SETBIT", "b", "7", "1"]).await.unwrap());
+        assert_eq!(Frame::Integer(1), conn.request(["SETBIT", "b", "7", "0"]).await.unwrap());
+        assert_eq!(Frame::Integer(0), conn.request(["GETBIT", "b", "100"]).await.unwrap());
+        assert_eq!(Frame::Integer(1), conn.request(["STRLEN", "b"]).await.unwrap());
+
+        conn.request(["SET", "s", "foobar"]).await.unwrap();
+        for (args, count) in [
+            (&["BITCOUNT", "s"][..], 26),
+            (&["BITCOUNT", "s", "1", "1"], 6),
+            (&["BITCOUNT", "s", "-2", "-1", "BYTE"], 7),
+            (&["BITCOUNT", "s", "5", "30", "BIT"], 17),
+            (&["BITCOUNT", "missing"], 0),
+        ] {
+            let reply = conn.request(args.to_vec()).await.unwrap();
+            assert_eq!(Frame::Integer(count), reply, "{:?}", args);
+        }
+
+        conn.request([&b"SET"[..], b"p", b"\xff\xf0\x00"]).await.unwrap();
+        for (args, pos) in [
+            (&["BITPOS", "p", "0"][..], 12),
+            (&["BITPOS", "p", "1", "2"], -1),
+            (&["BITPOS", "p", "0", "7", "15", "BIT"], 12),
+            (&["BITPOS", "missing", "1"], -1),
+        ] {
+            let reply = conn.request(args.to_vec()).await.unwrap();
+            assert_eq!(Frame::Integer(pos), reply, "{:?}", args);
+        }
+
+        conn.request(["SET", "x", "abc"]).await.unwrap();
+        let reply = conn.request(["BITOP", "AND", "dest", "s", "x"]).await.unwrap();
+        assert_eq!(Frame::Integer(6), reply);
+        assert_eq!(Frame::Integer(6), conn.request(["STRLEN", "dest"]).await.unwrap());
+        // 结果为空字符串时删除目标 key
+        let reply = conn.request(["BITOP", "NOT", "dest", "missing"]).await.unwrap();
+        assert_eq!(Frame::Integer(0), reply);
+        assert_eq!(Frame::Integer(0), conn.request(["EXISTS", "dest"]).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn bitfield() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+
+        let reply = conn.request(["BITFIELD", "f", "INCRBY", "i5", "100", "1", "GET", "u4", "0"]);
+        assert_eq!(ints(&[1, 0]), reply.await.unwrap());
+        let reply = conn.request(["BITFIELD", "f", "SET", "u8", "#1", "255", "GET", "u8", "8"]);
+        assert_eq!(ints(&[0, 255]), reply.await.unwrap());
+
+        // 溢出时按照 OVERFLOW 的设置处理, FAIL 时返回 Null 并且不修改
+        let args = [
+            "BITFIELD", "o", "INCRBY", "u2", "0", "5", "OVERFLOW", "SAT", "INCRBY", "u2", "2", "5",
+            "OVERFLOW", "FAIL", "INCRBY", "i4", "4", "8",
+        ];
+        let reply = conn.request(args).await.unwrap();
+        assert_eq!(Frame::Array(vec![Frame::Integer(1), Frame::Integer(3), Frame::Null]), reply);
+        let reply = conn.request(["BITFIELD_RO", "o", "GET", "i4", "4"]).await.unwrap();
+        assert_eq!(ints(&[0]), reply);
+        let reply = conn.request(["BITFIELD_RO", "missing", "GET", "i64", "0"]).await.unwrap();
+        assert_eq!(ints(&[0]), reply);
+        assert_eq!(Frame::Integer(0), conn.request(["EXISTS", "missing"]).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn argument_errors() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+
+        // 偏移量不能超过 512MB 字符串的范围
+        let too_far = "4294967296";
+        for (args, msg) in [
+            (&["SETBIT", "b", "0"][..], "wrong number of arguments"),
+            (&["SETBIT", "b", too_far, "1"], "bit offset is not an integer or out of range"),
+            (&["SETBIT", "b", "-1", "1"], "bit offset is not an integer or out of range"),
+            (&["SETBIT", "b", "0", "2"], "bit is not an integer"),
+            (&["GETBIT", "b", too_far], "bit offset is not an integer or out of range"),
+            (&["BITCOUNT", "b", "0"], "syntax error"),
+            (&["BITCOUNT", "b", "0", "1", "WORD"], "syntax error"),
+            (&["BITCOUNT", "b", "x", "1"], "not an integer"),
+            (&["BITPOS", "b", "2"], "must be 1 or 0"),
+            (&["BITOP", "NAND", "d", "a"], "syntax error"),
+            (&["BITOP", "NOT", "d", "a", "b"], "single source key"),
+            (&["BITFIELD", "b", "GET", "u64", "0"], "Invalid bitfield type"),
+            (&["BITFIELD", "b", "GET", "i65", "0"], "Invalid bitfield type"),
+            (&["BITFIELD", "b", "GET", "x8", "0"], "Invalid bitfield type"),
+            (&["BITFIELD", "b", "GET", "u8", too_far], "bit offset"),
+            (&["BITFIELD", "b", "SET", "u8", "0"], "syntax error"),
+            (&["BITFIELD", "b", "OVERFLOW", "NOPE"], "Invalid OVERFLOW type"),
+            (&["BITFIELD", "b", "NOPE"], "syntax error"),
+            (&["BITFIELD_RO", "b", "SET", "u8", "0", "1"], "only supports the GET"),
+        ] {
+            let reply = conn.request(args.to_vec()).await.unwrap();
+            assert!(is_err(&reply, msg), "{:?}: {}", args, reply);
+        }
+
+        conn.request(["LPUSH", "list", "x"]).await.unwrap();
+        for args in [
+            &["SETBIT", "list", "0", "1"][..],
+            &["GETBIT", "list", "0"],
+            &["BITCOUNT", "list"],
+            &["BITPOS", "list", "1"],
+            &["BITOP", "OR", "d", "list"],
+            &["BITFIELD", "list", "GET", "u8", "0"],
+            &["BITFIELD", "list", "SET", "u8", "0", "1"],
+        ] {
+            let reply = conn.request(args.to_vec()).await.unwrap();
+            assert!(is_err(&reply, "WRONGTYPE"), "{:?}", args);
+        }
+    }
+}
@@ -0,0 +1,215 @@
+//! 布隆过滤器命令, 实现见 [`crate::bloom`]
+
+use bytes::Bytes;
+
+use super::{int, is, key, ok, zsets::score, CmdResult, Context, WRONGTYPE_ERR};
+use crate::{
+    bloom::{Bloom, DEFAULT_CAPACITY, DEFAULT_ERROR_RATE, DEFAULT_EXPANSION},
+    db::{Guard, Value},
+    notify::Class,
+    Frame,
+};
+
+/// BF.RESERVE key error_rate capacity [EXPANSION expansion] [NONSCALING]
+pub fn reserve(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let error_rate = score(&args[2]).map_err(|_| "ERR bad error rate")?;
+    if !(error_rate > 0.0 && error_rate < 1.0) {
+        return Err("ERR (0 < error rate range < 1)".to_string());
+    }
+    let capacity = int(&args[3]).map_err(|_| "ERR bad capacity")?;
+    if capacity <= 0 {
+        return Err("ERR (capacity should be larger than 0)".to_string());
+    }
+
+    let mut expansion = None;
+    let mut nonscaling = false;
+    let mut options = args[4..].iter();
+    while let Some(option) = options.next() {
+        if is(option, "nonscaling") {
+            nonscaling = true;
+        } else if is(option, "expansion") {
+            let n = options.next().ok_or("ERR no expansion")?;
+            match int(n) {
+                Ok(n) if n >= 1 => expansion = Some(n as u64),
+                _ => return Err("ERR expansion should be greater or equal to 1".to_string()),
+            }
+        } else {
+            return Err(format!(
+                "ERR unknown argument '{}'",
+                String::from_utf8_lossy(option)
+            ));
+        }
+    }
+    if nonscaling && expansion.is_some() {
+        return Err("ERR nonscaling filters cannot expand".to_string());
+    }
+    let expansion = if nonscaling {
+        0
+    } else {
+        expansion.unwrap_or(DEFAULT_EXPANSION)
+    };
+
+    let mut guard = ctx.lock(&[key]);
+    if guard.exists(key) {
+        return Err("ERR item exists".to_string());
+    }
+    let bloom = Bloom::new(error_rate, capacity as u64, expansion)?;
+    guard.insert(key, Value::Bloom(bloom), None);
+    guard.notify(Class::Module, "bf.reserve", key);
+
+    ok()
+}
+
+/// BF.ADD key item
+pub fn add(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let mut guard = ctx.lock(&[key]);
+
+    let added = insert(&mut guard, key, &args[2..])?;
+    match added.into_iter().next().unwrap() {
+        Ok(added) => Ok(Frame::Integer(added as i64).into()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// BF.MADD key item [item ...]
+///
+/// 过滤器已满时, 之后的每个元素都回复一个错误
+pub fn madd(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let mut guard = ctx.lock(&[key]);
+
+    let out = insert(&mut guard, key, &args[2..])?
+        .into_iter()
+        .map(|added| match added {
+            Ok(added) => Frame::Integer(added as i64),
+            Err(e) => Frame::Error(e.to_string()),
+        })
+        .collect();
+
+    Ok(Frame::Array(out).into())
+}
+
+/// BF.EXISTS key item
+pub fn exists(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let mut guard = ctx.lock(&[key]);
+
+    let exists = match guard.get(key) {
+        Some(Value::Bloom(bloom)) => bloom.contains(&args[2]),
+        Some(_) => return Err(WRONGTYPE_ERR.to_string()),
+        None => false,
+    };
+
+    Ok(Frame::Integer(exists as i64).into())
+}
+
+/// 添加多个元素, key 不存在时使用默认参数创建过滤器
+fn insert(
+    guard: &mut Guard<'_>,
+    key: &str,
+    items: &[Bytes],
+) -> Result<Vec<Result<bool, &'static str>>, String> {
+    if !guard.exists(key) {
+        let bloom = Bloom::new(DEFAULT_ERROR_RATE, DEFAULT_CAPACITY, DEFAULT_EXPANSION)?;
+        guard.insert(key, Value::Bloom(bloom), None);
+    }
+
+    let added: Vec<_> = guard
+        .update(key, |value| match value {
+            Value::Bloom(bloom) => Ok(items.iter().map(|item| bloom.insert(item)).collect()),
+            _ => Err(WRONGTYPE_ERR.to_string()),
+        })
+        .unwrap()?;
+    if added.contains(&Ok(true)) {
+        guard.notify(Class::Module, "bf.add", key);
+    }
+
+    Ok(added)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{server, Frame};
+
+    fn is_err(frame: &Frame, msg: &str) -> bool {
+        matches!(frame, Frame::Error(err) if err.contains(msg))
+    }
+
+    #[tokio::test]
+    async fn reserve_and_add() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+
+        let ok = Frame::Simple("OK".to_string());
+        assert_eq!(ok, conn.request(["BF.RESERVE", "bf", "0.01", "100"]).await.unwrap());
+        let reply = conn.request(["BF.RESERVE", "bf", "0.01", "100"]).await.unwrap();
+        assert!(is_err(&reply, "exists"));
+        assert_eq!(Frame::Integer(1), conn.request(["BF.ADD", "bf", "a"]).await.unwrap());
+        assert_eq!(Frame::Integer(0), conn.request(["BF.ADD", "bf", "a"]).await.unwrap());
+        let reply = conn.request(["BF.MADD", "bf", "a", "b"]).await.unwrap();
+        assert_eq!(Frame::Array(vec![Frame::Integer(0), Frame::Integer(1)]), reply);
+        assert_eq!(Frame::Integer(1), conn.request(["BF.EXISTS", "bf", "b"]).await.unwrap());
+        assert_eq!(Frame::Integer(0), conn.request(["BF.EXISTS", "missing", "b"]).await.unwrap());
+
+        // 不存在的 key 使用默认参数自动创建
+        assert_eq!(Frame::Integer(1), conn.request(["BF.ADD", "auto", "a"]).await.unwrap());
+        let reply = conn.request(["TYPE", "auto"]).await.unwrap();
+        assert_eq!(Frame::Simple("MBbloom--".to_string()), reply);
+
+        // 不可扩展的过滤器满了之后, 之后的元素都回复错误
+        conn.request(["BF.RESERVE", "fixed", "0.01", "1", "NONSCALING"]).await.unwrap();
+        conn.request(["BF.ADD", "fixed", "a"]).await.unwrap();
+        assert!(is_err(&conn.request(["BF.ADD", "fixed", "b"]).await.unwrap(), "full"));
+    }
+
+    #[tokio::test]
+    async fn argument_errors() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+
+        for (args, msg) in [
+            (&["BF.RESERVE", "bf", "x", "100"][..], "bad error rate"),
+            (&["BF.RESERVE", "bf", "1", "100"], "error rate range"),
+            (&["BF.RESERVE", "bf", "0.01", "x"], "bad capacity"),
+            (&["BF.RESERVE", "bf", "0.01", "0"], "capacity should be larger"),
+            (&["BF.RESERVE", "bf", "0.01", "10", "EXPANSION", "0"], "expansion should be"),
+            (&["BF.RESERVE", "bf", "0.01", "10", "EXPANSION"], "no expansion"),
+            (&["BF.RESERVE", "bf", "0.01", "10", "NONSCALING", "EXPANSION", "2"], "cannot expand"),
+            (&["BF.RESERVE", "bf", "0.01", "10", "NOPE"], "unknown argument"),
+            (&["BF.ADD", "bf"], "wrong number of arguments"),
+        ] {
+            let reply = conn.request(args.to_vec()).await.unwrap();
+            assert!(is_err(&reply, msg), "{:?}: {}", args, reply);
+        }
+
+        conn.request(["SET", "str", "x"]).await.unwrap();
+        let commands = [&["BF.ADD", "str"][..], &["BF.MADD", "str"], &["BF.EXISTS", "str"]];
+        for args in commands {
+            let reply = conn.request([args, &["a"]].concat()).await.unwrap();
+            assert!(is_err(&reply, "WRONGTYPE"), "{:?}", args);
+        }
+    }
+
+    #[tokio::test]
+    async fn too_large() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+
+        // 位数组超过上限的过滤器在分配内存之前就被拒绝
+        let reply = conn.request(["BF.RESERVE", "huge", "0.0001", "9223372036854775807"]).await;
+        assert!(matches!(reply.unwrap(), Frame::Error(err) if err.contains("maximum size")));
+        assert_eq!(Frame::Integer(0), conn.request(["EXISTS", "huge"]).await.unwrap());
+
+        // 扩展出的子过滤器超过上限时, 只有需要扩展的元素失败
+        let reserve = ["BF.RESERVE", "bf", "0.01", "1", "EXPANSION", "9223372036854775807"];
+        conn.request(reserve).await.unwrap();
+        let Frame::Array(added) = conn.request(["BF.MADD", "bf", "a", "b"]).await.unwrap() else {
+            panic!("BF.MADD should reply an array");
+        };
+        assert_eq!(Frame::Integer(1), added[0]);
+        assert!(matches!(&added[1], Frame::Error(err) if err.contains("maximum size")));
+        assert_eq!(Frame::Integer(1), conn.request(["BF.EXISTS", "bf", "a"]).await.unwrap());
+    }
+}
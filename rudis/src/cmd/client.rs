@@ -0,0 +1,356 @@
+//! 客户端连接相关的命令: HELLO 与 CLIENT
+
+use bytes::Bytes;
+
+use super::{int, is, ok, string, CmdResult, Context, SYNTAX_ERR};
+use crate::{tracking::Options, Frame};
+
+/// HELLO [protover]
+///
+/// rudis 的回复仍然使用 RESP2 的类型, 切换到 RESP3 只影响推送消息:
+/// pub/sub 与 tracking 的消息以 `>` 发送, 订阅模式下也可以执行普通的命令
+pub fn hello(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    // 不支持 AUTH 与 SETNAME
+    if args.len() > 2 {
+        return Err(SYNTAX_ERR.to_string());
+    }
+
+    if let Some(version) = args.get(1) {
+        let protocol = match int(version) {
+            Ok(version @ (2 | 3)) => version as u8,
+            Ok(_) => return Err("NOPROTO unsupported protocol version".to_string()),
+            Err(_) => {
+                return Err("ERR Protocol version is not an integer or out of range".to_string())
+            }
+        };
+
+        ctx.session.set_protocol(protocol);
+        if ctx.session.tracking.is_some() {
+            ctx.db.tracking().set_resp3(ctx.session.id(), protocol == 3);
+        }
+    }
+
+    let bulk = |s: &str| Frame::Bulk(Bytes::from(s.to_string()));
+    Ok(Frame::Array(vec![
+        bulk("server"),
+        bulk("redis"),
+        bulk("version"),
+        bulk(env!("CARGO_PKG_VERSION")),
+        bulk("proto"),
+        Frame::Integer(ctx.session.protocol() as i64),
+        bulk("id"),
+        Frame::Integer(ctx.session.id() as i64),
+        bulk("mode"),
+        bulk("standalone"),
+        bulk("role"),
+        bulk("master"),
+        bulk("modules"),
+        Frame::Array(vec![]),
+    ])
+    .into())
+}
+
+/// CLIENT ID | TRACKING ... | CACHING yes|no | GETREDIR | TRACKINGINFO
+pub fn client(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let sub = &args[1];
+
+    if is(sub, "id") && args.len() == 2 {
+        Ok(Frame::Integer(ctx.session.id() as i64).into())
+    } else if is(sub, "tracking") && args.len() >= 3 {
+        tracking(ctx, &args[2..])
+    } else if is(sub, "caching") && args.len() == 3 {
+        caching(ctx, &args[2])
+    } else if is(sub, "getredir") && args.len() == 2 {
+        let redirect = match &ctx.session.tracking {
+            None => -1,
+            Some(options) => options.redirect.map_or(0, |id| id as i64),
+        };
+
+        Ok(Frame::Integer(redirect).into())
+    } else if is(sub, "trackinginfo") && args.len() == 2 {
+        Ok(tracking_info(ctx.session.tracking.as_ref()).into())
+    } else {
+        Err(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'. Try CLIENT HELP.",
+            String::from_utf8_lossy(sub)
+        ))
+    }
+}
+
+/// CLIENT TRACKING ON|OFF [REDIRECT client-id] [PREFIX prefix ...] [BCAST] [OPTIN] [OPTOUT]
+fn tracking(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let mut options = Options::default();
+
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        if is(arg, "redirect") {
+            let id = rest.next().ok_or(SYNTAX_ERR)?;
+            options.redirect = match int(id) {
+                Ok(id) if id > 0 => Some(id as u64),
+                _ => return Err("ERR Invalid client ID".to_string()),
+            };
+        } else if is(arg, "prefix") {
+            let prefix = rest.next().ok_or(SYNTAX_ERR)?;
+            options.prefixes.push(string(prefix)?.to_string());
+        } else if is(arg, "bcast") {
+            options.bcast = true;
+        } else if is(arg, "optin") {
+            options.optin = true;
+        } else if is(arg, "optout") {
+            options.optout = true;
+        } else {
+            return Err(SYNTAX_ERR.to_string());
+        }
+    }
+
+    let (id, tracking) = (ctx.session.id(), ctx.db.tracking());
+
+    if is(&args[0], "off") {
+        ctx.session.tracking = None;
+        ctx.session.caching = None;
+        tracking.disable(id);
+        return ok();
+    }
+    if !is(&args[0], "on") {
+        return Err(SYNTAX_ERR.to_string());
+    }
+
+    if !options.prefixes.is_empty() && !options.bcast {
+        return Err("ERR PREFIX option requires BCAST mode to be enabled".to_string());
+    }
+    if options.optin && options.optout {
+        return Err("ERR You can't use both OPTIN and OPTOUT".to_string());
+    }
+    if options.bcast && (options.optin || options.optout) {
+        return Err("ERR OPTIN and OPTOUT are not compatible with BCAST".to_string());
+    }
+
+    // 与 redis 相同, 已经开启时不能切换模式, 广播模式的前缀会被追加
+    if let Some(current) = &ctx.session.tracking {
+        if current.bcast != options.bcast
+            || current.optin != options.optin
+            || current.optout != options.optout
+        {
+            return Err("ERR You can't switch BCAST mode on/off before disabling tracking for \
+                        this client, and then re-enabling it with a different mode."
+                .to_string());
+        }
+    }
+
+    let push = ctx.session.push();
+    tracking.enable(id, push, ctx.session.protocol() == 3, &options);
+
+    if let Some(current) = ctx.session.tracking.take() {
+        for prefix in current.prefixes {
+            if !options.prefixes.contains(&prefix) {
+                options.prefixes.push(prefix);
+            }
+        }
+    }
+    ctx.session.tracking = Some(options);
+
+    ok()
+}
+
+/// CLIENT CACHING YES|NO, 只对下一条命令生效
+fn caching(ctx: &mut Context<'_>, arg: &Bytes) -> CmdResult {
+    let Some(options) = &ctx.session.tracking else {
+        return Err("ERR CLIENT CACHING can be called only when the client is in tracking mode \
+                    with OPTIN or OPTOUT mode enabled"
+            .to_string());
+    };
+
+    if is(arg, "yes") {
+        if !options.optin {
+            return Err(
+                "ERR CLIENT CACHING YES is only valid when tracking is enabled in OPTIN mode."
+                    .to_string(),
+            );
+        }
+        ctx.session.caching = Some(true);
+    } else if is(arg, "no") {
+        if !options.optout {
+            return Err(
+                "ERR CLIENT CACHING NO is only valid when tracking is enabled in OPTOUT mode."
+                    .to_string(),
+            );
+        }
+        ctx.session.caching = Some(false);
+    } else {
+        return Err(SYNTAX_ERR.to_string());
+    }
+
+    ok()
+}
+
+fn tracking_info(options: Option<&Options>) -> Frame {
+    let bulk = |s: &str| Frame::Bulk(Bytes::from(s.to_string()));
+
+    let (flags, redirect, prefixes) = match options {
+        None => (vec![bulk("off")], -1, vec![]),
+        Some(options) => {
+            let mut flags = vec![bulk("on")];
+            for (enabled, flag) in [
+                (options.bcast, "bcast"),
+                (options.optin, "optin"),
+                (options.optout, "optout"),
+            ] {
+                if enabled {
+                    flags.push(bulk(flag));
+                }
+            }
+
+            let prefixes = options.prefixes.iter().map(|prefix| bulk(prefix)).collect();
+            (flags, options.redirect.map_or(0, |id| id as i64), prefixes)
+        }
+    };
+
+    Frame::Array(vec![
+        bulk("flags"),
+        Frame::Array(flags),
+        bulk("redirect"),
+        Frame::Integer(redirect),
+        bulk("prefixes"),
+        Frame::Array(prefixes),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use tokio::time::timeout;
+
+    use crate::{server, Connection, Frame};
+
+    fn is_err(frame: &Frame, msg: &str) -> bool {
+        matches!(frame, Frame::Error(err) if err.contains(msg))
+    }
+
+    fn bulk(s: &str) -> Frame {
+        Frame::Bulk(Bytes::copy_from_slice(s.as_bytes()))
+    }
+
+    fn invalidate(keys: &[&str]) -> Frame {
+        let keys = Frame::Array(keys.iter().map(|key| bulk(key)).collect());
+        Frame::Push(vec![bulk("invalidate"), keys])
+    }
+
+    async fn push(conn: &mut Connection) -> Frame {
+        let frame = timeout(Duration::from_secs(5), conn.read_frame());
+        frame.await.expect("no push received").unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn tracking_pushes() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+        let mut other = server.connect().await.unwrap();
+
+        let ok = Frame::Simple("OK".to_string());
+        conn.request(["HELLO", "3"]).await.unwrap();
+        assert_eq!(ok, conn.request(["CLIENT", "TRACKING", "ON", "OPTIN"]).await.unwrap());
+        assert_eq!(Frame::Integer(0), conn.request(["CLIENT", "GETREDIR"]).await.unwrap());
+
+        // OPTIN 模式只记录 CLIENT CACHING YES 之后的一条命令读取的 key
+        conn.request(["GET", "skipped"]).await.unwrap();
+        conn.request(["CLIENT", "CACHING", "YES"]).await.unwrap();
+        conn.request(["GET", "cached"]).await.unwrap();
+        other.request(["SET", "skipped", "1"]).await.unwrap();
+        other.request(["SET", "cached", "1"]).await.unwrap();
+        assert_eq!(invalidate(&["cached"]), push(&mut conn).await);
+
+        // 切换模式之前需要先关闭
+        let reply = conn.request(["CLIENT", "TRACKING", "ON", "BCAST"]).await.unwrap();
+        assert!(is_err(&reply, "can't switch BCAST mode"), "{}", reply);
+        conn.request(["CLIENT", "TRACKING", "OFF"]).await.unwrap();
+        let args = ["CLIENT", "TRACKING", "ON", "BCAST", "PREFIX", "user:"];
+        assert_eq!(ok, conn.request(args).await.unwrap());
+        let reply = conn.request(["CLIENT", "TRACKINGINFO"]).await.unwrap();
+        let expected = Frame::Array(vec![
+            bulk("flags"),
+            Frame::Array(vec![bulk("on"), bulk("bcast")]),
+            bulk("redirect"),
+            Frame::Integer(0),
+            bulk("prefixes"),
+            Frame::Array(vec![bulk("user:")]),
+        ]);
+        assert_eq!(expected, reply);
+
+        other.request(["SET", "order:1", "1"]).await.unwrap();
+        other.request(["SET", "user:1", "1"]).await.unwrap();
+        assert_eq!(invalidate(&["user:1"]), push(&mut conn).await);
+
+        conn.request(["CLIENT", "TRACKING", "OFF"]).await.unwrap();
+        assert_eq!(Frame::Integer(-1), conn.request(["CLIENT", "GETREDIR"]).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn tracking_table_limit() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+
+        let mut listener = server.connect().await.unwrap();
+
+        // 失效消息转发到另一条连接, 不会与 GET 的回复交错
+        let Frame::Integer(id) = listener.request(["CLIENT", "ID"]).await.unwrap() else {
+            panic!("CLIENT ID should reply with an integer");
+        };
+        listener.request(["SUBSCRIBE", "__redis__:invalidate"]).await.unwrap();
+        conn.request(["CONFIG", "SET", "tracking-table-max-keys", "1"]).await.unwrap();
+        let tracking = ["CLIENT", "TRACKING", "ON", "REDIRECT"].map(String::from);
+        conn.request(tracking.into_iter().chain([id.to_string()])).await.unwrap();
+
+        // 超过上限时提前让其中一个已经记录的 key 失效, 另一个 key 仍然被记录
+        conn.request(["GET", "a"]).await.unwrap();
+        conn.request(["GET", "b"]).await.unwrap();
+        let message = |key: &str| {
+            let keys = Frame::Array(vec![bulk(key)]);
+            Frame::Array(vec![bulk("message"), bulk("__redis__:invalidate"), keys])
+        };
+        let evicted = push(&mut listener).await;
+        let kept = match evicted {
+            _ if evicted == message("a") => "b",
+            _ if evicted == message("b") => "a",
+            _ => panic!("unexpected message: {}", evicted),
+        };
+
+        conn.request(["SET", kept, "1"]).await.unwrap();
+        assert_eq!(message(kept), push(&mut listener).await);
+    }
+
+    #[tokio::test]
+    async fn argument_errors() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+
+        for (args, msg) in [
+            (&["HELLO", "4"][..], "NOPROTO"),
+            (&["HELLO", "x"], "Protocol version is not an integer"),
+            (&["HELLO", "3", "AUTH"], "syntax error"),
+            (&["CLIENT", "NOPE"], "unknown subcommand"),
+            (&["CLIENT", "ID", "x"], "unknown subcommand"),
+            (&["CLIENT", "TRACKING", "MAYBE"], "syntax error"),
+            (&["CLIENT", "TRACKING", "ON", "REDIRECT"], "syntax error"),
+            (&["CLIENT", "TRACKING", "ON", "REDIRECT", "0"], "Invalid client ID"),
+            (&["CLIENT", "TRACKING", "ON", "PREFIX", "a"], "requires BCAST"),
+            (&["CLIENT", "TRACKING", "ON", "OPTIN", "OPTOUT"], "both OPTIN and OPTOUT"),
+            (&["CLIENT", "TRACKING", "ON", "BCAST", "OPTIN"], "not compatible with BCAST"),
+            (&["CLIENT", "TRACKING", "ON", "NOPE"], "syntax error"),
+            (&["CLIENT", "CACHING", "YES"], "only when the client is in tracking mode"),
+        ] {
+            let reply = conn.request(args.to_vec()).await.unwrap();
+            assert!(is_err(&reply, msg), "{:?}: {}", args, reply);
+        }
+
+        conn.request(["CLIENT", "TRACKING", "ON", "OPTOUT"]).await.unwrap();
+        for (args, msg) in [
+            (&["CLIENT", "CACHING", "YES"][..], "only valid when tracking is enabled in OPTIN"),
+            (&["CLIENT", "CACHING", "MAYBE"], "syntax error"),
+        ] {
+            let reply = conn.request(args.to_vec()).await.unwrap();
+            assert!(is_err(&reply, msg), "{:?}: {}", args, reply);
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! Count-Min Sketch 命令, 实现见 [`crate::cms`]
+
+use bytes::Bytes;
+
+use super::{int, key, ok, CmdResult, Context, WRONGTYPE_ERR};
+use crate::{cms::CountMinSketch, db::Value, notify::Class, Frame};
+
+const MISSING_ERR: &str = "ERR CMS: key does not exist";
+
+/// CMS.INITBYDIM key width depth
+pub fn initbydim(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let (width, depth) = match (int(&args[2]), int(&args[3])) {
+        (Ok(width), Ok(depth)) if width > 0 && depth > 0 => (width as usize, depth as usize),
+        _ => return Err("ERR CMS: invalid width/depth".to_string()),
+    };
+    // 与 redis 的字符串相同, 单个 value 最多 512MB
+    if width.saturating_mul(depth) > 512 * 1024 * 1024 / 8 {
+        return Err("ERR CMS: width/depth is too large".to_string());
+    }
+
+    let mut guard = ctx.lock(&[key]);
+    if guard.exists(key) {
+        return Err("ERR CMS: key already exists".to_string());
+    }
+    guard.insert(key, Value::Cms(CountMinSketch::new(width, depth)), None);
+    guard.notify(Class::Module, "cms.init", key);
+
+    ok()
+}
+
+/// CMS.INCRBY key item increment [item increment ...]
+pub fn incrby(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    if !args[2..].len().is_multiple_of(2) {
+        return Err("ERR wrong number of arguments for 'cms.incrby' command".to_string());
+    }
+    let items = args[2..]
+        .chunks(2)
+        .map(|pair| match int(&pair[1]) {
+            Ok(delta) if delta >= 0 => Ok((&pair[0], delta as u64)),
+            _ => Err("ERR CMS: Cannot parse number".to_string()),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut guard = ctx.lock(&[key]);
+
+    let counts = guard.update(key, |value| match value {
+        Value::Cms(cms) => Ok(items
+            .iter()
+            .map(|(item, delta)| cms.incr_by(item, *delta))
+            .collect::<Vec<_>>()),
+        _ => Err(WRONGTYPE_ERR.to_string()),
+    });
+    let counts = counts.ok_or(MISSING_ERR)??;
+    guard.notify(Class::Module, "cms.incrby", key);
+
+    let out = counts
+        .into_iter()
+        .map(|count| Frame::Integer(count.min(i64::MAX as u64) as i64))
+        .collect();
+
+    Ok(Frame::Array(out).into())
+}
+
+/// CMS.QUERY key item [item ...]
+pub fn query(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let mut guard = ctx.lock(&[key]);
+
+    let cms = match guard.get(key) {
+        Some(Value::Cms(cms)) => cms,
+        Some(_) => return Err(WRONGTYPE_ERR.to_string()),
+        None => return Err(MISSING_ERR.to_string()),
+    };
+    let out = args[2..]
+        .iter()
+        .map(|item| Frame::Integer(cms.query(item).min(i64::MAX as u64) as i64))
+        .collect();
+
+    Ok(Frame::Array(out).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{server, Frame};
+
+    fn is_err(frame: &Frame, msg: &str) -> bool {
+        matches!(frame, Frame::Error(err) if err.contains(msg))
+    }
+
+    #[tokio::test]
+    async fn incrby_and_query() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+
+        let reply = conn.request(["CMS.INITBYDIM", "cms", "100", "4"]).await.unwrap();
+        assert_eq!(Frame::Simple("OK".to_string()), reply);
+        let reply = conn.request(["CMS.INITBYDIM", "cms", "100", "4"]).await.unwrap();
+        assert!(is_err(&reply, "already exists"));
+
+        let reply = conn.request(["CMS.INCRBY", "cms", "a", "3", "b", "1"]).await.unwrap();
+        assert_eq!(Frame::Array(vec![Frame::Integer(3), Frame::Integer(1)]), reply);
+        conn.request(["CMS.INCRBY", "cms", "a", "2"]).await.unwrap();
+        let reply = conn.request(["CMS.QUERY", "cms", "a", "b", "c"]).await.unwrap();
+        let counts = vec![Frame::Integer(5), Frame::Integer(1), Frame::Integer(0)];
+        assert_eq!(Frame::Array(counts), reply);
+    }
+
+    #[tokio::test]
+    async fn argument_errors() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+        conn.request(["CMS.INITBYDIM", "cms", "10", "2"]).await.unwrap();
+        conn.request(["SET", "str", "x"]).await.unwrap();
+
+        for (args, msg) in [
+            (&["CMS.INITBYDIM", "c", "0", "2"][..], "invalid width/depth"),
+            (&["CMS.INITBYDIM", "c", "10", "x"], "invalid width/depth"),
+            // 计数器的总大小与字符串一样不能超过 512MB
+            (&["CMS.INITBYDIM", "c", "9223372036854775807", "2"], "too large"),
+            (&["CMS.INCRBY", "cms", "a"], "wrong number of arguments"),
+            (&["CMS.INCRBY", "cms", "a", "1", "b"], "wrong number of arguments"),
+            (&["CMS.INCRBY", "cms", "a", "-1"], "Cannot parse number"),
+            (&["CMS.INCRBY", "missing", "a", "1"], "key does not exist"),
+            (&["CMS.QUERY", "missing", "a"], "key does not exist"),
+            (&["CMS.INCRBY", "str", "a", "1"], "WRONGTYPE"),
+            (&["CMS.QUERY", "str", "a"], "WRONGTYPE"),
+        ] {
+            let reply = conn.request(args.to_vec()).await.unwrap();
+            assert!(is_err(&reply, msg), "{:?}: {}", args, reply);
+        }
+        assert_eq!(Frame::Integer(0), conn.request(["EXISTS", "c"]).await.unwrap());
+    }
+}
@@ -0,0 +1,401 @@
+//! 地理位置命令
+//!
+//! 地理位置保存在有序集合中, 成员的分数为经纬度的 geohash 编码, 见 [`crate::geo`]
+
+use std::ops::Bound;
+
+use bytes::Bytes;
+
+use super::{
+    int, is, key,
+    zsets::{self, score, AddOptions},
+    CmdResult, Context, SYNTAX_ERR,
+};
+use crate::{
+    geo::{self as geohash, Shape},
+    Frame,
+};
+
+/// GEOADD key [NX | XX] [CH] longitude latitude member [longitude latitude member ...]
+pub fn geoadd(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+
+    let mut options = AddOptions::default();
+    let mut i = 2;
+    while i < args.len() && (is(&args[i], "nx") || is(&args[i], "xx") || is(&args[i], "ch")) {
+        options.parse(&args[i]);
+        i += 1;
+    }
+    options.check()?;
+
+    let triples = &args[i..];
+    if triples.is_empty() || !triples.len().is_multiple_of(3) {
+        return Err(
+            "ERR syntax error. Try GEOADD key [x1] [y1] [name1] [x2] [y2] [name2] ... ".to_string(),
+        );
+    }
+
+    let mut items = Vec::with_capacity(triples.len() / 3);
+    for triple in triples.chunks(3) {
+        let (lon, lat) = lon_lat(&triple[0], &triple[1])?;
+        items.push((geohash::encode_score(lon, lat), triple[2].clone()));
+    }
+
+    let mut guard = ctx.lock(&[key]);
+    let count = zsets::add(&mut guard, key, items, options, "geoadd")?;
+
+    Ok(Frame::Integer(count as i64).into())
+}
+
+/// GEOPOS key [member [member ...]]
+pub fn geopos(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let mut guard = ctx.lock(&[key]);
+    let zset = zsets::get(&mut guard, key)?;
+
+    let out = args[2..]
+        .iter()
+        .map(|member| match zset.and_then(|zset| zset.score(member)) {
+            Some(score) => {
+                let (lon, lat) = geohash::decode_score(score);
+                Frame::Array(vec![coord(lon), coord(lat)])
+            }
+            None => Frame::Null,
+        })
+        .collect();
+
+    Ok(Frame::Array(out).into())
+}
+
+/// GEODIST key member1 member2 [M | KM | FT | MI]
+pub fn geodist(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let unit = match args.len() {
+        4 => 1.0,
+        5 => unit(&args[4])?,
+        _ => return Err(SYNTAX_ERR.to_string()),
+    };
+    let mut guard = ctx.lock(&[key]);
+    let Some(zset) = zsets::get(&mut guard, key)? else {
+        return Ok(Frame::Null.into());
+    };
+
+    match (zset.score(&args[2]), zset.score(&args[3])) {
+        (Some(a), Some(b)) => {
+            let (lon1, lat1) = geohash::decode_score(a);
+            let (lon2, lat2) = geohash::decode_score(b);
+            let dist = geohash::distance(lon1, lat1, lon2, lat2) / unit;
+
+            Ok(Frame::Bulk(Bytes::from(format!("{:.4}", dist))).into())
+        }
+        _ => Ok(Frame::Null.into()),
+    }
+}
+
+/// GEOHASH key [member [member ...]]
+pub fn geohash(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let mut guard = ctx.lock(&[key]);
+    let zset = zsets::get(&mut guard, key)?;
+
+    let out = args[2..]
+        .iter()
+        .map(|member| match zset.and_then(|zset| zset.score(member)) {
+            Some(score) => Frame::Bulk(Bytes::from(geohash::to_string(score))),
+            None => Frame::Null,
+        })
+        .collect();
+
+    Ok(Frame::Array(out).into())
+}
+
+/// GEOSEARCH key <FROMMEMBER member | FROMLONLAT longitude latitude>
+///   <BYRADIUS radius <M | KM | FT | MI> | BYBOX width height <M | KM | FT | MI>>
+///   [ASC | DESC] [COUNT count [ANY]] [WITHCOORD] [WITHDIST] [WITHHASH]
+pub fn geosearch(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+
+    let mut from_member = None;
+    let mut from_lon_lat = None;
+    let mut shape = None;
+    let mut unit_scale = 1.0;
+    let mut order = None;
+    let mut count = None;
+    let mut any = false;
+    let (mut with_coord, mut with_dist, mut with_hash) = (false, false, false);
+
+    let mut i = 2;
+    while i < args.len() {
+        let remaining = args.len() - i - 1;
+        let arg = &args[i];
+        if is(arg, "frommember") && remaining >= 1 {
+            from_member = Some(args[i + 1].clone());
+            i += 1;
+        } else if is(arg, "fromlonlat") && remaining >= 2 {
+            from_lon_lat = Some(lon_lat(&args[i + 1], &args[i + 2])?);
+            i += 2;
+        } else if is(arg, "byradius") && remaining >= 2 {
+            let radius = score(&args[i + 1])?;
+            if radius < 0.0 {
+                return Err("ERR radius cannot be negative".to_string());
+            }
+            unit_scale = unit(&args[i + 2])?;
+            shape = Some(Shape::Radius(radius * unit_scale));
+            i += 2;
+        } else if is(arg, "bybox") && remaining >= 3 {
+            let (width, height) = (score(&args[i + 1])?, score(&args[i + 2])?);
+            if width < 0.0 || height < 0.0 {
+                return Err("ERR height or width cannot be negative".to_string());
+            }
+            unit_scale = unit(&args[i + 3])?;
+            shape = Some(Shape::Box {
+                width: width * unit_scale,
+                height: height * unit_scale,
+            });
+            i += 3;
+        } else if is(arg, "asc") {
+            order = Some(true);
+        } else if is(arg, "desc") {
+            order = Some(false);
+        } else if is(arg, "count") && remaining >= 1 {
+            let n = int(&args[i + 1])?;
+            if n <= 0 {
+                return Err("ERR COUNT must be > 0".to_string());
+            }
+            count = Some(n as usize);
+            i += 1;
+            if i + 1 < args.len() && is(&args[i + 1], "any") {
+                any = true;
+                i += 1;
+            }
+        } else if is(arg, "withcoord") {
+            with_coord = true;
+        } else if is(arg, "withdist") {
+            with_dist = true;
+        } else if is(arg, "withhash") {
+            with_hash = true;
+        } else {
+            return Err(SYNTAX_ERR.to_string());
+        }
+        i += 1;
+    }
+
+    if from_member.is_some() == from_lon_lat.is_some() {
+        return Err(
+            "ERR exactly one of FROMMEMBER or FROMLONLAT can be specified for GEOSEARCH"
+                .to_string(),
+        );
+    }
+    let Some(shape) = shape else {
+        return Err(
+            "ERR exactly one of BYRADIUS and BYBOX can be specified for GEOSEARCH".to_string(),
+        );
+    };
+    if any && count.is_none() {
+        return Err("ERR the ANY argument requires COUNT argument".to_string());
+    }
+    // 指定了 COUNT 但没有指定 ANY 时, 需要先排序才能返回最近的几个成员
+    if count.is_some() && !any && order.is_none() {
+        order = Some(true);
+    }
+
+    let mut guard = ctx.lock(&[key]);
+    let Some(zset) = zsets::get(&mut guard, key)? else {
+        return Ok(Frame::Array(vec![]).into());
+    };
+
+    let center = match (from_member, from_lon_lat) {
+        (Some(member), _) => match zset.score(&member) {
+            Some(score) => geohash::decode_score(score),
+            None => return Err("ERR could not decode requested zset member".to_string()),
+        },
+        (None, Some(center)) => center,
+        (None, None) => unreachable!(),
+    };
+
+    // 在中心点及其周围的格子中查找, 再逐个检查是否在范围内
+    let mut found = vec![];
+    'search: for (min, max) in shape.score_ranges(center) {
+        for (member, score) in zset.range_by_score(Bound::Included(min), Bound::Excluded(max)) {
+            let (lon, lat) = geohash::decode_score(score);
+            if let Some(dist) = shape.distance(center, lon, lat) {
+                found.push((member, score, dist, lon, lat));
+                if any && Some(found.len()) == count {
+                    break 'search;
+                }
+            }
+        }
+    }
+
+    match order {
+        Some(true) => found.sort_by(|a, b| a.2.total_cmp(&b.2)),
+        Some(false) => found.sort_by(|a, b| b.2.total_cmp(&a.2)),
+        None => {}
+    }
+    if let Some(count) = count {
+        found.truncate(count);
+    }
+
+    let out = found
+        .into_iter()
+        .map(|(member, score, dist, lon, lat)| {
+            if !(with_coord || with_dist || with_hash) {
+                return Frame::Bulk(member.clone());
+            }
+
+            let mut item = vec![Frame::Bulk(member.clone())];
+            if with_dist {
+                item.push(Frame::Bulk(Bytes::from(format!("{:.4}", dist / unit_scale))));
+            }
+            if with_hash {
+                item.push(Frame::Integer(score as i64));
+            }
+            if with_coord {
+                item.push(Frame::Array(vec![coord(lon), coord(lat)]));
+            }
+
+            Frame::Array(item)
+        })
+        .collect();
+
+    Ok(Frame::Array(out).into())
+}
+
+/// 解析并检查经纬度
+fn lon_lat(lon: &Bytes, lat: &Bytes) -> Result<(f64, f64), String> {
+    let (lon, lat) = (score(lon)?, score(lat)?);
+    if !geohash::is_valid(lon, lat) {
+        return Err(format!(
+            "ERR invalid longitude,latitude pair {:.6},{:.6}",
+            lon, lat
+        ));
+    }
+
+    Ok((lon, lat))
+}
+
+/// 距离单位对应的米数
+fn unit(arg: &Bytes) -> Result<f64, String> {
+    match arg.to_ascii_lowercase().as_slice() {
+        b"m" => Ok(1.0),
+        b"km" => Ok(1000.0),
+        b"ft" => Ok(0.3048),
+        b"mi" => Ok(1609.34),
+        _ => Err("ERR unsupported unit provided. please use M, KM, FT, MI".to_string()),
+    }
+}
+
+/// 与 redis 相同, 坐标保留 17 位小数并去掉末尾的 0
+fn coord(value: f64) -> Frame {
+    let s = format!("{:.17}", value);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+
+    Frame::Bulk(Bytes::copy_from_slice(s.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::{server, Frame};
+
+    fn is_err(frame: &Frame, msg: &str) -> bool {
+        matches!(frame, Frame::Error(err) if err.contains(msg))
+    }
+
+    fn bulk(s: &str) -> Frame {
+        Frame::Bulk(Bytes::copy_from_slice(s.as_bytes()))
+    }
+
+    const SICILY: [&str; 8] = [
+        "GEOADD", "Sicily", "13.361389", "38.115556", "Palermo", "15.087269", "37.502669",
+        "Catania",
+    ];
+
+    #[tokio::test]
+    async fn add_and_search() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+
+        assert_eq!(Frame::Integer(2), conn.request(SICILY).await.unwrap());
+        assert_eq!(Frame::Integer(0), conn.request(SICILY).await.unwrap());
+        let reply = conn.request(["TYPE", "Sicily"]).await.unwrap();
+        assert_eq!(Frame::Simple("zset".to_string()), reply);
+
+        let reply = conn.request(["GEODIST", "Sicily", "Palermo", "Catania", "KM"]).await.unwrap();
+        assert_eq!(bulk("166.2742"), reply);
+        let reply = conn.request(["GEODIST", "Sicily", "Palermo", "missing"]).await.unwrap();
+        assert_eq!(Frame::Null, reply);
+        let reply = conn.request(["GEOHASH", "Sicily", "Palermo", "missing"]).await.unwrap();
+        assert_eq!(Frame::Array(vec![bulk("sqc8b49rny0"), Frame::Null]), reply);
+        let reply = conn.request(["GEOPOS", "Sicily", "Palermo"]).await.unwrap();
+        let Frame::Array(items) = reply else { panic!("{}", reply) };
+        assert!(matches!(&items[0], Frame::Array(coord) if coord.len() == 2));
+
+        let args = [
+            "GEOSEARCH", "Sicily", "FROMLONLAT", "15", "37", "BYRADIUS", "200", "KM", "ASC",
+        ];
+        assert_eq!(
+            Frame::Array(vec![bulk("Catania"), bulk("Palermo")]),
+            conn.request(args).await.unwrap()
+        );
+        let args = [
+            "GEOSEARCH", "Sicily", "FROMMEMBER", "Palermo", "BYBOX", "400", "400", "KM", "DESC",
+            "COUNT", "1", "WITHDIST",
+        ];
+        assert_eq!(
+            Frame::Array(vec![Frame::Array(vec![bulk("Catania"), bulk("166.2742")])]),
+            conn.request(args).await.unwrap()
+        );
+        let args = ["GEOSEARCH", "missing", "FROMLONLAT", "15", "37", "BYRADIUS", "1", "M"];
+        assert_eq!(Frame::Array(vec![]), conn.request(args).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn argument_errors() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+
+        conn.request(SICILY).await.unwrap();
+        let search = ["GEOSEARCH", "Sicily"];
+        let from = ["FROMLONLAT", "15", "37"];
+        let by = ["BYRADIUS", "1", "KM"];
+        let flags = ["ASC", "WITHDIST"];
+        for (args, msg) in [
+            (vec!["GEOADD", "g", "1", "2"], "wrong number of arguments"),
+            (vec!["GEOADD", "g", "1", "2", "a", "3"], "Try GEOADD"),
+            (vec!["GEOADD", "g", "NX", "XX", "1", "2", "a"], "not compatible"),
+            (vec!["GEOADD", "g", "181", "0", "a"], "invalid longitude,latitude"),
+            (vec!["GEOADD", "g", "0", "86", "a"], "invalid longitude,latitude"),
+            (vec!["GEOADD", "g", "x", "0", "a"], "not a valid float"),
+            (vec!["GEODIST", "Sicily", "a", "b", "LY"], "unsupported unit"),
+            (vec!["GEODIST", "Sicily", "a", "b", "M", "M"], "syntax error"),
+            ([&search[..], &by, &flags].concat(), "exactly one of FROMMEMBER or FROMLONLAT"),
+            ([&search[..], &from, &flags].concat(), "exactly one of BYRADIUS and BYBOX"),
+            ([&search[..], &from, &["BYRADIUS", "-1", "M"]].concat(), "cannot be negative"),
+            ([&search[..], &from, &["BYBOX", "1", "-1", "M"]].concat(), "cannot be negative"),
+            ([&search[..], &from, &by, &["COUNT", "0"]].concat(), "COUNT must be > 0"),
+            ([&search[..], &from, &by, &["ANY"]].concat(), "syntax error"),
+            ([&search[..], &from, &by, &["NOPE"]].concat(), "syntax error"),
+            (
+                [&search[..], &["FROMMEMBER", "missing"], &by].concat(),
+                "could not decode requested zset member",
+            ),
+        ] {
+            let reply = conn.request(args.clone()).await.unwrap();
+            assert!(is_err(&reply, msg), "{:?}: {}", args, reply);
+        }
+
+        conn.request(["SET", "str", "x"]).await.unwrap();
+        for args in [
+            vec!["GEOADD", "str", "1", "2", "a"],
+            vec!["GEOPOS", "str", "a"],
+            vec!["GEODIST", "str", "a", "b"],
+            vec!["GEOHASH", "str", "a"],
+            [&["GEOSEARCH", "str"][..], &from, &by].concat(),
+        ] {
+            let reply = conn.request(args.clone()).await.unwrap();
+            assert!(is_err(&reply, "WRONGTYPE"), "{:?}", args);
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! 哈希命令
+
+use bytes::Bytes;
+
+use super::{key, CmdResult, Context, WRONGTYPE_ERR};
+use crate::{db::Value, hash::Hash, notify::Class, Frame};
+
+/// HSET key field value [field value ...], 返回新增的字段个数
+pub fn hset(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    if !args.len().is_multiple_of(2) {
+        return Err("ERR wrong number of arguments for 'hset' command".to_string());
+    }
+    let limits = ctx.db.config().hash_limits();
+    let mut guard = ctx.lock(&[key]);
+
+    let set_all = |hash: &mut Hash| {
+        args[2..]
+            .chunks(2)
+            .filter(|pair| hash.insert(pair[0].clone(), pair[1].clone(), limits))
+            .count()
+    };
+
+    let added = match guard.update(key, |value| match value {
+        Value::Hash(hash) => Ok(set_all(hash)),
+        _ => Err(WRONGTYPE_ERR.to_string()),
+    }) {
+        Some(added) => added?,
+        None => {
+            let mut hash = Hash::new();
+            let added = set_all(&mut hash);
+            guard.insert(key, Value::Hash(hash), None);
+
+            added
+        }
+    };
+    guard.notify(Class::Hash, "hset", key);
+
+    Ok(Frame::Integer(added as i64).into())
+}
+
+pub fn hget(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let mut guard = ctx.lock(&[key]);
+
+    match guard.get(key) {
+        Some(Value::Hash(hash)) => Ok(hash.get(&args[2]).map_or(Frame::Null, Frame::Bulk).into()),
+        Some(_) => Err(WRONGTYPE_ERR.to_string()),
+        None => Ok(Frame::Null.into()),
+    }
+}
+
+/// HDEL key field [field ...], 返回删除的字段个数
+pub fn hdel(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let mut guard = ctx.lock(&[key]);
+
+    let removed = guard.update(key, |value| match value {
+        Value::Hash(hash) => Ok(args[2..].iter().filter(|field| hash.remove(field)).count()),
+        _ => Err(WRONGTYPE_ERR.to_string()),
+    });
+    let removed = match removed {
+        Some(removed) => removed?,
+        None => 0,
+    };
+
+    if removed > 0 {
+        guard.notify(Class::Hash, "hdel", key);
+        if !guard.exists(key) {
+            guard.notify(Class::Generic, "del", key);
+        }
+    }
+
+    Ok(Frame::Integer(removed as i64).into())
+}
+
+pub fn hlen(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let mut guard = ctx.lock(&[key]);
+
+    match guard.get(key) {
+        Some(Value::Hash(hash)) => Ok(Frame::Integer(hash.len() as i64).into()),
+        Some(_) => Err(WRONGTYPE_ERR.to_string()),
+        None => Ok(Frame::Integer(0).into()),
+    }
+}
+
+pub fn hexists(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let mut guard = ctx.lock(&[key]);
+
+    match guard.get(key) {
+        Some(Value::Hash(hash)) => Ok(Frame::Integer(hash.get(&args[2]).is_some() as i64).into()),
+        Some(_) => Err(WRONGTYPE_ERR.to_string()),
+        None => Ok(Frame::Integer(0).into()),
+    }
+}
+
+/// 依次返回每个字段与它的值
+pub fn hgetall(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let mut guard = ctx.lock(&[key]);
+
+    let hash = match guard.get(key) {
+        Some(Value::Hash(hash)) => hash,
+        Some(_) => return Err(WRONGTYPE_ERR.to_string()),
+        None => return Ok(Frame::Array(vec![]).into()),
+    };
+
+    let items = hash
+        .iter()
+        .flat_map(|(field, value)| [field, value])
+        .map(|val| Frame::Bulk(Bytes::copy_from_slice(val)))
+        .collect();
+
+    Ok(Frame::Array(items).into())
+}
@@ -0,0 +1,177 @@
+//! HyperLogLog 命令, 编码的细节见 [`crate::hll`]
+
+use bytes::Bytes;
+
+use super::{key, ok, strings::update_string, CmdResult, Context, WRONGTYPE_ERR};
+use crate::{
+    db::{Guard, Value},
+    hll::{self, REGISTERS},
+    notify::Class,
+    Frame,
+};
+
+/// PFADD key [element [element ...]]
+pub fn pfadd(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let sparse_max = ctx.db.config().hll_sparse_max_bytes();
+    let mut guard = ctx.lock(&[key]);
+
+    // 新创建的 key 即使没有元素也算作修改
+    let created = !guard.exists(key);
+    let updated = update_string(&mut guard, key, |buf| {
+        let mut updated = created;
+        if created {
+            *buf = hll::create();
+        } else if !hll::is_valid(buf) {
+            return Err(hll::INVALID_ERR.to_string());
+        }
+
+        for element in &args[2..] {
+            updated |= hll::add(buf, element, sparse_max)?;
+        }
+
+        Ok(updated)
+    })?;
+    if updated {
+        guard.notify(Class::String, "pfadd", key);
+    }
+
+    Ok(Frame::Integer(updated as i64).into())
+}
+
+/// PFCOUNT key [key ...]
+///
+/// 只有一个 key 时, 计算的结果会缓存在 HyperLogLog 的头部中, 多个 key 时合并之后计算, 不会缓存
+pub fn pfcount(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let keys = args[1..].iter().map(key).collect::<Result<Vec<_>, _>>()?;
+    let mut guard = ctx.lock(&keys);
+
+    if let [key] = keys[..] {
+        if !guard.exists(key) {
+            return Ok(Frame::Integer(0).into());
+        }
+
+        let card = update_string(&mut guard, key, |buf| {
+            if !hll::is_valid(buf) {
+                return Err(hll::INVALID_ERR.to_string());
+            }
+            Ok(hll::count(buf)?)
+        })?;
+
+        return Ok(Frame::Integer(card as i64).into());
+    }
+
+    let mut max = Box::new([0; REGISTERS]);
+    for key in keys {
+        merge(&mut guard, key, &mut max)?;
+    }
+
+    Ok(Frame::Integer(hll::count_registers(&max) as i64).into())
+}
+
+/// PFMERGE destkey [sourcekey [sourcekey ...]]
+pub fn pfmerge(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let keys = args[1..].iter().map(key).collect::<Result<Vec<_>, _>>()?;
+    let dest = keys[0];
+    let sparse_max = ctx.db.config().hll_sparse_max_bytes();
+    let mut guard = ctx.lock(&keys);
+    let created = !guard.exists(dest);
+
+    // 目标 key 本身也参与合并, 任何一个输入是 dense 编码时, 结果也使用 dense 编码
+    let mut max = Box::new([0; REGISTERS]);
+    let mut dense = false;
+    for key in &keys {
+        dense |= merge(&mut guard, key, &mut max)?;
+    }
+
+    update_string(&mut guard, dest, |buf| {
+        if created {
+            *buf = hll::create();
+        }
+        if dense {
+            hll::to_dense(buf)?;
+        }
+
+        for (index, count) in max.iter().enumerate() {
+            if *count > 0 {
+                hll::set(buf, index, *count, sparse_max)?;
+            }
+        }
+        hll::invalidate_cache(buf);
+
+        Ok(())
+    })?;
+    guard.notify(Class::String, "pfadd", dest);
+
+    ok()
+}
+
+/// 将 key 中的 HyperLogLog 合并到寄存器数组中, 返回是否为 dense 编码. key 不存在时视为空
+fn merge(guard: &mut Guard<'_>, key: &str, max: &mut [u8; REGISTERS]) -> Result<bool, String> {
+    match guard.get(key) {
+        Some(Value::String(val)) if hll::is_valid(val) => {
+            hll::merge(max, val)?;
+            Ok(hll::is_dense(val))
+        }
+        Some(Value::String(_)) => Err(hll::INVALID_ERR.to_string()),
+        Some(_) => Err(WRONGTYPE_ERR.to_string()),
+        None => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{server, Frame};
+
+    fn is_err(frame: &Frame, msg: &str) -> bool {
+        matches!(frame, Frame::Error(err) if err.contains(msg))
+    }
+
+    #[tokio::test]
+    async fn add_count_and_merge() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+
+        // 新创建的 key 即使没有元素也算作修改
+        assert_eq!(Frame::Integer(1), conn.request(["PFADD", "empty"]).await.unwrap());
+        assert_eq!(Frame::Integer(0), conn.request(["PFCOUNT", "empty"]).await.unwrap());
+
+        assert_eq!(Frame::Integer(1), conn.request(["PFADD", "a", "x", "y", "z"]).await.unwrap());
+        assert_eq!(Frame::Integer(0), conn.request(["PFADD", "a", "x", "y"]).await.unwrap());
+        assert_eq!(Frame::Integer(3), conn.request(["PFCOUNT", "a"]).await.unwrap());
+        conn.request(["PFADD", "b", "z", "w"]).await.unwrap();
+        let reply = conn.request(["PFCOUNT", "a", "b", "missing"]).await.unwrap();
+        assert_eq!(Frame::Integer(4), reply);
+
+        let ok = Frame::Simple("OK".to_string());
+        assert_eq!(ok, conn.request(["PFMERGE", "dest", "a", "b"]).await.unwrap());
+        assert_eq!(Frame::Integer(4), conn.request(["PFCOUNT", "dest"]).await.unwrap());
+        assert_eq!(Frame::Integer(0), conn.request(["PFCOUNT", "missing"]).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn argument_errors() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+
+        let reply = conn.request(["PFADD"]).await.unwrap();
+        assert!(is_err(&reply, "wrong number of arguments"), "{}", reply);
+
+        // 不是 HyperLogLog 的字符串
+        conn.request(["SET", "str", "x"]).await.unwrap();
+        conn.request(["LPUSH", "list", "x"]).await.unwrap();
+        for key in ["str", "list"] {
+            for args in [
+                &["PFADD", key, "a"][..],
+                &["PFCOUNT", key],
+                &["PFCOUNT", key, "other"],
+                &["PFMERGE", "dest", key],
+            ] {
+                let reply = conn.request(args.to_vec()).await.unwrap();
+                assert!(is_err(&reply, "WRONGTYPE"), "{:?}: {}", args, reply);
+            }
+        }
+        let reply = conn.request(["PFCOUNT", "str"]).await.unwrap();
+        assert!(is_err(&reply, "not a valid HyperLogLog"), "{}", reply);
+    }
+}
@@ -0,0 +1,497 @@
+//! JSON 文档命令, 路径语法见 [`crate::json`]
+//!
+//! 使用 JSONPath(`$` 开头)时, 命令作用于所有匹配的节点, 每个节点的结果组成数组返回;
+//! 使用旧的路径语法时只作用于第一个匹配的节点, 路径不存在时返回错误
+
+use bytes::Bytes;
+use serde_json::{Number, Value as Json};
+
+use super::{is, key, ok, string, CmdResult, Context, SYNTAX_ERR, WRONGTYPE_ERR};
+use crate::{
+    db::{Guard, Value},
+    json::{self, Format, Path, Step},
+    notify::Class,
+    Frame,
+};
+
+/// JSON.SET key path value [NX | XX]
+pub fn set(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let path = Path::parse(string(&args[2])?)?;
+    let value = parse(&args[3])?;
+    let (nx, xx) = match args.get(4) {
+        None => (false, false),
+        Some(arg) if is(arg, "nx") && args.len() == 5 => (true, false),
+        Some(arg) if is(arg, "xx") && args.len() == 5 => (false, true),
+        Some(_) => return Err(SYNTAX_ERR.to_string()),
+    };
+    let mut guard = ctx.lock(&[key]);
+
+    let set = match document(&mut guard, key)? {
+        None if !path.is_root() => {
+            return Err("ERR new objects must be created at the root".to_string());
+        }
+        None if xx => false,
+        None => {
+            guard.insert(key, Value::Json(value), None);
+            true
+        }
+        Some(doc) => {
+            let mut matches = path.find(doc);
+            if !path.jsonpath {
+                matches.truncate(1);
+            }
+
+            if !matches.is_empty() {
+                // 修改已有的节点
+                !nx && update(&mut guard, key, |doc| {
+                    for steps in &matches {
+                        if let Some(node) = json::get_mut(doc, steps) {
+                            *node = value.clone();
+                        }
+                    }
+                    true
+                })?
+            } else if xx {
+                false
+            } else {
+                // 路径不存在时, 只能在已有的对象中添加新的字段
+                let Some((parent, field)) = path.parent() else {
+                    return missing(&path, &args[2]);
+                };
+                let parents = parent.find(doc);
+                let added = update(&mut guard, key, |doc| {
+                    let mut added = false;
+                    for steps in &parents {
+                        if let Some(Json::Object(map)) = json::get_mut(doc, steps) {
+                            map.insert(field.to_string(), value.clone());
+                            added = true;
+                            if !path.jsonpath {
+                                break;
+                            }
+                        }
+                    }
+                    added
+                })?;
+                if !added {
+                    return missing(&path, &args[2]);
+                }
+
+                true
+            }
+        }
+    };
+
+    if !set {
+        return Ok(Frame::Null.into());
+    }
+    guard.notify(Class::Module, "json.set", key);
+
+    ok()
+}
+
+/// JSON.GET key [INDENT indent] [NEWLINE newline] [SPACE space] [path [path ...]]
+pub fn get(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+
+    let mut format = Format::default();
+    let mut i = 2;
+    while i + 1 < args.len() {
+        let value = string(&args[i + 1])?.to_string();
+        if is(&args[i], "indent") {
+            format.indent = value;
+        } else if is(&args[i], "newline") {
+            format.newline = value;
+        } else if is(&args[i], "space") {
+            format.space = value;
+        } else {
+            break;
+        }
+        i += 2;
+    }
+
+    let paths = if i == args.len() {
+        vec![(".", Path::parse(".")?)]
+    } else {
+        args[i..]
+            .iter()
+            .map(|arg| {
+                let path = string(arg)?;
+                Ok((path, Path::parse(path)?))
+            })
+            .collect::<Result<Vec<_>, String>>()?
+    };
+
+    let mut guard = ctx.lock(&[key]);
+    let Some(doc) = document(&mut guard, key)? else {
+        return Ok(Frame::Null.into());
+    };
+
+    // 取出路径对应的值, JSONPath 的结果为所有匹配节点组成的数组
+    let select = |path: &Path, raw: &str| -> Result<Json, String> {
+        let matches = path.find(doc);
+        let mut values = matches.iter().filter_map(|steps| json::get(doc, steps).cloned());
+        if path.jsonpath {
+            return Ok(Json::Array(values.collect()));
+        }
+
+        values
+            .next()
+            .ok_or_else(|| format!("ERR Path '{}' does not exist", raw))
+    };
+
+    let result = match &paths[..] {
+        [(raw, path)] => select(path, raw)?,
+        paths => {
+            let mut map = serde_json::Map::new();
+            for (raw, path) in paths {
+                map.insert(raw.to_string(), select(path, raw)?);
+            }
+            Json::Object(map)
+        }
+    };
+
+    Ok(Frame::Bulk(Bytes::from(format.to_string(&result))).into())
+}
+
+/// JSON.DEL key [path]
+///
+/// 删除根节点时删除整个 key, 返回删除的节点个数
+pub fn del(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let path = match args.get(2) {
+        Some(arg) => Path::parse(string(arg)?)?,
+        None => Path::parse("$")?,
+    };
+    let mut guard = ctx.lock(&[key]);
+
+    let Some(doc) = document(&mut guard, key)? else {
+        return Ok(Frame::Integer(0).into());
+    };
+
+    let deleted = if path.is_root() {
+        guard.remove(key);
+        guard.notify(Class::Generic, "del", key);
+        1
+    } else {
+        let mut matches = path.find(doc);
+        if !path.jsonpath {
+            matches.truncate(1);
+        }
+
+        // 先删除下标较大的数组元素以及子节点, 避免影响其他路径
+        matches.sort_unstable_by(|a, b| b.cmp(a));
+        let deleted = update(&mut guard, key, |doc| {
+            matches.iter().filter(|steps| json::remove(doc, steps)).count()
+        })?;
+        if deleted > 0 {
+            guard.notify(Class::Module, "json.del", key);
+        }
+
+        deleted
+    };
+
+    Ok(Frame::Integer(deleted as i64).into())
+}
+
+/// JSON.ARRAPPEND key path value [value ...]
+pub fn arrappend(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let path = Path::parse(string(&args[2])?)?;
+    let values = args[3..].iter().map(parse).collect::<Result<Vec<_>, _>>()?;
+    let mut guard = ctx.lock(&[key]);
+
+    let Some(doc) = document(&mut guard, key)? else {
+        return Err("ERR could not perform this operation on a key that doesn't exist".to_string());
+    };
+    let mut matches = path.find(doc);
+    if !path.jsonpath {
+        check(doc, &matches, &path, &args[2], Json::is_array, "an array")?;
+        matches.truncate(1);
+    }
+
+    // 每个匹配节点追加之后的长度, 不是数组的节点为 None
+    let lens = update(&mut guard, key, |doc| {
+        matches
+            .iter()
+            .map(|steps| match json::get_mut(doc, steps) {
+                Some(Json::Array(array)) => {
+                    array.extend(values.iter().cloned());
+                    Some(array.len())
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    })?;
+    if lens.iter().any(Option::is_some) {
+        guard.notify(Class::Module, "json.arrappend", key);
+    }
+
+    if !path.jsonpath {
+        return Ok(Frame::Integer(lens[0].unwrap() as i64).into());
+    }
+
+    let out = lens
+        .into_iter()
+        .map(|len| match len {
+            Some(len) => Frame::Integer(len as i64),
+            None => Frame::Null,
+        })
+        .collect();
+
+    Ok(Frame::Array(out).into())
+}
+
+/// JSON.NUMINCRBY key path value
+pub fn numincrby(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let path = Path::parse(string(&args[2])?)?;
+    let Json::Number(delta) = parse(&args[3])? else {
+        return Err("ERR increment must be a number".to_string());
+    };
+    let mut guard = ctx.lock(&[key]);
+
+    let Some(doc) = document(&mut guard, key)? else {
+        return Err("ERR could not perform this operation on a key that doesn't exist".to_string());
+    };
+    let mut matches = path.find(doc);
+    if !path.jsonpath {
+        check(doc, &matches, &path, &args[2], Json::is_number, "a number")?;
+        matches.truncate(1);
+    }
+
+    // 先计算出所有的结果, 任何一个溢出时都不修改文档
+    let results = matches
+        .iter()
+        .map(|steps| match json::get(doc, steps) {
+            Some(Json::Number(n)) => add(n, &delta).map(Some),
+            _ => Ok(None),
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    update(&mut guard, key, |doc| {
+        for (steps, result) in matches.iter().zip(&results) {
+            if let (Some(node), Some(result)) = (json::get_mut(doc, steps), result) {
+                *node = Json::Number(result.clone());
+            }
+        }
+    })?;
+    if results.iter().any(Option::is_some) {
+        guard.notify(Class::Module, "json.numincrby", key);
+    }
+
+    let result = if path.jsonpath {
+        let results = results.into_iter().map(|n| n.map_or(Json::Null, Json::Number));
+        Json::Array(results.collect())
+    } else {
+        Json::Number(results.into_iter().next().flatten().unwrap())
+    };
+
+    Ok(Frame::Bulk(Bytes::from(result.to_string())).into())
+}
+
+/// JSON.TYPE key [path]
+pub fn type_(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let path = match args.get(2) {
+        Some(arg) => Path::parse(string(arg)?)?,
+        None => Path::parse(".")?,
+    };
+    let mut guard = ctx.lock(&[key]);
+
+    let Some(doc) = document(&mut guard, key)? else {
+        return Ok(Frame::Null.into());
+    };
+    let types = path
+        .find(doc)
+        .iter()
+        .filter_map(|steps| json::get(doc, steps))
+        .map(json::type_name)
+        .collect::<Vec<_>>();
+
+    if !path.jsonpath {
+        return match types.first() {
+            Some(name) => Ok(Frame::Simple(name.to_string()).into()),
+            None => Ok(Frame::Null.into()),
+        };
+    }
+
+    let out = types
+        .into_iter()
+        .map(|name| Frame::Bulk(Bytes::from_static(name.as_bytes())))
+        .collect();
+
+    Ok(Frame::Array(out).into())
+}
+
+/// 读取 JSON 文档, key 不存在时返回 None
+fn document<'g>(guard: &'g mut Guard<'_>, key: &str) -> Result<Option<&'g Json>, String> {
+    match guard.get(key) {
+        Some(Value::Json(doc)) => Ok(Some(doc)),
+        Some(_) => Err(WRONGTYPE_ERR.to_string()),
+        None => Ok(None),
+    }
+}
+
+/// 修改已经存在的 JSON 文档
+fn update<R>(
+    guard: &mut Guard<'_>,
+    key: &str,
+    f: impl FnOnce(&mut Json) -> R,
+) -> Result<R, String> {
+    match guard.update(key, |value| match value {
+        Value::Json(doc) => Ok(f(doc)),
+        _ => Err(WRONGTYPE_ERR.to_string()),
+    }) {
+        Some(res) => res,
+        None => Err("ERR no such key".to_string()),
+    }
+}
+
+fn parse(arg: &Bytes) -> Result<Json, String> {
+    serde_json::from_slice(arg).map_err(|e| format!("ERR invalid JSON: {}", e))
+}
+
+/// 旧的路径语法要求第一个匹配的节点存在并且类型正确
+fn check(
+    doc: &Json,
+    matches: &[Vec<Step>],
+    path: &Path,
+    raw: &Bytes,
+    expected: fn(&Json) -> bool,
+    name: &str,
+) -> Result<(), String> {
+    match matches.first().and_then(|steps| json::get(doc, steps)) {
+        Some(node) if expected(node) => Ok(()),
+        Some(node) => Err(format!(
+            "WRONGTYPE wrong type of path value - expected {} but found {}",
+            name,
+            json::type_name(node)
+        )),
+        None => missing(path, raw).map(|_| ()),
+    }
+}
+
+/// 路径不存在, JSONPath 返回 Null, 旧的路径语法返回错误
+fn missing(path: &Path, raw: &Bytes) -> CmdResult {
+    if path.jsonpath {
+        return Ok(Frame::Null.into());
+    }
+
+    Err(format!(
+        "ERR Path '{}' does not exist",
+        String::from_utf8_lossy(raw)
+    ))
+}
+
+/// 两个数都是整数并且没有溢出时结果为整数, 否则为浮点数
+fn add(a: &Number, b: &Number) -> Result<Number, String> {
+    if let (Some(a), Some(b)) = (a.as_i64(), b.as_i64()) {
+        if let Some(sum) = a.checked_add(b) {
+            return Ok(sum.into());
+        }
+    }
+
+    let sum = a.as_f64().unwrap_or(f64::NAN) + b.as_f64().unwrap_or(f64::NAN);
+    Number::from_f64(sum).ok_or_else(|| "ERR result is not a finite number".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::{server, Frame};
+
+    fn is_err(frame: &Frame, msg: &str) -> bool {
+        matches!(frame, Frame::Error(err) if err.contains(msg))
+    }
+
+    fn bulk(s: &str) -> Frame {
+        Frame::Bulk(Bytes::copy_from_slice(s.as_bytes()))
+    }
+
+    #[tokio::test]
+    async fn set_get_and_update() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+
+        let ok = Frame::Simple("OK".to_string());
+        let doc = r#"{"a":1,"b":[1,2],"c":{"a":"x"}}"#;
+        assert_eq!(ok, conn.request(["JSON.SET", "doc", "$", doc]).await.unwrap());
+        assert_eq!(bulk(doc), conn.request(["JSON.GET", "doc"]).await.unwrap());
+        assert_eq!(bulk("[1,2]"), conn.request(["JSON.GET", "doc", ".b"]).await.unwrap());
+        assert_eq!(bulk(r#"[1,"x"]"#), conn.request(["JSON.GET", "doc", "$..a"]).await.unwrap());
+        assert_eq!(Frame::Null, conn.request(["JSON.GET", "missing"]).await.unwrap());
+
+        // NX/XX 条件不满足时返回 Null
+        let reply = conn.request(["JSON.SET", "doc", ".a", "2", "NX"]).await.unwrap();
+        assert_eq!(Frame::Null, reply);
+        let reply = conn.request(["JSON.SET", "doc", ".d", "2", "XX"]).await.unwrap();
+        assert_eq!(Frame::Null, reply);
+        assert_eq!(ok, conn.request(["JSON.SET", "doc", ".d", "true"]).await.unwrap());
+
+        let reply = conn.request(["JSON.ARRAPPEND", "doc", ".b", "3", "4"]).await.unwrap();
+        assert_eq!(Frame::Integer(4), reply);
+        let reply = conn.request(["JSON.ARRAPPEND", "doc", "$.*", "5"]).await.unwrap();
+        assert_eq!(
+            Frame::Array(vec![Frame::Null, Frame::Integer(5), Frame::Null, Frame::Null]),
+            reply
+        );
+        let reply = conn.request(["JSON.NUMINCRBY", "doc", ".a", "1.5"]).await.unwrap();
+        assert_eq!(bulk("2.5"), reply);
+
+        let reply = conn.request(["JSON.TYPE", "doc"]).await.unwrap();
+        assert_eq!(Frame::Simple("object".to_string()), reply);
+        let reply = conn.request(["JSON.TYPE", "doc", "$.d"]).await.unwrap();
+        assert_eq!(Frame::Array(vec![bulk("boolean")]), reply);
+
+        assert_eq!(Frame::Integer(2), conn.request(["JSON.DEL", "doc", "$..a"]).await.unwrap());
+        assert_eq!(Frame::Integer(1), conn.request(["JSON.DEL", "doc"]).await.unwrap());
+        assert_eq!(Frame::Integer(0), conn.request(["EXISTS", "doc"]).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn argument_errors() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+
+        conn.request(["JSON.SET", "doc", ".", r#"{"n":1,"s":"x"}"#]).await.unwrap();
+        conn.request(["JSON.SET", "doc", ".max", "9223372036854775807"]).await.unwrap();
+        conn.request(["JSON.SET", "doc", ".f", "1.5e308"]).await.unwrap();
+        for (args, msg) in [
+            (&["JSON.SET", "doc", "."][..], "wrong number of arguments"),
+            (&["JSON.SET", "doc", ".", "{"], "invalid JSON"),
+            (&["JSON.SET", "doc", ".", "1", "NOPE"], "syntax error"),
+            (&["JSON.SET", "doc", ".", "1", "NX", "XX"], "syntax error"),
+            (&["JSON.SET", "new", ".a", "1"], "created at the root"),
+            (&["JSON.SET", "doc", ".x.y", "1"], "does not exist"),
+            (&["JSON.GET", "doc", ".x"], "does not exist"),
+            (&["JSON.ARRAPPEND", "doc", ".n"], "wrong number of arguments"),
+            (&["JSON.ARRAPPEND", "missing", ".", "1"], "doesn't exist"),
+            (&["JSON.ARRAPPEND", "doc", ".n", "1"], "expected an array but found integer"),
+            (&["JSON.NUMINCRBY", "doc", ".n", "\"1\""], "increment must be a number"),
+            (&["JSON.NUMINCRBY", "doc", ".s", "1"], "expected a number but found string"),
+            (&["JSON.NUMINCRBY", "doc", ".f", "1e308"], "not a finite number"),
+        ] {
+            let reply = conn.request(args.to_vec()).await.unwrap();
+            assert!(is_err(&reply, msg), "{:?}: {}", args, reply);
+        }
+
+        // 整数溢出时转换为浮点数
+        let reply = conn.request(["JSON.NUMINCRBY", "doc", ".max", "1"]).await.unwrap();
+        assert!(matches!(reply, Frame::Bulk(_)), "{}", reply);
+
+        conn.request(["SET", "str", "x"]).await.unwrap();
+        for args in [
+            &["JSON.SET", "str", ".", "1"][..],
+            &["JSON.GET", "str"],
+            &["JSON.DEL", "str"],
+            &["JSON.ARRAPPEND", "str", ".", "1"],
+            &["JSON.NUMINCRBY", "str", ".", "1"],
+            &["JSON.TYPE", "str"],
+        ] {
+            let reply = conn.request(args.to_vec()).await.unwrap();
+            assert!(is_err(&reply, "WRONGTYPE"), "{:?}", args);
+        }
+    }
+}
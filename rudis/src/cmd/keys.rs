@@ -0,0 +1,785 @@
+//! 通用的 key 命令
+
+use std::time::Duration;
+
+use bytes::{Bytes, BytesMut};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::{self, Instant},
+};
+use tokio_util::codec::{Decoder, Encoder};
+
+use super::{
+    db_index, expire_after, help, int, is, key, ok, string, unix_millis, CmdResult, Context, Reply,
+    NOT_INTEGER_ERR, SYNTAX_ERR,
+};
+use crate::{codec::RespCodec, db::Db, dump, glob, notify::Class, Frame};
+
+pub fn del(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let keys = args[1..].iter().map(key).collect::<Result<Vec<_>, _>>()?;
+    let mut guard = ctx.lock(&keys);
+
+    let mut deleted = 0;
+    for key in keys {
+        if guard.remove(key).is_some() {
+            guard.notify(Class::Generic, "del", key);
+            deleted += 1;
+        }
+    }
+
+    Ok(Frame::Integer(deleted).into())
+}
+
+pub fn exists(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let keys = args[1..].iter().map(key).collect::<Result<Vec<_>, _>>()?;
+    let mut guard = ctx.lock(&keys);
+
+    // 与 redis 一致, 重复的 key 会被重复计数
+    let count = keys.into_iter().filter(|key| guard.exists(key)).count();
+
+    Ok(Frame::Integer(count as i64).into())
+}
+
+pub fn expire(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let secs = int(&args[2])?;
+    set_expire(ctx, key(&args[1])?, secs.saturating_mul(1000), "expire")
+}
+
+pub fn pexpire(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let millis = int(&args[2])?;
+    set_expire(ctx, key(&args[1])?, millis, "pexpire")
+}
+
+/// 设置过期时间, 时间不大于 0 时直接删除 key
+fn set_expire(ctx: &mut Context<'_>, key: &str, millis: i64, name: &str) -> CmdResult {
+    let mut guard = ctx.lock(&[key]);
+
+    if millis <= 0 {
+        let deleted = guard.remove(key).is_some();
+        if deleted {
+            guard.notify(Class::Generic, "del", key);
+        }
+
+        return Ok(Frame::Integer(deleted as i64).into());
+    }
+
+    let Some(when) = expire_after(millis) else {
+        return Err(format!("ERR invalid expire time in '{}' command", name));
+    };
+    let updated = guard.set_expire(key, Some(when));
+    if updated {
+        guard.notify(Class::Generic, "expire", key);
+    }
+
+    Ok(Frame::Integer(updated as i64).into())
+}
+
+pub fn persist(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let mut guard = ctx.lock(&[key]);
+
+    let persisted = match guard.expires_at(key) {
+        Some(Some(_)) => guard.set_expire(key, None),
+        _ => false,
+    };
+    if persisted {
+        guard.notify(Class::Generic, "persist", key);
+    }
+
+    Ok(Frame::Integer(persisted as i64).into())
+}
+
+pub fn ttl(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    remaining(ctx, key(&args[1])?, |left| {
+        // 向上取整, 与 redis 保持一致
+        ((left.as_millis() + 500) / 1000) as i64
+    })
+}
+
+pub fn pttl(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    remaining(ctx, key(&args[1])?, |left| left.as_millis() as i64)
+}
+
+/// key 不存在时返回 -2, 没有设置过期时间时返回 -1
+fn remaining(ctx: &mut Context<'_>, key: &str, unit: fn(Duration) -> i64) -> CmdResult {
+    let mut guard = ctx.lock(&[key]);
+
+    let ttl = match guard.expires_at(key) {
+        None => -2,
+        Some(None) => -1,
+        Some(Some(when)) => unit(when.saturating_duration_since(Instant::now())),
+    };
+
+    Ok(Frame::Integer(ttl).into())
+}
+
+pub fn type_(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let mut guard = ctx.lock(&[key]);
+
+    let name = guard.get(key).map_or("none", |value| value.type_name());
+
+    Ok(Frame::Simple(name.to_string()).into())
+}
+
+/// OBJECT ENCODING|IDLETIME|FREQ|REFCOUNT key | OBJECT HELP
+///
+/// OBJECT 只是观察 key, 不会更新 key 的访问时间与访问频率
+pub fn object(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let sub = &args[1];
+    if is(sub, "help") && args.len() == 2 {
+        return help(&[
+            "OBJECT <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+            "ENCODING <key>",
+            "    Return the kind of internal representation used in order to store the value",
+            "    associated with a <key>.",
+            "FREQ <key>",
+            "    Return the access frequency index of the <key>. The returned integer is",
+            "    proportional to the logarithm of the recent access frequency of the key.",
+            "IDLETIME <key>",
+            "    Return the idle time of the <key>, that is the approximated number of",
+            "    seconds elapsed since the last access to the key.",
+            "REFCOUNT <key>",
+            "    Return the number of references of the value associated with the specified",
+            "    <key>.",
+        ]);
+    }
+
+    let known = ["encoding", "idletime", "freq", "refcount"].iter().any(|name| is(sub, name));
+    if !known || args.len() != 3 {
+        return Err(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'. Try OBJECT HELP.",
+            String::from_utf8_lossy(sub)
+        ));
+    }
+
+    let key = key(&args[2])?;
+    let mut guard = ctx.lock(&[key]);
+    let Some(entry) = guard.peek(key) else {
+        return Ok(Frame::Null.into());
+    };
+
+    let reply = if is(sub, "encoding") {
+        Frame::Bulk(Bytes::from(entry.value().encoding()))
+    } else if is(sub, "idletime") {
+        Frame::Integer(entry.idle().as_secs() as i64)
+    } else if is(sub, "freq") {
+        Frame::Integer(entry.freq() as i64)
+    } else {
+        // value 不会在 key 之间共享
+        Frame::Integer(1)
+    };
+
+    Ok(reply.into())
+}
+
+/// SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]
+///
+/// 遍历的方式见 `Db::scan`, 与 redis 相同, COUNT 只是每次遍历的 key 的个数,
+/// 经过 MATCH 与 TYPE 过滤之后返回的 key 可能更少, 甚至为空
+pub fn scan(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let cursor: u64 = string(&args[1])?
+        .parse()
+        .map_err(|_| "ERR invalid cursor".to_string())?;
+
+    let (mut pattern, mut count, mut type_name) = (None, 10, None);
+    let mut rest = args[2..].iter();
+    while let Some(arg) = rest.next() {
+        let val = rest.next().ok_or(SYNTAX_ERR)?;
+        if is(arg, "match") {
+            pattern = Some(val);
+        } else if is(arg, "count") {
+            count = match int(val)? {
+                n if n < 1 => return Err(SYNTAX_ERR.to_string()),
+                n => n as usize,
+            };
+        } else if is(arg, "type") {
+            type_name = Some(string(val)?);
+        } else {
+            return Err(SYNTAX_ERR.to_string());
+        }
+    }
+
+    let (next, keys) = ctx.db.scan(ctx.session.db(), cursor, count, |key, value| {
+        pattern.is_none_or(|pattern| glob::matches(pattern, key.as_bytes()))
+            && type_name.is_none_or(|name| value.type_name().eq_ignore_ascii_case(name))
+    });
+
+    let keys = keys.into_iter().map(|key| Frame::Bulk(Bytes::from(key))).collect();
+    Ok(Frame::Array(vec![Frame::Bulk(Bytes::from(next.to_string())), Frame::Array(keys)]).into())
+}
+
+/// MOVE key db: 将 key 连同过期时间移动到另一个数据库, 目标数据库中已经存在该 key 时不做任何操作
+pub fn move_(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let source = ctx.session.db();
+    let target = db_index(ctx.db, &args[2])?;
+    if source == target {
+        return Err("ERR source and destination objects are the same".to_string());
+    }
+
+    // 按照数据库下标的顺序加锁, 与 SWAPDB 保持一致
+    let (mut src, mut dst) = if source < target {
+        let src = ctx.db.lock(source, &[key]);
+        (src, ctx.db.lock(target, &[key]))
+    } else {
+        let dst = ctx.db.lock(target, &[key]);
+        (ctx.db.lock(source, &[key]), dst)
+    };
+
+    if dst.exists(key) {
+        return Ok(Frame::Integer(0).into());
+    }
+    let Some(expires_at) = src.expires_at(key) else {
+        return Ok(Frame::Integer(0).into());
+    };
+
+    let value = src.remove(key).expect("key exists in the source db");
+    dst.insert(key, value, expires_at);
+    src.notify(Class::Generic, "move_from", key);
+    dst.notify(Class::Generic, "move_to", key);
+
+    Ok(Frame::Integer(1).into())
+}
+
+pub fn dump(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let mut guard = ctx.lock(&[key]);
+
+    match guard.get(key) {
+        Some(value) => Ok(Frame::Bulk(dump::dump(value)).into()),
+        None => Ok(Frame::Null.into()),
+    }
+}
+
+/// RESTORE key ttl serialized-value [REPLACE] [ABSTTL]
+///
+/// ttl 为 0 时不设置过期时间, ABSTTL 代表 ttl 是以毫秒为单位的 unix 时间戳
+pub fn restore(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let ttl = match int(&args[2]) {
+        Ok(ttl) if ttl >= 0 => ttl,
+        _ => return Err("ERR Invalid TTL value, must be >= 0".to_string()),
+    };
+
+    let (mut replace, mut absttl) = (false, false);
+    for arg in &args[4..] {
+        if is(arg, "replace") {
+            replace = true;
+        } else if is(arg, "absttl") {
+            absttl = true;
+        } else {
+            return Err(SYNTAX_ERR.to_string());
+        }
+    }
+
+    let value = dump::restore(&args[3], ctx.db.config())?;
+
+    let mut guard = ctx.lock(&[key]);
+    if !replace && guard.exists(key) {
+        return Err("BUSYKEY Target key name already exists.".to_string());
+    }
+
+    let millis = match (ttl, absttl) {
+        (0, _) => None,
+        (ttl, true) => Some(ttl - unix_millis()),
+        (ttl, false) => Some(ttl),
+    };
+    let expires_at = match millis {
+        // 已经过期的 key 不会被写入, 与 redis 相同, REPLACE 时仍然会删除原有的 key
+        Some(millis) if millis <= 0 => {
+            if guard.remove(key).is_some() {
+                guard.notify(Class::Generic, "del", key);
+            }
+            return ok();
+        }
+        Some(millis) => Some(expire_after(millis).ok_or("ERR Invalid TTL value")?),
+        None => None,
+    };
+
+    guard.insert(key, value, expires_at);
+    guard.notify(Class::Generic, "restore", key);
+
+    ok()
+}
+
+/// MIGRATE host port key|"" destination-db timeout [COPY] [REPLACE] [KEYS key [key ...]]
+///
+/// 通过 DUMP 的格式将 key 发送到另一个实例, 目标实例确认写入之后再删除本地的 key(COPY 时保留).
+/// 与目标实例通信期间不持有锁, 这期间被修改的 key 会保留在本地, 此时回复错误并列出这些 key.
+/// 与 redis 相同, 命令会阻塞到目标实例回复或者超时, timeout 的单位为毫秒
+pub fn migrate(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let host = string(&args[1])?.to_string();
+    let port: u16 = string(&args[2])?.parse().map_err(|_| NOT_INTEGER_ERR)?;
+    let db = int(&args[4])?;
+    let timeout = match int(&args[5])? {
+        timeout if timeout <= 0 => Duration::from_secs(1),
+        timeout => Duration::from_millis(timeout as u64),
+    };
+
+    let (mut copy, mut replace, mut keys) = (false, false, None);
+    let mut i = 6;
+    while i < args.len() {
+        if is(&args[i], "copy") {
+            copy = true;
+        } else if is(&args[i], "replace") {
+            replace = true;
+        } else if is(&args[i], "keys") {
+            if !args[3].is_empty() {
+                return Err("ERR When using MIGRATE KEYS option, the key argument must be set \
+                            to the empty string"
+                    .to_string());
+            }
+            keys = Some(args[i + 1..].iter().map(key).collect::<Result<Vec<_>, _>>()?);
+            break;
+        } else {
+            return Err(SYNTAX_ERR.to_string());
+        }
+        i += 1;
+    }
+    let keys = match keys {
+        Some(keys) => keys,
+        None => vec![key(&args[3])?],
+    };
+
+    let mut guard = ctx.lock(&keys);
+
+    let now = Instant::now();
+    let mut commands = vec![command(["SELECT".into(), Bytes::from(db.to_string())])];
+    let mut migrating = vec![];
+    for key in keys {
+        let Some(value) = guard.get(key) else {
+            continue;
+        };
+        let payload = dump::dump(value);
+        // RESTORE 的 ttl 为 0 代表不过期, 剩余时间不足 1 毫秒的 key 按 1 毫秒处理
+        let ttl = match guard.expires_at(key) {
+            Some(Some(when)) => when.saturating_duration_since(now).as_millis().max(1),
+            _ => 0,
+        };
+
+        let mut restore = vec!["RESTORE".into(), Bytes::from(key.to_string())];
+        restore.extend([Bytes::from(ttl.to_string()), payload.clone()]);
+        if replace {
+            restore.push("REPLACE".into());
+        }
+        commands.push(command(restore));
+        migrating.push((key.to_string(), payload));
+    }
+    drop(guard);
+
+    if migrating.is_empty() {
+        return Ok(Frame::Simple("NOKEY".to_string()).into());
+    }
+
+    // 与目标实例的通信在释放锁之后进行, 等待回复期间不会阻塞其他命令.
+    // 目标实例就是自身时, 处理 RESTORE 的连接也能正常拿到锁
+    let db = ctx.db.clone();
+    let index = ctx.session.db();
+    Ok(Reply::Later(Box::pin(async move {
+        let replies = match exchange((host, port), timeout, &commands).await {
+            Ok(replies) => replies,
+            Err(err) => return Frame::Error(err),
+        };
+
+        // 第一个回复属于 SELECT, 之后的回复依次对应每个 key 的 RESTORE.
+        // 只有目标实例确认 RESTORE 成功的 key 才会从本地删除
+        let mut error = None;
+        let mut restored = vec![];
+        if let Frame::Error(err) = &replies[0] {
+            error = Some(err.clone());
+        } else {
+            for ((key, payload), reply) in migrating.into_iter().zip(&replies[1..]) {
+                match reply {
+                    Frame::Simple(ok) if ok == "OK" => restored.push((key, payload)),
+                    Frame::Error(err) => {
+                        error.get_or_insert_with(|| err.clone());
+                    }
+                    _ => {
+                        error.get_or_insert_with(|| format!("unexpected reply {}", reply));
+                    }
+                }
+            }
+        }
+
+        let kept = match copy {
+            true => vec![],
+            false => remove_migrated(&db, index, &restored),
+        };
+
+        // 被保留的 key 同时存在于两个实例中, 不能回复 OK
+        match error {
+            Some(err) => Frame::Error(format!("ERR Target instance replied with error: {}", err)),
+            None if !kept.is_empty() => Frame::Error(format!(
+                "ERR keys modified during MIGRATE were not removed: {}",
+                kept.join(" ")
+            )),
+            None => Frame::Simple("OK".to_string()),
+        }
+    })))
+}
+
+/// 删除已经迁移到目标实例的 key, 返回被保留的 key
+///
+/// 通信期间没有持有锁, 如果 key 在这期间被其他连接修改过, 说明目标实例上的并不是最新的值,
+/// 此时保留本地的 key. 在这期间被删除的 key 不算作保留
+fn remove_migrated(db: &Db, index: usize, restored: &[(String, Bytes)]) -> Vec<String> {
+    let keys: Vec<&str> = restored.iter().map(|(key, _)| key.as_str()).collect();
+    let mut guard = db.lock(index, &keys);
+    let mut kept = vec![];
+    for (key, payload) in restored {
+        match guard.get(key).map(|value| dump::dump(value) == payload) {
+            Some(true) => {
+                guard.remove(key);
+                guard.notify(Class::Generic, "del", key);
+            }
+            Some(false) => kept.push(key.clone()),
+            None => {}
+        }
+    }
+
+    kept
+}
+
+fn command(args: impl IntoIterator<Item = Bytes>) -> Frame {
+    Frame::Array(args.into_iter().map(Frame::Bulk).collect())
+}
+
+/// 发送多个命令, 依次读取每个命令的回复
+///
+/// 连接、写入与读取的超时时间都是 `timeout`, 与 redis 的 MIGRATE 相同
+async fn exchange(
+    addr: (String, u16),
+    timeout: Duration,
+    commands: &[Frame],
+) -> Result<Vec<Frame>, String> {
+    const CONNECT_ERR: &str = "IOERR error or timeout connecting to the client";
+    const WRITE_ERR: &str = "IOERR error or timeout writing to target instance";
+    const READ_ERR: &str = "IOERR error or timeout reading to target instance";
+
+    let mut stream = match time::timeout(timeout, TcpStream::connect(addr)).await {
+        Ok(Ok(stream)) => stream,
+        _ => return Err(CONNECT_ERR.to_string()),
+    };
+    let _ = stream.set_nodelay(true);
+
+    let mut codec = RespCodec::new();
+    let mut buf = BytesMut::new();
+    for command in commands {
+        codec.encode(command, &mut buf).map_err(|err| err.to_string())?;
+    }
+    match time::timeout(timeout, stream.write_all(&buf)).await {
+        Ok(Ok(())) => {}
+        _ => return Err(WRITE_ERR.to_string()),
+    }
+
+    buf.clear();
+    let mut replies = Vec::with_capacity(commands.len());
+    while replies.len() < commands.len() {
+        if let Some(reply) = codec.decode(&mut buf).map_err(|_| READ_ERR)? {
+            replies.push(reply);
+            continue;
+        }
+
+        match time::timeout(timeout, stream.read_buf(&mut buf)).await {
+            Ok(Ok(n)) if n > 0 => {}
+            _ => return Err(READ_ERR.to_string()),
+        }
+    }
+
+    Ok(replies)
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use tokio::net::TcpListener;
+
+    use crate::{server, Connection, Frame};
+
+    fn args(args: &[&str]) -> Vec<Bytes> {
+        args.iter().map(|arg| Bytes::copy_from_slice(arg.as_bytes())).collect()
+    }
+
+    fn bulk(val: &str) -> Frame {
+        Frame::Bulk(val.to_string().into())
+    }
+
+    fn ok() -> Frame {
+        Frame::Simple("OK".to_string())
+    }
+
+    #[tokio::test]
+    async fn migrate() {
+        let source = server::isolated().await;
+        let target = server::isolated().await;
+        let port = target.addr().port().to_string();
+        let mut src = source.connect().await.unwrap();
+        let mut dst = target.connect().await.unwrap();
+
+        // 迁移成功后本地的 key 被删除, 过期时间随 key 一起迁移
+        src.request(["SET", "foo", "bar", "EX", "100"]).await.unwrap();
+        let migrate = args(&["MIGRATE", "127.0.0.1", &port, "foo", "0", "1000"]);
+        assert_eq!(ok(), src.request(migrate.clone()).await.unwrap());
+        assert_eq!(Frame::Integer(0), src.request(["EXISTS", "foo"]).await.unwrap());
+        assert_eq!(bulk("bar"), dst.request(["GET", "foo"]).await.unwrap());
+        let Frame::Integer(ttl) = dst.request(["TTL", "foo"]).await.unwrap() else {
+            panic!("TTL should reply an integer");
+        };
+        assert!((90..=100).contains(&ttl));
+
+        // 本地不存在的 key
+        let reply = src.request(migrate).await.unwrap();
+        assert_eq!(Frame::Simple("NOKEY".to_string()), reply);
+
+        // COPY 保留本地的 key, 目标数据库由参数指定
+        src.request(["SET", "foo", "baz"]).await.unwrap();
+        let copy = args(&["MIGRATE", "127.0.0.1", &port, "foo", "3", "1000", "COPY"]);
+        assert_eq!(ok(), src.request(copy).await.unwrap());
+        assert_eq!(bulk("baz"), src.request(["GET", "foo"]).await.unwrap());
+        dst.request(["SELECT", "3"]).await.unwrap();
+        assert_eq!(bulk("baz"), dst.request(["GET", "foo"]).await.unwrap());
+        dst.request(["SELECT", "0"]).await.unwrap();
+
+        // 目标实例已经存在该 key 时, 没有 REPLACE 会失败并保留本地的 key
+        let migrate = args(&["MIGRATE", "127.0.0.1", &port, "foo", "0", "1000"]);
+        let reply = src.request(migrate).await.unwrap();
+        assert!(matches!(reply, Frame::Error(err) if err.contains("BUSYKEY")));
+        assert_eq!(bulk("baz"), src.request(["GET", "foo"]).await.unwrap());
+        assert_eq!(bulk("bar"), dst.request(["GET", "foo"]).await.unwrap());
+
+        let replace = args(&["MIGRATE", "127.0.0.1", &port, "", "0", "1000", "REPLACE"]);
+        let replace = [replace, args(&["KEYS", "foo", "missing"])].concat();
+        assert_eq!(ok(), src.request(replace).await.unwrap());
+        assert_eq!(Frame::Integer(0), src.request(["EXISTS", "foo"]).await.unwrap());
+        assert_eq!(bulk("baz"), dst.request(["GET", "foo"]).await.unwrap());
+        assert_eq!(Frame::Integer(-1), dst.request(["TTL", "foo"]).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn migrate_concurrent_write() {
+        let source = server::isolated().await;
+        let mut src = source.connect().await.unwrap();
+        let mut other = source.connect().await.unwrap();
+        src.request(["SET", "foo", "1"]).await.unwrap();
+        src.request(["SET", "bar", "1"]).await.unwrap();
+
+        // 由测试扮演目标实例, 在回复 RESTORE 之前修改 foo, 顺序是确定的
+        let target = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = target.local_addr().unwrap().port().to_string();
+        let migrate = [
+            args(&["MIGRATE", "127.0.0.1", &port, "", "0", "1000"]),
+            args(&["KEYS", "foo", "bar"]),
+        ]
+        .concat();
+        let migrating = tokio::spawn(async move { src.request(migrate).await.unwrap() });
+
+        let (stream, _) = target.accept().await.unwrap();
+        let mut conn = Connection::new(stream);
+        for _ in 0..3 {
+            conn.read_frame().await.unwrap().unwrap();
+        }
+        other.request(["SET", "foo", "2"]).await.unwrap();
+        conn.write_frames(&[ok(), ok(), ok()]).await.unwrap();
+
+        // 被修改的 foo 保留在本地并且回复错误, 没有被修改的 bar 正常删除
+        let reply = migrating.await.unwrap();
+        assert!(matches!(reply, Frame::Error(err) if err.ends_with("not removed: foo")));
+        assert_eq!(bulk("2"), other.request(["GET", "foo"]).await.unwrap());
+        assert_eq!(Frame::Integer(0), other.request(["EXISTS", "bar"]).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn restore_ttl_overflow() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+        conn.request(["SET", "foo", "bar"]).await.unwrap();
+        let Frame::Bulk(payload) = conn.request(["DUMP", "foo"]).await.unwrap() else {
+            panic!("DUMP should reply a bulk");
+        };
+
+        let restore = |ttl: &str| [args(&["RESTORE", "a", ttl]), vec![payload.clone()]].concat();
+        let reply = conn.request(restore("9223372036854775807")).await.unwrap();
+        assert_eq!(Frame::Error("ERR Invalid TTL value".to_string()), reply);
+        let reply = conn.request(restore("-1")).await.unwrap();
+        assert!(matches!(reply, Frame::Error(err) if err.contains("must be >= 0")));
+
+        // 同一个分片上的 key 仍然可以正常读写
+        assert_eq!(ok(), conn.request(restore("100000")).await.unwrap());
+        assert_eq!(bulk("bar"), conn.request(["GET", "a"]).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn object() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+
+        let long = "x".repeat(50);
+        for (val, encoding) in [("123", "int"), ("hello", "embstr"), (long.as_str(), "raw")] {
+            conn.request(args(&["SET", "foo", val])).await.unwrap();
+            let reply = conn.request(["OBJECT", "ENCODING", "foo"]).await.unwrap();
+            assert_eq!(bulk(encoding), reply, "{}", val);
+        }
+
+        assert_eq!(Frame::Integer(1), conn.request(["OBJECT", "REFCOUNT", "foo"]).await.unwrap());
+        assert_eq!(Frame::Integer(0), conn.request(["OBJECT", "IDLETIME", "foo"]).await.unwrap());
+        // 新写入的 key 的访问频率为 LFU_INIT_VAL, 第一次读取一定会让频率加 1. OBJECT 本身不算访问
+        assert_eq!(Frame::Integer(5), conn.request(["OBJECT", "FREQ", "foo"]).await.unwrap());
+        conn.request(["GET", "foo"]).await.unwrap();
+        assert_eq!(Frame::Integer(6), conn.request(["OBJECT", "FREQ", "foo"]).await.unwrap());
+
+        assert_eq!(Frame::Null, conn.request(["OBJECT", "ENCODING", "missing"]).await.unwrap());
+        let reply = conn.request(["OBJECT", "NOPE", "foo"]).await.unwrap();
+        assert!(matches!(reply, Frame::Error(err) if err.contains("Try OBJECT HELP")));
+        let reply = conn.request(["OBJECT", "HELP"]).await.unwrap();
+        assert!(matches!(reply, Frame::Array(lines) if lines.len() > 1));
+    }
+
+    #[tokio::test]
+    async fn scan() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+
+        for i in 0..25 {
+            conn.request(args(&["SET", &format!("key:{}", i), "v"])).await.unwrap();
+        }
+        for i in 0..5 {
+            conn.request(args(&["RPUSH", &format!("list:{}", i), "v"])).await.unwrap();
+        }
+
+        // 遍历直到游标回到 0, 每个 key 都恰好返回一次
+        let mut scan_all = async |options: &[&str]| {
+            let mut cursor = "0".to_string();
+            let mut found = vec![];
+            loop {
+                let scan = [args(&["SCAN", &cursor]), args(options)].concat();
+                let Frame::Array(reply) = conn.request(scan).await.unwrap() else {
+                    panic!("SCAN should reply an array");
+                };
+                let [Frame::Bulk(next), Frame::Array(keys)] = &reply[..] else {
+                    panic!("unexpected SCAN reply");
+                };
+                for key in keys {
+                    let Frame::Bulk(key) = key else { panic!("keys should be bulks") };
+                    found.push(String::from_utf8(key.to_vec()).unwrap());
+                }
+
+                cursor = String::from_utf8(next.to_vec()).unwrap();
+                if cursor == "0" {
+                    break;
+                }
+            }
+
+            found.sort();
+            let len = found.len();
+            found.dedup();
+            assert_eq!(len, found.len(), "duplicated keys");
+            found
+        };
+
+        assert_eq!(30, scan_all(&["COUNT", "7"]).await.len());
+        let found = scan_all(&["MATCH", "key:1*", "COUNT", "3"]).await;
+        let expected: Vec<String> = ["key:1"]
+            .into_iter()
+            .map(String::from)
+            .chain((10..20).map(|i| format!("key:{}", i)))
+            .collect();
+        assert_eq!(expected, found);
+        let found = scan_all(&["TYPE", "LIST"]).await;
+        assert_eq!((0..5).map(|i| format!("list:{}", i)).collect::<Vec<_>>(), found);
+
+        let reply = conn.request(["SCAN", "x"]).await.unwrap();
+        assert!(matches!(reply, Frame::Error(err) if err.contains("invalid cursor")));
+        let reply = conn.request(["SCAN", "0", "COUNT", "0"]).await.unwrap();
+        assert!(matches!(reply, Frame::Error(err) if err.contains("syntax")));
+    }
+
+    #[tokio::test]
+    async fn encodings() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+        let encoding = |reply: Frame| match reply {
+            Frame::Bulk(val) => String::from_utf8(val.to_vec()).unwrap(),
+            reply => panic!("unexpected reply {}", reply),
+        };
+
+        for (name, val) in [
+            ("hash-max-listpack-entries", "2"),
+            ("hash-max-listpack-value", "4"),
+            ("set-max-intset-entries", "2"),
+        ] {
+            assert_eq!(ok(), conn.request(args(&["CONFIG", "SET", name, val])).await.unwrap());
+        }
+
+        // 字段个数或者长度超过阈值之后转换为 hashtable
+        let reply = conn.request(["HSET", "h", "a", "1", "b", "2", "a", "3"]).await.unwrap();
+        assert_eq!(Frame::Integer(2), reply);
+        let reply = conn.request(["OBJECT", "ENCODING", "h"]).await.unwrap();
+        assert_eq!("listpack", encoding(reply));
+        conn.request(["HSET", "h", "c", "3"]).await.unwrap();
+        let reply = conn.request(["OBJECT", "ENCODING", "h"]).await.unwrap();
+        assert_eq!("hashtable", encoding(reply));
+        conn.request(["HSET", "long", "f", "too long"]).await.unwrap();
+        let reply = conn.request(["OBJECT", "ENCODING", "long"]).await.unwrap();
+        assert_eq!("hashtable", encoding(reply));
+
+        assert_eq!(bulk("3"), conn.request(["HGET", "h", "a"]).await.unwrap());
+        assert_eq!(Frame::Integer(1), conn.request(["HEXISTS", "h", "b"]).await.unwrap());
+        assert_eq!(Frame::Integer(2), conn.request(["HDEL", "h", "a", "b", "x"]).await.unwrap());
+        let reply = conn.request(["HGETALL", "h"]).await.unwrap();
+        assert_eq!(Frame::Array(vec![bulk("c"), bulk("3")]), reply);
+        conn.request(["HDEL", "h", "c"]).await.unwrap();
+        assert_eq!(Frame::Integer(0), conn.request(["EXISTS", "h"]).await.unwrap());
+
+        // 整数集合超过 set-max-intset-entries 或者插入非整数成员之后转换为 hashtable
+        assert_eq!(Frame::Integer(2), conn.request(["SADD", "s", "2", "1", "2"]).await.unwrap());
+        let reply = conn.request(["OBJECT", "ENCODING", "s"]).await.unwrap();
+        assert_eq!("intset", encoding(reply));
+        let reply = conn.request(["SMEMBERS", "s"]).await.unwrap();
+        assert_eq!(Frame::Array(vec![bulk("1"), bulk("2")]), reply);
+        conn.request(["SADD", "s", "x"]).await.unwrap();
+        let reply = conn.request(["OBJECT", "ENCODING", "s"]).await.unwrap();
+        assert_eq!("hashtable", encoding(reply));
+        assert_eq!(Frame::Integer(3), conn.request(["SCARD", "s"]).await.unwrap());
+        assert_eq!(Frame::Integer(1), conn.request(["SISMEMBER", "s", "x"]).await.unwrap());
+        assert_eq!(Frame::Integer(1), conn.request(["SREM", "s", "x"]).await.unwrap());
+        let reply = conn.request(["TYPE", "s"]).await.unwrap();
+        assert_eq!(Frame::Simple("set".to_string()), reply);
+        let reply = conn.request(["HGET", "s", "a"]).await.unwrap();
+        assert!(matches!(reply, Frame::Error(err) if err.starts_with("WRONGTYPE")));
+
+        // RESTORE 按照当前的阈值重新选择编码
+        conn.request(["RPUSH", "l", "a", "b"]).await.unwrap();
+        let Frame::Bulk(payload) = conn.request(["DUMP", "l"]).await.unwrap() else {
+            panic!("DUMP should reply a bulk");
+        };
+        let reply = conn.request(["CONFIG", "SET", "list-max-listpack-size", "1"]).await.unwrap();
+        assert_eq!(ok(), reply);
+        let restore = [args(&["RESTORE", "l", "0"]), vec![payload], args(&["REPLACE"])].concat();
+        assert_eq!(ok(), conn.request(restore).await.unwrap());
+        let reply = conn.request(["OBJECT", "ENCODING", "l"]).await.unwrap();
+        assert_eq!("quicklist", encoding(reply));
+    }
+
+    #[tokio::test]
+    async fn migrate_to_self() {
+        // 单线程的 runtime 中迁移到自身, 等待回复时不能阻塞处理 RESTORE 的连接
+        let server = server::isolated().await;
+        let port = server.addr().port().to_string();
+        let mut conn = server.connect().await.unwrap();
+
+        conn.request(["SET", "foo", "bar"]).await.unwrap();
+        let migrate = args(&["MIGRATE", "127.0.0.1", &port, "foo", "1", "1000"]);
+        assert_eq!(ok(), conn.request(migrate).await.unwrap());
+        assert_eq!(Frame::Integer(0), conn.request(["EXISTS", "foo"]).await.unwrap());
+        conn.request(["SELECT", "1"]).await.unwrap();
+        assert_eq!(bulk("bar"), conn.request(["GET", "foo"]).await.unwrap());
+
+        // 目标实例不可达
+        let migrate = args(&["MIGRATE", "127.0.0.1", "1", "foo", "0", "100"]);
+        let reply = conn.request(migrate).await.unwrap();
+        assert!(matches!(reply, Frame::Error(err) if err.starts_with("IOERR")));
+        assert_eq!(bulk("bar"), conn.request(["GET", "foo"]).await.unwrap());
+    }
+}
@@ -0,0 +1,149 @@
+//! 列表命令
+
+use bytes::Bytes;
+
+use super::{int, key, CmdResult, Context, SYNTAX_ERR, WRONGTYPE_ERR};
+use crate::{db::Value, list::List, notify::Class, Frame};
+
+pub fn lpush(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    push(ctx, args, true)
+}
+
+pub fn rpush(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    push(ctx, args, false)
+}
+
+fn push(ctx: &mut Context<'_>, args: &[Bytes], left: bool) -> CmdResult {
+    let key = key(&args[1])?;
+    let max_size = ctx.db.config().list_max_listpack_size();
+    let mut guard = ctx.lock(&[key]);
+
+    let push_all = |list: &mut List| {
+        for val in &args[2..] {
+            if left {
+                list.push_front(val.clone(), max_size);
+            } else {
+                list.push_back(val.clone(), max_size);
+            }
+        }
+
+        list.len()
+    };
+
+    let len = match guard.update(key, |value| match value {
+        Value::List(list) => Ok(push_all(list)),
+        _ => Err(WRONGTYPE_ERR.to_string()),
+    }) {
+        Some(len) => len?,
+        None => {
+            let mut list = List::new();
+            let len = push_all(&mut list);
+            guard.insert(key, Value::List(list), None);
+
+            len
+        }
+    };
+    guard.notify(Class::List, if left { "lpush" } else { "rpush" }, key);
+
+    Ok(Frame::Integer(len as i64).into())
+}
+
+pub fn lpop(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    pop(ctx, args, true)
+}
+
+pub fn rpop(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    pop(ctx, args, false)
+}
+
+/// LPOP/RPOP key [count]
+fn pop(ctx: &mut Context<'_>, args: &[Bytes], left: bool) -> CmdResult {
+    let key = key(&args[1])?;
+    let count = match args.get(2) {
+        Some(count) => match int(count)? {
+            count if count < 0 => return Err("ERR value is out of range, must be positive".to_string()),
+            count => Some(count as usize),
+        },
+        None => None,
+    };
+    if args.len() > 3 {
+        return Err(SYNTAX_ERR.to_string());
+    }
+
+    let mut guard = ctx.lock(&[key]);
+    let popped = guard.update(key, |value| match value {
+        Value::List(list) => {
+            let n = count.unwrap_or(1).min(list.len());
+            let popped: Vec<Bytes> = (0..n)
+                .filter_map(|_| if left { list.pop_front() } else { list.pop_back() })
+                .collect();
+
+            Ok(popped)
+        }
+        _ => Err(WRONGTYPE_ERR.to_string()),
+    });
+
+    let popped = match popped {
+        Some(popped) => popped?,
+        None => return Ok(Frame::Null.into()),
+    };
+
+    if !popped.is_empty() {
+        guard.notify(Class::List, if left { "lpop" } else { "rpop" }, key);
+        if !guard.exists(key) {
+            guard.notify(Class::Generic, "del", key);
+        }
+    }
+
+    match count {
+        Some(_) => Ok(Frame::Array(popped.into_iter().map(Frame::Bulk).collect()).into()),
+        None => Ok(popped
+            .into_iter()
+            .next()
+            .map_or(Frame::Null, Frame::Bulk)
+            .into()),
+    }
+}
+
+pub fn llen(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let mut guard = ctx.lock(&[key]);
+
+    match guard.get(key) {
+        Some(Value::List(list)) => Ok(Frame::Integer(list.len() as i64).into()),
+        Some(_) => Err(WRONGTYPE_ERR.to_string()),
+        None => Ok(Frame::Integer(0).into()),
+    }
+}
+
+pub fn lrange(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let (start, stop) = (int(&args[2])?, int(&args[3])?);
+    let mut guard = ctx.lock(&[key]);
+
+    let list = match guard.get(key) {
+        Some(Value::List(list)) => list,
+        Some(_) => return Err(WRONGTYPE_ERR.to_string()),
+        None => return Ok(Frame::Array(vec![]).into()),
+    };
+
+    let items = match range(start, stop, list.len()) {
+        Some((start, stop)) => list.range(start, stop).into_iter().map(Frame::Bulk).collect(),
+        None => vec![],
+    };
+
+    Ok(Frame::Array(items).into())
+}
+
+/// 将支持负数下标的 [start, stop] 转换为合法的下标范围, 范围为空时返回 None
+pub(crate) fn range(start: i64, stop: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 { (len + start).max(0) } else { start };
+    let stop = if stop < 0 { len + stop } else { stop.min(len - 1) };
+
+    if start > stop || start >= len {
+        return None;
+    }
+
+    Some((start as usize, stop as usize))
+}
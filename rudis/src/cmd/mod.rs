@@ -0,0 +1,809 @@
+//! 命令的解析与执行
+//!
+//! 客户端发送的命令是一个由 bulk 组成的 Array, 第一个元素为命令名称, 其余为参数.
+//! 所有的命令都声明在 [`Registry`] 中, 每个命令的实现都是一个 [`Handler`],
+//! 命令执行失败时返回的错误信息会作为 Error frame 回复给客户端
+
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+use tokio::time::Instant;
+
+use crate::{
+    db::{Db, Guard},
+    session::Session,
+    Frame,
+};
+
+mod bitmaps;
+mod bloom;
+mod client;
+mod cms;
+mod geo;
+mod hashes;
+mod hyperloglog;
+mod json;
+mod keys;
+mod lists;
+pub(crate) mod pubsub;
+mod registry;
+mod scripting;
+mod server;
+mod sets;
+mod strings;
+mod timeseries;
+mod zsets;
+
+pub use registry::{Categories, CommandSpec, Flags, Handler, Registry};
+
+/// 命令的回复
+pub enum Reply {
+    Frame(Frame),
+
+    /// 一个命令产生多个回复, 例如 SUBSCRIBE 每订阅一个频道都需要回复一次
+    Multi(Vec<Frame>),
+
+    /// 需要等待网络 I/O 的命令(例如 MIGRATE)不能在持有锁的时候阻塞,
+    /// 命令先释放锁并返回一个 future, 由连接所在的 task 等待它完成之后再回复客户端
+    Later(Pin<Box<dyn Future<Output = Frame> + Send>>),
+}
+
+impl fmt::Debug for Reply {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reply::Frame(frame) => f.debug_tuple("Frame").field(frame).finish(),
+            Reply::Multi(frames) => f.debug_tuple("Multi").field(frames).finish(),
+            Reply::Later(_) => f.write_str("Later"),
+        }
+    }
+}
+
+impl From<Frame> for Reply {
+    fn from(frame: Frame) -> Self {
+        Reply::Frame(frame)
+    }
+}
+
+/// 命令执行失败时, 错误信息(包括 `ERR`、`WRONGTYPE` 等前缀)会原样回复给客户端
+pub type CmdResult = Result<Reply, String>;
+
+/// 命令执行时的上下文
+pub struct Context<'a> {
+    pub registry: &'a Registry,
+    pub db: &'a Db,
+    pub session: &'a mut Session,
+}
+
+impl<'a> Context<'a> {
+    /// 锁定当前连接所选择的数据库中 keys 所在的分片
+    pub fn lock(&self, keys: &[&str]) -> Guard<'a> {
+        self.db.lock(self.session.db(), keys)
+    }
+}
+
+pub(crate) const SYNTAX_ERR: &str = "ERR syntax error";
+pub(crate) const WRONGTYPE_ERR: &str =
+    "WRONGTYPE Operation against a key holding the wrong kind of value";
+pub(crate) const NOT_INTEGER_ERR: &str = "ERR value is not an integer or out of range";
+
+/// 执行一个命令
+pub fn execute(registry: &Registry, db: &Db, session: &mut Session, frame: Frame) -> Reply {
+    let args = match into_args(frame) {
+        Ok(args) => args,
+        Err(err) => return Frame::Error(err).into(),
+    };
+
+    let name = String::from_utf8_lossy(&args[0]).to_ascii_lowercase();
+    let Some(spec) = registry.get(&name) else {
+        let rest: Vec<String> = args[1..]
+            .iter()
+            .map(|arg| format!("'{}'", String::from_utf8_lossy(arg)))
+            .collect();
+
+        return Frame::Error(format!(
+            "ERR unknown command '{}', with args beginning with: {}",
+            String::from_utf8_lossy(&args[0]),
+            rest.join(" ")
+        ))
+        .into();
+    };
+
+    if !spec.check_arity(args.len()) {
+        return Frame::Error(format!(
+            "ERR wrong number of arguments for '{}' command",
+            name
+        ))
+        .into();
+    }
+
+    // 订阅模式下只允许执行订阅相关的命令, RESP3 的推送消息与回复可以区分开, 不受该限制
+    if session.subscriptions() > 0
+        && session.protocol() == 2
+        && !matches!(
+            name.as_str(),
+            "subscribe" | "unsubscribe" | "psubscribe" | "punsubscribe" | "ping"
+        )
+    {
+        return Frame::Error(format!(
+            "ERR Can't execute '{}': only (P)SUBSCRIBE / (P)UNSUBSCRIBE / PING are allowed in this context",
+            name
+        ))
+        .into();
+    }
+
+    // 脚本是原子执行的: 普通命令共享 gate, EVAL 等命令独占 gate.
+    // 脚本执行超时之后, 除了 SCRIPT KILL 等命令之外都会立即返回 BUSY 错误
+    let _gate = if spec.flags.contains(Flags::ALLOW_BUSY) {
+        None
+    } else {
+        let exclusive = spec.flags.contains(Flags::EXCLUSIVE);
+        match db.scripts().enter(exclusive, db.config().lua_time_limit()) {
+            Ok(gate) => Some(gate),
+            Err(err) => return Frame::Error(err).into(),
+        }
+    };
+
+    if spec.flags.contains(Flags::WRITE) {
+        if let Err(err) = check_replicas(db) {
+            return Frame::Error(err).into();
+        }
+    }
+
+    // 命令执行之前, 内存超过限制时需要先淘汰 key
+    // 只有可能增加内存占用(denyoom)的命令才会在内存不足时被拒绝, DEL 等命令仍然可以执行
+    if spec.flags.contains(Flags::DENYOOM) {
+        if let Err(err) = db.evict() {
+            return Frame::Error(err).into();
+        }
+    }
+
+    // 开启了 tracking 的连接读取的 key 需要被记录下来, 见 `crate::tracking`.
+    // 在执行之前记录, 读取与记录之间其他连接对 key 的修改也会发出失效消息
+    let caching = session.caching.take();
+    if spec.flags.contains(Flags::READONLY)
+        && session.tracking.as_ref().is_some_and(|options| options.remember(caching))
+    {
+        let keys: Vec<&str> = spec
+            .key_indexes(args.len())
+            .into_iter()
+            .filter_map(|i| std::str::from_utf8(&args[i]).ok())
+            .collect();
+        let max_keys = db.config().tracking_table_max_keys();
+        db.tracking().remember(session.id(), &keys, max_keys, db.pubsub());
+    }
+
+    let mut ctx = Context {
+        registry,
+        db,
+        session,
+    };
+    match spec.call(&mut ctx, &args) {
+        Ok(reply) => reply,
+        Err(err) => Frame::Error(err).into(),
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        let mut registry = Registry::empty();
+        for spec in builtin() {
+            registry
+                .register(spec)
+                .expect("builtin commands must be unique");
+        }
+
+        registry
+    }
+}
+
+/// 内置命令的命令表
+fn builtin() -> Vec<CommandSpec> {
+    use Categories as Acl;
+    use CommandSpec as Cmd;
+
+    vec![
+        // 连接
+        Cmd::new("ping", -1, server::ping)
+            .flags(Flags::FAST)
+            .acl(Acl::CONNECTION)
+            .doc("connection", "Returns the server's liveliness response."),
+        Cmd::new("echo", 2, server::echo)
+            .flags(Flags::FAST)
+            .acl(Acl::CONNECTION)
+            .doc("connection", "Returns the given string."),
+        Cmd::new("select", 2, server::select)
+            .flags(Flags::FAST)
+            .acl(Acl::CONNECTION)
+            .doc("connection", "Changes the selected database."),
+        Cmd::new("hello", -1, client::hello)
+            .flags(Flags::FAST)
+            .acl(Acl::CONNECTION)
+            .doc("connection", "Handshakes with the server."),
+        Cmd::new("client", -2, client::client)
+            .acl(Acl::CONNECTION)
+            .doc("connection", "A container for client connection commands."),
+
+        // 服务端
+        Cmd::new("command", -1, server::command)
+            .acl(Acl::CONNECTION)
+            .doc("server", "Returns detailed information about all commands."),
+        Cmd::new("config", -2, server::config)
+            .flags(Flags::ADMIN | Flags::NOSCRIPT)
+            .doc("server", "Gets or sets the server configuration parameters."),
+        Cmd::new("info", -1, server::info)
+            .acl(Acl::DANGEROUS)
+            .doc("server", "Returns information and statistics about the server."),
+        Cmd::new("memory", -2, server::memory)
+            .flags(Flags::READONLY)
+            .keys(2, 2, 1)
+            .doc("server", "Memory introspection commands."),
+        Cmd::new("debug", -2, server::debug)
+            .flags(Flags::NOSCRIPT | Flags::EXCLUSIVE)
+            .doc("server", "A container for debugging commands."),
+        Cmd::new("wait", 3, server::wait)
+            .flags(Flags::NOSCRIPT)
+            .doc("generic", "Returns the number of replicas that acknowledged the preceding writes. rudis has no replicas, so it always returns 0 immediately."),
+        Cmd::new("dbsize", 1, server::dbsize)
+            .flags(Flags::READONLY | Flags::FAST)
+            .acl(Acl::KEYSPACE)
+            .doc("server", "Returns the number of keys in the database."),
+        Cmd::new("swapdb", 3, server::swapdb)
+            .flags(Flags::WRITE | Flags::FAST)
+            .acl(Acl::KEYSPACE | Acl::DANGEROUS)
+            .doc("server", "Swaps two databases."),
+        Cmd::new("flushdb", -1, server::flushdb)
+            .flags(Flags::WRITE)
+            .acl(Acl::KEYSPACE | Acl::DANGEROUS)
+            .doc("server", "Removes all keys from the current database."),
+        Cmd::new("flushall", -1, server::flushall)
+            .flags(Flags::WRITE)
+            .acl(Acl::KEYSPACE | Acl::DANGEROUS)
+            .doc("server", "Removes all keys from all databases."),
+
+        // 脚本
+        Cmd::new("eval", -3, scripting::eval)
+            .flags(Flags::NOSCRIPT | Flags::EXCLUSIVE)
+            .acl(Acl::SCRIPTING)
+            .doc("scripting", "Executes a server-side Lua script."),
+        Cmd::new("evalsha", -3, scripting::evalsha)
+            .flags(Flags::NOSCRIPT | Flags::EXCLUSIVE)
+            .acl(Acl::SCRIPTING)
+            .doc("scripting", "Executes a server-side Lua script by SHA1 digest."),
+        Cmd::new("script", -2, scripting::script)
+            .flags(Flags::NOSCRIPT | Flags::ALLOW_BUSY)
+            .acl(Acl::SCRIPTING)
+            .doc("scripting", "A container for Lua scripts management commands."),
+
+        // 通用的 key 命令
+        Cmd::new("del", -2, keys::del)
+            .flags(Flags::WRITE)
+            .keys(1, -1, 1)
+            .acl(Acl::KEYSPACE)
+            .doc("generic", "Deletes one or more keys."),
+        Cmd::new("exists", -2, keys::exists)
+            .flags(Flags::READONLY | Flags::FAST)
+            .keys(1, -1, 1)
+            .acl(Acl::KEYSPACE)
+            .doc("generic", "Determines whether one or more keys exist."),
+        Cmd::new("expire", 3, keys::expire)
+            .flags(Flags::WRITE | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::KEYSPACE)
+            .doc("generic", "Sets the expiration time of a key in seconds."),
+        Cmd::new("pexpire", 3, keys::pexpire)
+            .flags(Flags::WRITE | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::KEYSPACE)
+            .doc("generic", "Sets the expiration time of a key in milliseconds."),
+        Cmd::new("persist", 2, keys::persist)
+            .flags(Flags::WRITE | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::KEYSPACE)
+            .doc("generic", "Removes the expiration time of a key."),
+        Cmd::new("ttl", 2, keys::ttl)
+            .flags(Flags::READONLY | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::KEYSPACE)
+            .doc("generic", "Returns the expiration time in seconds of a key."),
+        Cmd::new("pttl", 2, keys::pttl)
+            .flags(Flags::READONLY | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::KEYSPACE)
+            .doc("generic", "Returns the expiration time in milliseconds of a key."),
+        Cmd::new("type", 2, keys::type_)
+            .flags(Flags::READONLY | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::KEYSPACE)
+            .doc("generic", "Determines the type of value stored at a key."),
+        Cmd::new("object", -2, keys::object)
+            .flags(Flags::READONLY)
+            .keys(2, 2, 1)
+            .acl(Acl::KEYSPACE)
+            .doc("generic", "Returns the internal encoding of a Redis object."),
+        Cmd::new("scan", -2, keys::scan)
+            .flags(Flags::READONLY)
+            .acl(Acl::KEYSPACE)
+            .doc("generic", "Iterates over the key names in the database."),
+        Cmd::new("move", 3, keys::move_)
+            .flags(Flags::WRITE | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::KEYSPACE)
+            .doc("generic", "Moves a key to another database."),
+        Cmd::new("dump", 2, keys::dump)
+            .flags(Flags::READONLY)
+            .keys(1, 1, 1)
+            .acl(Acl::KEYSPACE)
+            .doc("generic", "Returns a serialized representation of the value stored at a key."),
+        Cmd::new("restore", -4, keys::restore)
+            .flags(Flags::WRITE | Flags::DENYOOM)
+            .keys(1, 1, 1)
+            .acl(Acl::KEYSPACE | Acl::DANGEROUS)
+            .doc("generic", "Creates a key from the serialized representation of a value."),
+        Cmd::new("migrate", -6, keys::migrate)
+            .flags(Flags::WRITE | Flags::NOSCRIPT)
+            .keys(3, 3, 1)
+            .acl(Acl::KEYSPACE | Acl::DANGEROUS)
+            .doc("generic", "Atomically transfers a key from one Redis instance to another."),
+
+        // 字符串
+        Cmd::new("get", 2, strings::get)
+            .flags(Flags::READONLY | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::STRING)
+            .doc("string", "Returns the string value of a key."),
+        Cmd::new("set", -3, strings::set)
+            .flags(Flags::WRITE | Flags::DENYOOM)
+            .keys(1, 1, 1)
+            .acl(Acl::STRING)
+            .doc("string", "Sets the string value of a key, ignoring its type."),
+        Cmd::new("mget", -2, strings::mget)
+            .flags(Flags::READONLY | Flags::FAST)
+            .keys(1, -1, 1)
+            .acl(Acl::STRING)
+            .doc("string", "Atomically returns the string values of one or more keys."),
+        Cmd::new("mset", -3, strings::mset)
+            .flags(Flags::WRITE | Flags::DENYOOM)
+            .keys(1, -1, 2)
+            .acl(Acl::STRING)
+            .doc("string", "Atomically creates or modifies the string values of one or more keys."),
+        Cmd::new("append", 3, strings::append)
+            .flags(Flags::WRITE | Flags::DENYOOM | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::STRING)
+            .doc("string", "Appends a string to the value of a key."),
+        Cmd::new("strlen", 2, strings::strlen)
+            .flags(Flags::READONLY | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::STRING)
+            .doc("string", "Returns the length of a string value."),
+        Cmd::new("incr", 2, strings::incr)
+            .flags(Flags::WRITE | Flags::DENYOOM | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::STRING)
+            .doc("string", "Increments the integer value of a key by one."),
+        Cmd::new("decr", 2, strings::decr)
+            .flags(Flags::WRITE | Flags::DENYOOM | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::STRING)
+            .doc("string", "Decrements the integer value of a key by one."),
+        Cmd::new("incrby", 3, strings::incrby)
+            .flags(Flags::WRITE | Flags::DENYOOM | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::STRING)
+            .doc("string", "Increments the integer value of a key by a number."),
+        Cmd::new("decrby", 3, strings::decrby)
+            .flags(Flags::WRITE | Flags::DENYOOM | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::STRING)
+            .doc("string", "Decrements a number from the integer value of a key."),
+
+        // 位图
+        Cmd::new("setbit", 4, bitmaps::setbit)
+            .flags(Flags::WRITE | Flags::DENYOOM)
+            .keys(1, 1, 1)
+            .acl(Acl::BITMAP)
+            .doc("bitmap", "Sets or clears the bit at offset of the string value."),
+        Cmd::new("getbit", 3, bitmaps::getbit)
+            .flags(Flags::READONLY | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::BITMAP)
+            .doc("bitmap", "Returns a bit value by offset."),
+        Cmd::new("bitcount", -2, bitmaps::bitcount)
+            .flags(Flags::READONLY)
+            .keys(1, 1, 1)
+            .acl(Acl::BITMAP)
+            .doc("bitmap", "Counts the number of set bits (population counting) in a string."),
+        Cmd::new("bitpos", -3, bitmaps::bitpos)
+            .flags(Flags::READONLY)
+            .keys(1, 1, 1)
+            .acl(Acl::BITMAP)
+            .doc("bitmap", "Finds the first set (1) or clear (0) bit in a string."),
+        Cmd::new("bitop", -4, bitmaps::bitop)
+            .flags(Flags::WRITE | Flags::DENYOOM)
+            .keys(2, -1, 1)
+            .acl(Acl::BITMAP)
+            .doc("bitmap", "Performs bitwise operations on multiple strings, and stores the result."),
+        Cmd::new("bitfield", -2, bitmaps::bitfield)
+            .flags(Flags::WRITE | Flags::DENYOOM)
+            .keys(1, 1, 1)
+            .acl(Acl::BITMAP)
+            .doc("bitmap", "Performs arbitrary bitfield integer operations on strings."),
+        Cmd::new("bitfield_ro", -2, bitmaps::bitfield_ro)
+            .flags(Flags::READONLY | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::BITMAP)
+            .doc("bitmap", "Performs arbitrary read-only bitfield integer operations on strings."),
+
+        // HyperLogLog
+        Cmd::new("pfadd", -2, hyperloglog::pfadd)
+            .flags(Flags::WRITE | Flags::DENYOOM | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::HYPERLOGLOG)
+            .doc("hyperloglog", "Adds elements to a HyperLogLog key. Creates the key if it doesn't exist."),
+        Cmd::new("pfcount", -2, hyperloglog::pfcount)
+            .flags(Flags::READONLY)
+            .keys(1, -1, 1)
+            .acl(Acl::HYPERLOGLOG)
+            .doc("hyperloglog", "Returns the approximated cardinality of the set(s) observed by the HyperLogLog key(s)."),
+        Cmd::new("pfmerge", -2, hyperloglog::pfmerge)
+            .flags(Flags::WRITE | Flags::DENYOOM)
+            .keys(1, -1, 1)
+            .acl(Acl::HYPERLOGLOG)
+            .doc("hyperloglog", "Merges one or more HyperLogLog values into a single key."),
+
+        // 列表
+        Cmd::new("lpush", -3, lists::lpush)
+            .flags(Flags::WRITE | Flags::DENYOOM | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::LIST)
+            .doc("list", "Prepends one or more elements to a list."),
+        Cmd::new("rpush", -3, lists::rpush)
+            .flags(Flags::WRITE | Flags::DENYOOM | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::LIST)
+            .doc("list", "Appends one or more elements to a list."),
+        Cmd::new("lpop", -2, lists::lpop)
+            .flags(Flags::WRITE | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::LIST)
+            .doc("list", "Returns the first elements in a list after removing it."),
+        Cmd::new("rpop", -2, lists::rpop)
+            .flags(Flags::WRITE | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::LIST)
+            .doc("list", "Returns and removes the last elements of a list."),
+        Cmd::new("llen", 2, lists::llen)
+            .flags(Flags::READONLY | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::LIST)
+            .doc("list", "Returns the length of a list."),
+        Cmd::new("lrange", 4, lists::lrange)
+            .flags(Flags::READONLY)
+            .keys(1, 1, 1)
+            .acl(Acl::LIST)
+            .doc("list", "Returns a range of elements from a list."),
+
+        // 哈希
+        Cmd::new("hset", -4, hashes::hset)
+            .flags(Flags::WRITE | Flags::DENYOOM | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::HASH)
+            .doc("hash", "Creates or modifies the value of a field in a hash."),
+        Cmd::new("hget", 3, hashes::hget)
+            .flags(Flags::READONLY | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::HASH)
+            .doc("hash", "Returns the value of a field in a hash."),
+        Cmd::new("hdel", -3, hashes::hdel)
+            .flags(Flags::WRITE | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::HASH)
+            .doc("hash", "Deletes one or more fields and their values from a hash. Deletes the hash if no fields remain."),
+        Cmd::new("hlen", 2, hashes::hlen)
+            .flags(Flags::READONLY | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::HASH)
+            .doc("hash", "Returns the number of fields in a hash."),
+        Cmd::new("hexists", 3, hashes::hexists)
+            .flags(Flags::READONLY | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::HASH)
+            .doc("hash", "Determines whether a field exists in a hash."),
+        Cmd::new("hgetall", 2, hashes::hgetall)
+            .flags(Flags::READONLY)
+            .keys(1, 1, 1)
+            .acl(Acl::HASH)
+            .doc("hash", "Returns all fields and values in a hash."),
+
+        // 集合
+        Cmd::new("sadd", -3, sets::sadd)
+            .flags(Flags::WRITE | Flags::DENYOOM | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::SET)
+            .doc("set", "Adds one or more members to a set. Creates the key if it doesn't exist."),
+        Cmd::new("srem", -3, sets::srem)
+            .flags(Flags::WRITE | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::SET)
+            .doc("set", "Removes one or more members from a set. Deletes the set if the last member was removed."),
+        Cmd::new("sismember", 3, sets::sismember)
+            .flags(Flags::READONLY | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::SET)
+            .doc("set", "Determines whether a member belongs to a set."),
+        Cmd::new("scard", 2, sets::scard)
+            .flags(Flags::READONLY | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::SET)
+            .doc("set", "Returns the number of members in a set."),
+        Cmd::new("smembers", 2, sets::smembers)
+            .flags(Flags::READONLY)
+            .keys(1, 1, 1)
+            .acl(Acl::SET)
+            .doc("set", "Returns all members of a set."),
+
+        // 有序集合
+        Cmd::new("zadd", -4, zsets::zadd)
+            .flags(Flags::WRITE | Flags::DENYOOM | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::SORTEDSET)
+            .doc("sorted_set", "Adds one or more members to a sorted set, or updates their scores. Creates the key if it doesn't exist."),
+        Cmd::new("zincrby", 4, zsets::zincrby)
+            .flags(Flags::WRITE | Flags::DENYOOM | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::SORTEDSET)
+            .doc("sorted_set", "Increments the score of a member in a sorted set."),
+        Cmd::new("zrem", -3, zsets::zrem)
+            .flags(Flags::WRITE | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::SORTEDSET)
+            .doc("sorted_set", "Removes one or more members from a sorted set. Deletes the sorted set if all members were removed."),
+        Cmd::new("zscore", 3, zsets::zscore)
+            .flags(Flags::READONLY | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::SORTEDSET)
+            .doc("sorted_set", "Returns the score of a member in a sorted set."),
+        Cmd::new("zcard", 2, zsets::zcard)
+            .flags(Flags::READONLY | Flags::FAST)
+            .keys(1, 1, 1)
+            .acl(Acl::SORTEDSET)
+            .doc("sorted_set", "Returns the number of members in a sorted set."),
+        Cmd::new("zrange", -4, zsets::zrange)
+            .flags(Flags::READONLY)
+            .keys(1, 1, 1)
+            .acl(Acl::SORTEDSET)
+            .doc("sorted_set", "Returns members in a sorted set within a range of indexes or scores."),
+
+        // 地理位置
+        Cmd::new("geoadd", -5, geo::geoadd)
+            .flags(Flags::WRITE | Flags::DENYOOM)
+            .keys(1, 1, 1)
+            .acl(Acl::GEO)
+            .doc("geo", "Adds one or more members to a geospatial index. The key is created if it doesn't exist."),
+        Cmd::new("geopos", -2, geo::geopos)
+            .flags(Flags::READONLY)
+            .keys(1, 1, 1)
+            .acl(Acl::GEO)
+            .doc("geo", "Returns the longitude and latitude of members from a geospatial index."),
+        Cmd::new("geodist", -4, geo::geodist)
+            .flags(Flags::READONLY)
+            .keys(1, 1, 1)
+            .acl(Acl::GEO)
+            .doc("geo", "Returns the distance between two members of a geospatial index."),
+        Cmd::new("geohash", -2, geo::geohash)
+            .flags(Flags::READONLY)
+            .keys(1, 1, 1)
+            .acl(Acl::GEO)
+            .doc("geo", "Returns members from a geospatial index as geohash strings."),
+        Cmd::new("geosearch", -7, geo::geosearch)
+            .flags(Flags::READONLY)
+            .keys(1, 1, 1)
+            .acl(Acl::GEO)
+            .doc("geo", "Queries a geospatial index for members inside an area of a box or a circle."),
+
+        // JSON
+        Cmd::new("json.set", -4, json::set)
+            .flags(Flags::WRITE | Flags::DENYOOM)
+            .keys(1, 1, 1)
+            .doc("json", "Sets or updates the JSON value at a path."),
+        Cmd::new("json.get", -2, json::get)
+            .flags(Flags::READONLY)
+            .keys(1, 1, 1)
+            .doc("json", "Gets the value at one or more paths in JSON serialized form."),
+        Cmd::new("json.del", -2, json::del)
+            .flags(Flags::WRITE)
+            .keys(1, 1, 1)
+            .doc("json", "Deletes a value."),
+        Cmd::new("json.arrappend", -4, json::arrappend)
+            .flags(Flags::WRITE | Flags::DENYOOM)
+            .keys(1, 1, 1)
+            .doc("json", "Appends one or more JSON values into the array at path after the last element in it."),
+        Cmd::new("json.numincrby", 4, json::numincrby)
+            .flags(Flags::WRITE | Flags::DENYOOM)
+            .keys(1, 1, 1)
+            .doc("json", "Increments the numeric value at path by a value."),
+        Cmd::new("json.type", -2, json::type_)
+            .flags(Flags::READONLY)
+            .keys(1, 1, 1)
+            .doc("json", "Returns the type of the JSON value at path."),
+
+        // 布隆过滤器
+        Cmd::new("bf.reserve", -4, bloom::reserve)
+            .flags(Flags::WRITE | Flags::DENYOOM)
+            .keys(1, 1, 1)
+            .doc("bf", "Creates a new Bloom Filter."),
+        Cmd::new("bf.add", 3, bloom::add)
+            .flags(Flags::WRITE | Flags::DENYOOM | Flags::FAST)
+            .keys(1, 1, 1)
+            .doc("bf", "Adds an item to a Bloom Filter."),
+        Cmd::new("bf.madd", -3, bloom::madd)
+            .flags(Flags::WRITE | Flags::DENYOOM)
+            .keys(1, 1, 1)
+            .doc("bf", "Adds one or more items to a Bloom Filter. A filter will be created if it does not exist."),
+        Cmd::new("bf.exists", 3, bloom::exists)
+            .flags(Flags::READONLY | Flags::FAST)
+            .keys(1, 1, 1)
+            .doc("bf", "Checks whether an item exists in a Bloom Filter."),
+
+        // Count-Min Sketch
+        Cmd::new("cms.initbydim", 4, cms::initbydim)
+            .flags(Flags::WRITE | Flags::DENYOOM)
+            .keys(1, 1, 1)
+            .doc("cms", "Initializes a Count-Min Sketch to dimensions specified by user."),
+        Cmd::new("cms.incrby", -4, cms::incrby)
+            .flags(Flags::WRITE | Flags::DENYOOM)
+            .keys(1, 1, 1)
+            .doc("cms", "Increases the count of one or more items by increment."),
+        Cmd::new("cms.query", -3, cms::query)
+            .flags(Flags::READONLY)
+            .keys(1, 1, 1)
+            .doc("cms", "Returns the count for one or more items in a sketch."),
+
+        // 时间序列
+        Cmd::new("ts.create", -2, timeseries::create)
+            .flags(Flags::WRITE | Flags::DENYOOM)
+            .keys(1, 1, 1)
+            .doc("timeseries", "Create a new time series."),
+        Cmd::new("ts.add", -4, timeseries::add)
+            .flags(Flags::WRITE | Flags::DENYOOM)
+            .keys(1, 1, 1)
+            .doc("timeseries", "Append a sample to a time series."),
+        Cmd::new("ts.madd", -4, timeseries::madd)
+            .flags(Flags::WRITE | Flags::DENYOOM)
+            .keys(1, -1, 3)
+            .doc("timeseries", "Append new samples to one or more time series."),
+        Cmd::new("ts.range", -4, timeseries::range)
+            .flags(Flags::READONLY)
+            .keys(1, 1, 1)
+            .doc("timeseries", "Query a range in forward direction."),
+        Cmd::new("ts.createrule", 6, timeseries::createrule)
+            .flags(Flags::WRITE)
+            .keys(1, 2, 1)
+            .doc("timeseries", "Create a compaction rule."),
+        Cmd::new("ts.deleterule", 3, timeseries::deleterule)
+            .flags(Flags::WRITE)
+            .keys(1, 2, 1)
+            .doc("timeseries", "Delete a compaction rule."),
+        Cmd::new("ts.info", 2, timeseries::info)
+            .flags(Flags::READONLY)
+            .keys(1, 1, 1)
+            .doc("timeseries", "Returns information and statistics for a time series."),
+
+        // 发布订阅
+        Cmd::new("publish", 3, pubsub::publish)
+            .flags(Flags::PUBSUB | Flags::FAST)
+            .doc("pubsub", "Posts a message to a channel."),
+        Cmd::new("subscribe", -2, pubsub::subscribe)
+            .flags(Flags::PUBSUB | Flags::NOSCRIPT)
+            .doc("pubsub", "Listens for messages published to channels."),
+        Cmd::new("unsubscribe", -1, pubsub::unsubscribe)
+            .flags(Flags::PUBSUB | Flags::NOSCRIPT)
+            .doc("pubsub", "Stops listening to messages posted to channels."),
+        Cmd::new("psubscribe", -2, pubsub::psubscribe)
+            .flags(Flags::PUBSUB | Flags::NOSCRIPT)
+            .doc("pubsub", "Listens for messages published to channels that match one or more patterns."),
+        Cmd::new("punsubscribe", -1, pubsub::punsubscribe)
+            .flags(Flags::PUBSUB | Flags::NOSCRIPT)
+            .doc("pubsub", "Stops listening to messages published to channels that match one or more patterns."),
+    ]
+}
+
+/// 连接关闭时清理连接相关的状态
+pub fn disconnect(db: &Db, session: &mut Session) {
+    pubsub::unsubscribe_all(db, session);
+    db.tracking().disable(session.id());
+}
+
+/// 将命令 Frame 转换为参数列表
+fn into_args(frame: Frame) -> Result<Vec<Bytes>, String> {
+    let Frame::Array(parts) = frame else {
+        return Err("ERR Protocol error: expected array of bulk strings".to_string());
+    };
+
+    if parts.is_empty() {
+        return Err("ERR Protocol error: empty command".to_string());
+    }
+
+    parts
+        .into_iter()
+        .map(|part| match part {
+            Frame::Bulk(bytes) => Ok(bytes),
+            Frame::Simple(string) => Ok(Bytes::from(string)),
+            Frame::Integer(num) => Ok(Bytes::from(num.to_string())),
+            _ => Err("ERR Protocol error: expected array of bulk strings".to_string()),
+        })
+        .collect()
+}
+
+pub(crate) fn ok() -> CmdResult {
+    Ok(Frame::Simple("OK".to_string()).into())
+}
+
+/// HELP 子命令的回复, 每行一个 Simple String
+pub(crate) fn help(lines: &[&str]) -> CmdResult {
+    Ok(Frame::Array(lines.iter().map(|line| Frame::Simple(line.to_string())).collect()).into())
+}
+
+/// 将参数解析为 key, rudis 中的 key 必须是合法的 UTF-8 字符串
+pub(crate) fn key(arg: &Bytes) -> Result<&str, String> {
+    std::str::from_utf8(arg).map_err(|_| "ERR invalid key: keys must be valid UTF-8".to_string())
+}
+
+/// 与 redis 相同, 健康的副本少于 `min-replicas-to-write` 时拒绝写命令
+///
+/// rudis 还没有实现主从复制, 健康的副本总是 0 个, 因此设置了该配置之后所有的写命令都会被拒绝.
+/// `min-replicas-max-lag` 只影响哪些副本算是健康的, 目前没有用到
+pub(crate) fn check_replicas(db: &Db) -> Result<(), String> {
+    let good_replicas = 0;
+    if good_replicas < db.config().min_replicas_to_write() {
+        return Err("NOREPLICAS Not enough good replicas to write.".to_string());
+    }
+
+    Ok(())
+}
+
+/// 将参数解析为字符串, 用于选项、频道名称等
+pub(crate) fn string(arg: &Bytes) -> Result<&str, String> {
+    std::str::from_utf8(arg).map_err(|_| SYNTAX_ERR.to_string())
+}
+
+pub(crate) fn int(arg: &Bytes) -> Result<i64, String> {
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| NOT_INTEGER_ERR.to_string())
+}
+
+/// 当前的 unix 时间戳(毫秒)
+pub(crate) fn unix_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as i64)
+}
+
+/// 将 `millis` 毫秒之后的过期时间换算为 Instant, `millis` 必须大于 0
+///
+/// 与 redis 相同, 换算成 unix 时间戳之后超出 i64 范围的过期时间是无效的, 返回 None.
+/// 调用方需要在写入之前检查, 否则持有分片锁时 panic 会让整个分片不可用
+pub(crate) fn expire_after(millis: i64) -> Option<Instant> {
+    unix_millis().checked_add(millis)?;
+    Instant::now().checked_add(Duration::from_millis(millis as u64))
+}
+
+/// 将参数解析为数据库的下标
+pub(crate) fn db_index(db: &Db, arg: &Bytes) -> Result<usize, String> {
+    match int(arg)? {
+        index if index >= 0 && (index as usize) < db.databases() => Ok(index as usize),
+        _ => Err("ERR DB index is out of range".to_string()),
+    }
+}
+
+/// 忽略大小写比较参数与选项名称
+pub(crate) fn is(arg: &Bytes, option: &str) -> bool {
+    arg.eq_ignore_ascii_case(option.as_bytes())
+}
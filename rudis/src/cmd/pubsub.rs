@@ -0,0 +1,123 @@
+//! 发布订阅命令
+
+use bytes::Bytes;
+
+use super::{string, CmdResult, Context, Reply};
+use crate::{db::Db, session::Session, Frame};
+
+pub fn publish(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let channel = string(&args[1])?;
+    let receivers = ctx.db.pubsub().publish(channel, args[2].clone());
+
+    Ok(Frame::Integer(receivers as i64).into())
+}
+
+pub fn subscribe(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let mut replies = Vec::with_capacity(args.len() - 1);
+
+    for channel in &args[1..] {
+        let channel = string(channel)?;
+        if ctx.session.channels.insert(channel.to_string()) {
+            ctx.db
+                .pubsub()
+                .subscribe(channel, ctx.session.id(), ctx.session.push());
+        }
+
+        replies.push(confirm("subscribe", Some(channel), ctx.session));
+    }
+
+    Ok(Reply::Multi(replies))
+}
+
+pub fn unsubscribe(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    // 不指定频道时退订所有频道
+    let channels: Vec<String> = if args.len() > 1 {
+        args[1..]
+            .iter()
+            .map(|channel| string(channel).map(str::to_string))
+            .collect::<Result<_, _>>()?
+    } else {
+        ctx.session.channels.iter().cloned().collect()
+    };
+
+    if channels.is_empty() {
+        return Ok(confirm("unsubscribe", None, ctx.session).into());
+    }
+
+    let mut replies = Vec::with_capacity(channels.len());
+    for channel in channels {
+        if ctx.session.channels.remove(&channel) {
+            ctx.db.pubsub().unsubscribe(&channel, ctx.session.id());
+        }
+
+        replies.push(confirm("unsubscribe", Some(&channel), ctx.session));
+    }
+
+    Ok(Reply::Multi(replies))
+}
+
+pub fn psubscribe(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let mut replies = Vec::with_capacity(args.len() - 1);
+
+    for pattern in &args[1..] {
+        let pattern = string(pattern)?;
+        if ctx.session.patterns.insert(pattern.to_string()) {
+            ctx.db
+                .pubsub()
+                .psubscribe(pattern, ctx.session.id(), ctx.session.push());
+        }
+
+        replies.push(confirm("psubscribe", Some(pattern), ctx.session));
+    }
+
+    Ok(Reply::Multi(replies))
+}
+
+pub fn punsubscribe(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let patterns: Vec<String> = if args.len() > 1 {
+        args[1..]
+            .iter()
+            .map(|pattern| string(pattern).map(str::to_string))
+            .collect::<Result<_, _>>()?
+    } else {
+        ctx.session.patterns.iter().cloned().collect()
+    };
+
+    if patterns.is_empty() {
+        return Ok(confirm("punsubscribe", None, ctx.session).into());
+    }
+
+    let mut replies = Vec::with_capacity(patterns.len());
+    for pattern in patterns {
+        if ctx.session.patterns.remove(&pattern) {
+            ctx.db.pubsub().punsubscribe(&pattern, ctx.session.id());
+        }
+
+        replies.push(confirm("punsubscribe", Some(&pattern), ctx.session));
+    }
+
+    Ok(Reply::Multi(replies))
+}
+
+/// 退订当前连接的所有频道与模式
+pub(crate) fn unsubscribe_all(db: &Db, session: &mut Session) {
+    let id = session.id();
+
+    for channel in session.channels.drain() {
+        db.pubsub().unsubscribe(&channel, id);
+    }
+    for pattern in session.patterns.drain() {
+        db.pubsub().punsubscribe(&pattern, id);
+    }
+}
+
+/// 订阅/退订的回复: [kind, channel, 当前订阅总数]
+fn confirm(kind: &'static str, channel: Option<&str>, session: &Session) -> Frame {
+    Frame::Array(vec![
+        Frame::Bulk(Bytes::from_static(kind.as_bytes())),
+        channel.map_or(Frame::Null, |channel| {
+            Frame::Bulk(Bytes::from(channel.to_string()))
+        }),
+        Frame::Integer(session.subscriptions() as i64),
+    ])
+}
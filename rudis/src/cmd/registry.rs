@@ -0,0 +1,413 @@
+//! 命令表
+//!
+//! 与 redis 的 `redisCommandTable` 相同, 每个命令都声明了名称、参数个数、标记、key 的位置以及 ACL 分类,
+//! `execute` 根据命令表完成参数个数检查、OOM 检查等通用的逻辑, `COMMAND` 命令的回复也由命令表生成.
+//!
+//! 内置命令之外的扩展命令可以在启动服务端之前注册:
+//!
+//! ```
+//! use bytes::Bytes;
+//! use rudis::cmd::{Categories, CmdResult, CommandSpec, Context, Flags, Registry};
+//! use rudis::Frame;
+//!
+//! fn hello(_ctx: &mut Context<'_>, _args: &[Bytes]) -> CmdResult {
+//!     Ok(Frame::Simple("hello world".to_string()).into())
+//! }
+//!
+//! let mut registry = Registry::default();
+//! registry
+//!     .register(
+//!         CommandSpec::new("hello.world", 1, hello)
+//!             .flags(Flags::READONLY | Flags::FAST)
+//!             .acl(Categories::CONNECTION)
+//!             .doc("extension", "Replies with hello world"),
+//!     )
+//!     .unwrap();
+//! ```
+
+use std::{collections::HashMap, fmt, ops::BitOr, sync::Arc};
+
+use bytes::Bytes;
+
+use super::{CmdResult, Context};
+use crate::Frame;
+
+/// 命令的实现
+///
+/// 普通的函数以及闭包都实现了该特征, 有状态的扩展命令也可以自己实现
+pub trait Handler: Send + Sync + 'static {
+    fn call(&self, ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult;
+}
+
+impl<F> Handler for F
+where
+    F: Fn(&mut Context<'_>, &[Bytes]) -> CmdResult + Send + Sync + 'static,
+{
+    fn call(&self, ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+        self(ctx, args)
+    }
+}
+
+/// 命令的标记, 名称与 redis 的 `COMMAND INFO` 中的 flags 保持一致
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Flags(u32);
+
+impl Flags {
+    pub const WRITE: Flags = Flags(1 << 0);
+    pub const READONLY: Flags = Flags(1 << 1);
+    /// 内存超过 `maxmemory` 时拒绝执行
+    pub const DENYOOM: Flags = Flags(1 << 2);
+    pub const ADMIN: Flags = Flags(1 << 3);
+    pub const PUBSUB: Flags = Flags(1 << 4);
+    /// 不允许在脚本中调用
+    pub const NOSCRIPT: Flags = Flags(1 << 5);
+    pub const BLOCKING: Flags = Flags(1 << 6);
+    /// O(1) 或者 O(log(N)) 的命令
+    pub const FAST: Flags = Flags(1 << 7);
+    /// 脚本执行超时之后仍然可以执行, 例如 SCRIPT KILL
+    pub const ALLOW_BUSY: Flags = Flags(1 << 8);
+    /// 执行期间独占整个数据集, 例如 EVAL. 只在服务端内部使用, 不会出现在 `COMMAND INFO` 中
+    pub const EXCLUSIVE: Flags = Flags(1 << 9);
+
+    const NAMES: [(Flags, &'static str); 9] = [
+        (Flags::WRITE, "write"),
+        (Flags::READONLY, "readonly"),
+        (Flags::DENYOOM, "denyoom"),
+        (Flags::ADMIN, "admin"),
+        (Flags::PUBSUB, "pubsub"),
+        (Flags::NOSCRIPT, "noscript"),
+        (Flags::BLOCKING, "blocking"),
+        (Flags::FAST, "fast"),
+        (Flags::ALLOW_BUSY, "allow_busy"),
+    ];
+
+    pub fn contains(&self, other: Flags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        Flags::NAMES
+            .iter()
+            .filter(|(flag, _)| self.contains(*flag))
+            .map(|(_, name)| *name)
+    }
+}
+
+impl BitOr for Flags {
+    type Output = Flags;
+
+    fn bitor(self, rhs: Flags) -> Flags {
+        Flags(self.0 | rhs.0)
+    }
+}
+
+/// ACL 分类, 与 redis 的 `ACL CAT` 保持一致
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Categories(u32);
+
+impl Categories {
+    pub const KEYSPACE: Categories = Categories(1 << 0);
+    pub const READ: Categories = Categories(1 << 1);
+    pub const WRITE: Categories = Categories(1 << 2);
+    pub const SET: Categories = Categories(1 << 3);
+    pub const SORTEDSET: Categories = Categories(1 << 4);
+    pub const LIST: Categories = Categories(1 << 5);
+    pub const HASH: Categories = Categories(1 << 6);
+    pub const STRING: Categories = Categories(1 << 7);
+    pub const BITMAP: Categories = Categories(1 << 8);
+    pub const HYPERLOGLOG: Categories = Categories(1 << 9);
+    pub const GEO: Categories = Categories(1 << 10);
+    pub const STREAM: Categories = Categories(1 << 11);
+    pub const PUBSUB: Categories = Categories(1 << 12);
+    pub const ADMIN: Categories = Categories(1 << 13);
+    pub const FAST: Categories = Categories(1 << 14);
+    pub const SLOW: Categories = Categories(1 << 15);
+    pub const BLOCKING: Categories = Categories(1 << 16);
+    pub const DANGEROUS: Categories = Categories(1 << 17);
+    pub const CONNECTION: Categories = Categories(1 << 18);
+    pub const TRANSACTION: Categories = Categories(1 << 19);
+    pub const SCRIPTING: Categories = Categories(1 << 20);
+
+    const NAMES: [(Categories, &'static str); 21] = [
+        (Categories::KEYSPACE, "keyspace"),
+        (Categories::READ, "read"),
+        (Categories::WRITE, "write"),
+        (Categories::SET, "set"),
+        (Categories::SORTEDSET, "sortedset"),
+        (Categories::LIST, "list"),
+        (Categories::HASH, "hash"),
+        (Categories::STRING, "string"),
+        (Categories::BITMAP, "bitmap"),
+        (Categories::HYPERLOGLOG, "hyperloglog"),
+        (Categories::GEO, "geo"),
+        (Categories::STREAM, "stream"),
+        (Categories::PUBSUB, "pubsub"),
+        (Categories::ADMIN, "admin"),
+        (Categories::FAST, "fast"),
+        (Categories::SLOW, "slow"),
+        (Categories::BLOCKING, "blocking"),
+        (Categories::DANGEROUS, "dangerous"),
+        (Categories::CONNECTION, "connection"),
+        (Categories::TRANSACTION, "transaction"),
+        (Categories::SCRIPTING, "scripting"),
+    ];
+
+    pub fn contains(&self, other: Categories) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        Categories::NAMES
+            .iter()
+            .filter(|(category, _)| self.contains(*category))
+            .map(|(_, name)| *name)
+    }
+}
+
+impl BitOr for Categories {
+    type Output = Categories;
+
+    fn bitor(self, rhs: Categories) -> Categories {
+        Categories(self.0 | rhs.0)
+    }
+}
+
+/// 一个命令的声明
+#[derive(Clone)]
+pub struct CommandSpec {
+    pub name: &'static str,
+
+    /// 为正数时代表参数(包括命令名称)的个数必须相等, 为负数时代表参数个数至少为 -arity
+    pub arity: i32,
+    pub flags: Flags,
+
+    /// 第一个 key 的位置, 0 代表命令没有 key
+    pub first_key: i32,
+
+    /// 最后一个 key 的位置, 负数代表从末尾开始计算, 例如 -1 代表最后一个参数
+    pub last_key: i32,
+
+    /// key 之间的间隔, 例如 MSET 的 key 与 value 交替出现, step 为 2
+    pub step: i32,
+    pub categories: Categories,
+
+    /// COMMAND DOCS 中的分组, 例如 `string`、`list`
+    pub group: &'static str,
+    pub summary: &'static str,
+    handler: Arc<dyn Handler>,
+}
+
+impl CommandSpec {
+    pub fn new(name: &'static str, arity: i32, handler: impl Handler) -> CommandSpec {
+        CommandSpec {
+            name,
+            arity,
+            flags: Flags::default(),
+            first_key: 0,
+            last_key: 0,
+            step: 0,
+            categories: Categories::default(),
+            group: "generic",
+            summary: "",
+            handler: Arc::new(handler),
+        }
+    }
+
+    pub fn flags(mut self, flags: Flags) -> CommandSpec {
+        self.flags = flags;
+        self
+    }
+
+    /// key 的位置: (第一个 key, 最后一个 key, 间隔)
+    pub fn keys(mut self, first_key: i32, last_key: i32, step: i32) -> CommandSpec {
+        self.first_key = first_key;
+        self.last_key = last_key;
+        self.step = step;
+        self
+    }
+
+    pub fn acl(mut self, categories: Categories) -> CommandSpec {
+        self.categories = categories;
+        self
+    }
+
+    pub fn doc(mut self, group: &'static str, summary: &'static str) -> CommandSpec {
+        self.group = group;
+        self.summary = summary;
+        self
+    }
+
+    pub fn call(&self, ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+        self.handler.call(ctx, args)
+    }
+
+    /// 参数个数是否满足 arity
+    pub fn check_arity(&self, argc: usize) -> bool {
+        if self.arity >= 0 {
+            argc == self.arity as usize
+        } else {
+            argc >= self.arity.unsigned_abs() as usize
+        }
+    }
+
+    /// 根据 key 的位置声明, 返回 args 中所有 key 的下标
+    pub fn key_indexes(&self, argc: usize) -> Vec<usize> {
+        if self.first_key <= 0 || self.step <= 0 || argc <= self.first_key as usize {
+            return vec![];
+        }
+
+        let last = if self.last_key < 0 {
+            argc as i32 + self.last_key
+        } else {
+            self.last_key.min(argc as i32 - 1)
+        };
+
+        (self.first_key..=last)
+            .step_by(self.step as usize)
+            .map(|i| i as usize)
+            .collect()
+    }
+
+    /// COMMAND INFO 中一个命令的描述
+    pub fn info(&self) -> Frame {
+        let status = |name: &str| Frame::Simple(name.to_string());
+
+        Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(self.name.as_bytes())),
+            Frame::Integer(self.arity as i64),
+            Frame::Array(self.flags.names().map(status).collect()),
+            Frame::Integer(self.first_key as i64),
+            Frame::Integer(self.last_key as i64),
+            Frame::Integer(self.step as i64),
+            Frame::Array(
+                self.categories
+                    .names()
+                    .map(|name| status(&format!("@{}", name)))
+                    .collect(),
+            ),
+            // tips、key specs 以及子命令, rudis 目前没有
+            Frame::Array(vec![]),
+            Frame::Array(vec![]),
+            Frame::Array(vec![]),
+        ])
+    }
+
+    /// COMMAND DOCS 中一个命令的文档
+    pub fn docs(&self) -> Frame {
+        let bulk = |s: &'static str| Frame::Bulk(Bytes::from_static(s.as_bytes()));
+
+        Frame::Array(vec![
+            bulk("summary"),
+            bulk(self.summary),
+            bulk("group"),
+            bulk(self.group),
+        ])
+    }
+}
+
+impl fmt::Debug for CommandSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CommandSpec")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .field("flags", &self.flags)
+            .field("keys", &(self.first_key, self.last_key, self.step))
+            .finish()
+    }
+}
+
+/// 命令表, 命令名称不区分大小写
+///
+/// `Registry::default()` 包含了所有的内置命令
+#[derive(Clone)]
+pub struct Registry {
+    commands: HashMap<String, Arc<CommandSpec>>,
+}
+
+impl Registry {
+    /// 不包含任何命令的命令表, 一般使用包含了内置命令的 `Registry::default()`
+    pub fn empty() -> Registry {
+        Registry {
+            commands: HashMap::new(),
+        }
+    }
+
+    /// 注册一个命令, 命令已经存在时返回错误
+    ///
+    /// 与 redis 相同, 根据命令的标记自动补充隐含的 ACL 分类, 例如 write 标记对应 @write 分类
+    pub fn register(&mut self, mut spec: CommandSpec) -> Result<(), String> {
+        let name = spec.name.to_ascii_lowercase();
+        if self.commands.contains_key(&name) {
+            return Err(format!("command '{}' is already registered", name));
+        }
+
+        let implied = [
+            (Flags::WRITE, Categories::WRITE),
+            (Flags::READONLY, Categories::READ),
+            (Flags::ADMIN, Categories::ADMIN | Categories::DANGEROUS),
+            (Flags::PUBSUB, Categories::PUBSUB),
+            (Flags::FAST, Categories::FAST),
+            (Flags::BLOCKING, Categories::BLOCKING),
+        ];
+        for (flag, categories) in implied {
+            if spec.flags.contains(flag) {
+                spec.categories = spec.categories | categories;
+            }
+        }
+        if !spec.flags.contains(Flags::FAST) {
+            spec.categories = spec.categories | Categories::SLOW;
+        }
+
+        self.commands.insert(name, Arc::new(spec));
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&CommandSpec> {
+        self.commands.get(&name.to_ascii_lowercase()).map(|spec| &**spec)
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &CommandSpec> {
+        self.commands.values().map(|spec| &**spec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noop(_ctx: &mut Context<'_>, _args: &[Bytes]) -> CmdResult {
+        Ok(Frame::Null.into())
+    }
+
+    #[test]
+    fn key_positions() {
+        let get = CommandSpec::new("get", 2, noop).keys(1, 1, 1);
+        assert_eq!(vec![1], get.key_indexes(2));
+
+        let mset = CommandSpec::new("mset", -3, noop).keys(1, -1, 2);
+        assert_eq!(vec![1, 3, 5], mset.key_indexes(7));
+
+        let ping = CommandSpec::new("ping", -1, noop);
+        assert!(ping.key_indexes(2).is_empty());
+    }
+
+    #[test]
+    fn implied_categories() {
+        let mut registry = Registry::empty();
+        registry
+            .register(CommandSpec::new("SET", -3, noop).flags(Flags::WRITE | Flags::DENYOOM))
+            .unwrap();
+
+        let set = registry.get("set").unwrap();
+        assert!(set.categories.contains(Categories::WRITE | Categories::SLOW));
+        assert!(registry.register(CommandSpec::new("set", -3, noop)).is_err());
+    }
+}
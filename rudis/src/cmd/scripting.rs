@@ -0,0 +1,73 @@
+//! 脚本相关的命令, 脚本的编译与执行见 [`crate::script`]
+
+use bytes::Bytes;
+
+use super::{int, is, ok, string, CmdResult, Context, SYNTAX_ERR};
+use crate::{script::sha1hex, Frame};
+
+/// EVAL script numkeys [key [key ...]] [arg [arg ...]]
+pub fn eval(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let (keys, argv) = split_keys(&args[2], &args[3..])?;
+    let sha = sha1hex(&args[1]);
+
+    ctx.db.scripts().run(ctx, &sha, Some(&args[1]), keys, argv)
+}
+
+/// EVALSHA sha1 numkeys [key [key ...]] [arg [arg ...]]
+pub fn evalsha(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let (keys, argv) = split_keys(&args[2], &args[3..])?;
+    let sha = string(&args[1])?;
+
+    ctx.db.scripts().run(ctx, sha, None, keys, argv)
+}
+
+/// SCRIPT LOAD script | SCRIPT EXISTS sha1 [sha1 ...] | SCRIPT FLUSH [ASYNC|SYNC] | SCRIPT KILL
+pub fn script(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let scripts = ctx.db.scripts();
+
+    if is(&args[1], "load") && args.len() == 3 {
+        let sha = scripts.load(&args[2])?;
+        Ok(Frame::Bulk(Bytes::from(sha)).into())
+    } else if is(&args[1], "exists") && args.len() >= 3 {
+        let mut out = vec![];
+        for sha in &args[2..] {
+            out.push(Frame::Integer(scripts.exists(string(sha)?)? as i64));
+        }
+
+        Ok(Frame::Array(out).into())
+    } else if is(&args[1], "flush") && args.len() <= 3 {
+        // 缓存中只有编译好的函数, 同步清空就足够快了, ASYNC 与 SYNC 的行为相同
+        if let Some(mode) = args.get(2) {
+            if !is(mode, "async") && !is(mode, "sync") {
+                return Err(SYNTAX_ERR.to_string());
+            }
+        }
+
+        scripts.flush()?;
+        ok()
+    } else if is(&args[1], "kill") && args.len() == 2 {
+        scripts.kill()?;
+        ok()
+    } else {
+        Err(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'. Try SCRIPT HELP.",
+            String::from_utf8_lossy(&args[1])
+        ))
+    }
+}
+
+/// 按照 numkeys 将参数分为 KEYS 和 ARGV
+fn split_keys<'a>(
+    numkeys: &Bytes,
+    rest: &'a [Bytes],
+) -> Result<(&'a [Bytes], &'a [Bytes]), String> {
+    let numkeys = int(numkeys)?;
+    if numkeys < 0 {
+        return Err("ERR Number of keys can't be negative".to_string());
+    }
+    if numkeys as usize > rest.len() {
+        return Err("ERR Number of keys can't be greater than number of args".to_string());
+    }
+
+    Ok(rest.split_at(numkeys as usize))
+}
@@ -0,0 +1,794 @@
+//! 连接与服务端相关的命令
+
+use std::{cmp::Reverse, fmt::Write, process, thread, time::Duration};
+
+use bytes::Bytes;
+use log::error;
+
+use super::{db_index, help, int, is, key, ok, string, CmdResult, Context, SYNTAX_ERR};
+use crate::{
+    config::Policy,
+    db::{Db, ENTRY_OVERHEAD},
+    dump, script,
+    Frame,
+};
+
+pub fn ping(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    if args.len() > 2 {
+        return Err("ERR wrong number of arguments for 'ping' command".to_string());
+    }
+
+    // 订阅模式下 PING 的回复格式为 ["pong", message]
+    if ctx.session.subscriptions() > 0 {
+        return Ok(Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"pong")),
+            Frame::Bulk(args.get(1).cloned().unwrap_or_default()),
+        ])
+        .into());
+    }
+
+    match args.get(1) {
+        Some(message) => Ok(Frame::Bulk(message.clone()).into()),
+        None => Ok(Frame::Simple("PONG".to_string()).into()),
+    }
+}
+
+pub fn echo(_ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    Ok(Frame::Bulk(args[1].clone()).into())
+}
+
+/// CONFIG GET pattern [pattern ...] | CONFIG SET name value [name value ...]
+pub fn config(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let config = ctx.db.config();
+
+    if is(&args[1], "get") && args.len() >= 3 {
+        let mut out = vec![];
+        for pattern in &args[2..] {
+            for (name, value) in config.get(string(pattern)?) {
+                out.push(Frame::Bulk(Bytes::from(name)));
+                out.push(Frame::Bulk(Bytes::from(value)));
+            }
+        }
+
+        Ok(Frame::Array(out).into())
+    } else if is(&args[1], "set") && args.len() >= 4 && args.len().is_multiple_of(2) {
+        for pair in args[2..].chunks(2) {
+            config.set(string(&pair[0])?, string(&pair[1])?)?;
+        }
+
+        ok()
+    } else if is(&args[1], "get") || is(&args[1], "set") {
+        Err(format!(
+            "ERR wrong number of arguments for 'config|{}' command",
+            String::from_utf8_lossy(&args[1]).to_lowercase()
+        ))
+    } else {
+        Err(SYNTAX_ERR.to_string())
+    }
+}
+
+/// COMMAND [COUNT | INFO [name ...] | DOCS [name ...] | GETKEYS command [arg ...]]
+///
+/// 回复由命令表生成, 扩展命令也会出现在其中
+pub fn command(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let registry = ctx.registry;
+
+    // 未知的命令在 INFO 中返回 nil, 在 DOCS 中被忽略
+    let named = |names: &[Bytes]| -> Vec<Option<_>> {
+        names
+            .iter()
+            .map(|name| registry.get(&String::from_utf8_lossy(name)))
+            .collect()
+    };
+
+    let Some(sub) = args.get(1) else {
+        return Ok(Frame::Array(registry.iter().map(|spec| spec.info()).collect()).into());
+    };
+
+    if is(sub, "count") && args.len() == 2 {
+        Ok(Frame::Integer(registry.len() as i64).into())
+    } else if is(sub, "info") {
+        let infos = match args.len() {
+            2 => registry.iter().map(|spec| spec.info()).collect(),
+            _ => named(&args[2..])
+                .into_iter()
+                .map(|spec| spec.map_or(Frame::Null, |spec| spec.info()))
+                .collect(),
+        };
+
+        Ok(Frame::Array(infos).into())
+    } else if is(sub, "docs") {
+        let specs: Vec<_> = match args.len() {
+            2 => registry.iter().collect(),
+            _ => named(&args[2..]).into_iter().flatten().collect(),
+        };
+
+        let mut out = Vec::with_capacity(specs.len() * 2);
+        for spec in specs {
+            out.push(Frame::Bulk(Bytes::from_static(spec.name.as_bytes())));
+            out.push(spec.docs());
+        }
+
+        Ok(Frame::Array(out).into())
+    } else if is(sub, "getkeys") && args.len() >= 3 {
+        let spec = registry
+            .get(&String::from_utf8_lossy(&args[2]))
+            .ok_or("ERR Invalid command specified")?;
+        let argv = &args[2..];
+        if !spec.check_arity(argv.len()) {
+            return Err("ERR Invalid number of arguments specified for command".to_string());
+        }
+
+        let keys = spec.key_indexes(argv.len());
+        if keys.is_empty() {
+            return Err("ERR The command has no key arguments".to_string());
+        }
+
+        Ok(Frame::Array(keys.into_iter().map(|i| Frame::Bulk(argv[i].clone())).collect()).into())
+    } else {
+        Err(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'. Try COMMAND HELP.",
+            String::from_utf8_lossy(sub)
+        ))
+    }
+}
+
+pub fn select(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let index = db_index(ctx.db, &args[1])?;
+    ctx.session.select(index);
+
+    ok()
+}
+
+pub fn swapdb(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let a = db_index(ctx.db, &args[1])?;
+    let b = db_index(ctx.db, &args[2])?;
+    ctx.db.swap(a, b);
+
+    ok()
+}
+
+/// FLUSHDB [ASYNC | SYNC]
+///
+/// rudis 总是同步删除, ASYNC 只是为了与 redis 客户端兼容
+pub fn flushdb(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    flush_mode(args)?;
+    ctx.db.flush(ctx.session.db());
+    ctx.db.tracking().invalidate_all(ctx.db.pubsub());
+
+    ok()
+}
+
+pub fn flushall(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    flush_mode(args)?;
+    for db in 0..ctx.db.databases() {
+        ctx.db.flush(db);
+    }
+    ctx.db.tracking().invalidate_all(ctx.db.pubsub());
+
+    ok()
+}
+
+fn flush_mode(args: &[Bytes]) -> Result<(), String> {
+    match &args[1..] {
+        [] => Ok(()),
+        [mode] if is(mode, "async") || is(mode, "sync") => Ok(()),
+        _ => Err(SYNTAX_ERR.to_string()),
+    }
+}
+
+pub fn dbsize(ctx: &mut Context<'_>, _args: &[Bytes]) -> CmdResult {
+    let keys = ctx.db.stats(ctx.session.db()).keys;
+
+    Ok(Frame::Integer(keys as i64).into())
+}
+
+/// INFO [section ...]
+///
+/// 目前支持 server、memory、replication、keyspace 四个部分, 不指定时返回全部
+pub fn info(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    const SECTIONS: [&str; 4] = ["server", "memory", "replication", "keyspace"];
+
+    let all = args.len() == 1
+        || args[1..]
+            .iter()
+            .any(|arg| is(arg, "all") || is(arg, "default") || is(arg, "everything"));
+    let wanted = |section: &str| all || args[1..].iter().any(|arg| is(arg, section));
+
+    let mut out = String::new();
+    for section in SECTIONS.into_iter().filter(|section| wanted(section)) {
+        if !out.is_empty() {
+            out.push_str("\r\n");
+        }
+
+        // 写入 String 不会失败
+        let _ = match section {
+            "server" => info_server(&mut out),
+            "memory" => info_memory(ctx, &mut out),
+            "replication" => info_replication(ctx, &mut out),
+            _ => info_keyspace(ctx, &mut out),
+        };
+    }
+
+    Ok(Frame::Bulk(Bytes::from(out)).into())
+}
+
+fn info_server(out: &mut String) -> std::fmt::Result {
+    write!(out, "# Server\r\n")?;
+    write!(out, "rudis_version:{}\r\n", env!("CARGO_PKG_VERSION"))?;
+    write!(out, "process_id:{}\r\n", process::id())
+}
+
+fn info_memory(ctx: &Context<'_>, out: &mut String) -> std::fmt::Result {
+    let config = ctx.db.config();
+    let used = ctx.db.used_memory();
+
+    write!(out, "# Memory\r\n")?;
+    write!(out, "used_memory:{}\r\n", used)?;
+    write!(out, "used_memory_human:{}\r\n", human_bytes(used))?;
+    write!(out, "used_memory_peak:{}\r\n", ctx.db.peak_memory())?;
+    write!(out, "used_memory_peak_human:{}\r\n", human_bytes(ctx.db.peak_memory()))?;
+    write!(out, "maxmemory:{}\r\n", config.maxmemory())?;
+    write!(out, "maxmemory_human:{}\r\n", human_bytes(config.maxmemory()))?;
+    write!(out, "maxmemory_policy:{}\r\n", config.maxmemory_policy().name())
+}
+
+/// 每个非空的数据库一行, 例如 `db0:keys=1,expires=0,avg_ttl=0`
+fn info_keyspace(ctx: &Context<'_>, out: &mut String) -> std::fmt::Result {
+    write!(out, "# Keyspace\r\n")?;
+
+    for db in 0..ctx.db.databases() {
+        let stats = ctx.db.stats(db);
+        if stats.keys > 0 {
+            write!(
+                out,
+                "db{}:keys={},expires={},avg_ttl={}\r\n",
+                db, stats.keys, stats.expires, stats.avg_ttl
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// rudis 还没有实现主从复制, 总是没有副本的主节点
+fn info_replication(ctx: &Context<'_>, out: &mut String) -> std::fmt::Result {
+    write!(out, "# Replication\r\n")?;
+    write!(out, "role:master\r\n")?;
+    write!(out, "connected_slaves:0\r\n")?;
+    if ctx.db.config().min_replicas_to_write() > 0 {
+        write!(out, "min_slaves_good_slaves:0\r\n")?;
+    }
+    write!(out, "master_repl_offset:0\r\n")
+}
+
+/// MEMORY USAGE key [SAMPLES count] | STATS | DOCTOR | HELP
+///
+/// rudis 不统计分配器实际分配的内存, 所有的数值都来自 key 的内存估算值, 见 `Value::approx_size`
+pub fn memory(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let sub = &args[1];
+
+    if is(sub, "usage") && args.len() >= 3 {
+        memory_usage(ctx, &args[2..])
+    } else if is(sub, "stats") && args.len() == 2 {
+        Ok(memory_stats(ctx).into())
+    } else if is(sub, "doctor") && args.len() == 2 {
+        Ok(Frame::Bulk(Bytes::from(memory_doctor(ctx))).into())
+    } else if is(sub, "help") && args.len() == 2 {
+        help(&[
+            "MEMORY <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+            "DOCTOR",
+            "    Return memory problems reports.",
+            "STATS",
+            "    Return information about the memory usage of the server.",
+            "USAGE <key> [SAMPLES <count>]",
+            "    Return memory in bytes used by <key> and its value. Nested values are",
+            "    sampled up to <count> times (default: 5, 0 means sample all).",
+        ])
+    } else {
+        Err(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'. Try MEMORY HELP.",
+            String::from_utf8_lossy(sub)
+        ))
+    }
+}
+
+/// 与 redis 相同, 集合类型默认采样 5 个元素, SAMPLES 0 代表遍历所有的元素
+fn memory_usage(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[0])?;
+    let samples = match &args[1..] {
+        [] => 5,
+        [opt, count] if is(opt, "samples") => match int(count)? {
+            0 => usize::MAX,
+            n if n < 0 => return Err(SYNTAX_ERR.to_string()),
+            n => n as usize,
+        },
+        _ => return Err(SYNTAX_ERR.to_string()),
+    };
+
+    let mut guard = ctx.lock(&[key]);
+    let usage = guard
+        .peek(key)
+        .map(|entry| key.len() + entry.value().sampled_size(samples) + ENTRY_OVERHEAD);
+
+    Ok(usage.map_or(Frame::Null, |usage| Frame::Integer(usage as i64)).into())
+}
+
+/// 回复是 名称/值 交替的数组, 每个非空的数据库一项
+fn memory_stats(ctx: &Context<'_>) -> Frame {
+    let bulk = |s: &str| Frame::Bulk(Bytes::from(s.to_string()));
+    let int = |n: usize| Frame::Integer(n as i64);
+    let percentage = |part: usize, total: usize| {
+        let pct = if total == 0 { 0.0 } else { part as f64 * 100.0 / total as f64 };
+        bulk(&format!("{:.2}", pct))
+    };
+
+    let (used, peak) = (ctx.db.used_memory(), ctx.db.peak_memory());
+    let mut fields = vec![
+        (bulk("peak.allocated"), int(peak)),
+        (bulk("total.allocated"), int(used)),
+    ];
+
+    let mut keys = 0;
+    for index in 0..ctx.db.databases() {
+        let stats = ctx.db.stats(index);
+        if stats.keys == 0 {
+            continue;
+        }
+
+        keys += stats.keys;
+        let detail = vec![
+            bulk("keys"),
+            int(stats.keys),
+            bulk("expires"),
+            int(stats.expires),
+            bulk("overhead.hashtable.main"),
+            int(stats.keys * ENTRY_OVERHEAD),
+        ];
+        fields.push((bulk(&format!("db.{}", index)), Frame::Array(detail)));
+    }
+
+    let overhead = keys * ENTRY_OVERHEAD;
+    let dataset = used.saturating_sub(overhead);
+    fields.extend([
+        (bulk("overhead.total"), int(overhead)),
+        (bulk("keys.count"), int(keys)),
+        (bulk("keys.bytes-per-key"), int(used.checked_div(keys).unwrap_or(0))),
+        (bulk("dataset.bytes"), int(dataset)),
+        (bulk("dataset.percentage"), percentage(dataset, used)),
+        (bulk("peak.percentage"), percentage(used, peak)),
+    ]);
+
+    Frame::Array(fields.into_iter().flat_map(|(name, value)| [name, value]).collect())
+}
+
+/// 根据内存的使用情况给出建议, 没有发现问题时也会说明
+fn memory_doctor(ctx: &Context<'_>) -> String {
+    let config = ctx.db.config();
+    let (used, peak, maxmemory) = (ctx.db.used_memory(), ctx.db.peak_memory(), config.maxmemory());
+
+    let keys: usize = (0..ctx.db.databases()).map(|index| ctx.db.stats(index).keys).sum();
+    if keys == 0 {
+        return "This instance is empty, there is nothing to diagnose.".to_string();
+    }
+
+    let mut issues = vec![];
+    if peak > used / 2 * 3 {
+        issues.push(format!(
+            " * Peak memory: in the past this instance used {} which is more than 150% of the \
+             memory currently in use ({}). Large keys may have been deleted, or a burst of \
+             writes may have been followed by expiration or eviction.",
+            human_bytes(peak),
+            human_bytes(used)
+        ));
+    }
+    if maxmemory > 0 && used > maxmemory / 10 * 9 {
+        let consequence = if config.maxmemory_policy() == Policy::NoEviction {
+            "Write commands will be rejected with OOM errors once the limit is reached, \
+             consider an eviction policy or a higher maxmemory."
+        } else {
+            "Keys are being evicted, consider a higher maxmemory if this is not intended."
+        };
+        issues.push(format!(
+            " * High memory usage: {} of maxmemory {} is in use. {}",
+            human_bytes(used),
+            human_bytes(maxmemory),
+            consequence
+        ));
+    }
+    if used / keys > 1024 * 1024 {
+        issues.push(format!(
+            " * Big keys: keys use {} each on average. Run `rudis-cli --memkeys` to find them.",
+            human_bytes(used / keys)
+        ));
+    }
+
+    if issues.is_empty() {
+        return "I can't find any memory issue in this instance.".to_string();
+    }
+
+    let mut report = "I detected the following memory issues:\n\n".to_string();
+    report.push_str(&issues.join("\n\n"));
+    report.push('\n');
+    report
+}
+
+/// WAIT numreplicas timeout: 返回确认了当前连接之前所有写命令的副本个数
+///
+/// rudis 还没有实现主从复制, 没有可以等待的副本, 因此总是立即返回 0,
+/// 而不是像 redis 那样阻塞到超时. 调用者比较返回值与 numreplicas 就能知道写入没有被复制
+pub fn wait(_ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    if int(&args[1])? < 0 {
+        return Err("ERR value is out of range, must be positive".to_string());
+    }
+    if int(&args[2])? < 0 {
+        return Err("ERR timeout is negative".to_string());
+    }
+
+    Ok(Frame::Integer(0).into())
+}
+
+/// DEBUG SLEEP seconds | RELOAD | OBJECT key | SET-ACTIVE-EXPIRE 0|1 | JMAP | SEGFAULT | HELP
+///
+/// 用于在测试中让服务端变慢、重启或者崩溃, 只有启动时指定了 `--enable-debug-command yes` 才能执行.
+/// DEBUG 会独占整个数据集, 执行期间其他连接的命令都会等待, 与 redis 阻塞整个服务端的效果相同
+pub fn debug(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    if !ctx.db.config().debug_command_enabled() {
+        return Err("ERR DEBUG command not allowed. You need to start the server with \
+                    `--enable-debug-command yes` to use it."
+            .to_string());
+    }
+    let sub = &args[1];
+
+    if is(sub, "sleep") && args.len() == 3 {
+        // 负数、NaN 以及超出 Duration 范围的值都无法转换
+        let duration = match string(&args[2])?.parse::<f64>().map(Duration::try_from_secs_f64) {
+            Ok(Ok(duration)) => duration,
+            _ => return Err("ERR value is not a valid float".to_string()),
+        };
+        script::blocking(|| thread::sleep(duration));
+        ok()
+    } else if is(sub, "reload") && args.len() == 2 {
+        match ctx.db.reload() {
+            0 => ok(),
+            failed => Err(format!(
+                "ERR Error trying to load the RDB dump, {} keys could not be restored",
+                failed
+            )),
+        }
+    } else if is(sub, "object") && args.len() == 3 {
+        debug_object(ctx, &args[2])
+    } else if is(sub, "set-active-expire") && args.len() == 3 {
+        let enabled = match &args[2][..] {
+            b"0" => false,
+            b"1" => true,
+            _ => return Err(SYNTAX_ERR.to_string()),
+        };
+        ctx.db.set_active_expire(enabled);
+        ok()
+    } else if is(sub, "jmap") && args.len() == 2 {
+        Ok(Frame::Bulk(Bytes::from(jmap(ctx.db))).into())
+    } else if is(sub, "segfault") && args.len() == 2 {
+        // 与 redis 相同, 不做任何清理直接崩溃, 客户端会看到连接被重置
+        error!("crashing the server on DEBUG SEGFAULT");
+        process::abort()
+    } else if is(sub, "help") && args.len() == 2 {
+        help(&[
+            "DEBUG <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+            "JMAP",
+            "    Show the number of keys and the memory they use, grouped by type.",
+            "OBJECT <key>",
+            "    Show low level info about the key and associated value.",
+            "RELOAD",
+            "    Serialize every key and load it back, like saving and reloading a snapshot.",
+            "SEGFAULT",
+            "    Crash the server immediately.",
+            "SET-ACTIVE-EXPIRE <0|1>",
+            "    Setting it to 0 disables expiring keys in the background when they are not",
+            "    accessed (otherwise the Redis behavior). Setting it to 1 reenables back the",
+            "    default.",
+            "SLEEP <seconds>",
+            "    Stop the server for <seconds>. Decimals allowed.",
+        ])
+    } else {
+        Err(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'. Try DEBUG HELP.",
+            String::from_utf8_lossy(sub)
+        ))
+    }
+}
+
+/// 格式与 redis 相同, rudis 的 value 不会被共享, refcount 总是 1
+fn debug_object(ctx: &mut Context<'_>, key: &Bytes) -> CmdResult {
+    let key = super::key(key)?;
+    let mut guard = ctx.lock(&[key]);
+    let entry = guard.peek(key).ok_or("ERR no such key")?;
+
+    Ok(Frame::Simple(format!(
+        "Value at:{:p} refcount:1 encoding:{} serializedlength:{} lru_seconds_idle:{} lfu_freq:{}",
+        entry.value(),
+        entry.value().encoding(),
+        dump::dump(entry.value()).len(),
+        entry.idle().as_secs(),
+        entry.freq()
+    ))
+    .into())
+}
+
+/// 与 `jmap -histo` 类似, 按照类型统计所有数据库中 key 的个数与内存占用, 占用多的类型排在前面
+fn jmap(db: &Db) -> String {
+    let mut types: Vec<(&'static str, usize, usize)> = vec![];
+    db.for_each(|_, _, entry| {
+        let name = entry.value().type_name();
+        match types.iter_mut().find(|(type_name, _, _)| *type_name == name) {
+            Some((_, keys, bytes)) => {
+                *keys += 1;
+                *bytes += entry.size();
+            }
+            None => types.push((name, 1, entry.size())),
+        }
+    });
+    types.sort_by_key(|(_, _, bytes)| Reverse(*bytes));
+
+    let mut out = format!("{:>5} {:>12} {:>14}  type\n", "num", "#keys", "#bytes");
+    for (i, (name, keys, bytes)) in types.iter().enumerate() {
+        let _ = writeln!(out, "{:>5} {:>12} {:>14}  {}", format!("{}:", i + 1), keys, bytes, name);
+    }
+    let (keys, bytes) = types.iter().fold((0, 0), |(k, b), (_, keys, bytes)| (k + keys, b + bytes));
+    let _ = writeln!(out, "{:<5} {:>12} {:>14}", "Total", keys, bytes);
+
+    out
+}
+
+/// 与 redis 相同的内存格式, 例如 `1.50M`
+fn human_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+
+    if bytes < 1024 {
+        return format!("{}B", bytes);
+    }
+
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.2}{}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{server, Connection, Frame};
+
+    fn bulk(val: &str) -> Frame {
+        Frame::Bulk(val.to_string().into())
+    }
+
+    fn ok() -> Frame {
+        Frame::Simple("OK".to_string())
+    }
+
+    fn is_err(frame: &Frame, msg: &str) -> bool {
+        matches!(frame, Frame::Error(err) if err.contains(msg))
+    }
+
+    #[tokio::test]
+    async fn databases() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+
+        // 默认 16 个数据库, 下标从 0 开始
+        for index in ["-1", "16", "x"] {
+            let reply = conn.request(["SELECT", index]).await.unwrap();
+            assert!(matches!(reply, Frame::Error(_)), "SELECT {}", index);
+        }
+        assert_eq!(ok(), conn.request(["SELECT", "15"]).await.unwrap());
+        assert_eq!(ok(), conn.request(["SELECT", "0"]).await.unwrap());
+
+        // MOVE 会保留过期时间, 目标数据库存在同名 key 时不移动
+        conn.request(["SET", "foo", "bar", "EX", "100"]).await.unwrap();
+        conn.request(["SET", "dup", "0"]).await.unwrap();
+        assert_eq!(Frame::Integer(1), conn.request(["MOVE", "foo", "1"]).await.unwrap());
+        assert_eq!(Frame::Integer(0), conn.request(["MOVE", "missing", "1"]).await.unwrap());
+        let reply = conn.request(["MOVE", "dup", "0"]).await.unwrap();
+        assert!(is_err(&reply, "same"));
+        assert!(is_err(&conn.request(["MOVE", "dup", "16"]).await.unwrap(), "out of range"));
+
+        conn.request(["SELECT", "1"]).await.unwrap();
+        conn.request(["SET", "dup", "1"]).await.unwrap();
+        assert_eq!(Frame::Integer(0), conn.request(["MOVE", "dup", "0"]).await.unwrap());
+        assert_eq!(bulk("bar"), conn.request(["GET", "foo"]).await.unwrap());
+        assert!(matches!(conn.request(["TTL", "foo"]).await.unwrap(), Frame::Integer(90..=100)));
+
+        // SWAPDB 对所有连接可见
+        let mut other = server.connect().await.unwrap();
+        assert_eq!(ok(), other.request(["SWAPDB", "0", "1"]).await.unwrap());
+        assert_eq!(bulk("0"), conn.request(["GET", "dup"]).await.unwrap());
+        assert_eq!(bulk("bar"), other.request(["GET", "foo"]).await.unwrap());
+        assert!(matches!(other.request(["SWAPDB", "0", "16"]).await.unwrap(), Frame::Error(_)));
+
+        let info = |frame: Frame| match frame {
+            Frame::Bulk(info) => String::from_utf8(info.to_vec()).unwrap(),
+            frame => panic!("unexpected frame: {}", frame),
+        };
+        // 每个非空的数据库一行, avg_ttl 的单位为毫秒
+        let keyspace = info(conn.request(["INFO", "keyspace"]).await.unwrap());
+        let lines: Vec<&str> = keyspace.lines().collect();
+        assert_eq!(3, lines.len(), "{}", keyspace);
+        let avg_ttl = lines[1].strip_prefix("db0:keys=2,expires=1,avg_ttl=").unwrap();
+        assert!((90_000..=100_000).contains(&avg_ttl.parse::<u64>().unwrap()));
+        assert_eq!("db1:keys=1,expires=0,avg_ttl=0", lines[2]);
+
+        // FLUSHDB 只清空当前数据库, FLUSHALL 清空所有数据库
+        assert_eq!(ok(), conn.request(["FLUSHDB"]).await.unwrap());
+        assert_eq!(Frame::Integer(0), conn.request(["DBSIZE"]).await.unwrap());
+        assert_eq!(Frame::Integer(2), other.request(["DBSIZE"]).await.unwrap());
+        assert!(matches!(conn.request(["FLUSHDB", "LAZY"]).await.unwrap(), Frame::Error(_)));
+
+        assert_eq!(ok(), conn.request(["FLUSHALL", "ASYNC"]).await.unwrap());
+        assert_eq!(Frame::Integer(0), other.request(["DBSIZE"]).await.unwrap());
+        let keyspace = info(conn.request(["INFO", "keyspace"]).await.unwrap());
+        assert_eq!("# Keyspace\r\n", keyspace);
+    }
+
+    #[tokio::test]
+    async fn database_argument_errors() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+
+        for (args, msg) in [
+            (&["SELECT"][..], "wrong number of arguments"),
+            (&["SELECT", "0", "1"], "wrong number of arguments"),
+            (&["SELECT", "x"], "not an integer"),
+            (&["SELECT", "16"], "DB index is out of range"),
+            (&["MOVE", "foo"], "wrong number of arguments"),
+            (&["MOVE", "foo", "x"], "not an integer"),
+            (&["MOVE", "foo", "-1"], "DB index is out of range"),
+            (&["SWAPDB", "0"], "wrong number of arguments"),
+            (&["SWAPDB", "x", "1"], "not an integer"),
+            (&["SWAPDB", "-1", "1"], "DB index is out of range"),
+            (&["FLUSHDB", "ASYNC", "SYNC"], "syntax error"),
+            (&["FLUSHALL", "LAZY"], "syntax error"),
+            (&["DBSIZE", "x"], "wrong number of arguments"),
+            (&["CONFIG", "SET", "databases", "4"], "immutable"),
+        ] {
+            let reply = conn.request(args.to_vec()).await.unwrap();
+            assert!(is_err(&reply, msg), "{:?}: {}", args, reply);
+        }
+
+        // 出错时不会切换数据库, 也不会移动 key
+        conn.request(["SET", "foo", "bar"]).await.unwrap();
+        conn.request(["SELECT", "16"]).await.unwrap();
+        conn.request(["MOVE", "foo", "16"]).await.unwrap();
+        assert_eq!(bulk("bar"), conn.request(["GET", "foo"]).await.unwrap());
+        let reply = conn.request(["CONFIG", "GET", "databases"]).await.unwrap();
+        assert_eq!(Frame::Array(vec![bulk("databases"), bulk("16")]), reply);
+    }
+
+    #[tokio::test]
+    async fn command_replies() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+        let status = |s: &str| Frame::Simple(s.to_string());
+
+        // COUNT 与完整的命令表一致
+        let Frame::Array(all) = conn.request(["COMMAND"]).await.unwrap() else {
+            panic!("COMMAND should reply with an array");
+        };
+        let count = conn.request(["COMMAND", "COUNT"]).await.unwrap();
+        assert_eq!(Frame::Integer(all.len() as i64), count);
+
+        let reply = conn.request(["COMMAND", "INFO", "GET", "nope"]).await.unwrap();
+        let Frame::Array(infos) = reply else { panic!("{}", reply) };
+        assert_eq!(Frame::Null, infos[1]);
+        let Frame::Array(get) = &infos[0] else { panic!("{}", infos[0]) };
+        assert_eq!(bulk("get"), get[0]);
+        assert_eq!(Frame::Integer(2), get[1]);
+        assert_eq!(Frame::Array(vec![status("readonly"), status("fast")]), get[2]);
+        assert_eq!([1, 1, 1].map(Frame::Integer), get[3..6]);
+        let Frame::Array(categories) = &get[6] else { panic!("{}", get[6]) };
+        for category in ["@read", "@string", "@fast"] {
+            assert!(categories.contains(&status(category)), "{:?}", categories);
+        }
+
+        // 未知的命令在 DOCS 中被忽略
+        let reply = conn.request(["COMMAND", "DOCS", "get", "nope"]).await.unwrap();
+        let docs = Frame::Array(vec![
+            bulk("summary"),
+            bulk("Returns the string value of a key."),
+            bulk("group"),
+            bulk("string"),
+        ]);
+        assert_eq!(Frame::Array(vec![bulk("get"), docs]), reply);
+
+        let reply = conn.request(["COMMAND", "GETKEYS", "MSET", "a", "1", "b", "2"]).await;
+        assert_eq!(Frame::Array(vec![bulk("a"), bulk("b")]), reply.unwrap());
+
+        for (args, msg) in [
+            (&["COMMAND", "GETKEYS", "nope"][..], "Invalid command specified"),
+            (&["COMMAND", "GETKEYS", "GET"], "Invalid number of arguments"),
+            (&["COMMAND", "GETKEYS", "PING"], "no key arguments"),
+            (&["COMMAND", "GETKEYS"], "unknown subcommand"),
+            (&["COMMAND", "COUNT", "x"], "unknown subcommand"),
+            (&["COMMAND", "NOPE"], "unknown subcommand"),
+        ] {
+            let reply = conn.request(args.to_vec()).await.unwrap();
+            assert!(is_err(&reply, msg), "{:?}: {}", args, reply);
+        }
+    }
+
+    #[tokio::test]
+    async fn memory_usage() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+        async fn usage(conn: &mut Connection, args: &[&str]) -> i64 {
+            let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+            match conn.request(args).await.unwrap() {
+                Frame::Integer(usage) => usage,
+                reply => panic!("unexpected reply {}", reply),
+            }
+        }
+
+        // 除了 value 之外, 还包括 key 本身与固定的开销
+        conn.request(["SET", "a", "x"]).await.unwrap();
+        conn.request(["SET".to_string(), "b".into(), "x".repeat(101)]).await.unwrap();
+        let small = usage(&mut conn, &["MEMORY", "USAGE", "a"]).await;
+        assert!(small > 2);
+        assert_eq!(small + 100, usage(&mut conn, &["MEMORY", "USAGE", "b"]).await);
+
+        // quicklist 编码的列表按照采样的元素估算, SAMPLES 0 代表遍历所有的元素
+        let reply = conn.request(["CONFIG", "SET", "list-max-listpack-size", "1"]).await.unwrap();
+        assert_eq!(ok(), reply);
+        conn.request(["RPUSH", "list", "a"]).await.unwrap();
+        conn.request(["RPUSH".to_string(), "list".into(), "b".repeat(1000)]).await.unwrap();
+        let sampled = usage(&mut conn, &["MEMORY", "USAGE", "list", "SAMPLES", "1"]).await;
+        let all = usage(&mut conn, &["MEMORY", "USAGE", "list", "SAMPLES", "0"]).await;
+        assert!(all > sampled + 900, "{} {}", all, sampled);
+        assert_eq!(sampled, usage(&mut conn, &["MEMORY", "USAGE", "list", "SAMPLES", "1"]).await);
+
+        let reply = conn.request(["MEMORY", "USAGE", "missing"]).await.unwrap();
+        assert_eq!(Frame::Null, reply);
+        let reply = conn.request(["MEMORY", "USAGE", "a", "SAMPLES", "-1"]).await.unwrap();
+        assert!(is_err(&reply, "syntax"));
+        let reply = conn.request(["MEMORY", "USAGE", "a", "COUNT", "1"]).await.unwrap();
+        assert!(is_err(&reply, "syntax"));
+    }
+
+    #[tokio::test]
+    async fn wait_and_noreplicas() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+
+        // 没有副本, WAIT 立即返回 0, 即使 timeout 为 0(redis 中代表一直等待)
+        conn.request(["SET", "foo", "bar"]).await.unwrap();
+        assert_eq!(Frame::Integer(0), conn.request(["WAIT", "1", "0"]).await.unwrap());
+        assert_eq!(Frame::Integer(0), conn.request(["WAIT", "0", "100"]).await.unwrap());
+        assert!(is_err(&conn.request(["WAIT", "-1", "0"]).await.unwrap(), "out of range"));
+        assert!(is_err(&conn.request(["WAIT", "1", "-1"]).await.unwrap(), "negative"));
+
+        // 设置 min-replicas-to-write 之后写命令都会被拒绝, 读命令不受影响
+        let reply = conn.request(["CONFIG", "SET", "min-replicas-to-write", "1"]).await.unwrap();
+        assert_eq!(ok(), reply);
+        for reply in [
+            conn.request(["SET", "foo", "baz"]).await.unwrap(),
+            conn.request(["DEL", "foo"]).await.unwrap(),
+            conn.request(["RPUSH", "list", "a"]).await.unwrap(),
+        ] {
+            assert!(is_err(&reply, "NOREPLICAS"), "{}", reply);
+        }
+        assert_eq!(bulk("bar"), conn.request(["GET", "foo"]).await.unwrap());
+
+        // 脚本中的写命令同样被拒绝, 只读的脚本可以执行
+        let script = "return redis.call('SET', KEYS[1], 'baz')";
+        let reply = conn.request(["EVAL", script, "1", "foo"]).await.unwrap();
+        assert!(is_err(&reply, "NOREPLICAS"), "{}", reply);
+        let script = "return redis.call('GET', KEYS[1])";
+        assert_eq!(bulk("bar"), conn.request(["EVAL", script, "1", "foo"]).await.unwrap());
+
+        let reply = conn.request(["CONFIG", "SET", "min-replicas-to-write", "0"]).await.unwrap();
+        assert_eq!(ok(), reply);
+        assert_eq!(ok(), conn.request(["SET", "foo", "baz"]).await.unwrap());
+    }
+}
@@ -0,0 +1,96 @@
+//! 集合命令
+
+use bytes::Bytes;
+
+use super::{key, CmdResult, Context, WRONGTYPE_ERR};
+use crate::{db::Value, notify::Class, set::Set, Frame};
+
+/// SADD key member [member ...], 返回新增的成员个数
+pub fn sadd(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let max_intset = ctx.db.config().set_max_intset_entries();
+    let mut guard = ctx.lock(&[key]);
+
+    let add_all = |set: &mut Set| {
+        args[2..]
+            .iter()
+            .filter(|member| set.insert((*member).clone(), max_intset))
+            .count()
+    };
+
+    let added = match guard.update(key, |value| match value {
+        Value::Set(set) => Ok(add_all(set)),
+        _ => Err(WRONGTYPE_ERR.to_string()),
+    }) {
+        Some(added) => added?,
+        None => {
+            let mut set = Set::new();
+            let added = add_all(&mut set);
+            guard.insert(key, Value::Set(set), None);
+
+            added
+        }
+    };
+    if added > 0 {
+        guard.notify(Class::Set, "sadd", key);
+    }
+
+    Ok(Frame::Integer(added as i64).into())
+}
+
+/// SREM key member [member ...], 返回删除的成员个数
+pub fn srem(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let mut guard = ctx.lock(&[key]);
+
+    let removed = guard.update(key, |value| match value {
+        Value::Set(set) => Ok(args[2..].iter().filter(|member| set.remove(member)).count()),
+        _ => Err(WRONGTYPE_ERR.to_string()),
+    });
+    let removed = match removed {
+        Some(removed) => removed?,
+        None => 0,
+    };
+
+    if removed > 0 {
+        guard.notify(Class::Set, "srem", key);
+        if !guard.exists(key) {
+            guard.notify(Class::Generic, "del", key);
+        }
+    }
+
+    Ok(Frame::Integer(removed as i64).into())
+}
+
+pub fn sismember(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let mut guard = ctx.lock(&[key]);
+
+    match guard.get(key) {
+        Some(Value::Set(set)) => Ok(Frame::Integer(set.contains(&args[2]) as i64).into()),
+        Some(_) => Err(WRONGTYPE_ERR.to_string()),
+        None => Ok(Frame::Integer(0).into()),
+    }
+}
+
+pub fn scard(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let mut guard = ctx.lock(&[key]);
+
+    match guard.get(key) {
+        Some(Value::Set(set)) => Ok(Frame::Integer(set.len() as i64).into()),
+        Some(_) => Err(WRONGTYPE_ERR.to_string()),
+        None => Ok(Frame::Integer(0).into()),
+    }
+}
+
+pub fn smembers(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let mut guard = ctx.lock(&[key]);
+
+    match guard.get(key) {
+        Some(Value::Set(set)) => Ok(Frame::Array(set.iter().map(Frame::Bulk).collect()).into()),
+        Some(_) => Err(WRONGTYPE_ERR.to_string()),
+        None => Ok(Frame::Array(vec![]).into()),
+    }
+}
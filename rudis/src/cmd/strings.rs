@@ -0,0 +1,275 @@
+//! 字符串命令
+
+use std::mem;
+
+use bytes::{Bytes, BytesMut};
+
+use super::{expire_after, int, is, key, ok, CmdResult, Context, SYNTAX_ERR, WRONGTYPE_ERR};
+use crate::{
+    db::{Guard, Value},
+    notify::Class,
+    Frame,
+};
+
+pub fn get(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let mut guard = ctx.lock(&[key]);
+
+    match guard.get(key) {
+        Some(Value::String(val)) => Ok(Frame::Bulk(val.clone()).into()),
+        Some(_) => Err(WRONGTYPE_ERR.to_string()),
+        None => Ok(Frame::Null.into()),
+    }
+}
+
+const EXPIRE_ERR: &str = "ERR invalid expire time in 'set' command";
+
+/// SET key value [NX | XX] [GET] [EX seconds | PX milliseconds | KEEPTTL]
+pub fn set(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let value = args[2].clone();
+
+    let mut nx = false;
+    let mut xx = false;
+    let mut get = false;
+    let mut keep_ttl = false;
+    let mut expire: Option<i64> = None;
+
+    let mut options = args[3..].iter();
+    while let Some(option) = options.next() {
+        if is(option, "nx") && !xx {
+            nx = true;
+        } else if is(option, "xx") && !nx {
+            xx = true;
+        } else if is(option, "get") {
+            get = true;
+        } else if is(option, "keepttl") && expire.is_none() {
+            keep_ttl = true;
+        } else if (is(option, "ex") || is(option, "px")) && expire.is_none() && !keep_ttl {
+            let amount = int(options.next().ok_or(SYNTAX_ERR)?)?;
+            if amount <= 0 {
+                return Err(EXPIRE_ERR.to_string());
+            }
+
+            // 统一换算为毫秒, 溢出时与换算成 Instant 时溢出一样都是无效的过期时间
+            expire = Some(match is(option, "ex") {
+                true => amount.checked_mul(1000).ok_or(EXPIRE_ERR)?,
+                false => amount,
+            });
+        } else {
+            return Err(SYNTAX_ERR.to_string());
+        }
+    }
+
+    let mut guard = ctx.lock(&[key]);
+
+    let old = match guard.get(key) {
+        Some(Value::String(val)) => Some(val.clone()),
+        Some(_) if get => return Err(WRONGTYPE_ERR.to_string()),
+        Some(_) => None,
+        None => None,
+    };
+    let exists = old.is_some() || guard.exists(key);
+    let reply = |old: Option<Bytes>| -> CmdResult {
+        match (get, old) {
+            (true, Some(old)) => Ok(Frame::Bulk(old).into()),
+            (true, None) => Ok(Frame::Null.into()),
+            (false, _) => ok(),
+        }
+    };
+
+    if (nx && exists) || (xx && !exists) {
+        return match get {
+            true => reply(old),
+            false => Ok(Frame::Null.into()),
+        };
+    }
+
+    let expires_at = match (expire, keep_ttl) {
+        (Some(expire), _) => Some(expire_after(expire).ok_or(EXPIRE_ERR)?),
+        (None, true) => guard.expires_at(key).flatten(),
+        (None, false) => None,
+    };
+
+    guard.insert(key, Value::String(value), expires_at);
+    guard.notify(Class::String, "set", key);
+    if expire.is_some() {
+        guard.notify(Class::Generic, "expire", key);
+    }
+
+    reply(old)
+}
+
+pub fn mget(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let keys = args[1..].iter().map(key).collect::<Result<Vec<_>, _>>()?;
+    let mut guard = ctx.lock(&keys);
+
+    let values = keys
+        .into_iter()
+        .map(|key| match guard.get(key) {
+            Some(Value::String(val)) => Frame::Bulk(val.clone()),
+            _ => Frame::Null,
+        })
+        .collect();
+
+    Ok(Frame::Array(values).into())
+}
+
+pub fn mset(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    if args.len().is_multiple_of(2) {
+        return Err("ERR wrong number of arguments for 'mset' command".to_string());
+    }
+
+    let pairs = args[1..]
+        .chunks(2)
+        .map(|pair| Ok((key(&pair[0])?, pair[1].clone())))
+        .collect::<Result<Vec<_>, String>>()?;
+    let keys: Vec<&str> = pairs.iter().map(|(key, _)| *key).collect();
+
+    // 同时锁定所有 key 所在的分片, 保证 MSET 是原子的
+    let mut guard = ctx.lock(&keys);
+    for (key, value) in pairs {
+        guard.insert(key, Value::String(value), None);
+        guard.notify(Class::String, "set", key);
+    }
+
+    ok()
+}
+
+pub fn append(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let mut guard = ctx.lock(&[key]);
+
+    let len = match guard.update(key, |value| match value {
+        Value::String(val) => {
+            let mut buf = BytesMut::with_capacity(val.len() + args[2].len());
+            buf.extend_from_slice(val);
+            buf.extend_from_slice(&args[2]);
+            *val = buf.freeze();
+
+            Ok(val.len())
+        }
+        _ => Err(WRONGTYPE_ERR.to_string()),
+    }) {
+        Some(len) => len?,
+        None => {
+            guard.insert(key, Value::String(args[2].clone()), None);
+            args[2].len()
+        }
+    };
+    guard.notify(Class::String, "append", key);
+
+    Ok(Frame::Integer(len as i64).into())
+}
+
+pub fn strlen(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let mut guard = ctx.lock(&[key]);
+
+    match guard.get(key) {
+        Some(Value::String(val)) => Ok(Frame::Integer(val.len() as i64).into()),
+        Some(_) => Err(WRONGTYPE_ERR.to_string()),
+        None => Ok(Frame::Integer(0).into()),
+    }
+}
+
+pub fn incr(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    incr_by(ctx, key(&args[1])?, 1)
+}
+
+pub fn decr(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    incr_by(ctx, key(&args[1])?, -1)
+}
+
+pub fn incrby(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    incr_by(ctx, key(&args[1])?, int(&args[2])?)
+}
+
+pub fn decrby(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let delta = int(&args[2])?
+        .checked_neg()
+        .ok_or("ERR decrement would overflow")?;
+
+    incr_by(ctx, key(&args[1])?, delta)
+}
+
+/// 原地修改字符串, key 不存在时先创建一个空字符串, 用于 SETBIT、PFADD 等命令
+///
+/// `f` 需要在修改之前完成参数的检查, 返回错误时新创建的 key 不会被保留
+pub(crate) fn update_string<R>(
+    guard: &mut Guard<'_>,
+    key: &str,
+    f: impl FnOnce(&mut Vec<u8>) -> Result<R, String>,
+) -> Result<R, String> {
+    if !guard.exists(key) {
+        let mut buf = vec![];
+        let res = f(&mut buf)?;
+        guard.insert(key, Value::String(Bytes::from(buf)), None);
+
+        return Ok(res);
+    }
+
+    guard
+        .update(key, |value| match value {
+            Value::String(val) => {
+                // Bytes 没有被共享时, 转换为 Vec 不需要拷贝
+                let mut buf = Vec::from(mem::take(val));
+                let res = f(&mut buf);
+                *val = Bytes::from(buf);
+
+                res
+            }
+            _ => Err(WRONGTYPE_ERR.to_string()),
+        })
+        .unwrap()
+}
+
+fn incr_by(ctx: &mut Context<'_>, key: &str, delta: i64) -> CmdResult {
+    let mut guard = ctx.lock(&[key]);
+
+    let current = match guard.get(key) {
+        Some(Value::String(val)) => int(val)?,
+        Some(_) => return Err(WRONGTYPE_ERR.to_string()),
+        None => 0,
+    };
+    let next = current
+        .checked_add(delta)
+        .ok_or("ERR increment or decrement would overflow")?;
+
+    let value = Value::String(Bytes::from(next.to_string()));
+    if guard.update(key, |old| *old = value.clone()).is_none() {
+        guard.insert(key, value, None);
+    }
+    guard.notify(Class::String, "incrby", key);
+
+    Ok(Frame::Integer(next).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{server, Frame};
+
+    #[tokio::test]
+    async fn set_expire_overflow() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+
+        // 过大的过期时间返回错误, 不能让持有分片锁的命令 panic
+        for unit in ["EX", "PX"] {
+            let reply = conn.request(["SET", "a", "1", unit, "9223372036854775807"]).await.unwrap();
+            assert!(matches!(reply, Frame::Error(err) if err.contains("invalid expire time")));
+        }
+        let reply = conn.request(["SET", "a", "1", "EX", "0"]).await.unwrap();
+        assert!(matches!(reply, Frame::Error(err) if err.contains("invalid expire time")));
+
+        // 同一个分片上的其他命令不受影响
+        assert_eq!(Frame::Integer(0), conn.request(["EXISTS", "a"]).await.unwrap());
+        for key in ["a", "b", "d", "e", "f"] {
+            let reply = conn.request(["SET", key, "1", "EX", "100"]).await.unwrap();
+            assert_eq!(Frame::Simple("OK".to_string()), reply);
+        }
+        let reply = conn.request(["EXPIRE", "a", "9223372036854775807"]).await.unwrap();
+        assert!(matches!(reply, Frame::Error(err) if err.contains("in 'expire' command")));
+        assert_eq!(Frame::Integer(100), conn.request(["TTL", "a"]).await.unwrap());
+    }
+}
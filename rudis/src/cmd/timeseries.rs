@@ -0,0 +1,486 @@
+//! 时间序列命令, 实现见 [`crate::timeseries`]
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+
+use super::{int, is, key, ok, string, zsets::score, CmdResult, Context, SYNTAX_ERR, WRONGTYPE_ERR};
+use crate::{
+    db::{Entry, Guard, Value},
+    notify::Class,
+    timeseries::{self, Aggregation, DuplicatePolicy, Options, Rule, TimeSeries},
+    Frame,
+};
+
+const MISSING_ERR: &str = "ERR TSDB: the key does not exist";
+
+/// TS.CREATE key [RETENTION ms] [CHUNK_SIZE size] [DUPLICATE_POLICY policy] [LABELS label value ...]
+pub fn create(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let (options, _) = parse_options(&args[2..], false)?;
+
+    let mut guard = ctx.lock(&[key]);
+    if guard.exists(key) {
+        return Err("ERR TSDB: key already exists".to_string());
+    }
+    guard.insert(key, Value::TimeSeries(TimeSeries::new(options)), None);
+    guard.notify(Class::Module, "ts.create", key);
+
+    ok()
+}
+
+/// TS.ADD key timestamp value [RETENTION ms] [CHUNK_SIZE size] [ON_DUPLICATE policy] [LABELS ...]
+///
+/// key 不存在时使用指定的选项创建, 时间戳为 `*` 时使用服务端的当前时间
+pub fn add(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let (timestamp, val) = (timestamp(&args[2])?, value(&args[3])?);
+    let (options, on_duplicate) = parse_options(&args[4..], true)?;
+
+    let mut guard = lock_with_rules(ctx, &[key]);
+    if !guard.exists(key) {
+        guard.insert(key, Value::TimeSeries(TimeSeries::new(options)), None);
+        guard.notify(Class::Module, "ts.create", key);
+    }
+    add_sample(&mut guard, key, timestamp, val, on_duplicate)?;
+
+    Ok(Frame::Integer(timestamp).into())
+}
+
+/// TS.MADD key timestamp value [key timestamp value ...]
+///
+/// 每个样本单独返回写入的时间戳或者错误, key 必须已经存在
+pub fn madd(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    if !(args.len() - 1).is_multiple_of(3) {
+        return Err("ERR wrong number of arguments for 'ts.madd' command".to_string());
+    }
+    let keys = args[1..]
+        .iter()
+        .step_by(3)
+        .map(key)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut guard = lock_with_rules(ctx, &keys);
+    let out = args[1..]
+        .chunks(3)
+        .zip(keys.iter())
+        .map(|(sample, key)| {
+            let (timestamp, val) = (timestamp(&sample[1])?, value(&sample[2])?);
+            add_sample(&mut guard, key, timestamp, val, None)?;
+            Ok(timestamp)
+        })
+        .map(|res: Result<i64, String>| res.map_or_else(Frame::Error, Frame::Integer))
+        .collect();
+
+    Ok(Frame::Array(out).into())
+}
+
+/// TS.RANGE key from to [COUNT count] [AGGREGATION aggregator bucket]
+///
+/// from 与 to 可以是 `-` 和 `+`, 分别代表最早与最晚的样本
+pub fn range(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let from = match &args[2][..] {
+        b"-" => 0,
+        _ => timestamp(&args[2])?,
+    };
+    let to = match &args[3][..] {
+        b"+" => i64::MAX,
+        _ => timestamp(&args[3])?,
+    };
+
+    let (mut count, mut aggregation) = (None, None);
+    let mut rest = args[4..].iter();
+    while let Some(arg) = rest.next() {
+        if is(arg, "count") {
+            match rest.next().map(int) {
+                Some(Ok(n)) if n > 0 => count = Some(n as usize),
+                _ => return Err("ERR TSDB: Invalid COUNT value".to_string()),
+            }
+        } else if is(arg, "aggregation") {
+            let (Some(name), Some(bucket)) = (rest.next(), rest.next()) else {
+                return Err(SYNTAX_ERR.to_string());
+            };
+            aggregation = Some((parse_aggregation(name)?, parse_bucket(bucket)?));
+        } else {
+            return Err(SYNTAX_ERR.to_string());
+        }
+    }
+
+    let mut guard = ctx.lock(&[key]);
+    let samples = match guard.get(key) {
+        Some(Value::TimeSeries(series)) => series.range(from, to),
+        Some(_) => return Err(WRONGTYPE_ERR.to_string()),
+        None => return Err(MISSING_ERR.to_string()),
+    };
+    let samples = match aggregation {
+        Some((aggregation, bucket)) => timeseries::aggregate(&samples, aggregation, bucket),
+        None => samples,
+    };
+
+    let out = samples
+        .into_iter()
+        .take(count.unwrap_or(usize::MAX))
+        .map(|(ts, val)| Frame::Array(vec![Frame::Integer(ts), Frame::Simple(val.to_string())]))
+        .collect();
+
+    Ok(Frame::Array(out).into())
+}
+
+/// TS.CREATERULE source dest AGGREGATION aggregator bucket
+///
+/// 与 RedisTimeSeries 相同, 规则只对之后写入的样本生效. 为了简化加锁, 规则不能级联:
+/// 降采样的目标不能再作为其他规则的源
+pub fn createrule(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let (source, dest) = (key(&args[1])?, key(&args[2])?);
+    if !is(&args[3], "aggregation") {
+        return Err(SYNTAX_ERR.to_string());
+    }
+    let (aggregation, bucket) = (parse_aggregation(&args[4])?, parse_bucket(&args[5])?);
+    if source == dest {
+        return Err("ERR TSDB: the source key and destination key should be different".to_string());
+    }
+
+    let mut guard = ctx.lock(&[source, dest]);
+    let chained = series(&mut guard, source)?.source().is_some();
+    let dst = series(&mut guard, dest)?;
+    if dst.source().is_some() {
+        return Err("ERR TSDB: the destination key already has a src rule".to_string());
+    }
+    if chained || !dst.rules().is_empty() {
+        return Err("ERR TSDB: compaction rules can not be chained".to_string());
+    }
+
+    guard.update(dest, |value| {
+        if let Value::TimeSeries(series) = value {
+            series.set_source(Some(source.to_string()));
+        }
+    });
+    guard.update(source, |value| {
+        if let Value::TimeSeries(series) = value {
+            series.add_rule(Rule::new(dest.to_string(), aggregation, bucket));
+        }
+    });
+    guard.notify(Class::Module, "ts.createrule:src", source);
+    guard.notify(Class::Module, "ts.createrule:dest", dest);
+
+    ok()
+}
+
+/// TS.DELETERULE source dest
+pub fn deleterule(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let (source, dest) = (key(&args[1])?, key(&args[2])?);
+
+    let mut guard = ctx.lock(&[source, dest]);
+    series(&mut guard, source)?;
+    let removed = guard.update(source, |value| match value {
+        Value::TimeSeries(series) => series.remove_rule(dest),
+        _ => false,
+    });
+    if removed != Some(true) {
+        return Err("ERR TSDB: compaction rule does not exist".to_string());
+    }
+    guard.update(dest, |value| {
+        if let Value::TimeSeries(series) = value {
+            series.set_source(None);
+        }
+    });
+    guard.notify(Class::Module, "ts.deleterule:src", source);
+    guard.notify(Class::Module, "ts.deleterule:dest", dest);
+
+    ok()
+}
+
+/// TS.INFO key: 回复是 名称/值 交替的数组
+pub fn info(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let mut guard = ctx.lock(&[key]);
+    let series = series(&mut guard, key)?;
+
+    let bulk = |s: &str| Frame::Bulk(Bytes::from(s.to_string()));
+    let labels = series
+        .labels()
+        .iter()
+        .map(|(label, val)| Frame::Array(vec![bulk(label), bulk(val)]))
+        .collect();
+    let rules = series
+        .rules()
+        .iter()
+        .map(|rule| {
+            Frame::Array(vec![
+                bulk(&rule.dest),
+                Frame::Integer(rule.bucket as i64),
+                bulk(&rule.aggregation.name().to_ascii_uppercase()),
+            ])
+        })
+        .collect();
+
+    let fields = [
+        ("totalSamples", Frame::Integer(series.len() as i64)),
+        ("memoryUsage", Frame::Integer(series.size() as i64)),
+        ("firstTimestamp", Frame::Integer(series.first_timestamp().unwrap_or(0))),
+        ("lastTimestamp", Frame::Integer(series.last_timestamp().unwrap_or(0))),
+        ("retentionTime", Frame::Integer(series.retention() as i64)),
+        ("chunkCount", Frame::Integer(series.chunk_count() as i64)),
+        ("chunkSize", Frame::Integer(series.chunk_size() as i64)),
+        ("duplicatePolicy", bulk(series.policy().name())),
+        ("labels", Frame::Array(labels)),
+        ("sourceKey", series.source().map_or(Frame::Null, bulk)),
+        ("rules", Frame::Array(rules)),
+    ];
+
+    Ok(Frame::Array(fields.into_iter().flat_map(|(name, val)| [bulk(name), val]).collect()).into())
+}
+
+/// 锁定 keys 以及它们的降采样规则的目标
+///
+/// 规则保存在源时间序列中, 只有加锁之后才能读取: 先锁定 keys 读取规则,
+/// 目标没有被锁定时释放锁, 连同目标一起重新加锁, 直到所有的目标都已经被锁定
+fn lock_with_rules<'a>(ctx: &Context<'a>, keys: &[&str]) -> Guard<'a> {
+    let mut locked: Vec<String> = keys.iter().map(|key| key.to_string()).collect();
+
+    loop {
+        let refs: Vec<&str> = locked.iter().map(String::as_str).collect();
+        let mut guard = ctx.lock(&refs);
+
+        let mut missing = vec![];
+        for key in keys {
+            if let Some(Value::TimeSeries(series)) = guard.peek(key).map(Entry::value) {
+                let dests = series.rules().iter().map(|rule| &rule.dest);
+                missing.extend(dests.filter(|dest| !locked.contains(dest)).cloned());
+            }
+        }
+        if missing.is_empty() {
+            return guard;
+        }
+
+        drop(guard);
+        locked.extend(missing);
+    }
+}
+
+/// 写入一个样本, 并将结束的时间桶写入降采样的目标. 目标必须已经被锁定, 见 `lock_with_rules`
+fn add_sample(
+    guard: &mut Guard<'_>,
+    key: &str,
+    timestamp: i64,
+    val: f64,
+    policy: Option<DuplicatePolicy>,
+) -> Result<(), String> {
+    let compactions = guard.update(key, |value| match value {
+        Value::TimeSeries(series) => {
+            series.add(timestamp, val, policy)?;
+            Ok(series.compactions(timestamp))
+        }
+        _ => Err(WRONGTYPE_ERR.to_string()),
+    });
+    let compactions = compactions.ok_or(MISSING_ERR)??;
+    guard.notify(Class::Module, "ts.add", key);
+
+    // 目标被删除或者超过了 retention 时直接忽略
+    for (dest, timestamp, val) in compactions {
+        let added = guard.update(&dest, |value| match value {
+            Value::TimeSeries(series) => {
+                series.add(timestamp, val, Some(DuplicatePolicy::Last)).is_ok()
+            }
+            _ => false,
+        });
+        if added == Some(true) {
+            guard.notify(Class::Module, "ts.add", &dest);
+        }
+    }
+
+    Ok(())
+}
+
+fn series<'g>(guard: &'g mut Guard<'_>, key: &str) -> Result<&'g TimeSeries, String> {
+    match guard.peek(key).map(Entry::value) {
+        Some(Value::TimeSeries(series)) => Ok(series),
+        Some(_) => Err(WRONGTYPE_ERR.to_string()),
+        None => Err(MISSING_ERR.to_string()),
+    }
+}
+
+/// 解析 TS.CREATE 与 TS.ADD 的选项, 只有 TS.ADD 支持 ON_DUPLICATE
+fn parse_options(
+    args: &[Bytes],
+    on_duplicate: bool,
+) -> Result<(Options, Option<DuplicatePolicy>), String> {
+    let mut options = Options::default();
+    let mut policy = None;
+
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        if is(arg, "labels") {
+            let labels: Vec<&Bytes> = rest.by_ref().collect();
+            if !labels.len().is_multiple_of(2) {
+                return Err(SYNTAX_ERR.to_string());
+            }
+            for pair in labels.chunks(2) {
+                options.labels.push((string(pair[0])?.to_string(), string(pair[1])?.to_string()));
+            }
+            break;
+        }
+
+        let val = rest.next().ok_or(SYNTAX_ERR)?;
+        if is(arg, "retention") {
+            options.retention = match int(val) {
+                Ok(n) if n >= 0 => n as u64,
+                _ => return Err("ERR TSDB: invalid RETENTION value".to_string()),
+            };
+        } else if is(arg, "chunk_size") {
+            options.chunk_size = match int(val) {
+                Ok(n) if (48..=1048576).contains(&n) && n % 8 == 0 => n as usize,
+                _ => {
+                    return Err("ERR TSDB: CHUNK_SIZE value must be a multiple of 8 in the range \
+                                [48 .. 1048576]"
+                        .to_string())
+                }
+            };
+        } else if is(arg, "duplicate_policy") || (on_duplicate && is(arg, "on_duplicate")) {
+            let parsed = DuplicatePolicy::parse(string(val)?)
+                .ok_or("ERR TSDB: Unknown DUPLICATE_POLICY")?;
+            if is(arg, "duplicate_policy") {
+                options.policy = parsed;
+            } else {
+                policy = Some(parsed);
+            }
+        } else {
+            return Err(SYNTAX_ERR.to_string());
+        }
+    }
+
+    Ok((options, policy))
+}
+
+/// 毫秒级的 unix 时间戳, `*` 代表当前时间
+fn timestamp(arg: &Bytes) -> Result<i64, String> {
+    if &arg[..] == b"*" {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        return Ok(now.as_millis() as i64);
+    }
+
+    match int(arg) {
+        Ok(ts) if ts >= 0 => Ok(ts),
+        _ => Err("ERR TSDB: invalid timestamp".to_string()),
+    }
+}
+
+fn value(arg: &Bytes) -> Result<f64, String> {
+    score(arg).map_err(|_| "ERR TSDB: invalid value".to_string())
+}
+
+fn parse_aggregation(arg: &Bytes) -> Result<Aggregation, String> {
+    string(arg)
+        .ok()
+        .and_then(Aggregation::parse)
+        .ok_or_else(|| "ERR TSDB: Unknown aggregation type".to_string())
+}
+
+fn parse_bucket(arg: &Bytes) -> Result<u64, String> {
+    match int(arg) {
+        Ok(n) if n > 0 => Ok(n as u64),
+        _ => Err("ERR TSDB: bucketDuration must be greater than zero".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{server, Frame};
+
+    fn is_err(frame: &Frame, msg: &str) -> bool {
+        matches!(frame, Frame::Error(err) if err.contains(msg))
+    }
+
+    fn sample(ts: i64, val: &str) -> Frame {
+        Frame::Array(vec![Frame::Integer(ts), Frame::Simple(val.to_string())])
+    }
+
+    #[tokio::test]
+    async fn add_range_and_rules() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+
+        let ok = Frame::Simple("OK".to_string());
+        assert_eq!(ok, conn.request(["TS.CREATE", "src", "LABELS", "host", "a"]).await.unwrap());
+        assert!(is_err(&conn.request(["TS.CREATE", "src"]).await.unwrap(), "already exists"));
+        conn.request(["TS.CREATE", "avg"]).await.unwrap();
+        let rule = ["TS.CREATERULE", "src", "avg", "AGGREGATION", "avg", "10"];
+        assert_eq!(ok, conn.request(rule).await.unwrap());
+
+        // 写入下一个时间桶的样本时, 上一个时间桶的聚合结果写入目标
+        for (ts, val) in [("0", "1"), ("5", "2"), ("10", "6")] {
+            conn.request(["TS.ADD", "src", ts, val]).await.unwrap();
+        }
+        let reply = conn.request(["TS.RANGE", "avg", "-", "+"]).await.unwrap();
+        assert_eq!(Frame::Array(vec![sample(0, "1.5")]), reply);
+
+        let reply = conn.request(["TS.RANGE", "src", "-", "+", "COUNT", "2"]).await.unwrap();
+        assert_eq!(Frame::Array(vec![sample(0, "1"), sample(5, "2")]), reply);
+        let range = ["TS.RANGE", "src", "0", "10", "AGGREGATION", "max", "10"];
+        let reply = conn.request(range).await.unwrap();
+        assert_eq!(Frame::Array(vec![sample(0, "2"), sample(10, "6")]), reply);
+
+        // 默认的 DUPLICATE_POLICY 为 BLOCK, ON_DUPLICATE 只对本次写入生效
+        let reply = conn.request(["TS.ADD", "src", "10", "7"]).await.unwrap();
+        assert!(is_err(&reply, "BLOCK"));
+        let reply = conn.request(["TS.ADD", "src", "10", "7", "ON_DUPLICATE", "LAST"]).await;
+        assert_eq!(Frame::Integer(10), reply.unwrap());
+
+        // TS.ADD 自动创建, TS.MADD 的每个样本单独回复
+        let reply = conn.request(["TS.MADD", "src", "20", "1", "missing", "20", "1"]).await;
+        let Frame::Array(replies) = reply.unwrap() else { panic!("TS.MADD should reply an array") };
+        assert_eq!(Frame::Integer(20), replies[0]);
+        assert!(is_err(&replies[1], "does not exist"));
+        assert_eq!(Frame::Integer(1), conn.request(["TS.ADD", "auto", "1", "1"]).await.unwrap());
+
+        assert_eq!(ok, conn.request(["TS.DELETERULE", "src", "avg"]).await.unwrap());
+        let reply = conn.request(["TS.DELETERULE", "src", "avg"]).await.unwrap();
+        assert!(is_err(&reply, "rule does not exist"));
+        let Frame::Array(info) = conn.request(["TS.INFO", "src"]).await.unwrap() else {
+            panic!("TS.INFO should reply an array");
+        };
+        assert_eq!(Frame::Integer(4), info[1]);
+    }
+
+    #[tokio::test]
+    async fn argument_errors() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+        conn.request(["TS.CREATE", "ts"]).await.unwrap();
+        conn.request(["TS.CREATE", "dst"]).await.unwrap();
+        conn.request(["SET", "str", "x"]).await.unwrap();
+
+        for (args, msg) in [
+            (&["TS.CREATE", "a", "RETENTION", "-1"][..], "invalid RETENTION"),
+            (&["TS.CREATE", "a", "CHUNK_SIZE", "7"], "CHUNK_SIZE"),
+            (&["TS.CREATE", "a", "DUPLICATE_POLICY", "nope"], "Unknown DUPLICATE_POLICY"),
+            (&["TS.CREATE", "a", "ON_DUPLICATE", "last"], "syntax error"),
+            (&["TS.CREATE", "a", "LABELS", "odd"], "syntax error"),
+            (&["TS.ADD", "ts", "-1", "1"], "invalid timestamp"),
+            (&["TS.ADD", "ts", "1", "x"], "invalid value"),
+            (&["TS.MADD", "ts", "1"], "wrong number of arguments"),
+            (&["TS.RANGE", "ts", "-", "+", "COUNT", "0"], "Invalid COUNT"),
+            (&["TS.RANGE", "ts", "-", "+", "AGGREGATION", "nope", "10"], "Unknown aggregation"),
+            (&["TS.RANGE", "ts", "-", "+", "AGGREGATION", "avg", "0"], "bucketDuration"),
+            (&["TS.RANGE", "missing", "-", "+"], "does not exist"),
+            (&["TS.CREATERULE", "ts", "ts", "AGGREGATION", "avg", "10"], "should be different"),
+            (&["TS.CREATERULE", "ts", "dst", "NOPE", "avg", "10"], "syntax error"),
+            (&["TS.INFO", "missing"], "does not exist"),
+            (&["TS.ADD", "str", "1", "1"], "WRONGTYPE"),
+            (&["TS.RANGE", "str", "-", "+"], "WRONGTYPE"),
+            (&["TS.INFO", "str"], "WRONGTYPE"),
+        ] {
+            let reply = conn.request(args.to_vec()).await.unwrap();
+            assert!(is_err(&reply, msg), "{:?}: {}", args, reply);
+        }
+
+        // 规则不能级联, 目标也只能有一个来源
+        conn.request(["TS.CREATE", "third"]).await.unwrap();
+        conn.request(["TS.CREATERULE", "ts", "dst", "AGGREGATION", "avg", "10"]).await.unwrap();
+        let reply = conn.request(["TS.CREATERULE", "dst", "third", "AGGREGATION", "avg", "10"]);
+        assert!(is_err(&reply.await.unwrap(), "can not be chained"));
+        let reply = conn.request(["TS.CREATERULE", "third", "dst", "AGGREGATION", "avg", "10"]);
+        assert!(is_err(&reply.await.unwrap(), "already has a src rule"));
+    }
+}
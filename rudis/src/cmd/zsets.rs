@@ -0,0 +1,470 @@
+//! 有序集合命令
+
+use std::ops::Bound;
+
+use bytes::Bytes;
+
+use super::{int, is, key, lists::range, CmdResult, Context, SYNTAX_ERR, WRONGTYPE_ERR};
+use crate::{
+    db::{Guard, Value},
+    notify::Class,
+    zset::{format_score, SortedSet},
+    Frame,
+};
+
+pub(crate) const NOT_FLOAT_ERR: &str = "ERR value is not a valid float";
+
+/// ZADD 以及 GEOADD 的选项
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct AddOptions {
+    /// 只添加新成员
+    pub nx: bool,
+    /// 只更新已有的成员
+    pub xx: bool,
+    /// 新的分数大于原来的分数时才更新
+    pub gt: bool,
+    /// 新的分数小于原来的分数时才更新
+    pub lt: bool,
+    /// 返回值包括分数被修改的成员
+    pub ch: bool,
+}
+
+impl AddOptions {
+    /// 解析一个选项, 不是选项时返回 false
+    pub fn parse(&mut self, arg: &Bytes) -> bool {
+        match arg.to_ascii_lowercase().as_slice() {
+            b"nx" => self.nx = true,
+            b"xx" => self.xx = true,
+            b"gt" => self.gt = true,
+            b"lt" => self.lt = true,
+            b"ch" => self.ch = true,
+            _ => return false,
+        }
+
+        true
+    }
+
+    pub fn check(&self) -> Result<(), String> {
+        if self.nx && self.xx {
+            return Err("ERR XX and NX options at the same time are not compatible".to_string());
+        }
+        if (self.gt && self.lt) || (self.nx && (self.gt || self.lt)) {
+            return Err(
+                "ERR GT, LT, and/or NX options at the same time are not compatible".to_string(),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// 成员原来的分数为 `old` 时, 是否需要写入新的分数
+    fn allows(&self, old: Option<f64>, new: f64) -> bool {
+        match old {
+            None => !self.xx,
+            Some(_) if self.nx => false,
+            Some(old) => !((self.gt && new <= old) || (self.lt && new >= old)),
+        }
+    }
+}
+
+/// ZADD key [NX | XX] [GT | LT] [CH] [INCR] score member [score member ...]
+pub fn zadd(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+
+    let mut options = AddOptions::default();
+    let mut incr = false;
+    let mut i = 2;
+    while i < args.len() {
+        if is(&args[i], "incr") {
+            incr = true;
+        } else if !options.parse(&args[i]) {
+            break;
+        }
+        i += 1;
+    }
+    options.check()?;
+
+    let pairs = &args[i..];
+    if pairs.is_empty() || !pairs.len().is_multiple_of(2) {
+        return Err(SYNTAX_ERR.to_string());
+    }
+    if incr && pairs.len() != 2 {
+        return Err("ERR INCR option supports a single increment-element pair".to_string());
+    }
+
+    let items = pairs
+        .chunks(2)
+        .map(|pair| Ok((score(&pair[0])?, pair[1].clone())))
+        .collect::<Result<Vec<_>, String>>()?;
+    let mut guard = ctx.lock(&[key]);
+
+    if incr {
+        let (delta, member) = items.into_iter().next().unwrap();
+        return match incr_by(&mut guard, key, member, delta, options)? {
+            Some(score) => Ok(Frame::Bulk(Bytes::from(format_score(score))).into()),
+            None => Ok(Frame::Null.into()),
+        };
+    }
+
+    let count = add(&mut guard, key, items, options, "zadd")?;
+    Ok(Frame::Integer(count as i64).into())
+}
+
+/// ZINCRBY key increment member
+pub fn zincrby(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let delta = score(&args[2])?;
+    let mut guard = ctx.lock(&[key]);
+
+    let score = incr_by(&mut guard, key, args[3].clone(), delta, AddOptions::default())?;
+    Ok(Frame::Bulk(Bytes::from(format_score(score.unwrap()))).into())
+}
+
+/// ZREM key member [member ...]
+pub fn zrem(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let mut guard = ctx.lock(&[key]);
+
+    let removed = guard.update(key, |value| match value {
+        Value::ZSet(zset) => Ok(args[2..]
+            .iter()
+            .filter(|member| zset.remove(member).is_some())
+            .count()),
+        _ => Err(WRONGTYPE_ERR.to_string()),
+    });
+    let removed = match removed {
+        Some(removed) => removed?,
+        None => 0,
+    };
+
+    if removed > 0 {
+        guard.notify(Class::ZSet, "zrem", key);
+        if !guard.exists(key) {
+            guard.notify(Class::Generic, "del", key);
+        }
+    }
+
+    Ok(Frame::Integer(removed as i64).into())
+}
+
+/// ZSCORE key member
+pub fn zscore(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let mut guard = ctx.lock(&[key]);
+
+    match get(&mut guard, key)?.and_then(|zset| zset.score(&args[2])) {
+        Some(score) => Ok(Frame::Bulk(Bytes::from(format_score(score))).into()),
+        None => Ok(Frame::Null.into()),
+    }
+}
+
+/// ZCARD key
+pub fn zcard(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+    let mut guard = ctx.lock(&[key]);
+
+    let len = get(&mut guard, key)?.map_or(0, |zset| zset.len());
+    Ok(Frame::Integer(len as i64).into())
+}
+
+/// ZRANGE key start stop [BYSCORE] [REV] [LIMIT offset count] [WITHSCORES]
+pub fn zrange(ctx: &mut Context<'_>, args: &[Bytes]) -> CmdResult {
+    let key = key(&args[1])?;
+
+    let mut by_score = false;
+    let mut rev = false;
+    let mut limit = None;
+    let mut with_scores = false;
+    let mut options = args[4..].iter();
+    while let Some(option) = options.next() {
+        if is(option, "byscore") {
+            by_score = true;
+        } else if is(option, "rev") {
+            rev = true;
+        } else if is(option, "withscores") {
+            with_scores = true;
+        } else if is(option, "limit") {
+            let offset = int(options.next().ok_or(SYNTAX_ERR)?)?;
+            let count = int(options.next().ok_or(SYNTAX_ERR)?)?;
+            limit = Some((offset, count));
+        } else {
+            return Err(SYNTAX_ERR.to_string());
+        }
+    }
+    if limit.is_some() && !by_score {
+        return Err(
+            "ERR syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX"
+                .to_string(),
+        );
+    }
+
+    // BYSCORE 并且 REV 时, 参数的顺序为 max min
+    let bounds = if by_score {
+        let (min, max) = if rev {
+            (&args[3], &args[2])
+        } else {
+            (&args[2], &args[3])
+        };
+        Some((score_bound(min)?, score_bound(max)?))
+    } else {
+        None
+    };
+    let indexes = if by_score {
+        None
+    } else {
+        Some((int(&args[2])?, int(&args[3])?))
+    };
+
+    let mut guard = ctx.lock(&[key]);
+    let Some(zset) = get(&mut guard, key)? else {
+        return Ok(Frame::Array(vec![]).into());
+    };
+
+    let items: Vec<(&Bytes, f64)> = match (bounds, indexes) {
+        (Some((min, max)), _) => {
+            let mut items: Vec<_> = zset.range_by_score(min, max).collect();
+            if rev {
+                items.reverse();
+            }
+
+            // LIMIT 的 count 为负数时返回 offset 之后的所有成员
+            match limit {
+                Some((offset, _)) if offset < 0 => vec![],
+                Some((offset, count)) => items
+                    .into_iter()
+                    .skip(offset as usize)
+                    .take(if count < 0 { usize::MAX } else { count as usize })
+                    .collect(),
+                None => items,
+            }
+        }
+        (None, Some((start, stop))) => match range(start, stop, zset.len()) {
+            Some((start, stop)) if rev => {
+                zset.iter().rev().skip(start).take(stop - start + 1).collect()
+            }
+            Some((start, stop)) => zset.iter().skip(start).take(stop - start + 1).collect(),
+            None => vec![],
+        },
+        (None, None) => unreachable!(),
+    };
+
+    let mut out = vec![];
+    for (member, score) in items {
+        out.push(Frame::Bulk(member.clone()));
+        if with_scores {
+            out.push(Frame::Bulk(Bytes::from(format_score(score))));
+        }
+    }
+
+    Ok(Frame::Array(out).into())
+}
+
+/// 读取有序集合, key 不存在时返回 None
+pub(crate) fn get<'g>(
+    guard: &'g mut Guard<'_>,
+    key: &str,
+) -> Result<Option<&'g SortedSet>, String> {
+    match guard.get(key) {
+        Some(Value::ZSet(zset)) => Ok(Some(zset)),
+        Some(_) => Err(WRONGTYPE_ERR.to_string()),
+        None => Ok(None),
+    }
+}
+
+/// 添加或者更新多个成员, 返回新添加的成员个数, 指定了 CH 时还包括分数被修改的成员
+pub(crate) fn add(
+    guard: &mut Guard<'_>,
+    key: &str,
+    items: Vec<(f64, Bytes)>,
+    options: AddOptions,
+    event: &str,
+) -> Result<usize, String> {
+    let add_all = |zset: &mut SortedSet| {
+        let (mut added, mut changed) = (0, 0);
+        for (score, member) in items {
+            let old = zset.score(&member);
+            if !options.allows(old, score) {
+                continue;
+            }
+
+            match old {
+                None => added += 1,
+                Some(old) if old != score => changed += 1,
+                Some(_) => continue,
+            }
+            zset.insert(member, score);
+        }
+
+        if options.ch {
+            added + changed
+        } else {
+            added
+        }
+    };
+
+    let count = update(guard, key, |zset| Ok(add_all(zset)))?;
+    if count > 0 || options.ch {
+        guard.notify(Class::ZSet, event, key);
+    }
+
+    Ok(count)
+}
+
+/// 增加成员的分数, 选项不允许修改时返回 None
+fn incr_by(
+    guard: &mut Guard<'_>,
+    key: &str,
+    member: Bytes,
+    delta: f64,
+    options: AddOptions,
+) -> Result<Option<f64>, String> {
+    let incr = |zset: &mut SortedSet| {
+        let old = zset.score(&member);
+        let score = old.unwrap_or(0.0) + delta;
+        if score.is_nan() {
+            return Err("ERR resulting score is not a number (NaN)".to_string());
+        }
+        if !options.allows(old, score) {
+            return Ok(None);
+        }
+
+        zset.insert(member, score);
+        Ok(Some(score))
+    };
+
+    let score = update(guard, key, incr)?;
+    if score.is_some() {
+        guard.notify(Class::ZSet, "zincr", key);
+    }
+
+    Ok(score)
+}
+
+/// 修改有序集合, key 不存在时先创建空的有序集合, 修改之后为空时会被删除
+fn update<R>(
+    guard: &mut Guard<'_>,
+    key: &str,
+    f: impl FnOnce(&mut SortedSet) -> Result<R, String>,
+) -> Result<R, String> {
+    if !guard.exists(key) {
+        guard.insert(key, Value::ZSet(SortedSet::new()), None);
+    }
+
+    guard
+        .update(key, |value| match value {
+            Value::ZSet(zset) => f(zset),
+            _ => Err(WRONGTYPE_ERR.to_string()),
+        })
+        .unwrap()
+}
+
+/// 解析分数, 支持 `inf`、`-inf`, 不允许 NaN
+pub(crate) fn score(arg: &Bytes) -> Result<f64, String> {
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|s| s.parse::<f64>().ok())
+        .filter(|score| !score.is_nan())
+        .ok_or_else(|| NOT_FLOAT_ERR.to_string())
+}
+
+/// 解析分数范围的一端, `(` 开头代表不包含
+fn score_bound(arg: &Bytes) -> Result<Bound<f64>, String> {
+    const ERR: &str = "ERR min or max is not a float";
+
+    match arg.strip_prefix(b"(") {
+        Some(rest) => Ok(Bound::Excluded(
+            score(&Bytes::copy_from_slice(rest)).map_err(|_| ERR)?,
+        )),
+        None => Ok(Bound::Included(score(arg).map_err(|_| ERR)?)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use crate::{server, Frame};
+
+    fn is_err(frame: &Frame, msg: &str) -> bool {
+        matches!(frame, Frame::Error(err) if err.contains(msg))
+    }
+
+    fn bulks(items: &[&str]) -> Frame {
+        let items = items.iter().map(|s| Frame::Bulk(Bytes::copy_from_slice(s.as_bytes())));
+        Frame::Array(items.collect())
+    }
+
+    #[tokio::test]
+    async fn add_and_range() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+
+        let reply = conn.request(["ZADD", "z", "1", "a", "2", "b", "3", "c"]).await.unwrap();
+        assert_eq!(Frame::Integer(3), reply);
+        // CH 时返回值包括分数被修改的成员, GT 时只允许增大分数
+        let reply = conn.request(["ZADD", "z", "GT", "CH", "0", "a", "4", "b"]).await.unwrap();
+        assert_eq!(Frame::Integer(1), reply);
+        let reply = conn.request(["ZADD", "z", "XX", "INCR", "1", "d"]).await.unwrap();
+        assert_eq!(Frame::Null, reply);
+        let reply = conn.request(["ZINCRBY", "z", "1.5", "a"]).await.unwrap();
+        assert_eq!(Frame::Bulk(Bytes::from("2.5")), reply);
+        let reply = conn.request(["ZSCORE", "z", "b"]).await.unwrap();
+        assert_eq!(Frame::Bulk(Bytes::from("4")), reply);
+        assert_eq!(Frame::Null, conn.request(["ZSCORE", "z", "d"]).await.unwrap());
+
+        let reply = conn.request(["ZRANGE", "z", "0", "-1"]).await.unwrap();
+        assert_eq!(bulks(&["a", "c", "b"]), reply);
+        let reply = conn.request(["ZRANGE", "z", "0", "0", "REV", "WITHSCORES"]).await.unwrap();
+        assert_eq!(bulks(&["b", "4"]), reply);
+        let reply = conn.request(["ZRANGE", "z", "(2.5", "+inf", "BYSCORE"]).await.unwrap();
+        assert_eq!(bulks(&["c", "b"]), reply);
+        let args = ["ZRANGE", "z", "+inf", "-inf", "BYSCORE", "REV", "LIMIT", "1", "1"];
+        assert_eq!(bulks(&["c"]), conn.request(args).await.unwrap());
+
+        // 删除最后一个成员时删除 key
+        assert_eq!(Frame::Integer(2), conn.request(["ZREM", "z", "a", "b", "x"]).await.unwrap());
+        assert_eq!(Frame::Integer(1), conn.request(["ZCARD", "z"]).await.unwrap());
+        conn.request(["ZREM", "z", "c"]).await.unwrap();
+        assert_eq!(Frame::Integer(0), conn.request(["EXISTS", "z"]).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn argument_errors() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+
+        conn.request(["ZADD", "z", "inf", "a"]).await.unwrap();
+        for (args, msg) in [
+            (&["ZADD", "z", "1"][..], "wrong number of arguments"),
+            (&["ZADD", "z", "1", "a", "2"], "syntax error"),
+            (&["ZADD", "z", "NX", "XX", "1", "a"], "not compatible"),
+            (&["ZADD", "z", "GT", "LT", "1", "a"], "not compatible"),
+            (&["ZADD", "z", "NX", "GT", "1", "a"], "not compatible"),
+            (&["ZADD", "z", "INCR", "1", "a", "2", "b"], "single increment-element pair"),
+            (&["ZADD", "z", "x", "a"], "not a valid float"),
+            (&["ZADD", "z", "nan", "a"], "not a valid float"),
+            (&["ZINCRBY", "z", "-inf", "a"], "NaN"),
+            (&["ZRANGE", "z", "x", "1"], "not an integer"),
+            (&["ZRANGE", "z", "0", "x", "BYSCORE"], "min or max is not a float"),
+            (&["ZRANGE", "z", "0", "1", "LIMIT", "0", "1"], "LIMIT is only supported"),
+            (&["ZRANGE", "z", "0", "1", "BYSCORE", "LIMIT", "0"], "syntax error"),
+            (&["ZRANGE", "z", "0", "1", "NOPE"], "syntax error"),
+        ] {
+            let reply = conn.request(args.to_vec()).await.unwrap();
+            assert!(is_err(&reply, msg), "{:?}: {}", args, reply);
+        }
+
+        conn.request(["SET", "str", "x"]).await.unwrap();
+        for args in [
+            &["ZADD", "str", "1", "a"][..],
+            &["ZINCRBY", "str", "1", "a"],
+            &["ZREM", "str", "a"],
+            &["ZSCORE", "str", "a"],
+            &["ZCARD", "str"],
+            &["ZRANGE", "str", "0", "-1"],
+        ] {
+            let reply = conn.request(args.to_vec()).await.unwrap();
+            assert!(is_err(&reply, "WRONGTYPE"), "{:?}", args);
+        }
+    }
+}
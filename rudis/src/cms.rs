@@ -0,0 +1,104 @@
+//! Count-Min Sketch
+//!
+//! 一个 depth 行、width 列的计数器矩阵, 每一行使用不同的哈希函数.
+//! 增加计数时每一行对应的计数器都加上增量, 查询时取各行对应计数器的最小值:
+//! 哈希冲突只会让计数偏大, 因此结果不会小于真实的计数
+
+use crate::{
+    dump::{self, Reader},
+    hll::murmurhash64a,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CountMinSketch {
+    width: usize,
+    depth: usize,
+    counters: Vec<u64>,
+    /// 所有增量的总和
+    count: u64,
+}
+
+impl CountMinSketch {
+    /// width 和 depth 必须大于 0, 由调用者检查
+    pub fn new(width: usize, depth: usize) -> CountMinSketch {
+        CountMinSketch {
+            width,
+            depth,
+            counters: vec![0; width * depth],
+            count: 0,
+        }
+    }
+
+    /// 增加元素的计数, 返回增加之后的估计值
+    pub fn incr_by(&mut self, item: &[u8], delta: u64) -> u64 {
+        let mut min = u64::MAX;
+        for row in 0..self.depth {
+            let i = self.index(row, item);
+            self.counters[i] = self.counters[i].saturating_add(delta);
+            min = min.min(self.counters[i]);
+        }
+        self.count = self.count.saturating_add(delta);
+
+        min
+    }
+
+    /// 元素计数的估计值, 不会小于真实的计数
+    pub fn query(&self, item: &[u8]) -> u64 {
+        (0..self.depth)
+            .map(|row| self.counters[self.index(row, item)])
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// 计数器占用的字节数
+    pub fn size(&self) -> usize {
+        self.counters.len() * 8
+    }
+
+    /// 序列化, 见 `crate::dump`
+    pub(crate) fn encode(&self, out: &mut Vec<u8>) {
+        dump::put_u64(out, self.width as u64);
+        dump::put_u64(out, self.depth as u64);
+        dump::put_u64(out, self.count);
+        for counter in &self.counters {
+            dump::put_u64(out, *counter);
+        }
+    }
+
+    pub(crate) fn decode(reader: &mut Reader<'_>) -> Option<CountMinSketch> {
+        let width = usize::try_from(reader.u64()?).ok()?;
+        let depth = usize::try_from(reader.u64()?).ok()?;
+        let count = reader.u64()?;
+        if width == 0 || depth == 0 {
+            return None;
+        }
+
+        let counters = (0..width.checked_mul(depth)?)
+            .map(|_| reader.u64())
+            .collect::<Option<_>>()?;
+        Some(CountMinSketch {
+            width,
+            depth,
+            counters,
+            count,
+        })
+    }
+
+    fn index(&self, row: usize, item: &[u8]) -> usize {
+        // 每一行使用行号作为哈希的种子
+        let hash = murmurhash64a(item, row as u64);
+        row * self.width + (hash % self.width as u64) as usize
+    }
+}
@@ -0,0 +1,165 @@
+//! 基于 `tokio_util::codec` 的 RESP 编解码器
+//!
+//! [`Connection`](crate::Connection) 自己管理缓冲区并提供 `read_frame`/`write_frame`,
+//! 而 [`RespCodec`] 配合 `Framed` 可以把一个连接变成 Frame 的 `Stream` + `Sink`,
+//! 这样就能直接使用 `StreamExt`、`SinkExt` 中的各种组合子, 方便实现代理、测试工具等
+//!
+//! ```no_run
+//! use futures::SinkExt;
+//! use rudis::{codec::RespCodec, Frame};
+//! use tokio::net::TcpStream;
+//! use tokio_stream::StreamExt;
+//! use tokio_util::codec::Framed;
+//!
+//! # async fn run() -> rudis::Result<()> {
+//! let stream = TcpStream::connect("127.0.0.1:6379").await?;
+//! let mut framed = Framed::new(stream, RespCodec::new());
+//!
+//! framed.send(Frame::Array(vec![Frame::Bulk("PING".into())])).await?;
+//! let pong = framed.next().await.transpose()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use bytes::{BufMut, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::{inline, Error, Frame, Parser};
+
+#[derive(Debug, Default)]
+pub struct RespCodec {
+    /// 解码的进度保存在 Parser 中, 数据不完整时不需要从头重新解析
+    parser: Parser,
+
+    /// 是否接受 inline 命令, 与 `Connection::enable_inline` 相同, 只有服务端才需要开启
+    inline: bool,
+}
+
+impl RespCodec {
+    pub fn new() -> RespCodec {
+        RespCodec::default()
+    }
+
+    /// 接受 inline 命令的编解码器, 用于服务端. 与 `Connection::enable_inline` 相同,
+    /// RESP 格式的请求只能是由 bulk 组成的 Array
+    pub fn with_inline() -> RespCodec {
+        RespCodec {
+            parser: Parser::requests(),
+            inline: true,
+        }
+    }
+}
+
+impl Decoder for RespCodec {
+    type Item = Frame;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Frame>, Error> {
+        if self.inline && self.parser.is_idle() && src.first().is_some_and(|b| *b != b'*') {
+            return inline::parse(src);
+        }
+
+        self.parser.parse(src)
+    }
+
+    /// 对端关闭连接时, 缓冲区中还有数据或者 Frame 只解析了一半, 说明数据只发送了一部分
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Frame>, Error> {
+        match self.decode(src)? {
+            Some(frame) => Ok(Some(frame)),
+            None if src.is_empty() && self.parser.is_idle() => Ok(None),
+            None => Err("connection reset by peer".into()),
+        }
+    }
+}
+
+impl Encoder<Frame> for RespCodec {
+    type Error = Error;
+
+    fn encode(&mut self, frame: Frame, dst: &mut BytesMut) -> Result<(), Error> {
+        encode(&frame, dst);
+        Ok(())
+    }
+}
+
+/// 代理等场景下只持有 Frame 的引用, 避免为了发送而 clone
+impl Encoder<&Frame> for RespCodec {
+    type Error = Error;
+
+    fn encode(&mut self, frame: &Frame, dst: &mut BytesMut) -> Result<(), Error> {
+        encode(frame, dst);
+        Ok(())
+    }
+}
+
+/// 与 `Connection::write_value` 的格式相同, 嵌套的 Array 递归编码
+fn encode(frame: &Frame, dst: &mut BytesMut) {
+    match frame {
+        Frame::Simple(val) => {
+            dst.put_u8(b'+');
+            dst.put_slice(val.as_bytes());
+            dst.put_slice(b"\r\n");
+        }
+        Frame::Error(val) => {
+            dst.put_u8(b'-');
+            dst.put_slice(val.as_bytes());
+            dst.put_slice(b"\r\n");
+        }
+        Frame::Integer(val) => {
+            dst.put_u8(b':');
+            put_decimal(*val, dst);
+        }
+        Frame::Null => dst.put_slice(b"$-1\r\n"),
+        Frame::Bulk(val) => {
+            dst.reserve(val.len() + 16);
+            dst.put_u8(b'$');
+            put_decimal(val.len() as i64, dst);
+            dst.put_slice(val);
+            dst.put_slice(b"\r\n");
+        }
+        Frame::Array(val) | Frame::Push(val) => {
+            dst.put_u8(if matches!(frame, Frame::Push(_)) { b'>' } else { b'*' });
+            put_decimal(val.len() as i64, dst);
+            for entry in val {
+                encode(entry, dst);
+            }
+        }
+    }
+}
+
+fn put_decimal(val: i64, dst: &mut BytesMut) {
+    dst.put_slice(val.to_string().as_bytes());
+    dst.put_slice(b"\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+
+    #[test]
+    fn encode_then_decode() {
+        let frames = vec![
+            Frame::Array(vec![
+                Frame::Bulk(Bytes::from("SET")),
+                Frame::Bulk(Bytes::from("foo")),
+                Frame::Array(vec![Frame::Integer(-2), Frame::Null]),
+            ]),
+            Frame::Simple("OK".to_string()),
+            Frame::Error("ERR syntax error".to_string()),
+        ];
+
+        let mut codec = RespCodec::new();
+        let mut buf = BytesMut::new();
+        for frame in &frames {
+            codec.encode(frame, &mut buf).unwrap();
+        }
+
+        let mut decoded = vec![];
+        while let Some(frame) = codec.decode_eof(&mut buf).unwrap() {
+            decoded.push(frame);
+        }
+
+        assert_eq!(frames, decoded);
+    }
+}
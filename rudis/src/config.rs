@@ -0,0 +1,382 @@
+use std::{
+    sync::atomic::{AtomicI64, AtomicU32, AtomicU64, AtomicU8, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use crate::{glob, hash, list, notify::Flags, set};
+
+/// 服务端的运行时配置, 可以通过 `CONFIG GET`/`CONFIG SET` 读取和修改
+///
+/// 配置项在每个命令中都可能被读取, 因此尽量使用原子类型保存, 避免加锁
+pub struct Config {
+    /// 逻辑数据库的个数, 只能在启动时指定
+    databases: usize,
+    /// 是否允许执行 DEBUG 命令, 与 databases 相同只能在启动时指定
+    enable_debug_command: bool,
+    notify_keyspace_events: AtomicU32,
+    maxmemory: AtomicUsize,
+    maxmemory_policy: AtomicU8,
+    maxmemory_samples: AtomicUsize,
+    lua_time_limit: AtomicU64,
+    hll_sparse_max_bytes: AtomicUsize,
+    list_max_listpack_size: AtomicI64,
+    hash_max_listpack_entries: AtomicUsize,
+    hash_max_listpack_value: AtomicUsize,
+    set_max_intset_entries: AtomicUsize,
+    min_replicas_to_write: AtomicUsize,
+    min_replicas_max_lag: AtomicU64,
+    tracking_table_max_keys: AtomicUsize,
+}
+
+/// 内存超过 `maxmemory` 之后的淘汰策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    NoEviction,
+    AllKeysLru,
+    AllKeysRandom,
+    VolatileLru,
+    VolatileRandom,
+    VolatileTtl,
+    AllKeysLfu,
+    VolatileLfu,
+}
+
+impl Policy {
+    const ALL: [Policy; 8] = [
+        Policy::NoEviction,
+        Policy::AllKeysLru,
+        Policy::AllKeysRandom,
+        Policy::VolatileLru,
+        Policy::VolatileRandom,
+        Policy::VolatileTtl,
+        Policy::AllKeysLfu,
+        Policy::VolatileLfu,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Policy::NoEviction => "noeviction",
+            Policy::AllKeysLru => "allkeys-lru",
+            Policy::AllKeysRandom => "allkeys-random",
+            Policy::VolatileLru => "volatile-lru",
+            Policy::VolatileRandom => "volatile-random",
+            Policy::VolatileTtl => "volatile-ttl",
+            Policy::AllKeysLfu => "allkeys-lfu",
+            Policy::VolatileLfu => "volatile-lfu",
+        }
+    }
+
+    /// 是否只淘汰设置了过期时间的 key
+    pub fn volatile_only(&self) -> bool {
+        matches!(
+            self,
+            Policy::VolatileLru
+                | Policy::VolatileRandom
+                | Policy::VolatileTtl
+                | Policy::VolatileLfu
+        )
+    }
+}
+
+/// 一个配置项的读写方法
+struct Param {
+    name: &'static str,
+    get: fn(&Config) -> String,
+    set: fn(&Config, &str) -> Result<(), String>,
+}
+
+const PARAMS: &[Param] = &[
+    Param {
+        name: "databases",
+        get: |config| config.databases().to_string(),
+        set: |_, _| Err("can't set immutable config".to_string()),
+    },
+    Param {
+        name: "enable-debug-command",
+        get: |config| if config.debug_command_enabled() { "yes" } else { "no" }.to_string(),
+        set: |_, _| Err("can't set protected config".to_string()),
+    },
+    Param {
+        name: "notify-keyspace-events",
+        get: |config| config.notify_flags().to_string(),
+        set: |config, val| {
+            let flags = Flags::parse(val).ok_or("Invalid event class character. Use 'Ag$lshzxeKEtmdn'.")?;
+            config.notify_keyspace_events.store(flags.bits(), Ordering::Relaxed);
+            Ok(())
+        },
+    },
+    Param {
+        name: "maxmemory",
+        get: |config| config.maxmemory().to_string(),
+        set: |config, val| {
+            config.maxmemory.store(parse_memory(val)?, Ordering::Relaxed);
+            Ok(())
+        },
+    },
+    Param {
+        name: "maxmemory-policy",
+        get: |config| config.maxmemory_policy().name().to_string(),
+        set: |config, val| {
+            let idx = Policy::ALL
+                .iter()
+                .position(|policy| policy.name().eq_ignore_ascii_case(val))
+                .ok_or("argument(s) must be one of the following: volatile-lru, allkeys-lru, volatile-lfu, allkeys-lfu, volatile-random, allkeys-random, volatile-ttl, noeviction")?;
+            config.maxmemory_policy.store(idx as u8, Ordering::Relaxed);
+            Ok(())
+        },
+    },
+    Param {
+        name: "maxmemory-samples",
+        get: |config| config.maxmemory_samples().to_string(),
+        set: |config, val| {
+            let samples = parse_number(val)?;
+            if samples == 0 {
+                return Err("argument must be between 1 and 64 inclusive".to_string());
+            }
+            config
+                .maxmemory_samples
+                .store(samples.min(64) as usize, Ordering::Relaxed);
+            Ok(())
+        },
+    },
+    Param {
+        name: "lua-time-limit",
+        get: |config| config.lua_time_limit().as_millis().to_string(),
+        set: |config, val| {
+            config.lua_time_limit.store(parse_number(val)?, Ordering::Relaxed);
+            Ok(())
+        },
+    },
+    Param {
+        name: "hll-sparse-max-bytes",
+        get: |config| config.hll_sparse_max_bytes().to_string(),
+        set: |config, val| {
+            config
+                .hll_sparse_max_bytes
+                .store(parse_number(val)? as usize, Ordering::Relaxed);
+            Ok(())
+        },
+    },
+    Param {
+        name: "list-max-listpack-size",
+        get: |config| config.list_max_listpack_size().to_string(),
+        set: |config, val| {
+            let size = match val.parse::<i64>() {
+                Ok(size) if size != 0 && size >= -5 => size,
+                _ => return Err("argument must be a positive integer or between -5 and -1".to_string()),
+            };
+            config.list_max_listpack_size.store(size, Ordering::Relaxed);
+            Ok(())
+        },
+    },
+    Param {
+        name: "hash-max-listpack-entries",
+        get: |config| config.hash_limits().max_entries.to_string(),
+        set: |config, val| {
+            config
+                .hash_max_listpack_entries
+                .store(parse_number(val)? as usize, Ordering::Relaxed);
+            Ok(())
+        },
+    },
+    Param {
+        name: "hash-max-listpack-value",
+        get: |config| config.hash_limits().max_value.to_string(),
+        set: |config, val| {
+            config
+                .hash_max_listpack_value
+                .store(parse_number(val)? as usize, Ordering::Relaxed);
+            Ok(())
+        },
+    },
+    Param {
+        name: "set-max-intset-entries",
+        get: |config| config.set_max_intset_entries().to_string(),
+        set: |config, val| {
+            config
+                .set_max_intset_entries
+                .store(parse_number(val)? as usize, Ordering::Relaxed);
+            Ok(())
+        },
+    },
+    Param {
+        name: "min-replicas-to-write",
+        get: |config| config.min_replicas_to_write().to_string(),
+        set: |config, val| {
+            config
+                .min_replicas_to_write
+                .store(parse_number(val)? as usize, Ordering::Relaxed);
+            Ok(())
+        },
+    },
+    Param {
+        name: "min-replicas-max-lag",
+        get: |config| config.min_replicas_max_lag().as_secs().to_string(),
+        set: |config, val| {
+            config.min_replicas_max_lag.store(parse_number(val)?, Ordering::Relaxed);
+            Ok(())
+        },
+    },
+    Param {
+        name: "tracking-table-max-keys",
+        get: |config| config.tracking_table_max_keys().to_string(),
+        set: |config, val| {
+            config
+                .tracking_table_max_keys
+                .store(parse_number(val)? as usize, Ordering::Relaxed);
+            Ok(())
+        },
+    },
+];
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            databases: 16,
+            enable_debug_command: false,
+            notify_keyspace_events: AtomicU32::new(0),
+            maxmemory: AtomicUsize::new(0),
+            maxmemory_policy: AtomicU8::new(0),
+            maxmemory_samples: AtomicUsize::new(5),
+            lua_time_limit: AtomicU64::new(5000),
+            hll_sparse_max_bytes: AtomicUsize::new(3000),
+            list_max_listpack_size: AtomicI64::new(list::DEFAULT_MAX_LISTPACK_SIZE),
+            hash_max_listpack_entries: AtomicUsize::new(hash::DEFAULT_MAX_LISTPACK_ENTRIES),
+            hash_max_listpack_value: AtomicUsize::new(hash::DEFAULT_MAX_LISTPACK_VALUE),
+            set_max_intset_entries: AtomicUsize::new(set::DEFAULT_MAX_INTSET_ENTRIES),
+            min_replicas_to_write: AtomicUsize::new(0),
+            min_replicas_max_lag: AtomicU64::new(10),
+            tracking_table_max_keys: AtomicUsize::new(1_000_000),
+        }
+    }
+}
+
+impl Config {
+    /// 指定逻辑数据库的个数, 至少为 1
+    pub fn with_databases(mut self, databases: usize) -> Self {
+        self.databases = databases.max(1);
+        self
+    }
+
+    pub fn databases(&self) -> usize {
+        self.databases
+    }
+
+    /// 允许执行 DEBUG 命令. DEBUG 可以让服务端休眠甚至崩溃, 默认是关闭的
+    pub fn with_debug_command(mut self, enabled: bool) -> Self {
+        self.enable_debug_command = enabled;
+        self
+    }
+
+    pub fn debug_command_enabled(&self) -> bool {
+        self.enable_debug_command
+    }
+
+    pub fn notify_flags(&self) -> Flags {
+        Flags::from_bits(self.notify_keyspace_events.load(Ordering::Relaxed))
+    }
+
+    /// 最大内存, 0 代表不限制
+    pub fn maxmemory(&self) -> usize {
+        self.maxmemory.load(Ordering::Relaxed)
+    }
+
+    pub fn maxmemory_policy(&self) -> Policy {
+        Policy::ALL[self.maxmemory_policy.load(Ordering::Relaxed) as usize]
+    }
+
+    pub fn maxmemory_samples(&self) -> usize {
+        self.maxmemory_samples.load(Ordering::Relaxed)
+    }
+
+    /// 脚本执行超过该时间之后, 其他命令会收到 BUSY 错误, 并且可以通过 SCRIPT KILL 终止脚本
+    pub fn lua_time_limit(&self) -> Duration {
+        Duration::from_millis(self.lua_time_limit.load(Ordering::Relaxed))
+    }
+
+    /// HyperLogLog 的 sparse 编码超过该长度之后转换为 dense 编码
+    pub fn hll_sparse_max_bytes(&self) -> usize {
+        self.hll_sparse_max_bytes.load(Ordering::Relaxed)
+    }
+
+    /// 列表的 listpack 编码的上限, 正数限制元素个数, 负数限制占用的字节数, 见 `crate::list`
+    pub fn list_max_listpack_size(&self) -> i64 {
+        self.list_max_listpack_size.load(Ordering::Relaxed)
+    }
+
+    /// 哈希的 listpack 编码的上限
+    pub fn hash_limits(&self) -> hash::Limits {
+        hash::Limits {
+            max_entries: self.hash_max_listpack_entries.load(Ordering::Relaxed),
+            max_value: self.hash_max_listpack_value.load(Ordering::Relaxed),
+        }
+    }
+
+    /// 集合的 intset 编码最多包含的成员个数
+    pub fn set_max_intset_entries(&self) -> usize {
+        self.set_max_intset_entries.load(Ordering::Relaxed)
+    }
+
+    /// 健康的副本少于该值时拒绝写命令, 0 代表不检查
+    pub fn min_replicas_to_write(&self) -> usize {
+        self.min_replicas_to_write.load(Ordering::Relaxed)
+    }
+
+    /// 副本最近一次确认复制偏移量的时间距今不超过该值时, 才算是健康的副本
+    pub fn min_replicas_max_lag(&self) -> Duration {
+        Duration::from_secs(self.min_replicas_max_lag.load(Ordering::Relaxed))
+    }
+
+    /// CLIENT TRACKING 最多记录的 key 的个数, 0 代表不限制
+    pub fn tracking_table_max_keys(&self) -> usize {
+        self.tracking_table_max_keys.load(Ordering::Relaxed)
+    }
+
+    /// 返回所有名称匹配 pattern 的配置项
+    pub fn get(&self, pattern: &str) -> Vec<(String, String)> {
+        PARAMS
+            .iter()
+            .filter(|param| glob::matches_nocase(pattern.as_bytes(), param.name.as_bytes()))
+            .map(|param| (param.name.to_string(), (param.get)(self)))
+            .collect()
+    }
+
+    pub fn set(&self, name: &str, value: &str) -> Result<(), String> {
+        let param = PARAMS
+            .iter()
+            .find(|param| param.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("ERR Unknown option or number of arguments for CONFIG SET - '{}'", name))?;
+
+        (param.set)(self, value)
+            .map_err(|err| format!("ERR Invalid argument '{}' for CONFIG SET '{}' - {}", value, param.name, err))
+    }
+}
+
+fn parse_number(val: &str) -> Result<u64, String> {
+    val.parse().map_err(|_| "argument couldn't be parsed into an integer".to_string())
+}
+
+/// 解析带有单位的内存大小, 例如 `100mb`、`1gb`
+pub fn parse_memory(val: &str) -> Result<usize, String> {
+    let lower = val.to_ascii_lowercase();
+    let units: [(&str, usize); 8] = [
+        ("kb", 1024),
+        ("mb", 1024 * 1024),
+        ("gb", 1024 * 1024 * 1024),
+        ("k", 1000),
+        ("m", 1000 * 1000),
+        ("g", 1000 * 1000 * 1000),
+        ("b", 1),
+        ("", 1),
+    ];
+
+    for (suffix, multiplier) in units {
+        if let Some(num) = lower.strip_suffix(suffix) {
+            if let Ok(num) = num.parse::<usize>() {
+                return Ok(num.saturating_mul(multiplier));
+            }
+        }
+    }
+
+    Err("argument must be a memory value".to_string())
+}
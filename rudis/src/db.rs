@@ -0,0 +1,1017 @@
+use std::{
+    collections::BTreeSet,
+    hash::{DefaultHasher, Hash as _, Hasher},
+    mem,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::Duration,
+};
+
+use bytes::Bytes;
+use indexmap::IndexMap;
+use log::debug;
+use tokio::{
+    sync::Notify,
+    time::{self, Instant},
+};
+
+use crate::{
+    bloom::Bloom,
+    cms::CountMinSketch,
+    config::{Config, Policy},
+    dump,
+    hash::Hash,
+    json,
+    list::List,
+    notify::{self, Class},
+    pubsub::PubSub,
+    script::Scripts,
+    set::Set,
+    timeseries::TimeSeries,
+    tracking::Tracking,
+    zset::SortedSet,
+};
+
+/// 每个 key 除了 key 和 value 本身之外, 额外占用内存的估算值
+pub const ENTRY_OVERHEAD: usize = 64;
+
+/// 集合类型中每个元素额外占用内存的估算值
+const ELEMENT_OVERHEAD: usize = 16;
+
+/// 估算集合类型的内存时, 采样的元素个数
+const SIZE_SAMPLES: usize = 5;
+
+/// 新写入的 key 的访问频率, 避免新 key 立刻被 LFU 淘汰. 以下三个常量与 redis 的默认配置相同
+const LFU_INIT_VAL: u8 = 5;
+
+/// 访问频率是对数增长的, 该值越大, 计数器增长得越慢
+const LFU_LOG_FACTOR: f64 = 10.0;
+
+/// 每隔多久没有被访问, 访问频率减一
+const LFU_DECAY_TIME: Duration = Duration::from_secs(60);
+
+/// 后台清理过期 key 的间隔, 相当于 redis 中的 `hz 10`
+const PURGE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 服务端的共享状态, 与 `mini_redis::Db` 相同, 内部通过 Arc 共享, clone 的开销很小
+///
+/// 与 redis 一样, 服务端有多个从 0 开始编号的逻辑数据库, 每个数据库都有独立的 keyspace.
+/// key 按照 hash 分配到数据库的不同分片中, 每个分片都有一个独立的 `std::sync::Mutex`,
+/// 降低多个连接同时访问时的锁竞争
+///
+/// 需要同时锁定多个分片时, 一律按照 (数据库下标, 分片下标) 从小到大的顺序加锁, 避免死锁
+#[derive(Clone)]
+pub struct Db {
+    shared: Arc<Shared>,
+}
+
+/// Db 的包装, 被 drop 时会通知后台任务退出
+///
+/// 当所有的 Db 都被 drop 时, 后台任务中仍然持有 Db, 导致 Arc 无法被释放,
+/// 因此需要由最外层持有 DbDropGuard 显式的关闭后台任务
+pub struct DbDropGuard {
+    db: Db,
+}
+
+struct Shared {
+    /// 每个逻辑数据库的分片, 所有数据库的分片个数相同
+    databases: Vec<Vec<Mutex<Shard>>>,
+    pubsub: PubSub,
+    tracking: Tracking,
+    scripts: Scripts,
+    config: Config,
+
+    /// 所有数据库中 key 占用内存的估算值
+    used_memory: AtomicUsize,
+
+    /// used_memory 的历史最大值
+    peak_memory: AtomicUsize,
+
+    /// 淘汰 key 时用于随机采样
+    rng: AtomicU64,
+
+    /// 是否由后台任务主动清理过期的 key, 可以通过 DEBUG SET-ACTIVE-EXPIRE 关闭
+    active_expire: AtomicBool,
+
+    shutdown: AtomicBool,
+    background_task: Notify,
+}
+
+#[derive(Default)]
+pub struct Shard {
+    /// 使用 IndexMap 而不是 HashMap: 淘汰 key 时需要 O(1) 的随机采样
+    entries: IndexMap<String, Entry>,
+
+    /// 按照过期时间排序的 key, 后台任务从头部依次清理已经过期的 key
+    expires: BTreeSet<(Instant, String)>,
+
+    /// 当前分片中 key 占用内存的估算值
+    used: usize,
+}
+
+pub struct Entry {
+    value: Value,
+    expires_at: Option<Instant>,
+
+    /// 最近一次访问的时间, 用于 LRU 淘汰
+    accessed: Instant,
+
+    /// 对数访问频率, 用于 LFU 淘汰. 与 redis 不同, 无论使用哪种淘汰策略, 访问时间与频率都会被记录
+    freq: u8,
+
+    /// 内存占用的估算值
+    size: usize,
+}
+
+impl Entry {
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    /// 距离最近一次访问的时间
+    pub fn idle(&self) -> Duration {
+        self.accessed.elapsed()
+    }
+
+    /// 考虑衰减之后的访问频率
+    pub fn freq(&self) -> u8 {
+        let periods = self.idle().as_secs() / LFU_DECAY_TIME.as_secs();
+        self.freq.saturating_sub(periods.min(u8::MAX as u64) as u8)
+    }
+
+    /// 内存占用的估算值, 包括 key 本身
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// 记录一次访问: 先按照空闲时间衰减, 再以 `1 / ((freq - LFU_INIT_VAL) * LFU_LOG_FACTOR + 1)`
+    /// 的概率加一, 因此访问频率是对数增长的, 一个字节就能表示上百万次访问
+    fn touch(&mut self, random: usize) {
+        let mut freq = self.freq();
+        if freq < u8::MAX {
+            let base = freq.saturating_sub(LFU_INIT_VAL) as f64;
+            let r = (random % 1_000_000) as f64 / 1_000_000.0;
+            if r < 1.0 / (base * LFU_LOG_FACTOR + 1.0) {
+                freq += 1;
+            }
+        }
+
+        self.freq = freq;
+        self.accessed = Instant::now();
+    }
+}
+
+/// 一个数据库的统计信息
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stats {
+    pub keys: usize,
+
+    /// 设置了过期时间的 key 的个数
+    pub expires: usize,
+
+    /// 平均剩余过期时间(毫秒)
+    pub avg_ttl: u64,
+}
+
+/// key 对应的值
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(Bytes),
+    List(List),
+    Hash(Hash),
+    Set(Set),
+    ZSet(SortedSet),
+    Json(serde_json::Value),
+    Bloom(Bloom),
+    Cms(CountMinSketch),
+    TimeSeries(TimeSeries),
+}
+
+impl Value {
+    /// TYPE 命令返回的类型名称
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Hash(_) => "hash",
+            Value::Set(_) => "set",
+            Value::ZSet(_) => "zset",
+            // 与 RedisJSON 模块注册的类型名称相同
+            Value::Json(_) => "ReJSON-RL",
+            // 与 RedisBloom 模块注册的类型名称相同
+            Value::Bloom(_) => "MBbloom--",
+            Value::Cms(_) => "CMSk-TYPE",
+            // 与 RedisTimeSeries 模块注册的类型名称相同
+            Value::TimeSeries(_) => "TSDB-TYPE",
+        }
+    }
+
+    /// OBJECT ENCODING 返回的编码名称, 与 redis 保持一致
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Value::String(val) => string_encoding(val),
+            Value::List(list) => list.encoding(),
+            Value::Hash(hash) => hash.encoding(),
+            Value::Set(set) => set.encoding(),
+            // rudis 的有序集合使用哈希表 + BTreeSet, 对应 redis 的 skiplist 编码
+            Value::ZSet(_) => "skiplist",
+            // 与 redis 的模块类型相同
+            Value::Json(_) | Value::Bloom(_) | Value::Cms(_) | Value::TimeSeries(_) => "raw",
+        }
+    }
+
+    /// 集合类型为空时 key 会被自动删除
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Value::String(_) => false,
+            Value::List(list) => list.is_empty(),
+            Value::Hash(hash) => hash.is_empty(),
+            Value::Set(set) => set.is_empty(),
+            Value::ZSet(zset) => zset.is_empty(),
+            // 布隆过滤器等概率类型即使没有元素也不会被删除
+            Value::Json(_) | Value::Bloom(_) | Value::Cms(_) | Value::TimeSeries(_) => false,
+        }
+    }
+
+    /// 估算 value 占用的内存
+    ///
+    /// 集合类型只采样前几个元素, 再按照元素个数推算, 避免每次写入都遍历整个集合
+    pub fn approx_size(&self) -> usize {
+        self.sampled_size(SIZE_SAMPLES)
+    }
+
+    /// 与 `approx_size` 相同, 但是可以指定集合类型采样的元素个数, 用于 `MEMORY USAGE key SAMPLES n`
+    pub fn sampled_size(&self, samples: usize) -> usize {
+        match self {
+            Value::String(val) => val.len(),
+            Value::List(list) => match list.listpack_bytes() {
+                Some(bytes) => bytes,
+                None => sampled_size(list.iter().map(|val| val.len()), list.len(), samples),
+            },
+            Value::Hash(hash) => match hash.listpack_bytes() {
+                Some(bytes) => bytes,
+                None => sampled_size(
+                    hash.iter().map(|(field, value)| field.len() + value.len()),
+                    hash.len(),
+                    samples,
+                ),
+            },
+            Value::Set(set) => match set.intset_bytes() {
+                Some(bytes) => bytes,
+                None => sampled_size(set.iter().map(|member| member.len()), set.len(), samples),
+            },
+            // 每个成员同时保存在哈希表和 BTreeSet 中, 再加上两份分数
+            Value::ZSet(zset) => sampled_size(
+                zset.iter().map(|(member, _)| member.len() * 2 + 16),
+                zset.len(),
+                samples,
+            ),
+            Value::Json(doc) => json::approx_size(doc),
+            Value::Bloom(bloom) => bloom.size(),
+            Value::Cms(cms) => cms.size(),
+            Value::TimeSeries(series) => series.size(),
+        }
+    }
+}
+
+/// 与 redis 相同: 可以表示为 i64 的字符串为 int, 不超过 44 字节的为 embstr, 其余为 raw.
+/// rudis 的字符串都是 `Bytes`, 这里只是报告 redis 在相同情况下会使用的编码
+fn string_encoding(val: &[u8]) -> &'static str {
+    let is_int = val.len() <= 20
+        && std::str::from_utf8(val)
+            .ok()
+            .and_then(|s| s.parse::<i64>().ok())
+            .is_some_and(|n| n.to_string().as_bytes() == val);
+
+    if is_int {
+        "int"
+    } else if val.len() <= 44 {
+        "embstr"
+    } else {
+        "raw"
+    }
+}
+
+fn sampled_size(sizes: impl Iterator<Item = usize>, len: usize, samples: usize) -> usize {
+    let (n, sum) = sizes
+        .take(samples)
+        .fold((0, 0), |(n, sum), size| (n + 1, sum + size));
+
+    if n == 0 {
+        return 0;
+    }
+
+    (sum / n + ELEMENT_OVERHEAD) * len
+}
+
+impl Shard {
+    fn is_expired(&self, key: &str, now: Instant) -> bool {
+        matches!(
+            self.entries.get(key),
+            Some(Entry { expires_at: Some(when), .. }) if *when <= now
+        )
+    }
+
+    fn insert(&mut self, key: String, value: Value, expires_at: Option<Instant>) -> Option<Entry> {
+        let old = self.remove(&key);
+
+        if let Some(when) = expires_at {
+            self.expires.insert((when, key.clone()));
+        }
+
+        let size = key.len() + value.approx_size() + ENTRY_OVERHEAD;
+        self.used += size;
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                expires_at,
+                accessed: Instant::now(),
+                freq: LFU_INIT_VAL,
+                size,
+            },
+        );
+
+        old
+    }
+
+    fn remove(&mut self, key: &str) -> Option<Entry> {
+        // swap_remove 是 O(1) 的, 代价是会打乱 key 的顺序, 而我们并不关心顺序
+        let entry = self.entries.swap_remove(key)?;
+
+        if let Some(when) = entry.expires_at {
+            self.expires.remove(&(when, key.to_string()));
+        }
+        self.used -= entry.size;
+
+        Some(entry)
+    }
+
+    fn set_expire(&mut self, key: &str, expires_at: Option<Instant>) -> bool {
+        let Some(entry) = self.entries.get_mut(key) else {
+            return false;
+        };
+
+        if let Some(when) = entry.expires_at {
+            self.expires.remove(&(when, key.to_string()));
+        }
+        if let Some(when) = expires_at {
+            self.expires.insert((when, key.to_string()));
+        }
+        entry.expires_at = expires_at;
+
+        true
+    }
+
+    /// value 被修改之后重新估算内存占用
+    fn resize(&mut self, key: &str) {
+        if let Some(entry) = self.entries.get_mut(key) {
+            let size = key.len() + entry.value.approx_size() + ENTRY_OVERHEAD;
+            self.used = self.used - entry.size + size;
+            entry.size = size;
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.expires.clear();
+        self.used = 0;
+    }
+
+    /// 移除所有已经过期的 key, 返回被移除的 key
+    fn purge_expired(&mut self, now: Instant) -> Vec<String> {
+        let mut expired = vec![];
+
+        while let Some((when, key)) = self.expires.first() {
+            if *when > now {
+                break;
+            }
+
+            let key = key.clone();
+            self.remove(&key);
+            expired.push(key);
+        }
+
+        expired
+    }
+}
+
+/// 命令执行期间持有的分片锁
+///
+/// 所有对 key 的读写都需要通过 Guard 进行, Guard 负责:
+/// + 访问 key 时检查是否已经过期(惰性删除)
+/// + 维护内存占用的估算值
+/// + 发布 keyspace notifications
+pub struct Guard<'a> {
+    db: &'a Db,
+
+    /// 分片所属数据库的下标
+    index: usize,
+    shards: Vec<(usize, MutexGuard<'a, Shard>)>,
+}
+
+impl<'a> Guard<'a> {
+    /// 获取 key 所在的分片
+    ///
+    /// # Panics
+    ///
+    /// key 所在的分片没有被锁定时会 panic, 这属于命令实现的错误
+    fn shard(&mut self, key: &str) -> &mut Shard {
+        let idx = self.db.shard_index(key);
+
+        self.shards
+            .iter_mut()
+            .find(|(i, _)| *i == idx)
+            .map(|(_, shard)| &mut **shard)
+            .expect("the shard of key is not locked")
+    }
+
+    /// 在分片上执行操作, 并同步内存占用的变化
+    fn with_shard<R>(&mut self, key: &str, f: impl FnOnce(&mut Shard) -> R) -> R {
+        let db = self.db;
+        let shard = self.shard(key);
+
+        let before = shard.used;
+        let res = f(shard);
+        db.adjust_memory(before, shard.used);
+
+        res
+    }
+
+    /// key 已经过期时将其删除, 并发布 expired 事件
+    fn expire_if_needed(&mut self, key: &str) {
+        let now = Instant::now();
+        let expired = self.with_shard(key, |shard| {
+            if shard.is_expired(key, now) {
+                shard.remove(key);
+                true
+            } else {
+                false
+            }
+        });
+
+        if expired {
+            self.notify(Class::Expired, "expired", key);
+        }
+    }
+
+    pub fn get(&mut self, key: &str) -> Option<&Value> {
+        self.expire_if_needed(key);
+
+        let (db, index) = (self.db, self.index);
+        match self.shard(key).entries.get_mut(key) {
+            Some(entry) => {
+                entry.touch(db.random());
+                Some(&entry.value)
+            }
+            None => {
+                db.notify(index, Class::KeyMiss, "keymiss", key);
+                None
+            }
+        }
+    }
+
+    /// 读取 key 但是不记录访问, 用于 OBJECT、MEMORY 等观察 key 的命令
+    pub fn peek(&mut self, key: &str) -> Option<&Entry> {
+        self.expire_if_needed(key);
+        self.shard(key).entries.get(key)
+    }
+
+    pub fn exists(&mut self, key: &str) -> bool {
+        self.expire_if_needed(key);
+        self.shard(key).entries.contains_key(key)
+    }
+
+    /// 修改 key 对应的 value, key 不存在时返回 None
+    ///
+    /// 修改之后集合类型为空时, key 会被自动删除
+    pub fn update<R>(&mut self, key: &str, f: impl FnOnce(&mut Value) -> R) -> Option<R> {
+        self.expire_if_needed(key);
+
+        let random = self.db.random();
+        self.with_shard(key, |shard| {
+            let entry = shard.entries.get_mut(key)?;
+            entry.touch(random);
+
+            let res = f(&mut entry.value);
+            if entry.value.is_empty() {
+                shard.remove(key);
+            } else {
+                shard.resize(key);
+            }
+
+            Some(res)
+        })
+    }
+
+    /// 写入 key, 会覆盖原有的 value 以及过期时间, 返回原有的 value
+    pub fn insert(&mut self, key: &str, value: Value, expires_at: Option<Instant>) -> Option<Value> {
+        self.expire_if_needed(key);
+
+        let old = self.with_shard(key, |shard| shard.insert(key.to_string(), value, expires_at));
+        if old.is_none() {
+            self.notify(Class::New, "new", key);
+        }
+
+        old.map(|entry| entry.value)
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        self.expire_if_needed(key);
+
+        self.with_shard(key, |shard| shard.remove(key))
+            .map(|entry| entry.value)
+    }
+
+    /// key 的过期时间, key 不存在时返回 None
+    pub fn expires_at(&mut self, key: &str) -> Option<Option<Instant>> {
+        self.expire_if_needed(key);
+
+        self.shard(key).entries.get(key).map(|entry| entry.expires_at)
+    }
+
+    /// 设置(或者移除)过期时间, key 不存在时返回 false
+    pub fn set_expire(&mut self, key: &str, expires_at: Option<Instant>) -> bool {
+        self.expire_if_needed(key);
+
+        self.shard(key).set_expire(key, expires_at)
+    }
+
+    pub fn notify(&self, class: Class, event: &str, key: &str) {
+        self.db.notify(self.index, class, event, key);
+    }
+}
+
+impl DbDropGuard {
+    pub fn new(shards: usize, config: Config) -> DbDropGuard {
+        DbDropGuard {
+            db: Db::new(shards, config),
+        }
+    }
+
+    pub fn db(&self) -> Db {
+        self.db.clone()
+    }
+}
+
+impl Drop for DbDropGuard {
+    fn drop(&mut self) {
+        self.db.shutdown_purge_task();
+    }
+}
+
+impl Db {
+    /// 创建 Db 并启动清理过期 key 的后台任务, 需要在 tokio 运行时中调用
+    ///
+    /// 数据库的个数由 `config.databases()` 决定, 每个数据库都有 `shards` 个分片
+    pub fn new(mut shards: usize, config: Config) -> Db {
+        if shards == 0 {
+            shards = 1;
+        }
+
+        let databases = (0..config.databases())
+            .map(|_| (0..shards).map(|_| Mutex::new(Shard::default())).collect())
+            .collect();
+
+        let shared = Arc::new(Shared {
+            databases,
+            pubsub: PubSub::default(),
+            tracking: Tracking::default(),
+            scripts: Scripts::default(),
+            config,
+            used_memory: AtomicUsize::new(0),
+            peak_memory: AtomicUsize::new(0),
+            rng: AtomicU64::new(0x2545_F491_4F6C_DD1D),
+            active_expire: AtomicBool::new(true),
+            shutdown: AtomicBool::new(false),
+            background_task: Notify::new(),
+        });
+
+        let db = Db { shared };
+        tokio::spawn(purge_expired_tasks(db.clone()));
+
+        db
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.shared.config
+    }
+
+    pub fn pubsub(&self) -> &PubSub {
+        &self.shared.pubsub
+    }
+
+    pub fn tracking(&self) -> &Tracking {
+        &self.shared.tracking
+    }
+
+    pub fn scripts(&self) -> &Scripts {
+        &self.shared.scripts
+    }
+
+    pub fn used_memory(&self) -> usize {
+        self.shared.used_memory.load(Ordering::Relaxed)
+    }
+
+    pub fn peak_memory(&self) -> usize {
+        self.shared.peak_memory.load(Ordering::Relaxed)
+    }
+
+    /// 逻辑数据库的个数
+    pub fn databases(&self) -> usize {
+        self.shared.databases.len()
+    }
+
+    fn shard_index(&self, key: &str) -> usize {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+
+        (hasher.finish() % self.shared.databases[0].len() as u64) as usize
+    }
+
+    /// 锁定第 `db` 个数据库中 keys 所在的分片
+    ///
+    /// 分片按照下标从小到大的顺序加锁, 保证多个 key 的命令之间不会出现死锁
+    pub fn lock(&self, db: usize, keys: &[&str]) -> Guard<'_> {
+        let mut indexes: Vec<usize> = keys.iter().map(|key| self.shard_index(key)).collect();
+        indexes.sort_unstable();
+        indexes.dedup();
+
+        self.lock_shards(db, indexes)
+    }
+
+    /// 锁定第 `db` 个数据库的所有分片
+    pub fn lock_all(&self, db: usize) -> Guard<'_> {
+        self.lock_shards(db, (0..self.shared.databases[db].len()).collect())
+    }
+
+    fn lock_shards(&self, db: usize, indexes: Vec<usize>) -> Guard<'_> {
+        let shards = indexes
+            .into_iter()
+            .map(|i| (i, self.shared.databases[db][i].lock().unwrap()))
+            .collect();
+
+        Guard {
+            db: self,
+            index: db,
+            shards,
+        }
+    }
+
+    /// 删除第 `db` 个数据库中所有的 key
+    pub fn flush(&self, db: usize) {
+        for shard in &self.shared.databases[db] {
+            let mut shard = shard.lock().unwrap();
+            self.adjust_memory(shard.used, 0);
+            shard.clear();
+        }
+    }
+
+    /// 交换两个数据库的数据, 连接无需重新 SELECT 就能看到另一个数据库的数据
+    ///
+    /// 所有数据库的分片个数相同, 同一个 key 在两个数据库中位于相同下标的分片,
+    /// 因此逐个交换分片即可
+    pub fn swap(&self, a: usize, b: usize) {
+        if a == b {
+            return;
+        }
+
+        let lock = |db: usize| -> Vec<MutexGuard<'_, Shard>> {
+            self.shared.databases[db]
+                .iter()
+                .map(|shard| shard.lock().unwrap())
+                .collect()
+        };
+
+        let mut first = lock(a.min(b));
+        let mut second = lock(a.max(b));
+        for (x, y) in first.iter_mut().zip(second.iter_mut()) {
+            mem::swap(&mut **x, &mut **y);
+        }
+
+        // 选择了这两个数据库的连接看到的数据都变了, 与 FLUSHDB 相同, 所有的缓存都需要失效
+        self.shared.tracking.invalidate_all(&self.shared.pubsub);
+    }
+
+    /// 第 `db` 个数据库的统计信息, 用于 `DBSIZE` 与 `INFO keyspace`
+    ///
+    /// 分片被逐个锁定, 因此结果不是一个精确的快照. 与 redis 相同, 已经过期但尚未被清理的 key 也会被统计在内
+    pub fn stats(&self, db: usize) -> Stats {
+        let now = Instant::now();
+        let mut stats = Stats::default();
+        let mut ttl_sum: u128 = 0;
+
+        for shard in &self.shared.databases[db] {
+            let shard = shard.lock().unwrap();
+            stats.keys += shard.entries.len();
+            stats.expires += shard.expires.len();
+            ttl_sum += shard
+                .expires
+                .iter()
+                .map(|(when, _)| when.saturating_duration_since(now).as_millis())
+                .sum::<u128>();
+        }
+
+        if stats.expires > 0 {
+            stats.avg_ttl = (ttl_sum / stats.expires as u128) as u64;
+        }
+
+        stats
+    }
+
+    /// 遍历所有数据库的分片: (数据库下标, 分片下标, 分片)
+    fn all_shards(&self) -> impl Iterator<Item = (usize, usize, &Mutex<Shard>)> {
+        self.shared
+            .databases
+            .iter()
+            .enumerate()
+            .flat_map(|(db, shards)| {
+                shards.iter().enumerate().map(move |(idx, shard)| (db, idx, shard))
+            })
+    }
+
+    fn adjust_memory(&self, before: usize, after: usize) {
+        if after > before {
+            let used = self
+                .shared
+                .used_memory
+                .fetch_add(after - before, Ordering::Relaxed);
+            self.shared
+                .peak_memory
+                .fetch_max(used + after - before, Ordering::Relaxed);
+        } else if before > after {
+            self.shared
+                .used_memory
+                .fetch_sub(before - after, Ordering::Relaxed);
+        }
+    }
+
+    /// 发布第 `db` 个数据库的 keyspace notification
+    pub fn notify(&self, db: usize, class: Class, event: &str, key: &str) {
+        // 所有修改 key 的操作都会发出通知, 客户端缓存的失效也在这里处理.
+        // keymiss 不是修改, new 总是伴随着另一个事件
+        if !matches!(class, Class::KeyMiss | Class::New) {
+            self.shared.tracking.invalidate(key, &self.shared.pubsub);
+        }
+
+        let flags = self.shared.config.notify_flags();
+        if !flags.enabled(class) || self.shared.pubsub.is_empty() {
+            return;
+        }
+
+        let pubsub = &self.shared.pubsub;
+        if flags.keyspace() {
+            pubsub.publish(
+                &notify::keyspace_channel(db, key),
+                Bytes::from(event.to_string()),
+            );
+        }
+        if flags.keyevent() {
+            pubsub.publish(
+                &notify::keyevent_channel(db, event),
+                Bytes::from(key.to_string()),
+            );
+        }
+    }
+
+    /// 内存超过 `maxmemory` 时按照淘汰策略删除 key, 直到内存回到限制以内
+    ///
+    /// 写命令执行之前调用, 无法腾出足够的内存时返回 OOM 错误
+    pub fn evict(&self) -> Result<(), String> {
+        const OOM: &str = "OOM command not allowed when used memory > 'maxmemory'.";
+
+        let config = &self.shared.config;
+        let maxmemory = config.maxmemory();
+        if maxmemory == 0 {
+            return Ok(());
+        }
+
+        while self.used_memory() > maxmemory {
+            let policy = config.maxmemory_policy();
+            if policy == Policy::NoEviction {
+                return Err(OOM.to_string());
+            }
+
+            let Some((db, idx, key)) =
+                self.pick_eviction_candidate(policy, config.maxmemory_samples())
+            else {
+                return Err(OOM.to_string());
+            };
+
+            let evicted = {
+                let mut shard = self.shared.databases[db][idx].lock().unwrap();
+                let before = shard.used;
+                let evicted = shard.remove(&key).is_some();
+                self.adjust_memory(before, shard.used);
+
+                evicted
+            };
+
+            if evicted {
+                debug!("evict key={} by policy {}", key, policy.name());
+                self.notify(db, Class::Evicted, "evicted", &key);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 在所有数据库的每个分片中随机采样若干个 key, 按照淘汰策略选出最合适的一个
+    fn pick_eviction_candidate(
+        &self,
+        policy: Policy,
+        samples: usize,
+    ) -> Option<(usize, usize, String)> {
+        // (数据库下标, 分片下标, key, 分数), 分数越小越应该被淘汰.
+        // LFU 先比较访问频率, 频率相同时再比较访问时间
+        let mut best: Option<(usize, usize, String, (u8, Instant))> = None;
+
+        for (db, idx, shard) in self.all_shards() {
+            let shard = shard.lock().unwrap();
+            let len = shard.entries.len();
+            if len == 0 {
+                continue;
+            }
+
+            for _ in 0..samples {
+                let (key, entry) = shard.entries.get_index(self.random() % len).unwrap();
+
+                if policy.volatile_only() && entry.expires_at.is_none() {
+                    continue;
+                }
+                let score = match policy {
+                    Policy::AllKeysLru | Policy::VolatileLru => (0, entry.accessed),
+                    Policy::AllKeysLfu | Policy::VolatileLfu => (entry.freq(), entry.accessed),
+                    Policy::VolatileTtl => (0, entry.expires_at.unwrap()),
+                    // 随机淘汰时使用第一个采样到的 key 即可
+                    _ => return Some((db, idx, key.clone())),
+                };
+
+                if best.as_ref().is_none_or(|(_, _, _, best)| score < *best) {
+                    best = Some((db, idx, key.clone(), score));
+                }
+            }
+        }
+
+        best.map(|(db, idx, key, _)| (db, idx, key))
+    }
+
+    /// 从 cursor 开始遍历第 `db` 个数据库, 返回下一个 cursor 以及满足 filter 的 key, cursor 为 0 代表遍历结束
+    ///
+    /// cursor 的高 32 位是分片的下标, 低 32 位是分片中的位置, 每次只锁定一个分片.
+    /// 删除 key 时 swap_remove 会把最后一个 key 移动到被删除的位置,
+    /// 因此遍历期间有 key 被删除时, 少量一直存在的 key 可能会被跳过
+    pub fn scan(
+        &self,
+        db: usize,
+        cursor: u64,
+        count: usize,
+        filter: impl Fn(&str, &Value) -> bool,
+    ) -> (u64, Vec<String>) {
+        let (mut shard, mut pos) = ((cursor >> 32) as usize, (cursor & 0xffff_ffff) as usize);
+        let shards = &self.shared.databases[db];
+        let now = Instant::now();
+        let mut keys = vec![];
+
+        while shard < shards.len() {
+            let guard = shards[shard].lock().unwrap();
+            while pos < guard.entries.len() {
+                if keys.len() >= count {
+                    return (((shard as u64) << 32) | pos as u64, keys);
+                }
+
+                let (key, entry) = guard.entries.get_index(pos).unwrap();
+                if !guard.is_expired(key, now) && filter(key, &entry.value) {
+                    keys.push(key.clone());
+                }
+                pos += 1;
+            }
+
+            shard += 1;
+            pos = 0;
+        }
+
+        (0, keys)
+    }
+
+    /// 遍历所有数据库中没有过期的 key, 依次锁定每个分片, 参数为数据库的下标、key 以及 entry
+    pub fn for_each(&self, mut f: impl FnMut(usize, &str, &Entry)) {
+        let now = Instant::now();
+        for (db, _, shard) in self.all_shards() {
+            let shard = shard.lock().unwrap();
+            for (key, entry) in &shard.entries {
+                if !shard.is_expired(key, now) {
+                    f(db, key, entry);
+                }
+            }
+        }
+    }
+
+    /// 关闭之后过期的 key 只在被访问时删除, 用于测试过期相关的逻辑
+    pub fn set_active_expire(&self, enabled: bool) {
+        self.shared.active_expire.store(enabled, Ordering::Relaxed);
+    }
+
+    /// DEBUG RELOAD: 序列化所有的 key 之后清空, 再反序列化写回, 返回无法恢复的 key 的个数
+    ///
+    /// rudis 没有 RDB 文件, 这里使用 DUMP 的编码代替, 过期时间保持不变.
+    /// DUMP 的编码中没有时间序列的降采样规则, 需要从原来的值中取回, 否则 RELOAD 之后规则会丢失
+    pub fn reload(&self) -> usize {
+        let mut failed = 0;
+
+        for (_, _, shard) in self.all_shards() {
+            let mut shard = shard.lock().unwrap();
+            let before = shard.used;
+
+            let saved: Vec<(String, Entry)> = shard.entries.drain(..).collect();
+            shard.clear();
+
+            for (key, entry) in saved {
+                match dump::restore(&dump::dump(&entry.value), self.config()) {
+                    Ok(mut value) => {
+                        if let (Value::TimeSeries(old), Value::TimeSeries(new)) =
+                            (&entry.value, &mut value)
+                        {
+                            new.set_source(old.source().map(String::from));
+                            for rule in old.rules() {
+                                new.add_rule(rule.clone());
+                            }
+                        }
+                        shard.insert(key, value, entry.expires_at);
+                    }
+                    Err(_) => failed += 1,
+                }
+            }
+            self.adjust_memory(before, shard.used);
+        }
+
+        failed
+    }
+
+    fn random(&self) -> usize {
+        // xorshift, 只用于采样, 并发时出现重复的随机数也没有关系
+        let mut x = self.shared.rng.load(Ordering::Relaxed);
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.shared.rng.store(x, Ordering::Relaxed);
+
+        x as usize
+    }
+
+    /// 清理所有数据库中已经过期的 key
+    ///
+    /// 脚本执行期间跳过本轮清理, 保证脚本看到的数据集不会被修改
+    fn purge_expired_keys(&self) {
+        if !self.shared.active_expire.load(Ordering::Relaxed) {
+            return;
+        }
+        let Some(_gate) = self.scripts().try_shared() else {
+            return;
+        };
+        let now = Instant::now();
+
+        for (db, _, shard) in self.all_shards() {
+            let expired = {
+                let mut shard = shard.lock().unwrap();
+                let before = shard.used;
+                let expired = shard.purge_expired(now);
+                self.adjust_memory(before, shard.used);
+
+                expired
+            };
+
+            // 释放分片锁之后再发布事件
+            for key in expired {
+                self.notify(db, Class::Expired, "expired", &key);
+            }
+        }
+    }
+
+    fn is_shutdown(&self) -> bool {
+        self.shared.shutdown.load(Ordering::Relaxed)
+    }
+
+    fn shutdown_purge_task(&self) {
+        self.shared.shutdown.store(true, Ordering::Relaxed);
+        self.shared.background_task.notify_one();
+    }
+}
+
+/// 后台任务: 定期清理已经过期的 key
+///
+/// 即使没有客户端访问, 过期的 key 也会被及时删除并发布 expired 事件
+async fn purge_expired_tasks(db: Db) {
+    while !db.is_shutdown() {
+        db.purge_expired_keys();
+
+        tokio::select! {
+            _ = time::sleep(PURGE_INTERVAL) => {}
+            _ = db.shared.background_task.notified() => {}
+        }
+    }
+
+    debug!("purge background task shut down")
+}
@@ -0,0 +1,299 @@
+//! DUMP/RESTORE 使用的序列化格式
+//!
+//! 与 redis 相同, payload 由三部分组成, CRC64 覆盖前两部分, 使用与 redis 相同的 Jones 多项式:
+//!
+//! ```text
+//! | value 的编码 | 版本号(2 字节, 小端) | CRC64(8 字节, 小端) |
+//! ```
+//!
+//! value 的编码由 rudis 自己定义, 与 redis 的 RDB 格式不兼容, payload 只能在 rudis 实例之间传递:
+//! 以类型字节开头, 整数与浮点数都使用 8 字节小端, 字节串带有 8 字节的长度前缀
+
+use bytes::Bytes;
+
+use crate::{
+    bloom::Bloom, cms::CountMinSketch, config::Config, db::Value, hash::Hash, list::List,
+    set::Set, timeseries::TimeSeries, zset::SortedSet,
+};
+
+/// payload 的版本号, 编码格式发生不兼容的变化时递增, RESTORE 拒绝更高版本的 payload
+pub const VERSION: u16 = 1;
+
+pub const PAYLOAD_ERR: &str = "ERR DUMP payload version or checksum are wrong";
+
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_ZSET: u8 = 2;
+const TYPE_JSON: u8 = 3;
+const TYPE_BLOOM: u8 = 4;
+const TYPE_CMS: u8 = 5;
+const TYPE_TIMESERIES: u8 = 6;
+const TYPE_HASH: u8 = 7;
+const TYPE_SET: u8 = 8;
+
+/// 序列化一个 value
+pub fn dump(value: &Value) -> Bytes {
+    let mut out = vec![];
+
+    match value {
+        Value::String(val) => {
+            out.push(TYPE_STRING);
+            put_bytes(&mut out, val);
+        }
+        Value::List(list) => {
+            out.push(TYPE_LIST);
+            put_u64(&mut out, list.len() as u64);
+            for item in list.iter() {
+                put_bytes(&mut out, item);
+            }
+        }
+        Value::Hash(hash) => {
+            out.push(TYPE_HASH);
+            put_u64(&mut out, hash.len() as u64);
+            for (field, value) in hash.iter() {
+                put_bytes(&mut out, field);
+                put_bytes(&mut out, value);
+            }
+        }
+        Value::Set(set) => {
+            out.push(TYPE_SET);
+            put_u64(&mut out, set.len() as u64);
+            for member in set.iter() {
+                put_bytes(&mut out, &member);
+            }
+        }
+        Value::ZSet(zset) => {
+            out.push(TYPE_ZSET);
+            put_u64(&mut out, zset.len() as u64);
+            for (member, score) in zset.iter() {
+                put_bytes(&mut out, member);
+                put_f64(&mut out, score);
+            }
+        }
+        Value::Json(doc) => {
+            out.push(TYPE_JSON);
+            put_bytes(&mut out, doc.to_string().as_bytes());
+        }
+        Value::Bloom(bloom) => {
+            out.push(TYPE_BLOOM);
+            bloom.encode(&mut out);
+        }
+        Value::Cms(cms) => {
+            out.push(TYPE_CMS);
+            cms.encode(&mut out);
+        }
+        Value::TimeSeries(series) => {
+            out.push(TYPE_TIMESERIES);
+            series.encode(&mut out);
+        }
+    }
+
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    let crc = crc64(&out);
+    out.extend_from_slice(&crc.to_le_bytes());
+
+    Bytes::from(out)
+}
+
+/// 反序列化 DUMP 生成的 payload, 版本号或者校验和不正确时返回错误
+///
+/// payload 中不保存编码, 列表、哈希与集合按照 config 中当前的阈值重新选择编码
+pub fn restore(payload: &[u8], config: &Config) -> Result<Value, &'static str> {
+    let Some(split) = payload.len().checked_sub(10) else {
+        return Err(PAYLOAD_ERR);
+    };
+    let (data, crc) = payload.split_at(split + 2);
+    let version = u16::from_le_bytes([data[split], data[split + 1]]);
+    if version > VERSION || crc64(data).to_le_bytes() != crc {
+        return Err(PAYLOAD_ERR);
+    }
+
+    let mut reader = Reader {
+        data: &data[..split],
+    };
+    let value = decode(&mut reader, config).ok_or("ERR Bad data format")?;
+    if !reader.data.is_empty() {
+        return Err("ERR Bad data format");
+    }
+
+    Ok(value)
+}
+
+fn decode(reader: &mut Reader<'_>, config: &Config) -> Option<Value> {
+    let value = match reader.u8()? {
+        TYPE_STRING => Value::String(Bytes::copy_from_slice(reader.bytes()?)),
+        TYPE_LIST => {
+            let len = reader.len()?;
+            let list = (0..len)
+                .map(|_| reader.bytes().map(Bytes::copy_from_slice))
+                .collect::<Option<Vec<_>>>()?;
+            Value::List(List::from_items(list, config.list_max_listpack_size()))
+        }
+        TYPE_HASH => {
+            let len = reader.len()?;
+            let pairs = (0..len)
+                .map(|_| {
+                    let field = Bytes::copy_from_slice(reader.bytes()?);
+                    Some((field, Bytes::copy_from_slice(reader.bytes()?)))
+                })
+                .collect::<Option<Vec<_>>>()?;
+            Value::Hash(Hash::from_pairs(pairs, config.hash_limits()))
+        }
+        TYPE_SET => {
+            let len = reader.len()?;
+            let members = (0..len)
+                .map(|_| reader.bytes().map(Bytes::copy_from_slice))
+                .collect::<Option<Vec<_>>>()?;
+            Value::Set(Set::from_members(members, config.set_max_intset_entries()))
+        }
+        TYPE_ZSET => {
+            let mut zset = SortedSet::new();
+            for _ in 0..reader.len()? {
+                let member = Bytes::copy_from_slice(reader.bytes()?);
+                zset.insert(member, reader.f64()?);
+            }
+            Value::ZSet(zset)
+        }
+        TYPE_JSON => Value::Json(serde_json::from_slice(reader.bytes()?).ok()?),
+        TYPE_BLOOM => Value::Bloom(Bloom::decode(reader)?),
+        TYPE_CMS => Value::Cms(CountMinSketch::decode(reader)?),
+        TYPE_TIMESERIES => Value::TimeSeries(TimeSeries::decode(reader)?),
+        _ => return None,
+    };
+
+    Some(value)
+}
+
+pub(crate) fn put_u64(out: &mut Vec<u8>, val: u64) {
+    out.extend_from_slice(&val.to_le_bytes());
+}
+
+pub(crate) fn put_f64(out: &mut Vec<u8>, val: f64) {
+    out.extend_from_slice(&val.to_le_bytes());
+}
+
+pub(crate) fn put_bytes(out: &mut Vec<u8>, val: &[u8]) {
+    put_u64(out, val.len() as u64);
+    out.extend_from_slice(val);
+}
+
+/// 按顺序读取 value 的编码, 数据不足时返回 None
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.data.len() < n {
+            return None;
+        }
+
+        let (head, rest) = self.data.split_at(n);
+        self.data = rest;
+        Some(head)
+    }
+
+    pub(crate) fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    pub(crate) fn u64(&mut self) -> Option<u64> {
+        self.take(8).map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+    }
+
+    pub(crate) fn f64(&mut self) -> Option<f64> {
+        self.take(8).map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+    }
+
+    /// 读取元素个数, 每个元素至少占用 8 字节, 个数超过剩余的数据时说明数据已经损坏
+    pub(crate) fn len(&mut self) -> Option<usize> {
+        let len = self.u64()?;
+        (len <= self.data.len() as u64 / 8).then_some(len as usize)
+    }
+
+    pub(crate) fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.u64()?;
+        self.take(usize::try_from(len).ok()?)
+    }
+}
+
+/// redis 使用的 CRC-64/Jones 的反射多项式
+const POLY: u64 = 0x95ac_9329_ac4b_c9b5;
+
+const CRC64_TABLE: [u64; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ POLY } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+fn crc64(data: &[u8]) -> u64 {
+    data.iter().fold(0, |crc, &b| {
+        CRC64_TABLE[((crc ^ b as u64) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        // 与 redis 的 crc64 测试用例相同
+        assert_eq!(0xe9c6_d914_c4b8_d9ca, crc64(b"123456789"));
+
+        let mut zset = SortedSet::new();
+        zset.insert(Bytes::from("a"), 1.5);
+        zset.insert(Bytes::from("b"), f64::NEG_INFINITY);
+
+        let mut bloom = Bloom::new(0.01, 10, 2).unwrap();
+        for i in 0..30 {
+            bloom.insert(format!("item:{}", i).as_bytes()).unwrap();
+        }
+
+        let mut cms = CountMinSketch::new(10, 3);
+        cms.incr_by(b"a", 5);
+
+        let mut series = TimeSeries::new(Default::default());
+        for ts in [1000, 2000, 3500] {
+            series.add(ts, ts as f64 / 7.0, None).unwrap();
+        }
+
+        let config = Config::default();
+        let pairs = [(Bytes::from("f"), Bytes::from("v"))];
+        let values = [
+            Value::String(Bytes::from("hello")),
+            Value::List(List::from_items([Bytes::from("x"), Bytes::new()], -2)),
+            Value::Hash(Hash::from_pairs(pairs, Default::default())),
+            Value::Set(Set::from_members(["2", "x", "1"].map(Bytes::from), 512)),
+            Value::ZSet(zset),
+            Value::Json(serde_json::json!({"a": [1, "b", null]})),
+            Value::Bloom(bloom),
+            Value::Cms(cms),
+            Value::TimeSeries(series),
+        ];
+        for value in values {
+            assert_eq!(Ok(value.clone()), restore(&dump(&value), &config));
+        }
+
+        // 编码按照当前的阈值重新选择
+        config.set("list-max-listpack-size", "1").unwrap();
+        let list = Value::List(List::from_items(["a", "b"].map(Bytes::from), -2));
+        assert_eq!("listpack", list.encoding());
+        assert_eq!("quicklist", restore(&dump(&list), &config).unwrap().encoding());
+
+        let mut payload = dump(&Value::String(Bytes::from("hello"))).to_vec();
+        payload[3] ^= 1;
+        assert_eq!(Err(PAYLOAD_ERR), restore(&payload, &config));
+        assert_eq!(Err(PAYLOAD_ERR), restore(b"short", &config));
+    }
+}
@@ -0,0 +1,359 @@
+//! 与 redis 相同的 geohash 编码以及距离计算
+//!
+//! 经纬度被编码为 52 位的整数(经度和纬度各 26 位, 交错排列), 作为有序集合的分数保存,
+//! 这样地理位置相近的成员分数也相近, 按照范围查找时只需要查询中心点及其周围的 8 个格子.
+//!
+//! 与 redis 的 `geohash.c`、`geohash_helper.c` 保持一致, 包括纬度的范围只到 ±85.05112878
+//! (Web Mercator 投影的范围), 以及地球半径等常量, 保证 GEODIST 等命令的结果与 redis 完全相同
+
+pub const LONG_MIN: f64 = -180.0;
+pub const LONG_MAX: f64 = 180.0;
+pub const LAT_MIN: f64 = -85.051_128_78;
+pub const LAT_MAX: f64 = 85.051_128_78;
+
+/// 编码的精度, 经度和纬度各 26 位
+pub const STEP_MAX: u32 = 26;
+
+const EARTH_RADIUS_IN_METERS: f64 = 6_372_797.560_856;
+const MERCATOR_MAX: f64 = 20_037_726.37;
+
+const ALPHABET: &[u8; 32] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+/// 一个 geohash 格子
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hash {
+    pub bits: u64,
+    pub step: u32,
+}
+
+/// 格子覆盖的经纬度范围
+#[derive(Debug, Clone, Copy)]
+pub struct Area {
+    pub lon: (f64, f64),
+    pub lat: (f64, f64),
+}
+
+/// 搜索的范围, 长度的单位均为米
+#[derive(Debug, Clone, Copy)]
+pub enum Shape {
+    Radius(f64),
+    Box { width: f64, height: f64 },
+}
+
+pub fn is_valid(lon: f64, lat: f64) -> bool {
+    (LONG_MIN..=LONG_MAX).contains(&lon) && (LAT_MIN..=LAT_MAX).contains(&lat)
+}
+
+/// 编码为有序集合中的分数, 调用者需要保证经纬度在合法范围内
+pub fn encode_score(lon: f64, lat: f64) -> f64 {
+    encode(lon, lat, (LAT_MIN, LAT_MAX), STEP_MAX).bits as f64
+}
+
+/// 将分数解码为经纬度, 结果为格子的中心点
+pub fn decode_score(score: f64) -> (f64, f64) {
+    let area = decode(Hash {
+        bits: score as u64,
+        step: STEP_MAX,
+    });
+
+    let lon = ((area.lon.0 + area.lon.1) / 2.0).clamp(LONG_MIN, LONG_MAX);
+    let lat = ((area.lat.0 + area.lat.1) / 2.0).clamp(LAT_MIN, LAT_MAX);
+
+    (lon, lat)
+}
+
+/// GEOHASH 返回的 11 个字符的标准 geohash, 纬度的范围为 ±90
+pub fn to_string(score: f64) -> String {
+    let (lon, lat) = decode_score(score);
+    let hash = encode(lon, lat, (-90.0, 90.0), STEP_MAX);
+
+    // 52 位只够 10 个字符, 最后一个字符固定为 0
+    (0..11)
+        .map(|i| {
+            let idx = if i == 10 {
+                0
+            } else {
+                (hash.bits >> (52 - (i + 1) * 5)) & 0x1f
+            };
+            ALPHABET[idx as usize] as char
+        })
+        .collect()
+}
+
+fn encode(lon: f64, lat: f64, lat_range: (f64, f64), step: u32) -> Hash {
+    let lat_offset = (lat - lat_range.0) / (lat_range.1 - lat_range.0);
+    let lon_offset = (lon - LONG_MIN) / (LONG_MAX - LONG_MIN);
+
+    // 转换为定点数, 与 C 中 double 转换为 uint32_t 相同, 直接截断
+    let lat_offset = (lat_offset * (1u64 << step) as f64) as u32;
+    let lon_offset = (lon_offset * (1u64 << step) as f64) as u32;
+
+    Hash {
+        bits: interleave(lat_offset, lon_offset),
+        step,
+    }
+}
+
+fn decode(hash: Hash) -> Area {
+    let (ilat, ilon) = deinterleave(hash.bits);
+    let scale = (1u64 << hash.step) as f64;
+
+    let lat_scale = LAT_MAX - LAT_MIN;
+    let lon_scale = LONG_MAX - LONG_MIN;
+
+    Area {
+        lat: (
+            LAT_MIN + (ilat as f64 / scale) * lat_scale,
+            LAT_MIN + ((ilat as f64 + 1.0) / scale) * lat_scale,
+        ),
+        lon: (
+            LONG_MIN + (ilon as f64 / scale) * lon_scale,
+            LONG_MIN + ((ilon as f64 + 1.0) / scale) * lon_scale,
+        ),
+    }
+}
+
+/// 纬度放在偶数位, 经度放在奇数位
+fn interleave(lat: u32, lon: u32) -> u64 {
+    let spread = |v: u32| {
+        let mut v = v as u64;
+        v = (v | (v << 16)) & 0x0000_ffff_0000_ffff;
+        v = (v | (v << 8)) & 0x00ff_00ff_00ff_00ff;
+        v = (v | (v << 4)) & 0x0f0f_0f0f_0f0f_0f0f;
+        v = (v | (v << 2)) & 0x3333_3333_3333_3333;
+        (v | (v << 1)) & 0x5555_5555_5555_5555
+    };
+
+    spread(lat) | (spread(lon) << 1)
+}
+
+fn deinterleave(bits: u64) -> (u32, u32) {
+    let squash = |v: u64| {
+        let mut v = v & 0x5555_5555_5555_5555;
+        v = (v | (v >> 1)) & 0x3333_3333_3333_3333;
+        v = (v | (v >> 2)) & 0x0f0f_0f0f_0f0f_0f0f;
+        v = (v | (v >> 4)) & 0x00ff_00ff_00ff_00ff;
+        v = (v | (v >> 8)) & 0x0000_ffff_0000_ffff;
+        ((v | (v >> 16)) & 0x0000_0000_ffff_ffff) as u32
+    };
+
+    (squash(bits), squash(bits >> 1))
+}
+
+/// 相邻的格子, 超出边界时绕回另一侧
+fn neighbor(hash: Hash, dlon: i64, dlat: i64) -> Hash {
+    let (lat, lon) = deinterleave(hash.bits);
+    let mask = (1i64 << hash.step) - 1;
+    let lat = ((lat as i64 + dlat) & mask) as u32;
+    let lon = ((lon as i64 + dlon) & mask) as u32;
+
+    Hash {
+        bits: interleave(lat, lon),
+        step: hash.step,
+    }
+}
+
+/// 两点之间的距离(米), 使用 haversine 公式
+pub fn distance(lon1: f64, lat1: f64, lon2: f64, lat2: f64) -> f64 {
+    let (lon1r, lon2r) = (lon1.to_radians(), lon2.to_radians());
+    let v = ((lon2r - lon1r) / 2.0).sin();
+
+    // 经度相同时只需要计算纬度的距离
+    if v == 0.0 {
+        return lat_distance(lat1, lat2);
+    }
+
+    let (lat1r, lat2r) = (lat1.to_radians(), lat2.to_radians());
+    let u = ((lat2r - lat1r) / 2.0).sin();
+    let a = u * u + lat1r.cos() * lat2r.cos() * v * v;
+
+    2.0 * EARTH_RADIUS_IN_METERS * a.sqrt().asin()
+}
+
+fn lat_distance(lat1: f64, lat2: f64) -> f64 {
+    EARTH_RADIUS_IN_METERS * (lat2.to_radians() - lat1.to_radians()).abs()
+}
+
+impl Shape {
+    /// 点 (lon, lat) 在以 center 为中心的范围内时, 返回到中心点的距离
+    pub fn distance(&self, center: (f64, f64), lon: f64, lat: f64) -> Option<f64> {
+        match *self {
+            Shape::Radius(radius) => {
+                let distance = distance(center.0, center.1, lon, lat);
+                (distance <= radius).then_some(distance)
+            }
+            Shape::Box { width, height } => {
+                // 纬度方向的距离计算更快, 先检查纬度
+                if lat_distance(lat, center.1) > height / 2.0 {
+                    return None;
+                }
+                if distance(lon, lat, center.0, lat) > width / 2.0 {
+                    return None;
+                }
+
+                Some(distance(center.0, center.1, lon, lat))
+            }
+        }
+    }
+
+    /// 需要查找的分数范围 [min, max), 最多 9 个格子
+    pub fn score_ranges(&self, center: (f64, f64)) -> Vec<(f64, f64)> {
+        let (lon, lat) = center;
+        let (half_width, half_height) = match *self {
+            Shape::Radius(radius) => (radius, radius),
+            Shape::Box { width, height } => (width / 2.0, height / 2.0),
+        };
+
+        // 覆盖范围的经纬度边界
+        let lat_delta = (half_height / EARTH_RADIUS_IN_METERS).to_degrees();
+        let lon_delta_top =
+            (half_width / EARTH_RADIUS_IN_METERS / (lat + lat_delta).to_radians().cos()).to_degrees();
+        let lon_delta_bottom =
+            (half_width / EARTH_RADIUS_IN_METERS / (lat - lat_delta).to_radians().cos()).to_degrees();
+        let lon_delta = if lat < 0.0 { lon_delta_bottom } else { lon_delta_top };
+        let (min_lon, max_lon) = (lon - lon_delta, lon + lon_delta);
+        let (min_lat, max_lat) = (lat - lat_delta, lat + lat_delta);
+
+        // 矩形使用对角线的一半作为半径
+        let radius = match *self {
+            Shape::Radius(radius) => radius,
+            Shape::Box { .. } => half_width.hypot(half_height),
+        };
+        let mut step = estimate_steps(radius, lat);
+        let mut hash = encode(lon, lat, (LAT_MIN, LAT_MAX), step);
+        let mut neighbors = Neighbors::of(hash);
+
+        // 范围靠近格子的边缘时, 周围的格子可能不足以覆盖整个范围, 需要使用更大的格子
+        let north = decode(neighbors.0[Neighbors::NORTH]);
+        let south = decode(neighbors.0[Neighbors::SOUTH]);
+        let east = decode(neighbors.0[Neighbors::EAST]);
+        let west = decode(neighbors.0[Neighbors::WEST]);
+        if step > 1
+            && (north.lat.1 < max_lat
+                || south.lat.0 > min_lat
+                || east.lon.1 < max_lon
+                || west.lon.0 > min_lon)
+        {
+            step -= 1;
+            hash = encode(lon, lat, (LAT_MIN, LAT_MAX), step);
+            neighbors = Neighbors::of(hash);
+        }
+
+        // 排除与范围不相交的格子
+        let area = decode(hash);
+        let mut skip = [false; 9];
+        if step >= 2 {
+            if area.lat.0 < min_lat {
+                skip[Neighbors::SOUTH] = true;
+                skip[Neighbors::SOUTH_WEST] = true;
+                skip[Neighbors::SOUTH_EAST] = true;
+            }
+            if area.lat.1 > max_lat {
+                skip[Neighbors::NORTH] = true;
+                skip[Neighbors::NORTH_EAST] = true;
+                skip[Neighbors::NORTH_WEST] = true;
+            }
+            if area.lon.0 < min_lon {
+                skip[Neighbors::WEST] = true;
+                skip[Neighbors::SOUTH_WEST] = true;
+                skip[Neighbors::NORTH_WEST] = true;
+            }
+            if area.lon.1 > max_lon {
+                skip[Neighbors::EAST] = true;
+                skip[Neighbors::SOUTH_EAST] = true;
+                skip[Neighbors::NORTH_EAST] = true;
+            }
+        }
+
+        let shift = 52 - step * 2;
+        let mut ranges: Vec<(f64, f64)> = vec![];
+        for (i, hash) in neighbors.0.iter().enumerate() {
+            if skip[i] {
+                continue;
+            }
+
+            // 范围很大时周围的格子可能是同一个, 跳过重复的格子
+            let range = (
+                (hash.bits << shift) as f64,
+                ((hash.bits + 1) << shift) as f64,
+            );
+            if !ranges.contains(&range) {
+                ranges.push(range);
+            }
+        }
+
+        ranges
+    }
+}
+
+/// 中心格子以及周围的 8 个格子
+struct Neighbors([Hash; 9]);
+
+impl Neighbors {
+    const NORTH: usize = 1;
+    const SOUTH: usize = 2;
+    const EAST: usize = 3;
+    const WEST: usize = 4;
+    const NORTH_EAST: usize = 5;
+    const NORTH_WEST: usize = 6;
+    const SOUTH_EAST: usize = 7;
+    const SOUTH_WEST: usize = 8;
+
+    fn of(hash: Hash) -> Neighbors {
+        Neighbors([
+            hash,
+            neighbor(hash, 0, 1),
+            neighbor(hash, 0, -1),
+            neighbor(hash, 1, 0),
+            neighbor(hash, -1, 0),
+            neighbor(hash, 1, 1),
+            neighbor(hash, -1, 1),
+            neighbor(hash, 1, -1),
+            neighbor(hash, -1, -1),
+        ])
+    }
+}
+
+/// 根据搜索半径估算格子的精度, 使得格子的大小与半径相近
+fn estimate_steps(mut range: f64, lat: f64) -> u32 {
+    if range == 0.0 {
+        return STEP_MAX;
+    }
+
+    let mut step: i32 = 1;
+    while range < MERCATOR_MAX {
+        range *= 2.0;
+        step += 1;
+    }
+    step -= 2;
+
+    // 越靠近两极, 经度方向上的格子越窄
+    if !(-66.0..=66.0).contains(&lat) {
+        step -= 1;
+        if !(-80.0..=80.0).contains(&lat) {
+            step -= 1;
+        }
+    }
+
+    step.clamp(1, STEP_MAX as i32) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_and_decode() {
+        // redis 文档中 GEOADD Sicily 13.361389 38.115556 "Palermo" 的结果
+        let score = encode_score(13.361389, 38.115556);
+        assert_eq!(3479099956230698.0, score);
+        assert_eq!("sqc8b49rny0", to_string(score));
+
+        let (lon, lat) = decode_score(score);
+        assert!((lon - 13.361389).abs() < 1e-5 && (lat - 38.115556).abs() < 1e-5);
+
+        let catania = decode_score(encode_score(15.087269, 37.502669));
+        let dist = distance(lon, lat, catania.0, catania.1);
+        assert_eq!("166274.1516", format!("{:.4}", dist));
+    }
+}
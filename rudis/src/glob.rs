@@ -0,0 +1,140 @@
+/// redis 风格的通配符匹配, 用于 PSUBSCRIBE、CONFIG GET 等命令
+///
+/// + `*`: 匹配任意长度的字符
+/// + `?`: 匹配任意一个字符
+/// + `[abc]` `[^abc]` `[a-z]`: 匹配(或者不匹配)集合中的一个字符
+/// + `\x`: 对特殊字符进行转义
+pub fn matches(pattern: &[u8], string: &[u8]) -> bool {
+    matches_inner(pattern, string, false)
+}
+
+/// 忽略大小写的匹配
+pub fn matches_nocase(pattern: &[u8], string: &[u8]) -> bool {
+    matches_inner(pattern, string, true)
+}
+
+fn matches_inner(mut pattern: &[u8], mut string: &[u8], nocase: bool) -> bool {
+    let eq = |a: u8, b: u8| {
+        if nocase {
+            a.eq_ignore_ascii_case(&b)
+        } else {
+            a == b
+        }
+    };
+
+    while let Some(&p) = pattern.first() {
+        match p {
+            b'*' => {
+                // 合并连续的 `*`
+                while pattern.len() > 1 && pattern[1] == b'*' {
+                    pattern = &pattern[1..];
+                }
+                if pattern.len() == 1 {
+                    return true;
+                }
+
+                for i in 0..=string.len() {
+                    if matches_inner(&pattern[1..], &string[i..], nocase) {
+                        return true;
+                    }
+                }
+
+                return false;
+            }
+            b'?' => {
+                if string.is_empty() {
+                    return false;
+                }
+                string = &string[1..];
+            }
+            b'[' => {
+                let Some(&c) = string.first() else {
+                    return false;
+                };
+
+                pattern = &pattern[1..];
+                let negate = pattern.first() == Some(&b'^');
+                if negate {
+                    pattern = &pattern[1..];
+                }
+
+                let mut matched = false;
+                loop {
+                    match pattern {
+                        [] => break,
+                        [b']', ..] => break,
+                        [b'\\', escaped, ..] => {
+                            matched |= eq(*escaped, c);
+                            pattern = &pattern[2..];
+                        }
+                        [start, b'-', end, ..] if *end != b']' => {
+                            let (start, end) = if start <= end {
+                                (*start, *end)
+                            } else {
+                                (*end, *start)
+                            };
+
+                            let c = if nocase { c.to_ascii_lowercase() } else { c };
+                            let (start, end) = if nocase {
+                                (start.to_ascii_lowercase(), end.to_ascii_lowercase())
+                            } else {
+                                (start, end)
+                            };
+                            matched |= c >= start && c <= end;
+                            pattern = &pattern[3..];
+                        }
+                        [ch, ..] => {
+                            matched |= eq(*ch, c);
+                            pattern = &pattern[1..];
+                        }
+                    }
+                }
+
+                if matched == negate {
+                    return false;
+                }
+                string = &string[1..];
+
+                // pattern 此时指向 `]` (或者已经结束)
+                if pattern.is_empty() {
+                    return string.is_empty();
+                }
+            }
+            b'\\' if pattern.len() >= 2 => {
+                pattern = &pattern[1..];
+                match string.first() {
+                    Some(&c) if eq(pattern[0], c) => string = &string[1..],
+                    _ => return false,
+                }
+            }
+            _ => match string.first() {
+                Some(&c) if eq(p, c) => string = &string[1..],
+                _ => return false,
+            },
+        }
+
+        pattern = &pattern[1..];
+    }
+
+    string.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_patterns() {
+        assert!(matches(b"*", b""));
+        assert!(matches(b"h?llo", b"hello"));
+        assert!(matches(b"h*llo", b"heeeello"));
+        assert!(matches(b"h[ae]llo", b"hallo"));
+        assert!(!matches(b"h[^e]llo", b"hello"));
+        assert!(matches(b"h[a-b]llo", b"hbllo"));
+        assert!(matches(b"__keyspace@*__:*", b"__keyspace@0__:foo"));
+        assert!(matches(b"foo\\*", b"foo*"));
+        assert!(!matches(b"foo\\*", b"foobar"));
+        assert!(!matches(b"foo", b"foobar"));
+        assert!(matches_nocase(b"MAX*", b"maxmemory"));
+    }
+}
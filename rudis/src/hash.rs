@@ -0,0 +1,205 @@
+//! 哈希类型的存储
+//!
+//! 与 redis 相同, 哈希有两种编码:
+//! + listpack: field 与 value 依次交替保存, 查找需要遍历, 适合字段较少的哈希
+//! + hashtable: 字段个数超过 `hash-max-listpack-entries`,
+//!   或者 field/value 的长度超过 `hash-max-listpack-value` 之后转换为 `HashMap`
+//!
+//! 转换是单向的, 删除字段之后不会再转换回 listpack
+
+use std::collections::HashMap;
+
+use bytes::Bytes;
+
+use crate::listpack::Listpack;
+
+/// 与 redis 的默认值相同
+pub const DEFAULT_MAX_LISTPACK_ENTRIES: usize = 128;
+pub const DEFAULT_MAX_LISTPACK_VALUE: usize = 64;
+
+/// listpack 编码的阈值, 分别对应 `hash-max-listpack-entries` 与 `hash-max-listpack-value`
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub max_entries: usize,
+    pub max_value: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_entries: DEFAULT_MAX_LISTPACK_ENTRIES,
+            max_value: DEFAULT_MAX_LISTPACK_VALUE,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Hash {
+    Listpack(Listpack),
+    Table(HashMap<Bytes, Bytes>),
+}
+
+impl Default for Hash {
+    fn default() -> Self {
+        Hash::Listpack(Listpack::new())
+    }
+}
+
+impl PartialEq for Hash {
+    /// 只比较字段, 与编码以及字段的顺序无关
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|(field, value)| {
+            other.get(field).as_deref() == Some(value)
+        })
+    }
+}
+
+impl Hash {
+    pub fn new() -> Hash {
+        Hash::default()
+    }
+
+    /// 依次插入 pairs, 例如 RESTORE 时按照当前的阈值重新选择编码
+    pub fn from_pairs(pairs: impl IntoIterator<Item = (Bytes, Bytes)>, limits: Limits) -> Hash {
+        let mut hash = Hash::new();
+        for (field, value) in pairs {
+            hash.insert(field, value, limits);
+        }
+        hash
+    }
+
+    /// OBJECT ENCODING 返回的编码名称
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Hash::Listpack(_) => "listpack",
+            Hash::Table(_) => "hashtable",
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Hash::Listpack(lp) => lp.len() / 2,
+            Hash::Table(map) => map.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, field: &[u8]) -> Option<Bytes> {
+        match self {
+            Hash::Listpack(lp) => position(lp, field)
+                .and_then(|i| lp.get(i + 1))
+                .map(Bytes::copy_from_slice),
+            Hash::Table(map) => map.get(field).cloned(),
+        }
+    }
+
+    /// 设置字段的值, 字段是新增的时返回 true
+    pub fn insert(&mut self, field: Bytes, value: Bytes, limits: Limits) -> bool {
+        if let Hash::Listpack(lp) = self {
+            let too_long = field.len() > limits.max_value || value.len() > limits.max_value;
+            let index = position(lp, &field);
+            if !too_long && (index.is_some() || lp.len() / 2 < limits.max_entries) {
+                return match index {
+                    Some(i) => {
+                        lp.replace(i + 1, &value);
+                        false
+                    }
+                    None => {
+                        lp.push_back(&field);
+                        lp.push_back(&value);
+                        true
+                    }
+                };
+            }
+
+            self.convert();
+        }
+
+        let Hash::Table(map) = self else {
+            unreachable!()
+        };
+        map.insert(field, value).is_none()
+    }
+
+    /// 删除字段, 字段存在时返回 true
+    pub fn remove(&mut self, field: &[u8]) -> bool {
+        match self {
+            Hash::Listpack(lp) => match position(lp, field) {
+                Some(i) => {
+                    lp.remove(i + 1);
+                    lp.remove(i);
+                    true
+                }
+                None => false,
+            },
+            Hash::Table(map) => map.remove(field).is_some(),
+        }
+    }
+
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (&[u8], &[u8])> + '_> {
+        match self {
+            Hash::Listpack(lp) => {
+                let mut iter = lp.iter();
+                Box::new(std::iter::from_fn(move || Some((iter.next()?, iter.next()?))))
+            }
+            Hash::Table(map) => Box::new(map.iter().map(|(f, v)| (&f[..], &v[..]))),
+        }
+    }
+
+    /// listpack 编码占用的字节数, hashtable 编码返回 None
+    pub fn listpack_bytes(&self) -> Option<usize> {
+        match self {
+            Hash::Listpack(lp) => Some(lp.bytes()),
+            Hash::Table(_) => None,
+        }
+    }
+
+    fn convert(&mut self) {
+        let map = self
+            .iter()
+            .map(|(f, v)| (Bytes::copy_from_slice(f), Bytes::copy_from_slice(v)))
+            .collect();
+        *self = Hash::Table(map);
+    }
+}
+
+/// field 在 listpack 中的下标, 只比较偶数位置
+fn position(lp: &Listpack, field: &[u8]) -> Option<usize> {
+    lp.iter().step_by(2).position(|f| f == field).map(|i| i * 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert() {
+        let limits = Limits {
+            max_entries: 2,
+            max_value: 8,
+        };
+        let mut hash = Hash::new();
+        assert!(hash.insert(Bytes::from("a"), Bytes::from("1"), limits));
+        assert!(hash.insert(Bytes::from("b"), Bytes::from("2"), limits));
+        assert!(!hash.insert(Bytes::from("a"), Bytes::from("3"), limits));
+        assert_eq!("listpack", hash.encoding());
+        assert_eq!(Some(Bytes::from("3")), hash.get(b"a"));
+        // 值与字段名相同时不会被当成字段
+        assert_eq!(None, hash.get(b"3"));
+
+        let mut table = hash.clone();
+        assert!(table.insert(Bytes::from("c"), Bytes::from("4"), limits));
+        assert_eq!("hashtable", table.encoding());
+        assert!(table.remove(b"c"));
+        assert_eq!(hash, table);
+
+        assert!(hash.remove(b"a"));
+        assert!(!hash.remove(b"a"));
+        hash.insert(Bytes::from("b"), Bytes::from("long value"), limits);
+        assert_eq!("hashtable", hash.encoding());
+        assert_eq!(1, hash.len());
+    }
+}
@@ -0,0 +1,526 @@
+//! 与 redis 字节兼容的 HyperLogLog
+//!
+//! HyperLogLog 保存在普通的字符串中, 格式与 redis 的 `hyperloglog.c` 完全相同,
+//! 因此 GET 出来的内容可以直接与 redis 中的值比较, 也可以 SET 到 redis 中继续使用:
+//!
+//! ```text
+//! +------+---+-----+----------+
+//! | HYLL | E | N/U | Cardin.  |
+//! +------+---+-----+----------+
+//! ```
+//!
+//! + 4 字节的魔数 `HYLL`
+//! + 1 字节的编码: 0 为 dense, 1 为 sparse
+//! + 3 字节未使用
+//! + 8 字节小端序的基数缓存, 最高位为 1 代表缓存已失效
+//!
+//! dense 编码中 16384 个 6 bit 的寄存器依次排列, 共 12288 字节.
+//! sparse 编码用于大部分寄存器都为 0 的情况, 由三种操作码组成:
+//!
+//! + ZERO `00xxxxxx`: 连续 1-64 个值为 0 的寄存器
+//! + XZERO `01xxxxxx yyyyyyyy`: 连续 1-16384 个值为 0 的寄存器
+//! + VAL `1vvvvvxx`: 连续 1-4 个值为 1-32 的寄存器
+//!
+//! 寄存器的值超过 32 或者 sparse 编码超过 `hll-sparse-max-bytes` 之后转换为 dense 编码
+
+/// 寄存器个数为 2^14
+const P: u32 = 14;
+pub const REGISTERS: usize = 1 << P;
+const P_MASK: u64 = REGISTERS as u64 - 1;
+/// 哈希值中用于计算连续 0 个数的位数
+const Q: usize = 64 - P as usize;
+const BITS: usize = 6;
+const REGISTER_MAX: u8 = (1 << BITS) - 1;
+
+const HDR_SIZE: usize = 16;
+const DENSE_SIZE: usize = HDR_SIZE + (REGISTERS * BITS).div_ceil(8);
+
+const DENSE: u8 = 0;
+const SPARSE: u8 = 1;
+
+const ZERO_MAX_LEN: usize = 64;
+const XZERO_MAX_LEN: usize = 16384;
+const VAL_MAX_VALUE: u8 = 32;
+const VAL_MAX_LEN: usize = 4;
+
+/// 0.5 / ln(2)
+const ALPHA_INF: f64 = 0.721_347_520_444_481_7;
+
+pub const INVALID_ERR: &str = "WRONGTYPE Key is not a valid HyperLogLog string value.";
+pub const CORRUPTED_ERR: &str = "INVALIDOBJ Corrupted HLL object detected";
+
+/// 创建一个空的 HyperLogLog, 使用 sparse 编码, 只需要 18 字节
+pub fn create() -> Vec<u8> {
+    let mut hll = vec![0; HDR_SIZE];
+    hll[..4].copy_from_slice(b"HYLL");
+    hll[4] = SPARSE;
+
+    let mut remaining = REGISTERS;
+    while remaining > 0 {
+        let len = remaining.min(XZERO_MAX_LEN);
+        hll.extend_from_slice(&xzero(len));
+        remaining -= len;
+    }
+
+    hll
+}
+
+/// 字符串是否是一个 HyperLogLog, 只检查头部以及 dense 编码的长度
+pub fn is_valid(hll: &[u8]) -> bool {
+    hll.len() >= HDR_SIZE
+        && &hll[..4] == b"HYLL"
+        && hll[4] <= SPARSE
+        && (hll[4] != DENSE || hll.len() == DENSE_SIZE)
+}
+
+/// 添加一个元素, 有寄存器被修改时返回 true
+pub fn add(hll: &mut Vec<u8>, element: &[u8], sparse_max: usize) -> Result<bool, &'static str> {
+    let (index, count) = pattern_len(element);
+    set(hll, index, count, sparse_max)
+}
+
+/// 将寄存器的值设置为 `count`, 只有 `count` 大于寄存器原来的值时才会修改
+pub fn set(
+    hll: &mut Vec<u8>,
+    index: usize,
+    count: u8,
+    sparse_max: usize,
+) -> Result<bool, &'static str> {
+    let updated = match hll[4] {
+        DENSE => dense_set(&mut hll[HDR_SIZE..], index, count),
+        _ => sparse_set(hll, index, count, sparse_max)?,
+    };
+    if updated {
+        invalidate_cache(hll);
+    }
+
+    Ok(updated)
+}
+
+/// 估算基数, 缓存有效时直接返回缓存, 否则计算之后写入缓存
+pub fn count(hll: &mut [u8]) -> Result<u64, &'static str> {
+    if hll[15] & 0x80 == 0 {
+        let mut card = [0; 8];
+        card.copy_from_slice(&hll[8..16]);
+        return Ok(u64::from_le_bytes(card));
+    }
+
+    let mut histogram = [0; 64];
+    match hll[4] {
+        DENSE => {
+            for i in 0..REGISTERS {
+                histogram[dense_get(&hll[HDR_SIZE..], i) as usize] += 1;
+            }
+        }
+        _ => sparse_each(&hll[HDR_SIZE..], |_, len, value| {
+            histogram[value as usize] += len
+        })?,
+    }
+
+    let card = estimate(&histogram);
+    hll[8..16].copy_from_slice(&card.to_le_bytes());
+
+    Ok(card)
+}
+
+/// 将 HyperLogLog 合并到寄存器数组中, 每个寄存器取最大值
+pub fn merge(max: &mut [u8; REGISTERS], hll: &[u8]) -> Result<(), &'static str> {
+    match hll[4] {
+        DENSE => {
+            for (i, reg) in max.iter_mut().enumerate() {
+                *reg = (*reg).max(dense_get(&hll[HDR_SIZE..], i));
+            }
+        }
+        _ => sparse_each(&hll[HDR_SIZE..], |first, len, value| {
+            for reg in &mut max[first..first + len] {
+                *reg = (*reg).max(value);
+            }
+        })?,
+    }
+
+    Ok(())
+}
+
+/// 是否使用 dense 编码
+pub fn is_dense(hll: &[u8]) -> bool {
+    hll[4] == DENSE
+}
+
+/// 转换为 dense 编码, PFMERGE 的输入中有 dense 编码时, 结果也使用 dense 编码
+pub fn to_dense(hll: &mut Vec<u8>) -> Result<(), &'static str> {
+    if hll[4] == DENSE {
+        return Ok(());
+    }
+
+    let mut dense = vec![0; DENSE_SIZE];
+    dense[..HDR_SIZE].copy_from_slice(&hll[..HDR_SIZE]);
+    dense[4] = DENSE;
+    sparse_each(&hll[HDR_SIZE..], |first, len, value| {
+        for i in first..first + len {
+            dense_set(&mut dense[HDR_SIZE..], i, value);
+        }
+    })?;
+
+    *hll = dense;
+    Ok(())
+}
+
+/// 根据寄存器的值的分布估算基数, 即 redis 5.0 之后使用的 Otmar Ertl 的改进算法
+pub fn estimate(histogram: &[usize; 64]) -> u64 {
+    let m = REGISTERS as f64;
+
+    let mut z = m * tau((m - histogram[Q + 1] as f64) / m);
+    for j in (1..=Q).rev() {
+        z += histogram[j] as f64;
+        z *= 0.5;
+    }
+    z += m * sigma(histogram[0] as f64 / m);
+
+    (ALPHA_INF * m * m / z).round() as u64
+}
+
+/// 合并之后的寄存器数组的基数
+pub fn count_registers(registers: &[u8; REGISTERS]) -> u64 {
+    let mut histogram = [0; 64];
+    for reg in registers {
+        histogram[*reg as usize] += 1;
+    }
+
+    estimate(&histogram)
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+
+    let mut y = 1.0;
+    let mut z = x;
+    loop {
+        x *= x;
+        let prev = z;
+        z += x * y;
+        y += y;
+        if prev == z {
+            return z;
+        }
+    }
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+
+    let mut y = 1.0;
+    let mut z = 1.0 - x;
+    loop {
+        x = x.sqrt();
+        let prev = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if prev == z {
+            return z / 3.0;
+        }
+    }
+}
+
+/// 使缓存的基数失效, 下一次 PFCOUNT 时重新计算
+pub fn invalidate_cache(hll: &mut [u8]) {
+    hll[15] |= 0x80;
+}
+
+/// 元素对应的寄存器下标以及哈希值中从低位开始连续 0 的个数加 1
+fn pattern_len(element: &[u8]) -> (usize, u8) {
+    let hash = murmurhash64a(element, 0xadc8_3b19);
+    let index = (hash & P_MASK) as usize;
+
+    // 最高位补 1, 保证循环一定会结束
+    let hash = (hash >> P) | (1 << Q);
+
+    (index, hash.trailing_zeros() as u8 + 1)
+}
+
+/// redis 使用的 MurmurHash64A, 按照小端序读取, 与平台无关
+pub(crate) fn murmurhash64a(key: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4_a793_5bd1_e995;
+    const R: u32 = 47;
+
+    let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+
+    let mut chunks = key.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut k = u64::from_le_bytes(chunk.try_into().unwrap());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, byte) in tail.iter().enumerate() {
+            h ^= (*byte as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+
+    h
+}
+
+fn dense_get(registers: &[u8], index: usize) -> u8 {
+    let byte = index * BITS / 8;
+    let fb = (index * BITS) & 7;
+
+    let b0 = registers[byte] as u16;
+    let b1 = registers.get(byte + 1).copied().unwrap_or(0) as u16;
+
+    (((b0 >> fb) | (b1 << (8 - fb))) & REGISTER_MAX as u16) as u8
+}
+
+fn dense_set(registers: &mut [u8], index: usize, count: u8) -> bool {
+    if count <= dense_get(registers, index) {
+        return false;
+    }
+
+    let byte = index * BITS / 8;
+    let fb = (index * BITS) & 7;
+    let val = count as u16;
+
+    registers[byte] &= !((REGISTER_MAX as u16) << fb) as u8;
+    registers[byte] |= (val << fb) as u8;
+    if let Some(next) = registers.get_mut(byte + 1) {
+        *next &= !((REGISTER_MAX as u16) >> (8 - fb)) as u8;
+        *next |= (val >> (8 - fb)) as u8;
+    }
+
+    true
+}
+
+/// sparse 编码中的一个操作码
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Zero(usize),
+    XZero(usize),
+    Val(u8, usize),
+}
+
+impl Op {
+    fn decode(bytes: &[u8]) -> Option<Op> {
+        let b = *bytes.first()?;
+        let op = if b & 0x80 != 0 {
+            Op::Val(((b >> 2) & 0x1f) + 1, (b & 0x03) as usize + 1)
+        } else if b & 0x40 != 0 {
+            let next = *bytes.get(1)? as usize;
+            Op::XZero((((b & 0x3f) as usize) << 8 | next) + 1)
+        } else {
+            Op::Zero((b & 0x3f) as usize + 1)
+        };
+
+        Some(op)
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Op::XZero(_) => 2,
+            _ => 1,
+        }
+    }
+
+    fn len(&self) -> usize {
+        match *self {
+            Op::Zero(len) | Op::XZero(len) | Op::Val(_, len) => len,
+        }
+    }
+}
+
+fn zero(len: usize) -> u8 {
+    (len - 1) as u8
+}
+
+fn xzero(len: usize) -> [u8; 2] {
+    let len = len - 1;
+    [0x40 | (len >> 8) as u8, len as u8]
+}
+
+fn val(value: u8, len: usize) -> u8 {
+    0x80 | ((value - 1) << 2) | (len - 1) as u8
+}
+
+/// 0 值寄存器的操作码, 长度不超过 64 时使用 ZERO, 否则使用 XZERO
+fn push_zeros(seq: &mut Vec<u8>, len: usize) {
+    if len > ZERO_MAX_LEN {
+        seq.extend_from_slice(&xzero(len));
+    } else {
+        seq.push(zero(len));
+    }
+}
+
+/// 依次遍历 sparse 编码中的每一段寄存器: (起始下标, 个数, 值)
+fn sparse_each(ops: &[u8], mut f: impl FnMut(usize, usize, u8)) -> Result<(), &'static str> {
+    let mut index = 0;
+    let mut pos = 0;
+    while pos < ops.len() {
+        let op = Op::decode(&ops[pos..]).ok_or(CORRUPTED_ERR)?;
+        let (len, value) = match op {
+            Op::Zero(len) | Op::XZero(len) => (len, 0),
+            Op::Val(value, len) => (len, value),
+        };
+        if index + len > REGISTERS {
+            return Err(CORRUPTED_ERR);
+        }
+
+        f(index, len, value);
+        index += len;
+        pos += op.size();
+    }
+
+    if index != REGISTERS {
+        return Err(CORRUPTED_ERR);
+    }
+
+    Ok(())
+}
+
+/// 与 redis 的 `hllSparseSet` 相同的原地修改, 保证修改之后的字节与 redis 完全一致
+fn sparse_set(
+    hll: &mut Vec<u8>,
+    index: usize,
+    count: u8,
+    sparse_max: usize,
+) -> Result<bool, &'static str> {
+    if count > VAL_MAX_VALUE {
+        return promote(hll, index, count);
+    }
+
+    // 找到 index 所在的操作码
+    let mut first = 0;
+    let mut prev = None;
+    let mut pos = HDR_SIZE;
+    let op = loop {
+        let op = Op::decode(&hll[pos..]).ok_or(CORRUPTED_ERR)?;
+        if index < first + op.len() {
+            break op;
+        }
+
+        prev = Some(pos);
+        pos += op.size();
+        first += op.len();
+        if pos >= hll.len() {
+            return Err(CORRUPTED_ERR);
+        }
+    };
+
+    match op {
+        // 寄存器的值只会增大
+        Op::Val(value, _) if value >= count => return Ok(false),
+        // 只有一个寄存器时直接替换操作码
+        Op::Val(_, 1) | Op::Zero(1) => hll[pos] = val(count, 1),
+        _ => {
+            // 将操作码拆分为最多三段: index 之前、index、index 之后
+            let last = first + op.len() - 1;
+            let mut seq = Vec::with_capacity(5);
+            match op {
+                Op::Val(value, _) => {
+                    if index != first {
+                        seq.push(val(value, index - first));
+                    }
+                    seq.push(val(count, 1));
+                    if index != last {
+                        seq.push(val(value, last - index));
+                    }
+                }
+                _ => {
+                    if index != first {
+                        push_zeros(&mut seq, index - first);
+                    }
+                    seq.push(val(count, 1));
+                    if index != last {
+                        push_zeros(&mut seq, last - index);
+                    }
+                }
+            }
+
+            if seq.len() > op.size() && hll.len() + seq.len() - op.size() > sparse_max {
+                return promote(hll, index, count);
+            }
+            hll.splice(pos..pos + op.size(), seq);
+        }
+    }
+
+    // 合并修改位置附近相邻的、值相同的 VAL 操作码
+    let mut pos = prev.unwrap_or(HDR_SIZE);
+    let mut scan = 5;
+    while pos < hll.len() && scan > 0 {
+        scan -= 1;
+
+        let op = Op::decode(&hll[pos..]).ok_or(CORRUPTED_ERR)?;
+        let next = hll.get(pos + 1..).and_then(Op::decode);
+        if let (Op::Val(v1, l1), Some(Op::Val(v2, l2))) = (op, next) {
+            if v1 == v2 && l1 + l2 <= VAL_MAX_LEN {
+                hll[pos] = val(v1, l1 + l2);
+                hll.remove(pos + 1);
+                continue;
+            }
+        }
+
+        pos += op.size();
+    }
+
+    Ok(true)
+}
+
+/// 转换为 dense 编码之后再修改寄存器
+fn promote(hll: &mut Vec<u8>, index: usize, count: u8) -> Result<bool, &'static str> {
+    to_dense(hll)?;
+    Ok(dense_set(&mut hll[HDR_SIZE..], index, count))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sparse_and_dense_agree() {
+        let mut sparse = create();
+        let mut dense = create();
+        to_dense(&mut dense).unwrap();
+
+        for i in 0..2000 {
+            let element = format!("element:{}", i);
+            add(&mut sparse, element.as_bytes(), usize::MAX).unwrap();
+            add(&mut dense, element.as_bytes(), usize::MAX).unwrap();
+        }
+        assert!(!is_dense(&sparse));
+
+        let (mut a, mut b) = ([0; REGISTERS], [0; REGISTERS]);
+        merge(&mut a, &sparse).unwrap();
+        merge(&mut b, &dense).unwrap();
+        assert!(a == b);
+
+        let card = count(&mut sparse).unwrap();
+        assert_eq!(card, count(&mut dense).unwrap());
+        assert!(card.abs_diff(2000) < 40, "{}", card);
+
+        // sparse 编码超过限制之后转换为 dense 编码
+        add(&mut sparse, b"overflow", 0).unwrap();
+        assert!(is_dense(&sparse) && is_valid(&sparse));
+    }
+
+    #[test]
+    fn estimate_large_cardinality() {
+        let mut hll = create();
+        for i in 0..100_000 {
+            add(&mut hll, format!("{}", i).as_bytes(), 3000).unwrap();
+        }
+
+        let card = count(&mut hll).unwrap();
+        assert!(card.abs_diff(100_000) < 2000, "{}", card);
+    }
+}
@@ -0,0 +1,163 @@
+//! inline 命令
+//!
+//! 除了 RESP Array 之外, redis 还接受以换行结尾、空白字符分隔的命令, 例如 `SET foo bar\r\n`,
+//! 这样就可以直接通过 telnet、nc 等工具与服务端交互
+
+use bytes::{Bytes, BytesMut};
+
+use crate::{Frame, Result};
+
+/// inline 命令一行的最大长度, 与 redis 相同
+const MAX_INLINE_SIZE: usize = 64 * 1024;
+
+/// 在缓冲区中解析一个 inline 命令, 解析成功后将其转换为由 bulk 组成的 Array
+///
+/// 空行会被直接跳过, 数据不足一行时返回 `Ok(None)`
+pub fn parse(buf: &mut BytesMut) -> Result<Option<Frame>> {
+    loop {
+        let Some(end) = buf.iter().position(|b| *b == b'\n') else {
+            if buf.len() > MAX_INLINE_SIZE {
+                return Err("Protocol error: too big inline request".into());
+            }
+
+            return Ok(None);
+        };
+
+        let line = buf.split_to(end + 1);
+        let line = line.strip_suffix(b"\n").unwrap_or(&line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+        let args = split_args(line).map_err(|_| "Protocol error: unbalanced quotes in request")?;
+        if !args.is_empty() {
+            return Ok(Some(Frame::Array(args.into_iter().map(Frame::Bulk).collect())));
+        }
+
+        if buf.is_empty() {
+            return Ok(None);
+        }
+    }
+}
+
+/// 按照 redis 的规则拆分参数(与 redis 中的 `sdssplitargs` 一致)
+///
+/// + 参数之间使用空白字符分隔
+/// + 双引号中支持 `\n` `\r` `\t` `\b` `\a` `\"` `\\` 以及 `\xHH` 转义
+/// + 单引号中只支持 `\'` 转义
+/// + 右引号之后必须是空白字符或者行尾
+pub fn split_args(line: &[u8]) -> std::result::Result<Vec<Bytes>, &'static str> {
+    let mut args = vec![];
+    let mut chars = line.iter().copied().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+
+        if chars.peek().is_none() {
+            return Ok(args);
+        }
+
+        let mut current = Vec::new();
+        let mut in_double = false;
+        let mut in_single = false;
+
+        loop {
+            if in_double {
+                match chars.next() {
+                    None => return Err("unbalanced quotes"),
+                    Some(b'\\') => match chars.next() {
+                        Some(b'x') => {
+                            let hi = chars.next().and_then(hex_value);
+                            let lo = chars.next().and_then(hex_value);
+                            match (hi, lo) {
+                                (Some(hi), Some(lo)) => current.push(hi << 4 | lo),
+                                _ => return Err("invalid \\x escape"),
+                            }
+                        }
+                        Some(b'n') => current.push(b'\n'),
+                        Some(b'r') => current.push(b'\r'),
+                        Some(b't') => current.push(b'\t'),
+                        Some(b'b') => current.push(0x08),
+                        Some(b'a') => current.push(0x07),
+                        Some(c) => current.push(c),
+                        None => return Err("unbalanced quotes"),
+                    },
+                    Some(b'"') => {
+                        if chars.peek().is_some_and(|c| !c.is_ascii_whitespace()) {
+                            return Err("closing quote must be followed by a space");
+                        }
+                        break;
+                    }
+                    Some(c) => current.push(c),
+                }
+            } else if in_single {
+                match chars.next() {
+                    None => return Err("unbalanced quotes"),
+                    Some(b'\\') if chars.peek() == Some(&b'\'') => {
+                        chars.next();
+                        current.push(b'\'');
+                    }
+                    Some(b'\'') => {
+                        if chars.peek().is_some_and(|c| !c.is_ascii_whitespace()) {
+                            return Err("closing quote must be followed by a space");
+                        }
+                        break;
+                    }
+                    Some(c) => current.push(c),
+                }
+            } else {
+                match chars.peek() {
+                    None => break,
+                    Some(c) if c.is_ascii_whitespace() => break,
+                    Some(b'"') => in_double = true,
+                    Some(b'\'') => in_single = true,
+                    Some(&c) => current.push(c),
+                }
+                chars.next();
+            }
+        }
+
+        args.push(Bytes::from(current));
+    }
+}
+
+fn hex_value(c: u8) -> Option<u8> {
+    (c as char).to_digit(16).map(|v| v as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_quoted_args() {
+        let args = split_args(br#"SET "hello world" 'it\'s' "\x41\n""#).unwrap();
+        assert_eq!(
+            vec![
+                Bytes::from("SET"),
+                Bytes::from("hello world"),
+                Bytes::from("it's"),
+                Bytes::from("A\n"),
+            ],
+            args
+        );
+
+        assert!(split_args(br#"GET "foo"bar"#).is_err());
+        assert!(split_args(br#"GET "foo"#).is_err());
+        assert!(split_args(b"   ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_inline_commands() {
+        let mut buf = BytesMut::from(&b"\r\nSET foo bar\r\nPING\nGET"[..]);
+
+        let bulks = |args: &[&'static str]| {
+            Frame::Array(args.iter().map(|arg| Frame::Bulk(Bytes::from(*arg))).collect())
+        };
+
+        assert_eq!(Some(bulks(&["SET", "foo", "bar"])), parse(&mut buf).unwrap());
+        assert_eq!(Some(bulks(&["PING"])), parse(&mut buf).unwrap());
+
+        // 不完整的一行需要等待更多的数据
+        assert!(parse(&mut buf).unwrap().is_none());
+        assert_eq!(&b"GET"[..], &buf[..]);
+    }
+}
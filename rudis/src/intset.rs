@@ -0,0 +1,161 @@
+//! intset: 只包含整数的小集合的紧凑编码
+//!
+//! 与 redis 相同, 所有的整数按照从小到大的顺序连续保存, 通过二分查找访问.
+//! 每个整数占用的字节数(2、4 或者 8)由集合中绝对值最大的整数决定,
+//! 插入一个当前宽度放不下的整数时, 整个集合升级为更宽的编码. 集合不会降级
+//!
+//! 超过 `set-max-intset-entries` 或者插入了非整数的成员之后, 需要转换为完整的哈希集合, 见 `crate::set`
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntSet {
+    /// 每个整数占用的字节数
+    width: usize,
+    data: Vec<u8>,
+}
+
+impl Default for IntSet {
+    fn default() -> Self {
+        IntSet {
+            width: 2,
+            data: vec![],
+        }
+    }
+}
+
+impl IntSet {
+    pub fn new() -> IntSet {
+        IntSet::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len() / self.width
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// 编码之后占用的字节数
+    pub fn bytes(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn contains(&self, val: i64) -> bool {
+        width_of(val) <= self.width && self.search(val).is_ok()
+    }
+
+    /// 插入一个整数, 已经存在时返回 false
+    pub fn insert(&mut self, val: i64) -> bool {
+        let width = width_of(val);
+        if width > self.width {
+            self.upgrade(width);
+        }
+
+        match self.search(val) {
+            Ok(_) => false,
+            Err(pos) => {
+                let offset = pos * self.width;
+                let bytes = val.to_le_bytes();
+                self.data.splice(offset..offset, bytes[..self.width].iter().copied());
+                true
+            }
+        }
+    }
+
+    /// 删除一个整数, 不存在时返回 false
+    pub fn remove(&mut self, val: i64) -> bool {
+        if width_of(val) > self.width {
+            return false;
+        }
+
+        match self.search(val) {
+            Ok(pos) => {
+                let offset = pos * self.width;
+                self.data.drain(offset..offset + self.width);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<i64> {
+        (index < self.len()).then(|| self.at(index))
+    }
+
+    /// 从小到大遍历
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = i64> + ExactSizeIterator + '_ {
+        (0..self.len()).map(|i| self.at(i))
+    }
+
+    fn at(&self, index: usize) -> i64 {
+        let bytes = &self.data[index * self.width..(index + 1) * self.width];
+        match self.width {
+            2 => i16::from_le_bytes(bytes.try_into().unwrap()) as i64,
+            4 => i32::from_le_bytes(bytes.try_into().unwrap()) as i64,
+            _ => i64::from_le_bytes(bytes.try_into().unwrap()),
+        }
+    }
+
+    /// 与 `slice::binary_search` 的返回值相同
+    fn search(&self, val: i64) -> Result<usize, usize> {
+        let (mut lo, mut hi) = (0, self.len());
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            match self.at(mid).cmp(&val) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return Ok(mid),
+            }
+        }
+        Err(lo)
+    }
+
+    fn upgrade(&mut self, width: usize) {
+        let mut data = Vec::with_capacity(self.len() * width);
+        for val in self.iter() {
+            data.extend_from_slice(&val.to_le_bytes()[..width]);
+        }
+        self.width = width;
+        self.data = data;
+    }
+}
+
+/// 保存 val 所需的最小宽度, 截断小端字节之后再按符号扩展即可还原
+fn width_of(val: i64) -> usize {
+    if i16::try_from(val).is_ok() {
+        2
+    } else if i32::try_from(val).is_ok() {
+        4
+    } else {
+        8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upgrade() {
+        let mut set = IntSet::new();
+        for val in [5, -3, 100, 5] {
+            set.insert(val);
+        }
+        assert_eq!(vec![-3, 5, 100], set.iter().collect::<Vec<_>>());
+        assert_eq!(6, set.bytes());
+
+        // 插入 i32 范围的整数之后整个集合升级为 4 字节
+        assert!(set.insert(-70000));
+        assert_eq!(16, set.bytes());
+        assert!(set.insert(i64::MAX));
+        assert_eq!(vec![-70000, -3, 5, 100, i64::MAX], set.iter().collect::<Vec<_>>());
+        assert_eq!(40, set.bytes());
+
+        assert!(set.contains(-70000));
+        assert!(!set.contains(6));
+        assert!(set.remove(5));
+        assert!(!set.remove(5));
+        assert_eq!(Some(100), set.get(2));
+        assert_eq!(4, set.len());
+    }
+}
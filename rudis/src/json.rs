@@ -0,0 +1,344 @@
+//! JSON 文档以及路径查询
+//!
+//! 与 RedisJSON 相同, 支持两种路径语法:
+//! + JSONPath: 以 `$` 开头, 可以匹配多个节点, 命令对每个匹配的节点分别执行, 结果以数组返回
+//! + 旧的路径语法: 例如 `.a.b[0]`、`a.b`, `.` 代表根节点, 只使用第一个匹配的节点
+//!
+//! 支持的 JSONPath 子集:
+//! `.name`、`['name']`、`[index]`(负数从末尾开始)、`.*`、`[*]` 以及递归查找 `..name`、`..*`
+
+use serde_json::Value;
+
+pub const INVALID_PATH_ERR: &str = "ERR invalid JSON path";
+
+/// 解析之后的路径
+#[derive(Debug, Clone, PartialEq)]
+pub struct Path {
+    /// 是否为 `$` 开头的 JSONPath
+    pub jsonpath: bool,
+    selectors: Vec<Selector>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Selector {
+    Key(String),
+    Index(i64),
+    Wildcard,
+    /// `..` 之后的选择器, 匹配当前节点以及所有子孙节点
+    Descendant(Box<Selector>),
+}
+
+/// 从根节点到某个节点的具体路径
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Step {
+    Key(String),
+    Index(usize),
+}
+
+impl Path {
+    pub fn parse(path: &str) -> Result<Path, String> {
+        let (jsonpath, rest) = match path.strip_prefix('$') {
+            Some(rest) => (true, rest.to_string()),
+            None if path == "." => (false, String::new()),
+            None if path.starts_with(['.', '[']) => (false, path.to_string()),
+            // 旧的路径语法中开头的 `.` 可以省略
+            None => (false, format!(".{}", path)),
+        };
+
+        let mut rest = rest.as_str();
+        let mut selectors = vec![];
+        while !rest.is_empty() {
+            let (selector, next) = if let Some(after) = rest.strip_prefix("..") {
+                let (selector, next) = parse_member(after)?;
+                (Selector::Descendant(Box::new(selector)), next)
+            } else if let Some(after) = rest.strip_prefix('.') {
+                parse_member(after)?
+            } else if rest.starts_with('[') {
+                parse_bracket(rest)?
+            } else {
+                return Err(INVALID_PATH_ERR.to_string());
+            };
+
+            selectors.push(selector);
+            rest = next;
+        }
+
+        Ok(Path {
+            jsonpath,
+            selectors,
+        })
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.selectors.is_empty()
+    }
+
+    /// 查找所有匹配的节点, 返回它们的具体路径
+    pub fn find(&self, root: &Value) -> Vec<Vec<Step>> {
+        find(root, &self.selectors)
+    }
+
+    /// 最后一个选择器为对象的 key 时, 返回父节点的路径以及 key, 用于 JSON.SET 添加新的字段
+    pub fn parent(&self) -> Option<(Path, &str)> {
+        match self.selectors.split_last()? {
+            (Selector::Key(key), parent) => Some((
+                Path {
+                    jsonpath: self.jsonpath,
+                    selectors: parent.to_vec(),
+                },
+                key,
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// `.` 之后的成员名称或者 `*`, 也可以是 `[...]`
+fn parse_member(rest: &str) -> Result<(Selector, &str), String> {
+    if rest.starts_with('[') {
+        return parse_bracket(rest);
+    }
+
+    let end = rest.find(['.', '[']).unwrap_or(rest.len());
+    match &rest[..end] {
+        "" => Err(INVALID_PATH_ERR.to_string()),
+        "*" => Ok((Selector::Wildcard, &rest[end..])),
+        name => Ok((Selector::Key(name.to_string()), &rest[end..])),
+    }
+}
+
+/// `[index]`、`[*]`、`['name']` 或者 `["name"]`
+fn parse_bracket(rest: &str) -> Result<(Selector, &str), String> {
+    let rest = &rest[1..];
+    if let Some(quote) = rest.chars().next().filter(|c| *c == '\'' || *c == '"') {
+        let body = &rest[1..];
+        let end = body.find(quote).ok_or(INVALID_PATH_ERR)?;
+        let next = body[end + 1..]
+            .strip_prefix(']')
+            .ok_or(INVALID_PATH_ERR)?;
+
+        return Ok((Selector::Key(body[..end].to_string()), next));
+    }
+
+    let end = rest.find(']').ok_or(INVALID_PATH_ERR)?;
+    let selector = match rest[..end].trim() {
+        "*" => Selector::Wildcard,
+        index => Selector::Index(index.parse().map_err(|_| INVALID_PATH_ERR)?),
+    };
+
+    Ok((selector, &rest[end + 1..]))
+}
+
+fn find(root: &Value, selectors: &[Selector]) -> Vec<Vec<Step>> {
+    let mut current = vec![(vec![], root)];
+    for selector in selectors {
+        let mut next = vec![];
+        for (path, node) in current {
+            match selector {
+                Selector::Descendant(selector) => {
+                    for (path, node) in descendants(path, node) {
+                        select(selector, path, node, &mut next);
+                    }
+                }
+                selector => select(selector, path, node, &mut next),
+            }
+        }
+        current = next;
+    }
+
+    current.into_iter().map(|(path, _)| path).collect()
+}
+
+/// 对一个节点应用选择器, 结果追加到 out 中
+fn select<'a>(
+    selector: &Selector,
+    path: Vec<Step>,
+    node: &'a Value,
+    out: &mut Vec<(Vec<Step>, &'a Value)>,
+) {
+    let child = |step: Step| {
+        let mut path = path.clone();
+        path.push(step);
+        path
+    };
+
+    match (selector, node) {
+        (Selector::Key(key), Value::Object(map)) => {
+            if let Some(value) = map.get(key) {
+                out.push((child(Step::Key(key.clone())), value));
+            }
+        }
+        (Selector::Index(index), Value::Array(array)) => {
+            let len = array.len() as i64;
+            let index = if *index < 0 { len + index } else { *index };
+            if (0..len).contains(&index) {
+                out.push((child(Step::Index(index as usize)), &array[index as usize]));
+            }
+        }
+        (Selector::Wildcard, Value::Object(map)) => {
+            for (key, value) in map {
+                out.push((child(Step::Key(key.clone())), value));
+            }
+        }
+        (Selector::Wildcard, Value::Array(array)) => {
+            for (i, value) in array.iter().enumerate() {
+                out.push((child(Step::Index(i)), value));
+            }
+        }
+        _ => {}
+    }
+}
+
+/// 节点本身以及所有的子孙节点, 先序遍历
+fn descendants(path: Vec<Step>, node: &Value) -> Vec<(Vec<Step>, &Value)> {
+    let mut out = vec![];
+    let mut stack = vec![(path, node)];
+    while let Some((path, node)) = stack.pop() {
+        let mut children = vec![];
+        select(&Selector::Wildcard, path.clone(), node, &mut children);
+        out.push((path, node));
+        stack.extend(children.into_iter().rev());
+    }
+
+    out
+}
+
+/// 根据具体路径获取节点
+pub fn get<'a>(root: &'a Value, path: &[Step]) -> Option<&'a Value> {
+    path.iter().try_fold(root, |node, step| match (step, node) {
+        (Step::Key(key), Value::Object(map)) => map.get(key),
+        (Step::Index(i), Value::Array(array)) => array.get(*i),
+        _ => None,
+    })
+}
+
+pub fn get_mut<'a>(root: &'a mut Value, path: &[Step]) -> Option<&'a mut Value> {
+    path.iter().try_fold(root, |node, step| match (step, node) {
+        (Step::Key(key), Value::Object(map)) => map.get_mut(key),
+        (Step::Index(i), Value::Array(array)) => array.get_mut(*i),
+        _ => None,
+    })
+}
+
+/// 删除节点, 返回是否删除成功
+pub fn remove(root: &mut Value, path: &[Step]) -> bool {
+    let Some((last, parent)) = path.split_last() else {
+        return false;
+    };
+
+    match (last, get_mut(root, parent)) {
+        // shift_remove 保持其余字段的顺序
+        (Step::Key(key), Some(Value::Object(map))) => map.shift_remove(key).is_some(),
+        (Step::Index(i), Some(Value::Array(array))) if *i < array.len() => {
+            array.remove(*i);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// JSON.TYPE 返回的类型名称
+pub fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// JSON.GET 的输出格式, 默认输出紧凑的 JSON
+#[derive(Debug, Clone, Default)]
+pub struct Format {
+    pub indent: String,
+    pub newline: String,
+    pub space: String,
+}
+
+impl Format {
+    pub fn to_string(&self, value: &Value) -> String {
+        let mut out = String::new();
+        self.write(value, 0, &mut out);
+        out
+    }
+
+    fn write(&self, value: &Value, depth: usize, out: &mut String) {
+        let items: Vec<(Option<&String>, &Value)> = match value {
+            Value::Array(array) if !array.is_empty() => array.iter().map(|v| (None, v)).collect(),
+            Value::Object(map) if !map.is_empty() => map.iter().map(|(k, v)| (Some(k), v)).collect(),
+            // 标量以及空的数组、对象直接使用 serde_json 序列化
+            value => {
+                out.push_str(&value.to_string());
+                return;
+            }
+        };
+
+        let (open, close) = if value.is_array() { ('[', ']') } else { ('{', '}') };
+        out.push(open);
+        for (i, (key, value)) in items.into_iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str(&self.newline);
+            out.push_str(&self.indent.repeat(depth + 1));
+            if let Some(key) = key {
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                out.push_str(&self.space);
+            }
+            self.write(value, depth + 1, out);
+        }
+        out.push_str(&self.newline);
+        out.push_str(&self.indent.repeat(depth));
+        out.push(close);
+    }
+}
+
+/// 估算 JSON 文档占用的内存
+pub fn approx_size(value: &Value) -> usize {
+    const NODE: usize = 32;
+
+    match value {
+        Value::String(s) => NODE + s.len(),
+        Value::Array(array) => NODE + array.iter().map(approx_size).sum::<usize>(),
+        Value::Object(map) => {
+            NODE + map
+                .iter()
+                .map(|(key, value)| key.len() + approx_size(value))
+                .sum::<usize>()
+        }
+        _ => NODE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn find_paths() {
+        let doc = json!({"a": {"b": [1, 2, {"b": 3}]}, "c": {"b": true}});
+        let find = |path: &str| -> Vec<Value> {
+            let path = Path::parse(path).unwrap();
+            path.find(&doc)
+                .iter()
+                .map(|steps| get(&doc, steps).unwrap().clone())
+                .collect()
+        };
+
+        assert_eq!(vec![doc.clone()], find("$"));
+        assert_eq!(vec![doc.clone()], find("."));
+        assert_eq!(vec![json!(2)], find("a.b[1]"));
+        assert_eq!(vec![json!({"b": 3})], find("$.a.b[-1]"));
+        assert_eq!(vec![json!(true)], find("$['c'].b"));
+        assert_eq!(vec![json!([1, 2, {"b": 3}]), json!(3), json!(true)], find("$..b"));
+        assert_eq!(vec![json!(1), json!(2), json!({"b": 3})], find("$.a.b[*]"));
+        assert!(find("$.x.y").is_empty());
+        assert!(Path::parse("$.a[").is_err());
+    }
+}
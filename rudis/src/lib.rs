@@ -1,13 +1,55 @@
-use std::io::Cursor;
+#[cfg(unix)]
+use std::path::Path;
+use std::{future::Future, pin::Pin};
 
-use bytes::{Buf, Bytes, BytesMut};
-use mini_redis::{Frame, Result};
+use bytes::{Bytes, BytesMut};
+#[cfg(unix)]
+use tokio::net::UnixStream;
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt, BufWriter},
-    net::TcpStream,
+    io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter},
+    net::{TcpStream, ToSocketAddrs},
     sync::oneshot::Sender,
 };
 
+pub mod bloom;
+pub mod cache;
+pub mod cmd;
+pub mod cms;
+pub mod codec;
+pub mod config;
+pub mod db;
+pub mod dump;
+pub mod frame;
+pub use frame::Frame;
+pub mod geo;
+pub mod glob;
+pub mod hash;
+pub mod hll;
+pub mod inline;
+pub mod intset;
+pub mod json;
+pub mod list;
+pub mod listpack;
+pub mod notify;
+pub mod parser;
+pub use parser::Parser;
+pub mod pool;
+pub use pool::Pool;
+pub mod proxy;
+pub mod pubsub;
+pub mod script;
+pub mod server;
+pub mod session;
+pub mod set;
+pub mod timeseries;
+pub mod tracking;
+pub mod zset;
+
+/// 与 `mini_redis::Error` 相同, 使用 `Box<dyn Error>` 作为统一的错误类型
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
 #[derive(Debug)]
 pub enum Command {
     Set {
@@ -23,28 +65,136 @@ pub enum Command {
 
 type Response<T> = Sender<Result<T>>;
 
-pub struct Connection {
+/// 对 RESP 连接读写的封装
+///
+/// 底层的 stream 默认为 TcpStream, 也可以是 UnixStream 等任意实现了 AsyncRead + AsyncWrite 的类型
+pub struct Connection<S = TcpStream> {
     /// 该结构体实现了 AsyncWrite 特征
     /// 当 write 方法被调用时, 不会直接写入到 socket 中, 而是先写入到缓冲区中
     ///
     /// **当缓冲区被填满时,其中的内容会自动刷到(写入到)内部的 socket 中, 然后再将缓冲区清空**
-    stream: BufWriter<TcpStream>,
+    stream: BufWriter<S>,
 
     /// 由于读取 stream 只会返回任意多的数据, 它可能返回帧的一部分、一个帧、多个帧，总之这种读取行为是不确定的
     /// 所以我们需要一个 buffer 将数据缓存下来, 然后进行解析 Frame
     /// 解析完毕之后在缓冲区中移除对应的 Frame 数据
     buffer: BytesMut,
+
+    /// 在多次读取之间保存解析的进度, 数据不完整时不需要从头重新解析
+    parser: Parser,
+
+    /// 读写过程中出现 IO 错误或协议错误后, 该连接就不能再被复用了
+    /// 连接池通过该标记驱逐损坏的连接
+    broken: bool,
+
+    /// 请求已经发出但还没有读到回复.
+    /// 等待回复的 Future 被取消时该标记会一直保留, 迟到的回复会被下一个请求读到, 因此同样视为损坏
+    in_flight: bool,
+
+    /// 是否接受 inline 命令, 只有服务端才需要开启
+    inline: bool,
 }
 
 impl Connection {
-    fn new(tcp_stream: TcpStream) -> Self {
+    /// 建立一个到 rudis(或者任意 RESP 服务) 的连接
+    pub async fn connect<T: ToSocketAddrs>(addr: T) -> Result<Connection> {
+        let tcp_stream = TcpStream::connect(addr).await?;
+        // 请求通常很小, 关闭 Nagle 算法, 否则 pipeline 时会与对端的延迟 ACK 相互等待
+        tcp_stream.set_nodelay(true)?;
+
+        Ok(Connection::new(tcp_stream))
+    }
+}
+
+#[cfg(unix)]
+impl Connection<UnixStream> {
+    /// 通过 Unix domain socket 建立连接, 同一台机器上的进程可以跳过 TCP 协议栈
+    pub async fn connect_unix<P: AsRef<Path>>(path: P) -> Result<Connection<UnixStream>> {
+        let unix_stream = UnixStream::connect(path).await?;
+
+        Ok(Connection::new(unix_stream))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> Connection<S> {
+    pub fn new(stream: S) -> Self {
         Connection {
-            stream: BufWriter::new(tcp_stream),
+            stream: BufWriter::new(stream),
             buffer: BytesMut::with_capacity(4096),
+            parser: Parser::new(),
+            broken: false,
+            in_flight: false,
+            inline: false,
+        }
+    }
+
+    /// 接受 inline 命令(例如 `SET foo bar\r\n`), 服务端开启之后就可以通过 telnet、nc 直接发送命令
+    ///
+    /// 客户端不应该开启: 回复总是 RESP 格式, 非法的数据应该作为协议错误处理.
+    /// 开启之后 RESP 格式的请求也只能是由 bulk 组成的 Array, 见 [`Parser::requests`]
+    pub fn enable_inline(&mut self) {
+        self.inline = true;
+        self.parser = Parser::requests();
+    }
+
+    /// 连接是否已经损坏
+    pub fn is_broken(&self) -> bool {
+        self.broken || self.in_flight
+    }
+
+    /// 发送一个命令并等待回复, 命令的每个参数都会作为 bulk 发送
+    pub async fn request<I, A>(&mut self, args: I) -> Result<Frame>
+    where
+        I: IntoIterator<Item = A>,
+        A: Into<Bytes>,
+    {
+        let mut frame = Frame::array();
+        for arg in args {
+            frame.push_bulk(arg.into());
+        }
+
+        self.request_frame(&frame).await
+    }
+
+    /// 发送一个已经编码好的命令并等待回复
+    pub async fn request_frame(&mut self, frame: &Frame) -> Result<Frame> {
+        self.in_flight = true;
+        self.write_frame(frame).await?;
+
+        match self.read_frame().await? {
+            Some(frame) => {
+                self.in_flight = false;
+                Ok(frame)
+            }
+            None => {
+                self.broken = true;
+                Err("connection reset by peer".into())
+            }
+        }
+    }
+
+    pub async fn read_frame(&mut self) -> Result<Option<Frame>> {
+        let res = self.read_frame_inner().await;
+        if res.is_err() {
+            self.broken = true;
+        }
+
+        res
+    }
+
+    /// 只从缓冲区中解析一个 Frame, 不会读取 socket
+    ///
+    /// 服务端通过它判断 pipeline 中是否还有已经到达的请求, 没有时再把积累的回复一起 flush
+    pub fn buffered_frame(&mut self) -> Result<Option<Frame>> {
+        let res = self.parse_frame();
+        if res.is_err() {
+            self.broken = true;
         }
+
+        res
     }
 
-    async fn read_frame(&mut self) -> Result<Option<Frame>> {
+    async fn read_frame_inner(&mut self) -> Result<Option<Frame>> {
         loop {
             // 首先在缓冲区中尝试解析一个 Frame
             if let Some(frame) = self.parse_frame()? {
@@ -57,7 +207,7 @@ impl Connection {
                 // n == 0 说明对端关闭了连接, 我们需要判断缓冲区内是否还有数据
                 // + 缓冲区为空: 代表解析了完整的Frame
                 // + 缓冲区不为空: 代表数据发送了一半
-                if self.buffer.is_empty() {
+                if self.buffer.is_empty() && self.parser.is_idle() {
                     return Ok(None);
                 } else {
                     return Err("connection reset by peer".into());
@@ -67,64 +217,104 @@ impl Connection {
     }
 
     fn parse_frame(&mut self) -> Result<Option<Frame>> {
-        // 在 buffer 中读取一个 Frame
-        // 解析 Frame
-        let mut buf = Cursor::new(&self.buffer[..]);
-
-        // Frame::check 实际上会移动内部的 curson 位置
-        match Frame::check(&mut buf) {
-            Ok(_) => {
-                // 获取当前 position 位置
-                let position = buf.position() as usize;
+        // 与 redis 相同, 不是以 `*` 开头的请求都作为 inline 命令处理
+        if self.inline
+            && self.parser.is_idle()
+            && self.buffer.first().is_some_and(|b| *b != b'*')
+        {
+            return inline::parse(&mut self.buffer);
+        }
 
-                // 将 position 设置为0
-                buf.set_position(0);
+        self.parser.parse(&mut self.buffer)
+    }
 
-                // 解析 frame
-                let frame = Frame::parse(&mut buf).unwrap();
+    pub async fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        self.feed_frame(frame).await?;
+        self.flush().await
+    }
 
-                // 将解析的 frame 数据从 buffer 中移除
-                self.buffer.advance(position);
+    /// 一次写入多个 Frame 并只 flush 一次, 用于 pipeline
+    pub async fn write_frames(&mut self, frames: &[Frame]) -> io::Result<()> {
+        self.feed_frames(frames).await?;
+        self.flush().await
+    }
 
-                Ok(Some(frame))
-            }
-            Err(mini_redis::frame::Error::Incomplete) => Ok(None),
-            Err(e) => Err(e.into()),
+    /// 只把 Frame 写入 BufWriter, 不 flush. 缓冲区被填满时仍然会自动写入 socket
+    pub async fn feed_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        let res = self.write_value(frame).await;
+        if res.is_err() {
+            self.broken = true;
         }
+
+        res
     }
 
-    async fn write_fram(&mut self, frame: Frame) -> Result<()> {
-        // match frame {
-        //     Frame::Simple(val) => {
-        //         self.stream.write_u8(b'+').await?;
-        //         self.stream.write_all(val.as_bytes()).await?;
-        //         self.stream.write_all(b"\r\n").await?;
-        //     }
-        //     Frame::Error(val) => {
-        //         self.stream.write_u8(b'-').await?;
-        //         self.stream.write_all(val.as_bytes()).await?;
-        //         self.stream.write_all(b"\r\n").await?;
-        //     }
-        //     Frame::Integer(val) => {
-        //         self.stream.write_u8(b':').await?;
-        //         self.write_decimal(*val).await?;
-        //     }
-        //     Frame::Null => {
-        //         self.stream.write_all(b"$-1\r\n").await?;
-        //     }
-        //     Frame::Bulk(val) => {
-        //         let len = val.len();
-
-        //         self.stream.write_u8(b'$').await?;
-        //         self.write_decimal(len as u64).await?;
-        //         self.stream.write_all(&val).await?;
-        //         self.stream.write_all(b"\r\n").await?;
-        //     }
-        //     Frame::Array(_val) => unimplemented!(),
-        // }
-
-        // self.stream.flush().await;
+    pub async fn feed_frames(&mut self, frames: &[Frame]) -> io::Result<()> {
+        for frame in frames {
+            self.feed_frame(frame).await?;
+        }
 
         Ok(())
     }
+
+    /// 将 BufWriter 中缓冲的数据刷到 socket 中
+    pub async fn flush(&mut self) -> io::Result<()> {
+        let res = self.stream.flush().await;
+        if res.is_err() {
+            self.broken = true;
+        }
+
+        res
+    }
+
+    /// 写入一个 Frame, Array 中允许嵌套 Array, 因此需要递归调用
+    ///
+    /// async fn 不能直接递归(Future 的大小无法确定), 所以这里返回 `Pin<Box<dyn Future>>`
+    fn write_value<'a>(
+        &'a mut self,
+        frame: &'a Frame,
+    ) -> Pin<Box<dyn Future<Output = io::Result<()>> + Send + 'a>> {
+        Box::pin(async move {
+            match frame {
+                Frame::Simple(val) => {
+                    self.stream.write_u8(b'+').await?;
+                    self.stream.write_all(val.as_bytes()).await?;
+                    self.stream.write_all(b"\r\n").await?;
+                }
+                Frame::Error(val) => {
+                    self.stream.write_u8(b'-').await?;
+                    self.stream.write_all(val.as_bytes()).await?;
+                    self.stream.write_all(b"\r\n").await?;
+                }
+                Frame::Integer(val) => {
+                    self.stream.write_u8(b':').await?;
+                    self.write_decimal(*val).await?;
+                }
+                Frame::Null => {
+                    self.stream.write_all(b"$-1\r\n").await?;
+                }
+                Frame::Bulk(val) => {
+                    self.stream.write_u8(b'$').await?;
+                    self.write_decimal(val.len() as i64).await?;
+                    self.stream.write_all(val).await?;
+                    self.stream.write_all(b"\r\n").await?;
+                }
+                Frame::Array(val) | Frame::Push(val) => {
+                    let kind = if matches!(frame, Frame::Push(_)) { b'>' } else { b'*' };
+                    self.stream.write_u8(kind).await?;
+                    self.write_decimal(val.len() as i64).await?;
+                    for entry in val {
+                        self.write_value(entry).await?;
+                    }
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    async fn write_decimal(&mut self, val: i64) -> io::Result<()> {
+        self.stream.write_all(val.to_string().as_bytes()).await?;
+        self.stream.write_all(b"\r\n").await
+    }
 }
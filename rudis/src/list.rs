@@ -0,0 +1,209 @@
+//! 列表类型
+//!
+//! 与 redis 相同, 列表有两种编码:
+//! + listpack: 元素较少时使用, 所有元素保存在一块连续的内存中, 见 `crate::listpack`
+//! + quicklist: 超过 `list-max-listpack-size` 之后转换为 `VecDeque<Bytes>`.
+//!   redis 的 quicklist 是由 listpack 组成的双向链表, rudis 使用 VecDeque 代替, 名称保持一致
+//!
+//! 转换只发生在写入时, 并且是单向的: 元素减少之后不会再转换回 listpack
+
+use std::collections::{vec_deque, VecDeque};
+
+use bytes::Bytes;
+
+use crate::listpack::{self, Listpack};
+
+/// `list-max-listpack-size` 的默认值, 与 redis 相同, 代表 listpack 最多占用 8KB
+pub const DEFAULT_MAX_LISTPACK_SIZE: i64 = -2;
+
+#[derive(Debug, Clone)]
+pub enum List {
+    Listpack(Listpack),
+    Quicklist(VecDeque<Bytes>),
+}
+
+impl Default for List {
+    fn default() -> Self {
+        List::Listpack(Listpack::new())
+    }
+}
+
+impl PartialEq for List {
+    /// 只比较元素, 与编码无关
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl List {
+    pub fn new() -> List {
+        List::default()
+    }
+
+    /// 依次插入 items, 例如 RESTORE 时按照当前的 `list-max-listpack-size` 重新选择编码
+    pub fn from_items(items: impl IntoIterator<Item = Bytes>, max_size: i64) -> List {
+        let mut list = List::new();
+        for val in items {
+            list.push_back(val, max_size);
+        }
+        list
+    }
+
+    /// OBJECT ENCODING 返回的编码名称
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            List::Listpack(_) => "listpack",
+            List::Quicklist(_) => "quicklist",
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            List::Listpack(lp) => lp.len(),
+            List::Quicklist(list) => list.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// max_size 为 `list-max-listpack-size`, 超过之后转换为 quicklist
+    pub fn push_back(&mut self, val: Bytes, max_size: i64) {
+        match self {
+            List::Listpack(lp) => lp.push_back(&val),
+            List::Quicklist(list) => list.push_back(val),
+        }
+        self.convert(max_size);
+    }
+
+    pub fn push_front(&mut self, val: Bytes, max_size: i64) {
+        match self {
+            List::Listpack(lp) => lp.push_front(&val),
+            List::Quicklist(list) => list.push_front(val),
+        }
+        self.convert(max_size);
+    }
+
+    pub fn pop_front(&mut self) -> Option<Bytes> {
+        match self {
+            List::Listpack(lp) => lp.pop_front(),
+            List::Quicklist(list) => list.pop_front(),
+        }
+    }
+
+    pub fn pop_back(&mut self) -> Option<Bytes> {
+        match self {
+            List::Listpack(lp) => lp.pop_back(),
+            List::Quicklist(list) => list.pop_back(),
+        }
+    }
+
+    /// 下标范围 [start, stop] 内的元素
+    pub fn range(&self, start: usize, stop: usize) -> Vec<Bytes> {
+        match self {
+            List::Listpack(lp) => lp
+                .iter()
+                .skip(start)
+                .take(stop + 1 - start)
+                .map(Bytes::copy_from_slice)
+                .collect(),
+            List::Quicklist(list) => list.range(start..=stop).cloned().collect(),
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        match self {
+            List::Listpack(lp) => Iter::Listpack(lp.iter()),
+            List::Quicklist(list) => Iter::Quicklist(list.iter()),
+        }
+    }
+
+    /// listpack 编码占用的字节数, quicklist 编码返回 None
+    pub fn listpack_bytes(&self) -> Option<usize> {
+        match self {
+            List::Listpack(lp) => Some(lp.bytes()),
+            List::Quicklist(_) => None,
+        }
+    }
+
+    fn convert(&mut self, max_size: i64) {
+        let List::Listpack(lp) = self else {
+            return;
+        };
+        if fits(lp, max_size) {
+            return;
+        }
+
+        let list = lp.iter().map(Bytes::copy_from_slice).collect();
+        *self = List::Quicklist(list);
+    }
+}
+
+
+/// 与 redis 相同, 正数限制元素个数, -1 到 -5 分别限制占用的字节数为 4KB 到 64KB
+fn fits(lp: &Listpack, max_size: i64) -> bool {
+    if max_size > 0 {
+        lp.len() <= max_size as usize
+    } else {
+        let shift = (-max_size).clamp(1, 5) - 1;
+        lp.bytes() <= 4096 << shift
+    }
+}
+
+pub enum Iter<'a> {
+    Listpack(listpack::Iter<'a>),
+    Quicklist(vec_deque::Iter<'a, Bytes>),
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Iter::Listpack(iter) => iter.next(),
+            Iter::Quicklist(iter) => iter.next().map(|val| &val[..]),
+        }
+    }
+}
+
+impl DoubleEndedIterator for Iter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        match self {
+            Iter::Listpack(iter) => iter.next_back(),
+            Iter::Quicklist(iter) => iter.next_back().map(|val| &val[..]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert() {
+        let mut list = List::new();
+        for i in 0..3 {
+            list.push_back(Bytes::from(i.to_string()), 3);
+        }
+        assert_eq!("listpack", list.encoding());
+
+        list.push_front(Bytes::from("x"), 3);
+        assert_eq!("quicklist", list.encoding());
+        assert_eq!(vec!["x", "0", "1", "2"], list.range(0, 3));
+
+        // 元素减少之后保持 quicklist 编码, 比较时与编码无关
+        list.pop_front();
+        assert_eq!("quicklist", list.encoding());
+        let compact = List::from_items(["0", "1", "2"].map(Bytes::from), 3);
+        assert_eq!("listpack", compact.encoding());
+        assert_eq!(compact, list);
+
+        // 负数按照字节数限制
+        let mut big = List::new();
+        big.push_back(Bytes::from(vec![0; 5000]), -2);
+        assert_eq!("listpack", big.encoding());
+        big.push_back(Bytes::from(vec![0; 5000]), -2);
+        assert_eq!("quicklist", big.encoding());
+    }
+}
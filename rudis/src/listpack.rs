@@ -0,0 +1,286 @@
+//! listpack: 小集合的紧凑编码
+//!
+//! 与 redis 的 listpack 类似, 所有的元素连续地保存在同一块内存中, 每个元素的布局为:
+//!
+//! ```text
+//! | 长度(varint) | 数据 | backlen(反向 varint) |
+//! ```
+//!
+//! + 长度使用 LEB128 编码: 每个字节保存 7 位, 最高位为 1 代表后面还有字节
+//! + backlen 是 `长度 + 数据` 占用的字节数, 从右往左读取, 因此可以从尾部反向遍历
+//!
+//! 与 `VecDeque<Bytes>` 相比, 每个元素省去了 `Bytes` 本身(32 字节)以及堆内存分配的开销,
+//! 代价是按照下标访问需要遍历, 插入和删除需要移动后面的数据. 因此只适合元素较少的集合,
+//! 超过阈值之后需要转换为完整的数据结构, 见 `crate::list`、`crate::hash`
+
+use bytes::Bytes;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Listpack {
+    data: Vec<u8>,
+    len: usize,
+}
+
+impl Listpack {
+    pub fn new() -> Listpack {
+        Listpack::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 编码之后占用的字节数
+    pub fn bytes(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn push_back(&mut self, val: &[u8]) {
+        let offset = self.data.len();
+        self.insert_at(offset, val);
+    }
+
+    pub fn push_front(&mut self, val: &[u8]) {
+        self.insert_at(0, val);
+    }
+
+    pub fn pop_front(&mut self) -> Option<Bytes> {
+        self.remove(0)
+    }
+
+    pub fn pop_back(&mut self) -> Option<Bytes> {
+        let index = self.len.checked_sub(1)?;
+        self.remove(index)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        self.iter().nth(index)
+    }
+
+    /// 在 index 之前插入, index 等于 len 时追加到尾部
+    pub fn insert(&mut self, index: usize, val: &[u8]) {
+        assert!(index <= self.len, "listpack index out of bounds");
+        let offset = self.offset(index);
+        self.insert_at(offset, val);
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Bytes> {
+        if index >= self.len {
+            return None;
+        }
+
+        let start = self.offset(index);
+        let (val, end) = self.entry_at(start);
+        let val = Bytes::copy_from_slice(val);
+        self.data.drain(start..end);
+        self.len -= 1;
+
+        Some(val)
+    }
+
+    /// 替换 index 处的元素
+    pub fn replace(&mut self, index: usize, val: &[u8]) {
+        assert!(index < self.len, "listpack index out of bounds");
+        let start = self.offset(index);
+        let (_, end) = self.entry_at(start);
+        self.data.splice(start..end, encode(val));
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            data: &self.data,
+            front: 0,
+            back: self.data.len(),
+            remaining: self.len,
+        }
+    }
+
+    fn insert_at(&mut self, offset: usize, val: &[u8]) {
+        self.data.splice(offset..offset, encode(val));
+        self.len += 1;
+    }
+
+    /// 第 index 个元素的起始位置, index 等于 len 时返回末尾
+    fn offset(&self, index: usize) -> usize {
+        // 靠近尾部时反向遍历
+        if index > self.len / 2 {
+            let mut offset = self.data.len();
+            for _ in index..self.len {
+                offset -= read_backlen(&self.data[..offset]);
+            }
+            return offset;
+        }
+
+        let mut offset = 0;
+        for _ in 0..index {
+            offset = self.entry_at(offset).1;
+        }
+        offset
+    }
+
+    /// 解析 start 处的元素, 返回数据以及下一个元素的起始位置
+    fn entry_at(&self, start: usize) -> (&[u8], usize) {
+        entry_at(&self.data, start)
+    }
+}
+
+impl<'a> FromIterator<&'a [u8]> for Listpack {
+    fn from_iter<I: IntoIterator<Item = &'a [u8]>>(iter: I) -> Self {
+        let mut listpack = Listpack::new();
+        for val in iter {
+            listpack.push_back(val);
+        }
+        listpack
+    }
+}
+
+pub struct Iter<'a> {
+    data: &'a [u8],
+    front: usize,
+    back: usize,
+    remaining: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let (val, next) = entry_at(self.data, self.front);
+        self.front = next;
+        self.remaining -= 1;
+        Some(val)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl DoubleEndedIterator for Iter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        self.back -= read_backlen(&self.data[..self.back]);
+        self.remaining -= 1;
+        Some(entry_at(self.data, self.back).0)
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+fn encode(val: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(val.len() + 4);
+    write_varint(&mut out, val.len());
+    out.extend_from_slice(val);
+
+    let backlen = out.len();
+    write_backlen(&mut out, backlen);
+    out
+}
+
+fn entry_at(data: &[u8], start: usize) -> (&[u8], usize) {
+    let (len, header) = read_varint(&data[start..]);
+    let end = start + header + len;
+    let backlen_size = varint_size(header + len);
+
+    (&data[start + header..end], end + backlen_size)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut n: usize) {
+    while n >= 0x80 {
+        out.push((n as u8 & 0x7f) | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+/// 返回数值以及占用的字节数
+fn read_varint(data: &[u8]) -> (usize, usize) {
+    let mut n = 0;
+    for (i, &b) in data.iter().enumerate() {
+        n |= ((b & 0x7f) as usize) << (7 * i);
+        if b & 0x80 == 0 {
+            return (n, i + 1);
+        }
+    }
+    unreachable!("corrupted listpack entry")
+}
+
+fn varint_size(mut n: usize) -> usize {
+    let mut size = 1;
+    while n >= 0x80 {
+        n >>= 7;
+        size += 1;
+    }
+    size
+}
+
+/// 与 varint 相同的分组, 但是字节的顺序相反: 最后一个字节保存最低的 7 位
+fn write_backlen(out: &mut Vec<u8>, n: usize) {
+    let start = out.len();
+    write_varint(out, n);
+    out[start..].reverse();
+}
+
+/// 从 data 的末尾读取 backlen, 返回前一个元素占用的总字节数
+fn read_backlen(data: &[u8]) -> usize {
+    let mut n = 0;
+    for (i, &b) in data.iter().rev().enumerate() {
+        n |= ((b & 0x7f) as usize) << (7 * i);
+        if b & 0x80 == 0 {
+            return n + i + 1;
+        }
+    }
+    unreachable!("corrupted listpack entry")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operations() {
+        let long = vec![b'x'; 300];
+        let mut lp = Listpack::new();
+        lp.push_back(b"b");
+        lp.push_back(&long);
+        lp.push_front(b"a");
+        lp.push_back(b"");
+        lp.insert(2, b"c");
+
+        let items: Vec<&[u8]> = vec![b"a", b"b", b"c", &long, b""];
+        assert_eq!(items, lp.iter().collect::<Vec<_>>());
+        let reversed: Vec<_> = items.iter().rev().copied().collect();
+        assert_eq!(reversed, lp.iter().rev().collect::<Vec<_>>());
+        assert_eq!(Some(&long[..]), lp.get(3));
+        assert_eq!(None, lp.get(5));
+
+        lp.replace(1, b"bb");
+        assert_eq!(Some(&b"bb"[..]), lp.get(1));
+        assert_eq!(Some(Bytes::from(long.clone())), lp.remove(3));
+        assert_eq!(Some(Bytes::from("")), lp.pop_back());
+        assert_eq!(Some(Bytes::from("a")), lp.pop_front());
+        assert_eq!(vec![&b"bb"[..], b"c"], lp.iter().collect::<Vec<_>>());
+
+        // 两端交替遍历
+        let mut iter = lp.iter();
+        assert_eq!(Some(&b"c"[..]), iter.next_back());
+        assert_eq!(Some(&b"bb"[..]), iter.next());
+        assert_eq!(None, iter.next());
+
+        lp.pop_back();
+        lp.pop_back();
+        assert!(lp.is_empty());
+        assert_eq!(0, lp.bytes());
+    }
+}
@@ -1,8 +1,18 @@
+use std::{env, process, time::Duration};
+
+use futures::SinkExt;
+use rudis::{
+    codec::RespCodec,
+    proxy::{self, Proxy},
+    Frame,
+};
 use tokio::{
     fs::{File, OpenOptions},
     io::{self, AsyncReadExt, AsyncWriteExt},
     net::TcpListener,
 };
+use tokio_stream::StreamExt;
+use tokio_util::codec::Framed;
 
 #[tokio::main]
 async fn main() {
@@ -10,9 +20,16 @@ async fn main() {
     // io().await
 
     // 2. 一个简单的 echo 服务
-    echo().await;
+    // echo().await;
+
+    // 3. 基于 RespCodec 的 echo 服务, 原样返回收到的 Frame
+    // resp_echo().await
+
+    // 4. 在 echo 服务的基础上更进一步: 解析 Frame 之后转发给后端的 rudis 节点
+    proxy().await
 }
 
+#[allow(dead_code)]
 async fn echo() {
     let tcp_listener = TcpListener::bind("127.0.0.1:9527").await.unwrap();
 
@@ -48,6 +65,151 @@ async fn echo() {
     }
 }
 
+/// 与 echo 相同, 区别在于读写的单位不再是字节, 而是一个个完整的 Frame
+///
+/// Framed 将 `AsyncRead + AsyncWrite` 与编解码器组合在一起:
+/// + 读取时通过 Decoder 把字节流解码为 Frame, Framed 实现了 `Stream<Item = Result<Frame>>`
+/// + 写入时通过 Encoder 把 Frame 编码为字节, Framed 实现了 `Sink<Frame>`
+///
+/// 因此可以直接使用 StreamExt、SinkExt 中的组合子, 而不需要自己管理缓冲区.
+/// 可以通过 `rudis-cli -p 9528 ping hello` 进行测试
+#[allow(dead_code)]
+async fn resp_echo() {
+    let tcp_listener = TcpListener::bind("127.0.0.1:9528").await.unwrap();
+
+    loop {
+        let (tcp_stream, _) = tcp_listener.accept().await.unwrap();
+
+        tokio::spawn(async move {
+            let mut framed = Framed::new(tcp_stream, RespCodec::with_inline());
+
+            // 将命令(一个 Array)转换为回复:
+            // + 只有命令名称时返回 PONG
+            // + 只有一个参数时返回该参数
+            // + 否则将除了命令名称之外的参数作为 Array 返回
+            while let Some(frame) = framed.next().await {
+                let reply = match frame {
+                    Ok(Frame::Array(mut args)) if args.len() > 1 => {
+                        args.remove(0);
+                        match args.len() {
+                            1 => args.remove(0),
+                            _ => Frame::Array(args),
+                        }
+                    }
+                    Ok(_) => Frame::Simple("PONG".to_string()),
+                    Err(err) => {
+                        eprintln!("failed to decode frame: {}", err);
+                        break;
+                    }
+                };
+
+                // send 会编码 Frame 并 flush
+                if framed.send(reply).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+/// RESP 代理, 将 key 通过一致性哈希分配到多个后端节点, 详见 `rudis::proxy`
+///
+/// ```text
+/// rudis [--bind addr] [--port 9527] --backend host:port [--backend host:port ...]
+///       [--timeout ms] [--failure-limit n] [--pool-size n]
+/// ```
+async fn proxy() {
+    env_logger::init();
+
+    let args = ProxyArgs::build(env::args()).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+
+    let proxy = Proxy::new(args.backends, args.config).await.unwrap_or_else(|err| {
+        eprintln!("failed to start proxy: {}", err);
+        process::exit(1);
+    });
+    let tcp_listener = match TcpListener::bind((args.bind.as_str(), args.port)).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("failed to listen on {}:{}: {}", args.bind, args.port, err);
+            process::exit(1);
+        }
+    };
+
+    loop {
+        let (tcp_stream, _) = tcp_listener.accept().await.unwrap();
+        let _ = tcp_stream.set_nodelay(true);
+
+        // 与 echo 相同, 每条连接一个任务, 所有的任务共享后端的连接池
+        let proxy = proxy.clone();
+        tokio::spawn(async move { proxy.serve(tcp_stream).await });
+    }
+}
+
+struct ProxyArgs {
+    bind: String,
+    port: u16,
+    backends: Vec<String>,
+    config: proxy::Config,
+}
+
+impl ProxyArgs {
+    fn build(mut args: impl Iterator<Item = String>) -> Result<Self, &'static str> {
+        const USAGE: &str = "Usage: rudis [--bind addr] [--port port] --backend host:port ... \
+                             [--timeout ms] [--failure-limit n] [--pool-size n]";
+
+        args.next();
+
+        let (mut bind, mut port) = ("127.0.0.1".to_string(), 9527);
+        let (mut backends, mut config) = (vec![], proxy::Config::default());
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--bind" => bind = args.next().ok_or("--bind requires an address")?,
+                "--port" => {
+                    port = match args.next().map(|v| v.parse()) {
+                        Some(Ok(port)) => port,
+                        _ => Err("--port requires a valid port")?,
+                    }
+                }
+                "--backend" => backends.push(args.next().ok_or("--backend requires an address")?),
+                "--timeout" => {
+                    config.timeout = match args.next().map(|v| v.parse()) {
+                        Some(Ok(ms)) => Duration::from_millis(ms),
+                        _ => Err("--timeout requires a number")?,
+                    }
+                }
+                "--failure-limit" => {
+                    config.failure_limit = match args.next().map(|v| v.parse()) {
+                        Some(Ok(n)) if n > 0 => n,
+                        _ => Err("--failure-limit requires a positive number")?,
+                    }
+                }
+                "--pool-size" => {
+                    config.pool_size = match args.next().map(|v| v.parse()) {
+                        Some(Ok(n)) if n > 0 => n,
+                        _ => Err("--pool-size requires a positive number")?,
+                    }
+                }
+                _ => return Err(USAGE),
+            }
+        }
+
+        if backends.is_empty() {
+            return Err(USAGE);
+        }
+
+        Ok(ProxyArgs {
+            bind,
+            port,
+            backends,
+            config,
+        })
+    }
+}
+
 #[allow(dead_code)]
 async fn io() {
     // 1. async read
@@ -0,0 +1,317 @@
+//! keyspace notifications
+//!
+//! 通过 `notify-keyspace-events` 配置需要发布的事件, 事件会通过 pub/sub 发布到两个频道:
+//! + `__keyspace@<db>__:<key>`: 消息内容为事件名, 例如 `set`、`del`、`expired`
+//! + `__keyevent@<db>__:<event>`: 消息内容为 key
+//!
+//! 配置字符串中每个字符代表一类事件, 与 redis 保持一致:
+//!
+//! ```text
+//! K  keyspace 事件      E  keyevent 事件
+//! g  通用命令(DEL、EXPIRE、RENAME...)
+//! $  字符串命令          l  列表命令
+//! s  集合命令            h  哈希命令
+//! z  有序集合命令        t  stream 命令
+//! x  过期事件            e  淘汰事件
+//! m  key miss 事件       d  module 事件
+//! n  新建 key 事件
+//! A  g$lshztxed 的别名
+//! ```
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Flags(u32);
+
+/// 事件的分类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    Generic,
+    String,
+    List,
+    Set,
+    Hash,
+    ZSet,
+    Stream,
+    Expired,
+    Evicted,
+    KeyMiss,
+    Module,
+    New,
+}
+
+impl Class {
+    fn bit(&self) -> u32 {
+        match self {
+            Class::Generic => Flags::GENERIC,
+            Class::String => Flags::STRING,
+            Class::List => Flags::LIST,
+            Class::Set => Flags::SET,
+            Class::Hash => Flags::HASH,
+            Class::ZSet => Flags::ZSET,
+            Class::Stream => Flags::STREAM,
+            Class::Expired => Flags::EXPIRED,
+            Class::Evicted => Flags::EVICTED,
+            Class::KeyMiss => Flags::KEY_MISS,
+            Class::Module => Flags::MODULE,
+            Class::New => Flags::NEW,
+        }
+    }
+}
+
+impl Flags {
+    const KEYSPACE: u32 = 1 << 0;
+    const KEYEVENT: u32 = 1 << 1;
+    const GENERIC: u32 = 1 << 2;
+    const STRING: u32 = 1 << 3;
+    const LIST: u32 = 1 << 4;
+    const SET: u32 = 1 << 5;
+    const HASH: u32 = 1 << 6;
+    const ZSET: u32 = 1 << 7;
+    const EXPIRED: u32 = 1 << 8;
+    const EVICTED: u32 = 1 << 9;
+    const STREAM: u32 = 1 << 10;
+    const KEY_MISS: u32 = 1 << 11;
+    const MODULE: u32 = 1 << 12;
+    const NEW: u32 = 1 << 13;
+
+    /// `A` 包含的事件, 不包含 `m` 和 `n`
+    const ALL: u32 = Self::GENERIC
+        | Self::STRING
+        | Self::LIST
+        | Self::SET
+        | Self::HASH
+        | Self::ZSET
+        | Self::EXPIRED
+        | Self::EVICTED
+        | Self::STREAM
+        | Self::MODULE;
+
+    /// 字符与事件的对应关系, 同时决定了 `to_string` 输出的顺序
+    const CHARS: [(char, u32); 13] = [
+        ('g', Self::GENERIC),
+        ('$', Self::STRING),
+        ('l', Self::LIST),
+        ('s', Self::SET),
+        ('h', Self::HASH),
+        ('z', Self::ZSET),
+        ('x', Self::EXPIRED),
+        ('e', Self::EVICTED),
+        ('t', Self::STREAM),
+        ('m', Self::KEY_MISS),
+        ('d', Self::MODULE),
+        ('n', Self::NEW),
+        ('K', Self::KEYSPACE),
+    ];
+
+    pub fn from_bits(bits: u32) -> Flags {
+        Flags(bits)
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+
+    /// 解析配置字符串, 存在非法字符时返回 None
+    pub fn parse(val: &str) -> Option<Flags> {
+        let mut bits = 0;
+
+        for c in val.chars() {
+            bits |= match c {
+                'A' => Self::ALL,
+                'E' => Self::KEYEVENT,
+                c => Self::CHARS.iter().find(|(ch, _)| *ch == c)?.1,
+            };
+        }
+
+        Some(Flags(bits))
+    }
+
+    pub fn keyspace(&self) -> bool {
+        self.0 & Self::KEYSPACE != 0
+    }
+
+    pub fn keyevent(&self) -> bool {
+        self.0 & Self::KEYEVENT != 0
+    }
+
+    /// 某一类事件是否需要发布
+    ///
+    /// 只配置了事件类型, 而没有配置 K 或 E 时, 不会发布任何事件
+    pub fn enabled(&self, class: Class) -> bool {
+        (self.keyspace() || self.keyevent()) && self.0 & class.bit() != 0
+    }
+}
+
+impl fmt::Display for Flags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut bits = self.0;
+
+        if bits & Self::ALL == Self::ALL {
+            write!(f, "A")?;
+            bits &= !Self::ALL;
+        }
+
+        for (c, bit) in Self::CHARS {
+            if bits & bit != 0 {
+                write!(f, "{}", c)?;
+            }
+        }
+
+        if bits & Self::KEYEVENT != 0 {
+            write!(f, "E")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// keyspace 事件发布到的频道
+pub fn keyspace_channel(db: usize, key: &str) -> String {
+    format!("__keyspace@{}__:{}", db, key)
+}
+
+/// keyevent 事件发布到的频道
+pub fn keyevent_channel(db: usize, event: &str) -> String {
+    format!("__keyevent@{}__:{}", db, event)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use tokio::time::timeout;
+
+    use super::*;
+    use crate::{server, Connection, Frame};
+
+    /// 读取下一条 pmessage, 返回 (channel, message)
+    async fn next_event(conn: &mut Connection) -> (String, String) {
+        let frame = timeout(Duration::from_secs(2), conn.read_frame())
+            .await
+            .expect("no event received")
+            .unwrap()
+            .unwrap();
+
+        match frame {
+            Frame::Array(items) => match &items[..] {
+                [_, _, Frame::Bulk(channel), Frame::Bulk(message)] => (
+                    String::from_utf8_lossy(channel).into_owned(),
+                    String::from_utf8_lossy(message).into_owned(),
+                ),
+                _ => panic!("unexpected message: {:?}", items),
+            },
+            frame => panic!("unexpected frame: {}", frame),
+        }
+    }
+
+    async fn subscribe(server: &server::Handle) -> Connection {
+        let mut conn = server.connect().await.unwrap();
+        conn.request(["PSUBSCRIBE", "__keyspace@0__:*", "__keyevent@0__:*"])
+            .await
+            .unwrap();
+        conn.read_frame().await.unwrap();
+
+        conn
+    }
+
+    fn event(channel: &str, message: &str) -> (String, String) {
+        (channel.to_string(), message.to_string())
+    }
+
+    #[test]
+    fn parse_flags() {
+        let flags = Flags::parse("KEA").unwrap();
+        assert!(flags.keyspace() && flags.keyevent());
+        assert!(flags.enabled(Class::Expired));
+        assert!(!flags.enabled(Class::KeyMiss));
+        assert_eq!("AKE", flags.to_string());
+
+        // 没有 K 或 E 时不发布任何事件
+        assert!(!Flags::parse("g$").unwrap().enabled(Class::Generic));
+        assert!(Flags::parse("Kq").is_none());
+    }
+
+    #[tokio::test]
+    async fn keyspace_and_keyevent() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+        conn.request(["CONFIG", "SET", "notify-keyspace-events", "KEA"])
+            .await
+            .unwrap();
+        let mut events = subscribe(&server).await;
+
+        conn.request(["SET", "foo", "bar"]).await.unwrap();
+        assert_eq!(event("__keyspace@0__:foo", "set"), next_event(&mut events).await);
+        assert_eq!(event("__keyevent@0__:set", "foo"), next_event(&mut events).await);
+
+        conn.request(["EXPIRE", "foo", "100"]).await.unwrap();
+        assert_eq!(event("__keyspace@0__:foo", "expire"), next_event(&mut events).await);
+        assert_eq!(event("__keyevent@0__:expire", "foo"), next_event(&mut events).await);
+
+        conn.request(["DEL", "foo"]).await.unwrap();
+        assert_eq!(event("__keyspace@0__:foo", "del"), next_event(&mut events).await);
+        assert_eq!(event("__keyevent@0__:del", "foo"), next_event(&mut events).await);
+
+        // 过期的 key 由后台任务删除时同样发布 expired 事件
+        conn.request(["SET", "tmp", "1", "PX", "10"]).await.unwrap();
+        assert_eq!(event("__keyspace@0__:tmp", "set"), next_event(&mut events).await);
+        assert_eq!(event("__keyevent@0__:set", "tmp"), next_event(&mut events).await);
+        assert_eq!(event("__keyspace@0__:tmp", "expire"), next_event(&mut events).await);
+        assert_eq!(event("__keyevent@0__:expire", "tmp"), next_event(&mut events).await);
+        assert_eq!(event("__keyspace@0__:tmp", "expired"), next_event(&mut events).await);
+        assert_eq!(event("__keyevent@0__:expired", "tmp"), next_event(&mut events).await);
+    }
+
+    #[tokio::test]
+    async fn flag_filtering() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+        let mut events = subscribe(&server).await;
+
+        // 默认不发布任何事件
+        conn.request(["SET", "foo", "bar"]).await.unwrap();
+
+        // 只发布 keyevent 中的列表事件
+        conn.request(["CONFIG", "SET", "notify-keyspace-events", "El"])
+            .await
+            .unwrap();
+        let reply = conn
+            .request(["CONFIG", "GET", "notify-keyspace-events"])
+            .await
+            .unwrap();
+        assert_eq!(
+            Frame::Array(vec![
+                Frame::Bulk(Bytes::from("notify-keyspace-events")),
+                Frame::Bulk(Bytes::from("lE")),
+            ]),
+            reply
+        );
+
+        conn.request(["SET", "foo", "baz"]).await.unwrap();
+        conn.request(["DEL", "foo"]).await.unwrap();
+        conn.request(["RPUSH", "list", "a"]).await.unwrap();
+        assert_eq!(event("__keyevent@0__:rpush", "list"), next_event(&mut events).await);
+
+        // 列表被 pop 空之后的 del 属于通用事件, 不会发布
+        conn.request(["LPOP", "list"]).await.unwrap();
+        assert_eq!(event("__keyevent@0__:lpop", "list"), next_event(&mut events).await);
+
+        let reply = conn
+            .request(["CONFIG", "SET", "notify-keyspace-events", "Kq"])
+            .await
+            .unwrap();
+        assert!(matches!(reply, Frame::Error(_)));
+
+        conn.request(["CONFIG", "SET", "notify-keyspace-events", "Kg"])
+            .await
+            .unwrap();
+        conn.request(["SET", "foo", "bar"]).await.unwrap();
+        conn.request(["DEL", "foo"]).await.unwrap();
+        assert_eq!(event("__keyspace@0__:foo", "del"), next_event(&mut events).await);
+
+        // 不会有多余的事件
+        assert!(timeout(Duration::from_millis(100), events.read_frame()).await.is_err());
+    }
+}
@@ -0,0 +1,315 @@
+//! 可恢复的单遍 RESP 解析器
+//!
+//! `Frame::check` + `Frame::parse` 的方式需要把缓冲区扫描两遍, 并且数据不完整时,
+//! 每次 `read_buf` 之后都要从 Frame 的开头重新扫描. 对于大批量的 pipeline 以及很大的 bulk,
+//! 重复扫描的开销会随着数据量平方级增长.
+//!
+//! [`Parser`] 在多次读取之间保存解析的进度:
+//! + 每解析出一个完整的元素就立即从缓冲区中移除, 已经解析的数据不会被再次扫描
+//! + 已知长度的 bulk 只需要等待数据到齐, 并提前为其预留缓冲区空间
+//! + bulk 的数据直接通过 `BytesMut::split_to` 从缓冲区中切出, 不需要拷贝
+
+use bytes::{Buf, BytesMut};
+
+use crate::{Frame, Result};
+
+/// bulk 的最大长度, 与 redis 的 `proto-max-bulk-len` 默认值相同
+const MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+
+/// 一行(例如 `$5\r\n`)的最大长度, 超过之后视为协议错误, 避免恶意的客户端耗尽内存
+const MAX_LINE_LEN: usize = 64 * 1024;
+
+/// 为 Array 预先分配的最大容量, 元素个数由客户端指定, 不能完全信任
+const MAX_PREALLOC: usize = 1024;
+
+/// Array 的最大嵌套层数, 与 hiredis 相同.
+/// Frame 的 Drop、Display 以及编码都是递归的, 嵌套过深会让工作线程栈溢出
+const MAX_DEPTH: usize = 1000;
+
+#[derive(Debug, Default)]
+pub struct Parser {
+    state: State,
+
+    /// 尚未解析完成的 Array, 嵌套的 Array 依次入栈
+    stack: Vec<Pending>,
+
+    /// 在当前行中已经扫描过的字节数, 下一次从这里继续查找 "\r\n"
+    scanned: usize,
+
+    /// 是否只接受由 bulk 组成的 Array, 见 [`Parser::requests`]
+    requests: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum State {
+    /// 等待一行以类型字节开头的数据
+    #[default]
+    Line,
+
+    /// 已经解析了 bulk 的长度, 等待数据以及结尾的 "\r\n"
+    Bulk(usize),
+}
+
+#[derive(Debug)]
+struct Pending {
+    len: usize,
+    items: Vec<Frame>,
+    /// 是否为 RESP3 的推送消息(`>`)
+    push: bool,
+}
+
+impl Parser {
+    pub fn new() -> Parser {
+        Parser::default()
+    }
+
+    /// 解析客户端请求的解析器, 用于服务端
+    ///
+    /// 与 redis 相同, 请求中 Array 的元素只能是 bulk, 其他类型的元素(包括嵌套的 Array)都是协议错误
+    pub fn requests() -> Parser {
+        Parser {
+            requests: true,
+            ..Parser::default()
+        }
+    }
+
+    /// 当前是否没有解析到一半的 Frame
+    pub fn is_idle(&self) -> bool {
+        self.state == State::Line && self.stack.is_empty() && self.scanned == 0
+    }
+
+    /// 从缓冲区中解析一个 Frame, 已经解析的数据会从缓冲区中移除
+    ///
+    /// 数据不完整时返回 `Ok(None)`, 解析的进度会被保存下来, 读取到更多数据之后再次调用即可继续解析.
+    /// 返回错误之后解析器的状态是不确定的, 连接应该被关闭
+    pub fn parse(&mut self, buf: &mut BytesMut) -> Result<Option<Frame>> {
+        loop {
+            let frame = match self.state {
+                State::Bulk(len) => {
+                    if buf.len() < len + 2 {
+                        // 提前为剩余的数据预留空间, 避免 read_buf 多次扩容
+                        buf.reserve(len + 2 - buf.len());
+                        return Ok(None);
+                    }
+
+                    if &buf[len..len + 2] != b"\r\n" {
+                        return Err(invalid());
+                    }
+
+                    let data = buf.split_to(len).freeze();
+                    buf.advance(2);
+                    self.state = State::Line;
+
+                    Frame::Bulk(data)
+                }
+                State::Line => {
+                    let Some(end) = self.find_line(buf)? else {
+                        return Ok(None);
+                    };
+
+                    let line = parse_line(&buf[..end])?;
+                    if self.requests && !self.stack.is_empty() && !matches!(line, Line::Bulk(_)) {
+                        let got = buf[0] as char;
+                        return Err(format!("protocol error; expected '$', got '{}'", got).into());
+                    }
+
+                    let frame = match line {
+                        Line::Frame(frame) => Some(frame),
+                        Line::Bulk(len) => {
+                            self.state = State::Bulk(len);
+                            None
+                        }
+                        Line::Array(_, _) if self.stack.len() >= MAX_DEPTH => {
+                            return Err("protocol error; too many nested arrays".into());
+                        }
+                        Line::Array(len, push) => {
+                            self.stack.push(Pending {
+                                len,
+                                items: Vec::with_capacity(len.min(MAX_PREALLOC)),
+                                push,
+                            });
+                            None
+                        }
+                    };
+                    buf.advance(end + 2);
+
+                    match frame {
+                        Some(frame) => frame,
+                        None => continue,
+                    }
+                }
+            };
+
+            if let Some(frame) = self.complete(frame) {
+                return Ok(Some(frame));
+            }
+        }
+    }
+
+    /// 查找当前行的结尾, 返回 "\r\n" 的位置
+    fn find_line(&mut self, buf: &BytesMut) -> Result<Option<usize>> {
+        match buf[self.scanned..].iter().position(|b| *b == b'\n') {
+            Some(i) => {
+                let end = self.scanned + i;
+                self.scanned = 0;
+
+                // RESP 中的每一行都以 "\r\n" 结尾
+                if end == 0 || buf[end - 1] != b'\r' {
+                    return Err(invalid());
+                }
+
+                Ok(Some(end - 1))
+            }
+            None if buf.len() > MAX_LINE_LEN => Err("protocol error; line is too long".into()),
+            None => {
+                self.scanned = buf.len();
+                Ok(None)
+            }
+        }
+    }
+
+    /// 将解析出的元素放入所属的 Array 中, 最外层的 Frame 解析完成时将其返回
+    fn complete(&mut self, mut frame: Frame) -> Option<Frame> {
+        while let Some(top) = self.stack.last_mut() {
+            top.items.push(frame);
+            if top.items.len() < top.len {
+                return None;
+            }
+
+            let pending = self.stack.pop().unwrap();
+            frame = match pending.push {
+                true => Frame::Push(pending.items),
+                false => Frame::Array(pending.items),
+            };
+        }
+
+        Some(frame)
+    }
+}
+
+/// 一行数据解析的结果
+enum Line {
+    Frame(Frame),
+
+    /// bulk 的长度, 数据在后续的字节中
+    Bulk(usize),
+
+    /// Array 的元素个数以及是否为推送消息, 元素在后续的字节中
+    Array(usize, bool),
+}
+
+fn parse_line(line: &[u8]) -> Result<Line> {
+    let Some((&kind, rest)) = line.split_first() else {
+        return Err(invalid());
+    };
+
+    let line = match kind {
+        b'+' => Line::Frame(Frame::Simple(string(rest)?)),
+        b'-' => Line::Frame(Frame::Error(string(rest)?)),
+        b':' => Line::Frame(Frame::Integer(decimal(rest)?)),
+        b'$' | b'*' if rest == b"-1" => Line::Frame(Frame::Null),
+        b'$' => match usize::try_from(decimal(rest)?) {
+            Ok(len) if len <= MAX_BULK_LEN => Line::Bulk(len),
+            _ => return Err("protocol error; invalid bulk length".into()),
+        },
+        b'*' => match usize::try_from(decimal(rest)?) {
+            Ok(0) => Line::Frame(Frame::Array(vec![])),
+            Ok(len) => Line::Array(len, false),
+            Err(_) => return Err("protocol error; invalid multibulk length".into()),
+        },
+        b'>' => match usize::try_from(decimal(rest)?) {
+            Ok(0) => Line::Frame(Frame::Push(vec![])),
+            Ok(len) => Line::Array(len, true),
+            Err(_) => return Err("protocol error; invalid multibulk length".into()),
+        },
+        actual => {
+            return Err(format!("protocol error; invalid frame type byte `{}`", actual).into())
+        }
+    };
+
+    Ok(line)
+}
+
+fn string(bytes: &[u8]) -> Result<String> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| invalid())
+}
+
+fn decimal(bytes: &[u8]) -> Result<i64> {
+    std::str::from_utf8(bytes)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(invalid)
+}
+
+fn invalid() -> crate::Error {
+    "protocol error; invalid frame format".into()
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+
+    #[test]
+    fn resume_across_reads() {
+        let input = b"*3\r\n$3\r\nSET\r\n$5\r\nhello\r\n*2\r\n:-1\r\n$-1\r\n+OK\r\n";
+        let expected = Frame::Array(vec![
+            Frame::Bulk(Bytes::from("SET")),
+            Frame::Bulk(Bytes::from("hello")),
+            Frame::Array(vec![Frame::Integer(-1), Frame::Null]),
+        ]);
+
+        // 每次只喂一个字节, 模拟数据被拆分成多次读取
+        let mut parser = Parser::new();
+        let mut buf = BytesMut::new();
+        let mut frames = vec![];
+        for byte in input {
+            buf.extend_from_slice(&[*byte]);
+            while let Some(frame) = parser.parse(&mut buf).unwrap() {
+                frames.push(frame);
+            }
+        }
+
+        assert_eq!(vec![expected, Frame::Simple("OK".to_string())], frames);
+        assert!(parser.is_idle());
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn reject_invalid_frames() {
+        for input in [&b"?\r\n"[..], b"$3\r\nabcd\r\n", b":abc\r\n", b"+OK\n"] {
+            let mut buf = BytesMut::from(input);
+            assert!(Parser::new().parse(&mut buf).is_err());
+        }
+    }
+
+    #[test]
+    fn nesting_depth() {
+        let nested = |depth: usize| {
+            let mut buf = BytesMut::from(&b"*1\r\n".repeat(depth)[..]);
+            buf.extend_from_slice(b":1\r\n");
+            buf
+        };
+
+        let frame = Parser::new().parse(&mut nested(MAX_DEPTH)).unwrap();
+        assert!(matches!(frame, Some(Frame::Array(_))));
+
+        // 超过上限时在入栈之前就返回错误, 不会构造出过深的 Frame
+        let mut buf = nested(500_000);
+        let err = Parser::new().parse(&mut buf).unwrap_err();
+        assert!(err.to_string().contains("too many nested arrays"));
+    }
+
+    #[test]
+    fn requests_only_accept_bulks() {
+        let mut buf = BytesMut::from(&b"*2\r\n$3\r\nGET\r\n$1\r\na\r\n"[..]);
+        assert!(Parser::requests().parse(&mut buf).unwrap().is_some());
+
+        for input in [&b"*2\r\n$3\r\nGET\r\n:1\r\n"[..], b"*1\r\n*1\r\n$1\r\na\r\n"] {
+            let mut buf = BytesMut::from(input);
+            let err = Parser::requests().parse(&mut buf).unwrap_err();
+            assert!(err.to_string().contains("expected '$'"), "{}", err);
+        }
+    }
+}
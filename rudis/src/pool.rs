@@ -0,0 +1,628 @@
+//! 有界连接池
+//!
+//! 阻塞命令(例如 BLPOP)以及事务(MULTI/EXEC)会独占一条连接, 不能与其他请求共用同一个 socket.
+//! 连接池负责维护若干条独立的连接:
+//! + `max_size`: 连接总数(空闲 + 已借出)的上限
+//! + `min_idle`/`max_idle`: 后台任务会维持最少的空闲连接数, 同时回收超出上限的空闲连接
+//! + `checkout_timeout`: 借出连接的最长等待时间
+//! + 借出连接时通过 PING 进行健康检查, 失败或者超时的连接会直接被驱逐
+//! + 请求还没有收到回复就被取消的连接不会被归还, 否则下一个使用者会读到上一个请求的回复
+
+use std::{
+    collections::VecDeque,
+    future::Future,
+    ops::{Deref, DerefMut},
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
+};
+
+use log::{debug, warn};
+use tokio::{
+    sync::{Notify, OwnedSemaphorePermit, Semaphore},
+    time::{self, Instant},
+};
+
+use crate::{Connection, Frame, Result};
+
+/// 可以被连接池管理的连接
+///
+/// 连接池对连接的类型是泛型的, rudis 的 [`Connection`] 实现了该特征
+pub trait Manageable: Sized + Send + 'static {
+    /// 建立一条新的连接
+    fn connect(addr: &str) -> impl Future<Output = Result<Self>> + Send;
+
+    /// 健康检查, 借出连接之前调用
+    fn ping(&mut self) -> impl Future<Output = Result<()>> + Send;
+
+    /// 连接是否已经损坏, 损坏的连接在归还时会被丢弃
+    ///
+    /// 请求发出之后还没有读到回复的连接也应该视为损坏
+    fn is_broken(&self) -> bool;
+}
+
+impl Manageable for Connection {
+    async fn connect(addr: &str) -> Result<Self> {
+        Connection::connect(addr).await
+    }
+
+    async fn ping(&mut self) -> Result<()> {
+        match self.request(["PING"]).await? {
+            Frame::Simple(pong) if pong == "PONG" => Ok(()),
+            frame => Err(format!("unexpected PING reply: {}", frame).into()),
+        }
+    }
+
+    fn is_broken(&self) -> bool {
+        Connection::is_broken(self)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub max_size: usize,
+    pub min_idle: usize,
+    pub max_idle: usize,
+    pub checkout_timeout: Duration,
+
+    /// 空闲超过该时长的连接会被回收(但会保留 `min_idle` 条)
+    pub idle_timeout: Option<Duration>,
+
+    /// 借出连接时是否需要 PING 检查
+    pub test_on_checkout: bool,
+
+    /// PING 检查的超时时间, 超时的连接与检查失败的连接一样会被驱逐
+    pub ping_timeout: Duration,
+
+    /// 后台维护任务的执行间隔
+    pub maintenance_interval: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            max_size: 10,
+            min_idle: 0,
+            max_idle: 10,
+            checkout_timeout: Duration::from_secs(5),
+            idle_timeout: Some(Duration::from_secs(300)),
+            test_on_checkout: true,
+            ping_timeout: Duration::from_secs(1),
+            maintenance_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+pub struct Builder {
+    addr: String,
+    config: Config,
+}
+
+impl Builder {
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.config.max_size = max_size;
+        self
+    }
+
+    pub fn min_idle(mut self, min_idle: usize) -> Self {
+        self.config.min_idle = min_idle;
+        self
+    }
+
+    pub fn max_idle(mut self, max_idle: usize) -> Self {
+        self.config.max_idle = max_idle;
+        self
+    }
+
+    pub fn checkout_timeout(mut self, timeout: Duration) -> Self {
+        self.config.checkout_timeout = timeout;
+        self
+    }
+
+    pub fn idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.config.idle_timeout = timeout;
+        self
+    }
+
+    pub fn test_on_checkout(mut self, test: bool) -> Self {
+        self.config.test_on_checkout = test;
+        self
+    }
+
+    pub fn ping_timeout(mut self, timeout: Duration) -> Self {
+        self.config.ping_timeout = timeout;
+        self
+    }
+
+    pub fn maintenance_interval(mut self, interval: Duration) -> Self {
+        self.config.maintenance_interval = interval;
+        self
+    }
+
+    /// 创建连接池, 并预先建立 `min_idle` 条连接
+    ///
+    /// 需要在 tokio 运行时中调用, 因为会启动后台维护任务
+    pub async fn build<C: Manageable>(self) -> Result<Pool<C>> {
+        let mut config = self.config;
+        if config.max_size == 0 {
+            return Err("pool max_size must be greater than 0".into());
+        }
+        config.max_idle = config.max_idle.min(config.max_size);
+        config.min_idle = config.min_idle.min(config.max_idle);
+
+        let shared = Arc::new(Shared {
+            addr: self.addr,
+            permits: Arc::new(Semaphore::new(config.max_size)),
+            idle: Mutex::new(VecDeque::new()),
+            returned: Notify::new(),
+            config,
+        });
+
+        shared.fill_idle().await?;
+
+        // 后台任务只持有 Weak 引用, 当所有的 Pool 被 drop 后任务会自动退出
+        tokio::spawn(maintain(Arc::downgrade(&shared)));
+
+        Ok(Pool { shared })
+    }
+}
+
+/// 连接池, 可以廉价的 clone 并在多个任务之间共享
+pub struct Pool<C: Manageable = Connection> {
+    shared: Arc<Shared<C>>,
+}
+
+impl<C: Manageable> Clone for Pool<C> {
+    fn clone(&self) -> Self {
+        Pool {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+struct Shared<C> {
+    addr: String,
+    config: Config,
+
+    /// 每条存活的连接(无论空闲还是已借出)都持有一个 permit, 以此限制连接总数
+    permits: Arc<Semaphore>,
+    idle: Mutex<VecDeque<Idle<C>>>,
+
+    /// 连接被归还或者被驱逐时通知等待中的借出者
+    returned: Notify,
+}
+
+struct Idle<C> {
+    conn: C,
+    permit: OwnedSemaphorePermit,
+    since: Instant,
+}
+
+impl Pool<Connection> {
+    pub fn builder(addr: impl Into<String>) -> Builder {
+        Builder {
+            addr: addr.into(),
+            config: Config::default(),
+        }
+    }
+}
+
+impl<C: Manageable> Pool<C> {
+    /// 借出一条连接, 超过 `checkout_timeout` 仍然没有可用连接时返回错误
+    pub async fn get(&self) -> Result<Pooled<C>> {
+        let shared = &self.shared;
+        let deadline = Instant::now() + shared.config.checkout_timeout;
+
+        loop {
+            // 驱逐连接之后会重新循环, 超时之后不再继续检查剩下的空闲连接
+            if Instant::now() >= deadline {
+                return Err("pool checkout timed out".into());
+            }
+
+            // 先注册通知再检查状态, 避免在检查与等待之间错过归还事件
+            let returned = shared.returned.notified();
+            tokio::pin!(returned);
+            returned.as_mut().enable();
+
+            // 1. 优先复用空闲连接
+            let idle = shared.idle.lock().unwrap().pop_front();
+            if let Some(mut idle) = idle {
+                if !shared.config.test_on_checkout {
+                    return Ok(self.pooled(idle.conn, idle.permit));
+                }
+
+                // PING 超时的连接同样不能复用: 迟到的 PONG 会被下一个请求读到
+                let ping_deadline = deadline.min(Instant::now() + shared.config.ping_timeout);
+                let err = match time::timeout_at(ping_deadline, idle.conn.ping()).await {
+                    Ok(Ok(())) => return Ok(self.pooled(idle.conn, idle.permit)),
+                    Ok(Err(err)) => err,
+                    Err(_) => "PING timed out".into(),
+                };
+
+                // 丢弃该连接并唤醒等待者, 然后继续尝试下一条空闲连接
+                warn!("evict broken pooled connection: {}", err);
+                shared.release(idle.permit);
+                continue;
+            }
+
+            // 2. 没有空闲连接, 在连接数未达上限时建立新的连接
+            if let Ok(permit) = shared.permits.clone().try_acquire_owned() {
+                let conn = match time::timeout_at(deadline, C::connect(&shared.addr)).await {
+                    Ok(Ok(conn)) => conn,
+                    res => {
+                        // 建立连接失败时名额需要还给等待者
+                        shared.release(permit);
+                        return Err(match res {
+                            Ok(Err(err)) => err,
+                            _ => "pool checkout timed out".into(),
+                        });
+                    }
+                };
+
+                debug!("pool opened a new connection to {}", shared.addr);
+                return Ok(self.pooled(conn, permit));
+            }
+
+            // 3. 连接数已达上限, 等待其他连接被归还
+            if time::timeout_at(deadline, returned).await.is_err() {
+                return Err("pool checkout timed out".into());
+            }
+        }
+    }
+
+    /// 当前的连接状态
+    pub fn state(&self) -> State {
+        let shared = &self.shared;
+        let idle = shared.idle.lock().unwrap().len();
+
+        State {
+            connections: shared.config.max_size - shared.permits.available_permits(),
+            idle,
+        }
+    }
+
+    fn pooled(&self, conn: C, permit: OwnedSemaphorePermit) -> Pooled<C> {
+        Pooled {
+            conn: Some(conn),
+            permit: Some(permit),
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct State {
+    /// 存活的连接数(空闲 + 已借出)
+    pub connections: usize,
+    pub idle: usize,
+}
+
+impl<C: Manageable> Shared<C> {
+    /// 建立新的连接, 直到空闲连接数达到 `min_idle`
+    async fn fill_idle(&self) -> Result<()> {
+        loop {
+            if self.idle.lock().unwrap().len() >= self.config.min_idle {
+                return Ok(());
+            }
+
+            let Ok(permit) = self.permits.clone().try_acquire_owned() else {
+                return Ok(());
+            };
+
+            let conn = C::connect(&self.addr).await?;
+            self.put_idle(conn, permit);
+        }
+    }
+
+    /// 回收超过 `idle_timeout` 的空闲连接
+    fn reap_idle(&self) {
+        let Some(timeout) = self.config.idle_timeout else {
+            return;
+        };
+
+        let mut idle = self.idle.lock().unwrap();
+        while idle.len() > self.config.min_idle {
+            // 空闲连接从队尾归还, 队首是空闲最久的连接
+            match idle.front() {
+                Some(conn) if conn.since.elapsed() >= timeout => {
+                    idle.pop_front();
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn put_idle(&self, conn: C, permit: OwnedSemaphorePermit) {
+        let mut idle = self.idle.lock().unwrap();
+        if idle.len() < self.config.max_idle {
+            idle.push_back(Idle {
+                conn,
+                permit,
+                since: Instant::now(),
+            });
+        }
+        drop(idle);
+
+        // 无论连接是被放回还是被丢弃, 都可能让等待者继续前进
+        self.returned.notify_one();
+    }
+
+    /// 丢弃一条连接之后释放它的名额, 并唤醒一个等待者
+    fn release(&self, permit: OwnedSemaphorePermit) {
+        drop(permit);
+        self.returned.notify_one();
+    }
+}
+
+async fn maintain<C: Manageable>(shared: Weak<Shared<C>>) {
+    let interval = match shared.upgrade() {
+        Some(shared) => shared.config.maintenance_interval,
+        None => return,
+    };
+
+    loop {
+        time::sleep(interval).await;
+
+        let Some(shared) = shared.upgrade() else {
+            return;
+        };
+
+        shared.reap_idle();
+        if let Err(err) = shared.fill_idle().await {
+            warn!("pool failed to refill idle connections: {}", err);
+        }
+    }
+}
+
+/// 从连接池借出的连接, drop 时会自动归还
+pub struct Pooled<C: Manageable> {
+    conn: Option<C>,
+    permit: Option<OwnedSemaphorePermit>,
+    shared: Arc<Shared<C>>,
+}
+
+impl<C: Manageable> Pooled<C> {
+    /// 将连接从连接池中分离, 分离后连接不再占用连接池的名额
+    pub fn detach(mut self) -> C {
+        self.conn.take().unwrap()
+    }
+}
+
+impl<C: Manageable> Deref for Pooled<C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        self.conn.as_ref().unwrap()
+    }
+}
+
+impl<C: Manageable> DerefMut for Pooled<C> {
+    fn deref_mut(&mut self) -> &mut C {
+        self.conn.as_mut().unwrap()
+    }
+}
+
+impl<C: Manageable> Drop for Pooled<C> {
+    fn drop(&mut self) {
+        let permit = self.permit.take().unwrap();
+
+        match self.conn.take() {
+            Some(conn) if !conn.is_broken() => self.shared.put_idle(conn, permit),
+            // 连接已损坏、请求被取消或者已被分离, 释放 permit 即可
+            _ => self.shared.release(permit),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            LazyLock,
+        },
+    };
+
+    use super::*;
+    use crate::server;
+
+    /// 测试使用的连接, 行为由地址对应的 Backend 控制
+    struct Mock {
+        backend: Arc<Backend>,
+    }
+
+    #[derive(Default)]
+    struct Backend {
+        connects: AtomicUsize,
+
+        /// 接下来的若干次建立连接会失败
+        refuse: AtomicUsize,
+        ping: Mutex<Ping>,
+    }
+
+    #[derive(Default, Clone, Copy)]
+    enum Ping {
+        #[default]
+        Pong,
+        /// 等待一段时间后返回错误
+        Fail(Duration),
+        /// 永远不返回
+        Hang,
+    }
+
+    static BACKENDS: LazyLock<Mutex<HashMap<String, Arc<Backend>>>> = LazyLock::new(Default::default);
+
+    impl Manageable for Mock {
+        async fn connect(addr: &str) -> Result<Self> {
+            let backend = BACKENDS.lock().unwrap()[addr].clone();
+            if backend
+                .refuse
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok()
+            {
+                return Err("connection refused".into());
+            }
+
+            backend.connects.fetch_add(1, Ordering::SeqCst);
+            Ok(Mock { backend })
+        }
+
+        async fn ping(&mut self) -> Result<()> {
+            let ping = *self.backend.ping.lock().unwrap();
+            match ping {
+                Ping::Pong => Ok(()),
+                Ping::Fail(delay) => {
+                    time::sleep(delay).await;
+                    Err("connection reset by peer".into())
+                }
+                Ping::Hang => std::future::pending().await,
+            }
+        }
+
+        fn is_broken(&self) -> bool {
+            false
+        }
+    }
+
+    /// 每个测试使用不同的地址, 互不影响
+    fn backend(addr: &str) -> (Arc<Backend>, Builder) {
+        let backend = Arc::new(Backend::default());
+        BACKENDS
+            .lock()
+            .unwrap()
+            .insert(addr.to_string(), backend.clone());
+
+        let builder = Builder {
+            addr: addr.to_string(),
+            config: Config::default(),
+        };
+
+        (backend, builder)
+    }
+
+    #[tokio::test]
+    async fn checkout_and_reuse() {
+        let (backend, builder) = backend("reuse");
+        let pool: Pool<Mock> = builder.build().await.unwrap();
+
+        let (a, b) = (pool.get().await.unwrap(), pool.get().await.unwrap());
+        assert_eq!(State { connections: 2, idle: 0 }, pool.state());
+        drop((a, b));
+        assert_eq!(State { connections: 2, idle: 2 }, pool.state());
+
+        // 归还的连接被复用, 不会建立新的连接
+        for _ in 0..5 {
+            pool.get().await.unwrap();
+        }
+        assert_eq!(2, backend.connects.load(Ordering::SeqCst));
+
+        // 分离的连接不再占用名额
+        pool.get().await.unwrap().detach();
+        assert_eq!(State { connections: 1, idle: 1 }, pool.state());
+    }
+
+    #[tokio::test]
+    async fn max_size_and_timeout() {
+        let (_, builder) = backend("max-size");
+        let pool: Pool<Mock> = builder
+            .max_size(2)
+            .checkout_timeout(Duration::from_millis(50))
+            .build()
+            .await
+            .unwrap();
+
+        let a = pool.get().await.unwrap();
+        let _b = pool.get().await.unwrap();
+        let err = pool.get().await.err().unwrap();
+        assert_eq!("pool checkout timed out", err.to_string());
+
+        // 等待中的借出者在连接归还后被唤醒
+        let waiter = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.get().await.map(|_| ()) }
+        });
+        time::sleep(Duration::from_millis(10)).await;
+        drop(a);
+        waiter.await.unwrap().unwrap();
+        assert_eq!(2, pool.state().connections);
+    }
+
+    #[tokio::test]
+    async fn evict_broken() {
+        let (backend, builder) = backend("evict");
+        let pool: Pool<Mock> = builder.min_idle(2).build().await.unwrap();
+        assert_eq!(State { connections: 2, idle: 2 }, pool.state());
+
+        // 两条空闲连接都无法通过健康检查, 被驱逐后建立一条新的连接
+        *backend.ping.lock().unwrap() = Ping::Fail(Duration::ZERO);
+        let _conn = pool.get().await.unwrap();
+        assert_eq!(3, backend.connects.load(Ordering::SeqCst));
+        assert_eq!(State { connections: 1, idle: 0 }, pool.state());
+    }
+
+    #[tokio::test]
+    async fn evict_on_ping_timeout() {
+        let (backend, builder) = backend("ping-timeout");
+        let pool: Pool<Mock> = builder
+            .min_idle(2)
+            .ping_timeout(Duration::from_millis(20))
+            .build()
+            .await
+            .unwrap();
+
+        // PING 超时与 PING 失败一样驱逐连接, 并继续尝试
+        *backend.ping.lock().unwrap() = Ping::Hang;
+        let _conn = pool.get().await.unwrap();
+        assert_eq!(3, backend.connects.load(Ordering::SeqCst));
+        assert_eq!(State { connections: 1, idle: 0 }, pool.state());
+    }
+
+    #[tokio::test]
+    async fn wake_waiter_after_eviction() {
+        let (backend, builder) = backend("wake");
+        let pool: Pool<Mock> = builder.max_size(1).min_idle(1).build().await.unwrap();
+
+        // 第一个借出者驱逐了唯一的空闲连接, 随后建立连接失败;
+        // 释放的名额需要让等待中的借出者继续前进, 而不是一直等到超时
+        *backend.ping.lock().unwrap() = Ping::Fail(Duration::from_millis(50));
+        backend.refuse.store(1, Ordering::SeqCst);
+
+        let first = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.get().await.map(|_| ()) }
+        });
+        time::sleep(Duration::from_millis(10)).await;
+
+        let start = Instant::now();
+        let _conn = pool.get().await.unwrap();
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(first.await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn cancelled_request_is_not_reused() {
+        let server = server::isolated().await;
+        let pool: Pool = Pool::builder(server.addr().to_string())
+            .max_size(1)
+            .build()
+            .await
+            .unwrap();
+
+        // 请求发出之后就被取消, 回复还留在连接上
+        let mut conn = pool.get().await.unwrap();
+        tokio::select! {
+            biased;
+            _ = conn.request(["PING"]) => panic!("the reply can't arrive before the first poll"),
+            _ = std::future::ready(()) => {}
+        }
+        drop(conn);
+        assert_eq!(State { connections: 0, idle: 0 }, pool.state());
+
+        let mut conn = pool.get().await.unwrap();
+        conn.request(["SET", "foo", "bar"]).await.unwrap();
+        let reply = conn.request(["GET", "foo"]).await.unwrap();
+        assert_eq!(Frame::Bulk("bar".into()), reply);
+        drop(conn);
+        assert_eq!(State { connections: 1, idle: 1 }, pool.state());
+    }
+}
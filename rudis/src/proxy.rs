@@ -0,0 +1,568 @@
+//! RESP 代理
+//!
+//! 与 twemproxy 类似, 代理位于客户端与多个 rudis 节点之间, 客户端看到的是一个完整的 keyspace:
+//! + key 通过一致性哈希分配到后端节点, 每个节点在哈希环上有多个虚拟节点,
+//!   增删节点时只有少量的 key 会被重新分配
+//! + key 中包含 `{tag}` 时只对 tag 计算哈希, 可以让相关的 key 落在同一个节点上
+//! + MGET/MSET/DEL/EXISTS 按照节点拆分, 并发发送之后再合并回复
+//! + 每个后端节点都有一个连接池, 客户端连接与后端连接不是一一对应的
+//! + 后台任务定期 PING 后端节点, 连续失败达到上限时将节点从哈希环上摘除, 恢复之后重新加入.
+//!   与 twemproxy 的 auto_eject_hosts 相同, 节点被摘除期间它的 key 会被分配到其他节点
+//!
+//! 代理只转发带有 key 的命令, 并且所有的 key 必须属于同一个节点(可以拆分的命令除外).
+//! SELECT、SUBSCRIBE 等没有 key 的命令依赖连接的状态, 无法在共享的后端连接上执行, 会直接返回错误
+
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, RwLock, Weak,
+    },
+    time::Duration,
+};
+
+use bytes::Bytes;
+use futures::future;
+use log::{info, warn};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    time,
+};
+
+use crate::{cmd::Registry, hll::murmurhash64a, Connection, Frame, Pool, Result};
+
+/// 每个后端节点在哈希环上的虚拟节点数, 与 ketama 相同
+const VIRTUAL_NODES: usize = 160;
+
+const HASH_SEED: u64 = 0xadc8_3b19;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// 每个后端节点的连接池大小
+    pub pool_size: usize,
+
+    /// 等待后端回复的超时时间
+    pub timeout: Duration,
+
+    /// 连续失败多少次之后摘除节点
+    pub failure_limit: usize,
+
+    /// 健康检查的间隔
+    pub health_check_interval: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            pool_size: 8,
+            timeout: Duration::from_millis(1000),
+            failure_limit: 2,
+            health_check_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// 一致性哈希环, 保存虚拟节点的哈希值到后端节点下标的映射
+#[derive(Debug, Default)]
+pub struct Ring {
+    points: BTreeMap<u64, usize>,
+}
+
+impl Ring {
+    /// nodes 是 (后端节点的下标, 地址), 虚拟节点的位置只与地址有关
+    pub fn new<'a>(nodes: impl IntoIterator<Item = (usize, &'a str)>) -> Ring {
+        let mut points = BTreeMap::new();
+        for (index, addr) in nodes {
+            for i in 0..VIRTUAL_NODES {
+                points.insert(hash(format!("{}-{}", addr, i).as_bytes()), index);
+            }
+        }
+
+        Ring { points }
+    }
+
+    /// 顺时针方向第一个虚拟节点所属的后端节点, 环为空时返回 None
+    pub fn get(&self, key: &[u8]) -> Option<usize> {
+        let h = hash(hash_tag(key));
+        self.points
+            .range(h..)
+            .next()
+            .or_else(|| self.points.iter().next())
+            .map(|(_, &index)| index)
+    }
+}
+
+fn hash(data: &[u8]) -> u64 {
+    murmurhash64a(data, HASH_SEED)
+}
+
+/// `{` 与之后第一个 `}` 之间的内容不为空时, 只使用这部分计算哈希
+fn hash_tag(key: &[u8]) -> &[u8] {
+    let Some(start) = key.iter().position(|&b| b == b'{') else {
+        return key;
+    };
+    match key[start + 1..].iter().position(|&b| b == b'}') {
+        Some(len) if len > 0 => &key[start + 1..start + 1 + len],
+        _ => key,
+    }
+}
+
+struct Backend {
+    addr: String,
+    pool: Pool,
+
+    /// 连续失败的次数
+    failures: AtomicUsize,
+    ejected: AtomicBool,
+}
+
+impl Backend {
+    /// 在一条池化的连接上发送请求.
+    /// 超时的连接不会被归还: 回复可能还在路上, 归还到连接池之后会被下一个请求读到
+    async fn request(&self, frame: &Frame, timeout: Duration) -> Result<Frame> {
+        let mut conn = self.pool.get().await?;
+
+        time::timeout(timeout, conn.request_frame(frame))
+            .await
+            .map_err(|_| "timed out")?
+    }
+}
+
+pub struct Proxy {
+    backends: Vec<Backend>,
+    ring: RwLock<Ring>,
+    registry: Registry,
+    config: Config,
+}
+
+impl Proxy {
+    /// 为每个后端节点创建连接池, 并启动健康检查的后台任务.
+    /// 后端节点此时不必可用, 连接在第一次使用时才会建立
+    pub async fn new(addrs: Vec<String>, config: Config) -> Result<Arc<Proxy>> {
+        if addrs.is_empty() {
+            return Err("proxy requires at least one backend".into());
+        }
+
+        let mut backends = Vec::with_capacity(addrs.len());
+        for addr in addrs {
+            // 健康检查已经定期 PING 每个节点, 借出连接时不再 PING, 否则每个请求都要多一次往返.
+            // 节点断开之后, 请求失败的连接会被标记为 broken, 归还时被连接池驱逐
+            let pool = Pool::builder(addr.as_str())
+                .max_size(config.pool_size)
+                .checkout_timeout(config.timeout)
+                .test_on_checkout(false)
+                .build()
+                .await?;
+            backends.push(Backend {
+                addr,
+                pool,
+                failures: AtomicUsize::new(0),
+                ejected: AtomicBool::new(false),
+            });
+        }
+
+        let ring = Ring::new(backends.iter().map(|b| b.addr.as_str()).enumerate());
+        let proxy = Arc::new(Proxy {
+            backends,
+            ring: RwLock::new(ring),
+            registry: Registry::default(),
+            config,
+        });
+        tokio::spawn(health_check(Arc::downgrade(&proxy)));
+
+        Ok(proxy)
+    }
+
+    /// 处理一条客户端连接
+    pub async fn serve<S: AsyncRead + AsyncWrite + Unpin + Send>(&self, socket: S) {
+        let mut connection = Connection::new(socket);
+        connection.enable_inline();
+
+        loop {
+            // 与服务端相同, pipeline 中的请求都处理完之后再 flush
+            let res = match connection.buffered_frame() {
+                Ok(None) => match connection.flush().await {
+                    Ok(()) => connection.read_frame().await,
+                    Err(_) => return,
+                },
+                res => res,
+            };
+            let reply = match res {
+                Ok(Some(frame)) => self.execute(frame).await,
+                Ok(None) => return,
+                Err(err) => Frame::Error(format!("ERR Protocol error: {}", err)),
+            };
+
+            if connection.is_broken() {
+                let _ = connection.write_frame(&reply).await;
+                return;
+            }
+            if connection.feed_frame(&reply).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// 执行一条命令, 返回给客户端的回复
+    pub async fn execute(&self, frame: Frame) -> Frame {
+        let Some(args) = bulk_args(&frame) else {
+            return Frame::Error("ERR Protocol error: expected array of bulk strings".to_string());
+        };
+        let name = String::from_utf8_lossy(args[0]).to_ascii_lowercase();
+
+        let Some(spec) = self.registry.get(&name) else {
+            return Frame::Error(format!("ERR unknown command '{}'", name));
+        };
+        if !spec.check_arity(args.len()) {
+            return Frame::Error(format!(
+                "ERR wrong number of arguments for '{}' command",
+                name
+            ));
+        }
+
+        let Frame::Array(parts) = &frame else {
+            unreachable!()
+        };
+        match name.as_str() {
+            "ping" => match parts.get(1) {
+                Some(msg) => msg.clone(),
+                None => Frame::Simple("PONG".to_string()),
+            },
+            "echo" => parts[1].clone(),
+            "mget" => self.mget(parts).await,
+            "mset" => self.mset(parts).await,
+            "del" | "exists" => self.sum(parts).await,
+            _ => self.forward(&frame, &args, spec.key_indexes(args.len())).await,
+        }
+    }
+
+    /// 所有的 key 都属于同一个节点时, 将命令原样转发给该节点
+    async fn forward(&self, frame: &Frame, args: &[&Bytes], keys: Vec<usize>) -> Frame {
+        let Some((&first, rest)) = keys.split_first() else {
+            return Frame::Error(format!(
+                "ERR command '{}' is not supported by the proxy",
+                String::from_utf8_lossy(args[0]).to_ascii_lowercase()
+            ));
+        };
+
+        let node = match self.route(args[first]) {
+            Ok(node) => node,
+            Err(err) => return err,
+        };
+        for &i in rest {
+            if self.route(args[i]) != Ok(node) {
+                return Frame::Error(
+                    "ERR keys in request don't hash to the same backend".to_string(),
+                );
+            }
+        }
+
+        self.request(node, frame).await
+    }
+
+    /// `MGET key ...`, 按照原来的顺序合并各个节点的回复
+    async fn mget(&self, parts: &[Frame]) -> Frame {
+        let replies = match self.scatter(parts, 1).await {
+            Ok(replies) => replies,
+            Err(err) => return err,
+        };
+
+        let mut values = vec![Frame::Null; parts.len() - 1];
+        for (positions, reply) in replies {
+            match reply {
+                Frame::Array(items) if items.len() == positions.len() => {
+                    for (i, item) in positions.into_iter().zip(items) {
+                        values[i] = item;
+                    }
+                }
+                Frame::Error(err) => return Frame::Error(err),
+                frame => return unexpected(&frame),
+            }
+        }
+
+        Frame::Array(values)
+    }
+
+    /// `MSET key value ...`, 各个节点都成功时才返回 OK.
+    /// 与 twemproxy 相同, 部分节点失败时不会回滚已经写入的 key
+    async fn mset(&self, parts: &[Frame]) -> Frame {
+        if parts.len().is_multiple_of(2) {
+            return Frame::Error("ERR wrong number of arguments for 'mset' command".to_string());
+        }
+
+        let replies = match self.scatter(parts, 2).await {
+            Ok(replies) => replies,
+            Err(err) => return err,
+        };
+        for (_, reply) in replies {
+            match reply {
+                Frame::Simple(_) => {}
+                Frame::Error(err) => return Frame::Error(err),
+                frame => return unexpected(&frame),
+            }
+        }
+
+        Frame::Simple("OK".to_string())
+    }
+
+    /// `DEL key ...`、`EXISTS key ...`, 回复是各个节点的整数之和
+    async fn sum(&self, parts: &[Frame]) -> Frame {
+        let replies = match self.scatter(parts, 1).await {
+            Ok(replies) => replies,
+            Err(err) => return err,
+        };
+
+        let mut total = 0;
+        for (_, reply) in replies {
+            match reply {
+                Frame::Integer(n) => total += n,
+                Frame::Error(err) => return Frame::Error(err),
+                frame => return unexpected(&frame),
+            }
+        }
+
+        Frame::Integer(total)
+    }
+
+    /// 将 `cmd key [value] key [value] ...` 按照节点拆分为多条命令并发发送.
+    /// 返回每个节点负责的 key 的序号以及该节点的回复
+    async fn scatter(
+        &self,
+        parts: &[Frame],
+        step: usize,
+    ) -> std::result::Result<Vec<(Vec<usize>, Frame)>, Frame> {
+        let mut groups: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for (i, chunk) in parts[1..].chunks(step).enumerate() {
+            let Frame::Bulk(key) = &chunk[0] else {
+                unreachable!()
+            };
+            groups.entry(self.route(key)?).or_default().push(i);
+        }
+
+        let requests = groups.into_iter().map(|(node, positions)| {
+            let mut command = vec![parts[0].clone()];
+            for &i in &positions {
+                command.extend_from_slice(&parts[1 + i * step..1 + (i + 1) * step]);
+            }
+
+            async move { (positions, self.request(node, &Frame::Array(command)).await) }
+        });
+
+        Ok(future::join_all(requests).await)
+    }
+
+    fn route(&self, key: &[u8]) -> std::result::Result<usize, Frame> {
+        self.ring
+            .read()
+            .unwrap()
+            .get(key)
+            .ok_or_else(|| Frame::Error("ERR no backend available".to_string()))
+    }
+
+    async fn request(&self, node: usize, frame: &Frame) -> Frame {
+        let backend = &self.backends[node];
+        match backend.request(frame, self.config.timeout).await {
+            Ok(reply) => reply,
+            Err(err) => {
+                warn!("backend {} failed: {}", backend.addr, err);
+                self.record(node, false);
+                Frame::Error(format!("ERR backend {} unavailable: {}", backend.addr, err))
+            }
+        }
+    }
+
+    /// 记录一次请求或者健康检查的结果, 节点的状态发生变化时重建哈希环
+    fn record(&self, node: usize, ok: bool) {
+        let backend = &self.backends[node];
+
+        let changed = if ok {
+            backend.failures.store(0, Ordering::Relaxed);
+            backend.ejected.swap(false, Ordering::Relaxed)
+        } else {
+            let failures = backend.failures.fetch_add(1, Ordering::Relaxed) + 1;
+            failures >= self.config.failure_limit && !backend.ejected.swap(true, Ordering::Relaxed)
+        };
+        if !changed {
+            return;
+        }
+
+        if ok {
+            info!("backend {} recovered, adding it back to the ring", backend.addr);
+        } else {
+            warn!("backend {} ejected from the ring", backend.addr);
+        }
+
+        let live = self
+            .backends
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| !b.ejected.load(Ordering::Relaxed))
+            .map(|(i, b)| (i, b.addr.as_str()));
+        *self.ring.write().unwrap() = Ring::new(live);
+    }
+}
+
+/// 定期 PING 所有的后端节点(包括已经被摘除的节点), Proxy 被 drop 之后退出
+async fn health_check(proxy: Weak<Proxy>) {
+    let Some(interval) = proxy.upgrade().map(|p| p.config.health_check_interval) else {
+        return;
+    };
+    let mut interval = time::interval(interval);
+    let ping = Frame::Array(vec![Frame::Bulk(Bytes::from("PING"))]);
+
+    loop {
+        interval.tick().await;
+        let Some(proxy) = proxy.upgrade() else {
+            return;
+        };
+
+        let checks = proxy.backends.iter().map(|backend| async {
+            let reply = backend.request(&ping, proxy.config.timeout).await;
+            matches!(reply, Ok(Frame::Simple(pong)) if pong == "PONG")
+        });
+        for (node, ok) in future::join_all(checks).await.into_iter().enumerate() {
+            proxy.record(node, ok);
+        }
+    }
+}
+
+/// 命令必须是由 Bulk 组成的非空 Array
+fn bulk_args(frame: &Frame) -> Option<Vec<&Bytes>> {
+    let Frame::Array(parts) = frame else {
+        return None;
+    };
+
+    let args = parts
+        .iter()
+        .map(|part| match part {
+            Frame::Bulk(bytes) => Some(bytes),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    (!args.is_empty()).then_some(args)
+}
+
+fn unexpected(frame: &Frame) -> Frame {
+    Frame::Error(format!("ERR unexpected reply from backend: {}", frame))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server;
+
+    #[test]
+    fn ring() {
+        let addrs = ["127.0.0.1:6379", "127.0.0.1:6380", "127.0.0.1:6381"];
+        let full = Ring::new(addrs.into_iter().enumerate());
+
+        let keys: Vec<_> = (0..3000).map(|i| format!("key:{}", i)).collect();
+        let mut counts = [0; 3];
+        for key in &keys {
+            counts[full.get(key.as_bytes()).unwrap()] += 1;
+        }
+        assert!(counts.iter().all(|&n| n > 600), "{:?}", counts);
+
+        // 摘除一个节点之后, 只有该节点的 key 会被重新分配
+        let ejected = Ring::new(addrs.into_iter().enumerate().filter(|&(i, _)| i != 1));
+        for key in &keys {
+            let before = full.get(key.as_bytes()).unwrap();
+            let after = ejected.get(key.as_bytes()).unwrap();
+            assert!(before == 1 || before == after);
+        }
+
+        assert_eq!(full.get(b"{user:1}:name"), full.get(b"{user:1}:age"));
+        assert_eq!(b"user:1", hash_tag(b"{user:1}:name"));
+        assert_eq!(b"{}a", hash_tag(b"{}a"));
+        assert_eq!(None, Ring::default().get(b"a"));
+    }
+
+    #[tokio::test]
+    async fn split_multi_key_commands() {
+        let (a, b) = (server::isolated().await, server::isolated().await);
+        let addrs = vec![a.addr().to_string(), b.addr().to_string()];
+        let proxy = Proxy::new(addrs, Config::default()).await.unwrap();
+
+        let command = |args: &[&str]| {
+            let parts = args.iter().map(|arg| Frame::Bulk(Bytes::from(arg.to_string())));
+            Frame::Array(parts.collect())
+        };
+        let keys: Vec<_> = (0..20).map(|i| format!("key:{}", i)).collect();
+
+        let mut mset = vec!["MSET"];
+        for key in &keys {
+            mset.extend([key.as_str(), key.as_str()]);
+        }
+        let reply = proxy.execute(command(&mset)).await;
+        assert_eq!(Frame::Simple("OK".to_string()), reply);
+
+        let mget: Vec<_> = ["MGET"].into_iter().chain(keys.iter().map(String::as_str)).collect();
+        let Frame::Array(values) = proxy.execute(command(&mget)).await else {
+            panic!("MGET should reply an array")
+        };
+        let expected: Vec<_> = keys
+            .iter()
+            .map(|key| Frame::Bulk(Bytes::from(key.clone())))
+            .collect();
+        assert_eq!(expected, values);
+
+        // key 分布在两个节点上
+        for server in [&a, &b] {
+            let mut conn = server.connect().await.unwrap();
+            let reply = conn.request(["DBSIZE"]).await.unwrap();
+            assert!(matches!(reply, Frame::Integer(n) if n > 0 && n < 20));
+        }
+
+        let del: Vec<_> = ["DEL"].into_iter().chain(mget[1..].iter().copied()).collect();
+        assert_eq!(Frame::Integer(20), proxy.execute(command(&del)).await);
+    }
+
+    #[tokio::test]
+    async fn forward_over_tcp() {
+        let mut backends = vec![server::isolated().await, server::isolated().await];
+        let addrs = backends.iter().map(|backend| backend.addr().to_string()).collect();
+        let config = Config {
+            health_check_interval: Duration::from_millis(20),
+            ..Config::default()
+        };
+        let proxy = Proxy::new(addrs, config).await.unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let proxy = proxy.clone();
+                tokio::spawn(async move { proxy.serve(socket).await });
+            }
+        });
+
+        let mut conn = Connection::connect(addr).await.unwrap();
+        let ok = Frame::Simple("OK".to_string());
+        assert_eq!(ok, conn.request(["SET", "foo", "bar"]).await.unwrap());
+        assert_eq!(Frame::Bulk("bar".into()), conn.request(["GET", "foo"]).await.unwrap());
+        let reply = conn.request(["SELECT", "1"]).await.unwrap();
+        assert!(matches!(reply, Frame::Error(_)), "{}", reply);
+
+        // key 只保存在一个后端节点上
+        let mut owner = None;
+        for (i, backend) in backends.iter().enumerate() {
+            let mut conn = backend.connect().await.unwrap();
+            if conn.request(["GET", "foo"]).await.unwrap() == Frame::Bulk("bar".into()) {
+                assert!(owner.replace(i).is_none());
+            }
+        }
+
+        // 节点下线之后, 健康检查将它摘除, 它的 key 被分配到另一个节点
+        backends.remove(owner.unwrap()).shutdown().await;
+        let set = async {
+            while conn.request(["SET", "foo", "baz"]).await.unwrap() != ok {
+                time::sleep(Duration::from_millis(20)).await;
+            }
+        };
+        time::timeout(Duration::from_secs(5), set).await.expect("backend should be ejected");
+        assert_eq!(Frame::Bulk("baz".into()), conn.request(["GET", "foo"]).await.unwrap());
+
+        let mut conn = backends[0].connect().await.unwrap();
+        assert_eq!(Frame::Bulk("baz".into()), conn.request(["GET", "foo"]).await.unwrap());
+    }
+}
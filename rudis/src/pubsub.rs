@@ -0,0 +1,129 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use bytes::Bytes;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::{glob, Frame};
+
+/// 发布订阅中心
+///
+/// 每个连接都拥有一个推送通道(`UnboundedSender<Frame>`), 发布消息时直接将消息 Frame
+/// 发送到订阅者的推送通道中, 由连接所在的任务负责写回 socket
+#[derive(Default)]
+pub struct PubSub {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// channel -> (连接 id -> 推送通道)
+    channels: HashMap<String, HashMap<u64, UnboundedSender<Frame>>>,
+    /// pattern -> (连接 id -> 推送通道)
+    patterns: HashMap<String, HashMap<u64, UnboundedSender<Frame>>>,
+}
+
+impl PubSub {
+    pub fn subscribe(&self, channel: &str, id: u64, push: UnboundedSender<Frame>) {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .channels
+            .entry(channel.to_string())
+            .or_default()
+            .insert(id, push);
+    }
+
+    pub fn unsubscribe(&self, channel: &str, id: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        remove(&mut inner.channels, channel, id);
+    }
+
+    pub fn psubscribe(&self, pattern: &str, id: u64, push: UnboundedSender<Frame>) {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .patterns
+            .entry(pattern.to_string())
+            .or_default()
+            .insert(id, push);
+    }
+
+    pub fn punsubscribe(&self, pattern: &str, id: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        remove(&mut inner.patterns, pattern, id);
+    }
+
+    /// 发布消息, 返回接收到消息的订阅者数量
+    pub fn publish(&self, channel: &str, message: Bytes) -> usize {
+        let mut inner = self.inner.lock().unwrap();
+        let mut receivers = 0;
+
+        if let Some(subscribers) = inner.channels.get_mut(channel) {
+            let frame = Frame::Array(vec![
+                Frame::Bulk(Bytes::from_static(b"message")),
+                Frame::Bulk(Bytes::from(channel.to_string())),
+                Frame::Bulk(message.clone()),
+            ]);
+
+            // 发送失败说明连接已经关闭, 顺便清理掉
+            subscribers.retain(|_, push| push.send(frame.clone()).is_ok());
+            receivers += subscribers.len();
+
+            if subscribers.is_empty() {
+                inner.channels.remove(channel);
+            }
+        }
+
+        let mut empty = vec![];
+        for (pattern, subscribers) in inner.patterns.iter_mut() {
+            if !glob::matches(pattern.as_bytes(), channel.as_bytes()) {
+                continue;
+            }
+
+            let frame = Frame::Array(vec![
+                Frame::Bulk(Bytes::from_static(b"pmessage")),
+                Frame::Bulk(Bytes::from(pattern.clone())),
+                Frame::Bulk(Bytes::from(channel.to_string())),
+                Frame::Bulk(message.clone()),
+            ]);
+
+            subscribers.retain(|_, push| push.send(frame.clone()).is_ok());
+            receivers += subscribers.len();
+
+            if subscribers.is_empty() {
+                empty.push(pattern.clone());
+            }
+        }
+
+        for pattern in empty {
+            inner.patterns.remove(&pattern);
+        }
+
+        receivers
+    }
+
+    /// 只向订阅了 channel 的某一个连接发送消息, 连接没有订阅时返回 false
+    ///
+    /// 用于 `CLIENT TRACKING ... REDIRECT` 的失效消息, 见 `crate::tracking`
+    pub fn send_to(&self, channel: &str, id: u64, message: Frame) -> bool {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .channels
+            .get(channel)
+            .and_then(|subscribers| subscribers.get(&id))
+            .is_some_and(|push| push.send(message).is_ok())
+    }
+
+    /// 没有任何订阅者时可以跳过构造消息, keyspace notifications 会频繁调用该方法
+    pub fn is_empty(&self) -> bool {
+        let inner = self.inner.lock().unwrap();
+        inner.channels.is_empty() && inner.patterns.is_empty()
+    }
+}
+
+fn remove(map: &mut HashMap<String, HashMap<u64, UnboundedSender<Frame>>>, name: &str, id: u64) {
+    if let Some(subscribers) = map.get_mut(name) {
+        subscribers.remove(&id);
+        if subscribers.is_empty() {
+            map.remove(name);
+        }
+    }
+}
@@ -0,0 +1,666 @@
+//! Lua 脚本
+//!
+//! 与 redis 相同, 脚本通过 `redis.call`/`redis.pcall` 执行命令, 并且整个脚本是原子执行的:
+//! 所有的普通命令在执行期间都持有 [`Scripts`] 中 gate 的读锁, 脚本执行期间持有写锁.
+//! 由于分片锁只在单个命令执行期间持有, 脚本中的每个命令仍然照常锁定自己需要的分片.
+//!
+//! gate 使用 `tokio::sync::RwLock`, 它是公平的: 等待中的写锁会排在之后到达的读锁前面,
+//! 源源不断的普通命令不会让 EVAL 一直拿不到写锁.
+//!
+//! 脚本执行超过 `lua-time-limit` 之后, 其他命令会立即收到 BUSY 错误, 此时可以通过 SCRIPT KILL 终止
+//! 还没有执行过写命令的脚本
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use mlua::{HookTriggers, Lua, LuaOptions, RegistryKey, StdLib, Value, Variadic};
+use tokio::{
+    runtime::{Handle, RuntimeFlavor},
+    sync::{Notify, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    task, time,
+};
+
+use crate::{
+    cmd::{self, CmdResult, Context, Flags, Reply},
+    Frame,
+};
+
+pub(crate) const BUSY_ERR: &str =
+    "BUSY Redis is busy running a script. You can only call SCRIPT KILL or SHUTDOWN NOSAVE.";
+
+/// 每执行多少条 Lua 指令检查一次 SCRIPT KILL
+const HOOK_INSTRUCTIONS: u32 = 100_000;
+
+/// 脚本返回的 table 转换为回复时的最大嵌套层数, 与解析请求时 Array 的嵌套上限相同
+const MAX_REPLY_DEPTH: usize = 1000;
+
+/// 脚本的缓存以及执行状态, 由所有连接共享
+pub struct Scripts {
+    /// 普通命令持有读锁, 脚本持有写锁
+    gate: RwLock<()>,
+    vm: Mutex<Vm>,
+
+    /// 正在执行的脚本的开始时间
+    running: Mutex<Option<Instant>>,
+
+    /// 脚本开始执行时通知等待 gate 的命令, 它们需要在脚本超时的时候返回 BUSY
+    started: Notify,
+
+    /// SCRIPT KILL 请求终止正在执行的脚本, Lua 的 hook 中会检查该标记
+    kill: Arc<AtomicBool>,
+
+    /// 正在执行的脚本是否已经执行过写命令, 执行过写命令的脚本不能被终止
+    wrote: AtomicBool,
+}
+
+struct Vm {
+    lua: Lua,
+
+    /// SHA1 -> 编译好的函数
+    functions: HashMap<String, RegistryKey>,
+}
+
+/// 命令执行期间持有的 gate
+pub enum Gate<'a> {
+    Shared(RwLockReadGuard<'a, ()>),
+    Exclusive(RwLockWriteGuard<'a, ()>),
+}
+
+impl Default for Scripts {
+    fn default() -> Self {
+        let kill = Arc::new(AtomicBool::new(false));
+
+        Scripts {
+            gate: RwLock::new(()),
+            vm: Mutex::new(Vm::new(kill.clone()).expect("failed to create lua vm")),
+            running: Mutex::new(None),
+            started: Notify::new(),
+            kill,
+            wrote: AtomicBool::new(false),
+        }
+    }
+}
+
+impl Scripts {
+    /// 命令执行之前获取 gate, `exclusive` 为 true 时独占整个数据集
+    ///
+    /// 脚本执行超过 `limit` 之后立即返回 BUSY 错误, 否则等待脚本执行完毕
+    pub fn enter(&self, exclusive: bool, limit: Duration) -> Result<Gate<'_>, String> {
+        // 没有脚本在执行, 也没有排队的写锁时不需要等待
+        let gate = if exclusive {
+            self.gate.try_write().map(Gate::Exclusive)
+        } else {
+            self.gate.try_read().map(Gate::Shared)
+        };
+        if let Ok(gate) = gate {
+            return Ok(gate);
+        }
+
+        // 命令是同步执行的, 只能阻塞当前的工作线程等待.
+        // 单线程的运行时中命令总是依次执行完毕, 不会走到这里, 真的发生时也无法等待
+        match Handle::try_current() {
+            Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+                task::block_in_place(|| handle.block_on(self.wait(exclusive, limit)))
+            }
+            _ => Err(BUSY_ERR.to_string()),
+        }
+    }
+
+    /// 排队等待 gate, 脚本执行超过 `limit` 时放弃排队并返回 BUSY 错误
+    async fn wait(&self, exclusive: bool, limit: Duration) -> Result<Gate<'_>, String> {
+        // 同一个 Future 在整个等待期间一直被 poll, 排队的位置不会丢失
+        let gate = async {
+            if exclusive {
+                Gate::Exclusive(self.gate.write().await)
+            } else {
+                Gate::Shared(self.gate.read().await)
+            }
+        };
+        tokio::pin!(gate);
+
+        loop {
+            // 先注册通知再读取开始时间, 避免错过两者之间开始执行的脚本
+            let started = self.started.notified();
+            tokio::pin!(started);
+            started.as_mut().enable();
+
+            let deadline = self.running.lock().unwrap().map(|started| started + limit);
+            let busy = async {
+                match deadline {
+                    Some(deadline) => time::sleep_until(deadline.into()).await,
+                    None => future::pending().await,
+                }
+            };
+
+            tokio::select! {
+                biased;
+                gate = &mut gate => return Ok(gate),
+                _ = busy => return Err(BUSY_ERR.to_string()),
+                _ = started => {}
+            }
+        }
+    }
+
+    /// 不等待的获取 gate 的读锁, 用于后台任务在脚本执行期间跳过本轮的工作
+    pub fn try_shared(&self) -> Option<RwLockReadGuard<'_, ()>> {
+        self.gate.try_read().ok()
+    }
+
+    /// 脚本执行期间返回 BUSY 错误, 而不是等待脚本执行完毕
+    fn vm(&self) -> Result<MutexGuard<'_, Vm>, String> {
+        self.vm.try_lock().map_err(|_| BUSY_ERR.to_string())
+    }
+
+    /// SCRIPT LOAD: 编译并缓存脚本, 返回脚本的 SHA1
+    pub fn load(&self, body: &[u8]) -> Result<String, String> {
+        let sha = sha1hex(body);
+        self.vm()?.compile(&sha, body)?;
+
+        Ok(sha)
+    }
+
+    pub fn exists(&self, sha: &str) -> Result<bool, String> {
+        Ok(self.vm()?.functions.contains_key(&sha.to_ascii_lowercase()))
+    }
+
+    pub fn flush(&self) -> Result<(), String> {
+        let Vm { lua, functions } = &mut *self.vm()?;
+        for (_, key) in functions.drain() {
+            let _ = lua.remove_registry_value(key);
+        }
+
+        Ok(())
+    }
+
+    /// SCRIPT KILL
+    pub fn kill(&self) -> Result<(), String> {
+        if self.running.lock().unwrap().is_none() {
+            return Err("NOTBUSY No scripts in execution right now.".to_string());
+        }
+        if self.wrote.load(Ordering::Relaxed) {
+            return Err("UNKILLABLE Sorry the script already executed write commands against the dataset. You can either wait the script termination or kill the server in a hard way using the SHUTDOWN NOSAVE command.".to_string());
+        }
+
+        self.kill.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// 执行脚本, `body` 为 None 时(EVALSHA)只从缓存中查找
+    ///
+    /// 调用者需要持有 gate 的写锁
+    pub fn run(
+        &self,
+        ctx: &mut Context<'_>,
+        sha: &str,
+        body: Option<&[u8]>,
+        keys: &[Bytes],
+        argv: &[Bytes],
+    ) -> CmdResult {
+        let mut vm = self.vm()?;
+        if let Some(body) = body {
+            vm.compile(sha, body)?;
+        }
+
+        let vm = &*vm;
+        let function = vm
+            .function(sha)
+            .ok_or("NOSCRIPT No matching script. Please use EVAL.")?;
+
+        let lua = &vm.lua;
+        let globals = lua.globals();
+        let set_args = || {
+            globals.set("KEYS", bulks(lua, keys)?)?;
+            globals.set("ARGV", bulks(lua, argv)?)
+        };
+        set_args().map_err(lua_err)?;
+
+        *self.running.lock().unwrap() = Some(Instant::now());
+        self.started.notify_waiters();
+        self.kill.store(false, Ordering::Relaxed);
+        self.wrote.store(false, Ordering::Relaxed);
+
+        // redis.call/redis.pcall 需要借用当前命令的上下文, 因此只能在 scope 中创建,
+        // scope 结束之后这两个函数就失效了
+        let ctx = RefCell::new(ctx);
+        let res = blocking(|| {
+            lua.scope(|scope| {
+                let redis: mlua::Table = globals.get("redis")?;
+
+                let call = scope.create_function(|lua, args: Variadic<Value>| {
+                    let reply = self.call(&mut ctx.borrow_mut(), &args)?;
+                    match reply {
+                        Frame::Error(err) => Err(mlua::Error::RuntimeError(err)),
+                        reply => frame_to_lua(lua, reply),
+                    }
+                })?;
+                let pcall = scope.create_function(|lua, args: Variadic<Value>| {
+                    let reply = match self.call(&mut ctx.borrow_mut(), &args) {
+                        Ok(reply) => reply,
+                        Err(err) => Frame::Error(err_message(&err)),
+                    };
+                    frame_to_lua(lua, reply)
+                })?;
+                redis.set("call", call)?;
+                redis.set("pcall", pcall)?;
+
+                let function: mlua::Function = lua.registry_value(function)?;
+                let value: Value = function.call(())?;
+
+                lua_to_frame(value, 0)
+            })
+        });
+
+        *self.running.lock().unwrap() = None;
+        self.kill.store(false, Ordering::Relaxed);
+
+        res.map(Reply::from).map_err(lua_err)
+    }
+
+    /// 在脚本中执行一个命令, 命令本身的错误作为 Error frame 返回, 调用方式的错误作为 Lua 错误返回
+    fn call(&self, ctx: &mut Context<'_>, args: &[Value]) -> mlua::Result<Frame> {
+        if args.is_empty() {
+            return Err(runtime(
+                "Please specify at least one argument for this redis lib call",
+            ));
+        }
+
+        let args = args
+            .iter()
+            .map(|arg| match arg {
+                Value::String(s) => Ok(Bytes::copy_from_slice(s.as_bytes())),
+                Value::Integer(n) => Ok(Bytes::from(n.to_string())),
+                Value::Number(n) => Ok(Bytes::from(number_to_string(*n))),
+                _ => Err(runtime(
+                    "Lua redis lib command arguments must be strings or integers",
+                )),
+            })
+            .collect::<mlua::Result<Vec<_>>>()?;
+
+        let name = String::from_utf8_lossy(&args[0]).to_ascii_lowercase();
+        let spec = ctx
+            .registry
+            .get(&name)
+            .ok_or_else(|| runtime("Unknown Redis command called from script"))?;
+        if !spec.check_arity(args.len()) {
+            return Err(runtime(
+                "Wrong number of args calling Redis command from script",
+            ));
+        }
+        if spec.flags.contains(Flags::NOSCRIPT) {
+            return Err(runtime("This Redis command is not allowed from script"));
+        }
+
+        if spec.flags.contains(Flags::DENYOOM) {
+            if let Err(err) = ctx.db.evict() {
+                return Ok(Frame::Error(err));
+            }
+        }
+        if spec.flags.contains(Flags::WRITE) {
+            if let Err(err) = cmd::check_replicas(ctx.db) {
+                return Ok(Frame::Error(err));
+            }
+            self.wrote.store(true, Ordering::Relaxed);
+        }
+
+        let reply = match spec.call(ctx, &args) {
+            Ok(Reply::Frame(frame)) => frame,
+            Ok(Reply::Multi(frames)) => Frame::Array(frames),
+            // 这类命令都带有 noscript 标记, 不会在脚本中执行
+            Ok(Reply::Later(_)) => unreachable!("commands replying later are noscript"),
+            Err(err) => Frame::Error(err),
+        };
+
+        Ok(reply)
+    }
+}
+
+impl Vm {
+    fn new(kill: Arc<AtomicBool>) -> mlua::Result<Vm> {
+        // 只加载不会访问外部环境的标准库, 脚本不能读写文件或者执行系统命令
+        let lua = Lua::new_with(
+            StdLib::TABLE | StdLib::STRING | StdLib::MATH,
+            LuaOptions::default(),
+        )?;
+
+        let redis = lua.create_table()?;
+        redis.set(
+            "sha1hex",
+            lua.create_function(|_, body: mlua::String| Ok(sha1hex(body.as_bytes())))?,
+        )?;
+        redis.set(
+            "error_reply",
+            lua.create_function(|lua, err: String| {
+                let table = lua.create_table()?;
+                table.set("err", err)?;
+                Ok(table)
+            })?,
+        )?;
+        redis.set(
+            "status_reply",
+            lua.create_function(|lua, status: String| {
+                let table = lua.create_table()?;
+                table.set("ok", status)?;
+                Ok(table)
+            })?,
+        )?;
+        lua.globals().set("redis", redis)?;
+
+        lua.set_hook(
+            HookTriggers::new().every_nth_instruction(HOOK_INSTRUCTIONS),
+            move |_, _| {
+                if kill.load(Ordering::Relaxed) {
+                    return Err(runtime("ERR Script killed by user with SCRIPT KILL..."));
+                }
+
+                Ok(())
+            },
+        );
+
+        Ok(Vm {
+            lua,
+            functions: HashMap::new(),
+        })
+    }
+
+    /// 编译脚本并缓存到 Lua registry 中
+    fn compile(&mut self, sha: &str, body: &[u8]) -> Result<(), String> {
+        if !self.functions.contains_key(sha) {
+            let function = self
+                .lua
+                .load(body)
+                .set_name(format!("@user_script:{}", sha))
+                .into_function()
+                .map_err(|err| format!("ERR Error compiling script: {}", err_message(&err)))?;
+            let key = self.lua.create_registry_value(function).map_err(lua_err)?;
+
+            self.functions.insert(sha.to_string(), key);
+        }
+
+        Ok(())
+    }
+
+    fn function(&self, sha: &str) -> Option<&RegistryKey> {
+        self.functions.get(&sha.to_ascii_lowercase())
+    }
+}
+
+/// 命令是在 tokio 的工作线程上同步执行的, 执行脚本或者等待脚本执行完毕时会长时间阻塞当前线程.
+/// 多线程的运行时中通过 `block_in_place` 把当前线程上的其他任务交给别的线程,
+/// 否则只有一个工作线程时, 其他连接(包括发送 SCRIPT KILL 的连接)都无法被处理
+pub(crate) fn blocking<R>(f: impl FnOnce() -> R) -> R {
+    match Handle::try_current().map(|handle| handle.runtime_flavor()) {
+        Ok(RuntimeFlavor::MultiThread) => task::block_in_place(f),
+        _ => f(),
+    }
+}
+
+pub fn sha1hex(body: &[u8]) -> String {
+    sha1_smol::Sha1::from(body).digest().to_string()
+}
+
+fn bulks<'lua>(lua: &'lua Lua, args: &[Bytes]) -> mlua::Result<mlua::Table<'lua>> {
+    lua.create_sequence_from(
+        args.iter()
+            .map(|arg| lua.create_string(arg))
+            .collect::<mlua::Result<Vec<_>>>()?,
+    )
+}
+
+/// 与 redis 相同的 RESP -> Lua 类型转换
+///
+/// + Integer -> number, Bulk -> string, Null -> false
+/// + Array -> table
+/// + Simple -> 带有 ok 字段的 table, Error -> 带有 err 字段的 table
+fn frame_to_lua(lua: &Lua, frame: Frame) -> mlua::Result<Value<'_>> {
+    let value = match frame {
+        Frame::Integer(n) => Value::Integer(n),
+        Frame::Bulk(bytes) => Value::String(lua.create_string(&bytes)?),
+        Frame::Null => Value::Boolean(false),
+        Frame::Array(frames) | Frame::Push(frames) => {
+            let table = lua.create_table_with_capacity(frames.len(), 0)?;
+            for frame in frames {
+                table.raw_push(frame_to_lua(lua, frame)?)?;
+            }
+            Value::Table(table)
+        }
+        Frame::Simple(status) => {
+            let table = lua.create_table()?;
+            table.set("ok", status)?;
+            Value::Table(table)
+        }
+        Frame::Error(err) => {
+            let table = lua.create_table()?;
+            table.set("err", err)?;
+            Value::Table(table)
+        }
+    };
+
+    Ok(value)
+}
+
+/// 与 redis 相同的 Lua -> RESP 类型转换
+///
+/// + number -> Integer(小数部分被截断), string -> Bulk
+/// + true -> Integer 1, false 以及 nil -> Null
+/// + 带有 ok/err 字段的 table -> Simple/Error, 其他 table 按照数组转换, 遇到第一个 nil 时结束
+///
+/// table 的嵌套层数由脚本决定, 超过 [`MAX_REPLY_DEPTH`] 时返回错误, 避免递归转换时栈溢出
+fn lua_to_frame(value: Value, depth: usize) -> mlua::Result<Frame> {
+    if depth > MAX_REPLY_DEPTH {
+        return Err(runtime("reached lua stack limit"));
+    }
+
+    let frame = match value {
+        Value::Integer(n) => Frame::Integer(n),
+        Value::Number(n) => Frame::Integer(n as i64),
+        Value::String(s) => Frame::Bulk(Bytes::copy_from_slice(s.as_bytes())),
+        Value::Boolean(true) => Frame::Integer(1),
+        Value::Table(table) => {
+            if let Ok(Value::String(err)) = table.raw_get("err") {
+                return Ok(Frame::Error(err.to_string_lossy().into_owned()));
+            }
+            if let Ok(Value::String(ok)) = table.raw_get("ok") {
+                return Ok(Frame::Simple(ok.to_string_lossy().into_owned()));
+            }
+
+            let mut frames = vec![];
+            for i in 1.. {
+                match table.raw_get::<_, Value>(i) {
+                    Ok(Value::Nil) | Err(_) => break,
+                    Ok(value) => frames.push(lua_to_frame(value, depth + 1)?),
+                }
+            }
+
+            Frame::Array(frames)
+        }
+        _ => Frame::Null,
+    };
+
+    Ok(frame)
+}
+
+/// 与 Lua 的 `tostring` 相同, 整数不带小数点
+fn number_to_string(n: f64) -> String {
+    if n.fract() == 0.0 && n.abs() < 1e15 {
+        (n as i64).to_string()
+    } else {
+        n.to_string()
+    }
+}
+
+fn runtime(msg: &str) -> mlua::Error {
+    mlua::Error::RuntimeError(msg.to_string())
+}
+
+/// 取出 Lua 错误中最原始的错误信息, redis.call 抛出的命令错误会被包装在 CallbackError 中
+fn err_message(err: &mlua::Error) -> String {
+    match err {
+        mlua::Error::CallbackError { cause, .. } => err_message(cause),
+        mlua::Error::RuntimeError(msg) => msg.clone(),
+        mlua::Error::SyntaxError { message, .. } => message.clone(),
+        err => err.to_string(),
+    }
+}
+
+/// 脚本的错误回复给客户端, 没有错误码前缀(例如 `ERR`、`WRONGTYPE`)时补充 `ERR`
+fn lua_err(err: mlua::Error) -> String {
+    let msg = err_message(&err);
+    let code = msg.split(' ').next().unwrap_or_default();
+
+    if !code.is_empty() && code.bytes().all(|b| b.is_ascii_uppercase()) {
+        msg
+    } else {
+        format!("ERR {}", msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{server, Connection};
+
+    fn bulk(val: &str) -> Frame {
+        Frame::Bulk(Bytes::from(val.to_string()))
+    }
+
+    /// 参数中包含运行时生成的字符串(例如 SHA1)时使用
+    fn args(args: &[&str]) -> Vec<Bytes> {
+        args.iter().map(|arg| Bytes::copy_from_slice(arg.as_bytes())).collect()
+    }
+
+    /// 订阅 `started` 频道, 脚本在开始执行时向该频道发布消息
+    async fn subscribe_started(server: &server::Handle) -> Connection {
+        let mut conn = server.connect().await.unwrap();
+        conn.request(["SUBSCRIBE", "started"]).await.unwrap();
+
+        conn
+    }
+
+    #[tokio::test]
+    async fn eval_and_script_cache() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+
+        let body = "redis.call('SET', KEYS[1], ARGV[1]); return redis.call('GET', KEYS[1])";
+        let reply = conn.request(["EVAL", body, "1", "foo", "bar"]).await.unwrap();
+        assert_eq!(bulk("bar"), reply);
+
+        // EVAL 执行过的脚本同样会被缓存
+        let sha = sha1hex(body.as_bytes());
+        let reply = conn.request(args(&["EVALSHA", &sha, "1", "foo", "baz"])).await.unwrap();
+        assert_eq!(bulk("baz"), reply);
+
+        let loaded = conn.request(["SCRIPT", "LOAD", "return {1, 'two', false}"]).await.unwrap();
+        let Frame::Bulk(loaded) = loaded else {
+            panic!("unexpected reply: {}", loaded);
+        };
+        let loaded = String::from_utf8(loaded.to_vec()).unwrap();
+        let reply = conn.request(args(&["EVALSHA", &loaded, "0"])).await.unwrap();
+        assert_eq!(Frame::Array(vec![Frame::Integer(1), bulk("two"), Frame::Null]), reply);
+
+        let reply = conn
+            .request(args(&["SCRIPT", "EXISTS", &sha, &loaded.to_uppercase(), "ffff"]))
+            .await
+            .unwrap();
+        assert_eq!(
+            Frame::Array(vec![Frame::Integer(1), Frame::Integer(1), Frame::Integer(0)]),
+            reply
+        );
+
+        // SCRIPT FLUSH 之后只能通过 EVAL 执行
+        conn.request(["SCRIPT", "FLUSH"]).await.unwrap();
+        let reply = conn.request(args(&["EVALSHA", &sha, "1", "foo", "bar"])).await.unwrap();
+        assert!(matches!(reply, Frame::Error(err) if err.starts_with("NOSCRIPT")));
+        let reply = conn.request(args(&["SCRIPT", "EXISTS", &sha])).await.unwrap();
+        assert_eq!(Frame::Array(vec![Frame::Integer(0)]), reply);
+    }
+
+    #[tokio::test]
+    async fn call_errors() {
+        let server = server::isolated().await;
+        let mut conn = server.connect().await.unwrap();
+        conn.request(["RPUSH", "list", "a"]).await.unwrap();
+
+        // redis.call 的命令错误中断脚本并原样返回给客户端
+        let body = "redis.call('GET', KEYS[1]); return 'unreachable'";
+        let reply = conn.request(["EVAL", body, "1", "list"]).await.unwrap();
+        assert!(matches!(reply, Frame::Error(err) if err.starts_with("WRONGTYPE")));
+
+        // redis.pcall 把错误作为 table 返回, 脚本可以继续执行
+        let body = "local err = redis.pcall('GET', KEYS[1]); return err['err']";
+        let reply = conn.request(["EVAL", body, "1", "list"]).await.unwrap();
+        assert!(matches!(reply, Frame::Bulk(err) if err.starts_with(b"WRONGTYPE")));
+
+        let reply = conn.request(["EVAL", "return redis.call('NOPE')", "0"]).await.unwrap();
+        assert!(matches!(reply, Frame::Error(err) if err.contains("Unknown Redis command")));
+
+        let reply = conn.request(["EVAL", "return redis.call('EVAL', 'return 1', '0')", "0"]).await;
+        assert!(matches!(reply.unwrap(), Frame::Error(err) if err.contains("not allowed")));
+
+        let reply = conn.request(["EVAL", "return redis.error_reply('MY custom')", "0"]).await;
+        assert_eq!(Frame::Error("MY custom".to_string()), reply.unwrap());
+
+        // 嵌套过深的 table 不会转换为回复, 服务端仍然可以正常处理之后的命令
+        let body = "local t = {} local c = t for i = 1, 1000000 do c[1] = {} c = c[1] end return t";
+        let reply = conn.request(["EVAL", body, "0"]).await.unwrap();
+        assert_eq!(Frame::Error("ERR reached lua stack limit".to_string()), reply);
+        let body = "local t = {} local c = t for i = 1, 100 do c[1] = {} c = c[1] end return t";
+        assert!(matches!(conn.request(["EVAL", body, "0"]).await.unwrap(), Frame::Array(_)));
+
+        let reply = conn.request(["EVAL", "return +", "0"]).await.unwrap();
+        assert!(matches!(reply, Frame::Error(err) if err.starts_with("ERR Error compiling")));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn writes_wait_for_script() {
+        let server = server::isolated().await;
+        let mut started = subscribe_started(&server).await;
+
+        // 脚本发布 started 之后继续执行一段时间, 最后写入 key
+        let mut conn = server.connect().await.unwrap();
+        let body = "redis.call('PUBLISH', 'started', '1') \
+                    local i = 0 while i < 30000000 do i = i + 1 end \
+                    redis.call('SET', KEYS[1], 'script') return i";
+        let script = tokio::spawn(async move { conn.request(["EVAL", body, "1", "key"]).await });
+        started.read_frame().await.unwrap();
+
+        // 脚本执行期间的写命令需要等待脚本执行完毕, 因此最后写入
+        let mut other = server.connect().await.unwrap();
+        let reply = other.request(["SET", "key", "other"]).await.unwrap();
+        assert_eq!(Frame::Simple("OK".to_string()), reply);
+        assert_eq!(Frame::Integer(30000000), script.await.unwrap().unwrap());
+        assert_eq!(bulk("other"), other.request(["GET", "key"]).await.unwrap());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn busy_and_kill() {
+        let server = server::isolated().await;
+        let mut started = subscribe_started(&server).await;
+        let mut conn = server.connect().await.unwrap();
+        conn.request(["CONFIG", "SET", "lua-time-limit", "50"]).await.unwrap();
+
+        let body = "redis.call('PUBLISH', 'started', '1') while true do end";
+        let script = tokio::spawn(async move { conn.request(["EVAL", body, "0"]).await });
+        started.read_frame().await.unwrap();
+
+        // 超过 lua-time-limit 之后其他命令立即收到 BUSY, 而不是一直等待
+        let mut other = server.connect().await.unwrap();
+        let reply = other.request(["PING"]).await.unwrap();
+        assert_eq!(Frame::Error(BUSY_ERR.to_string()), reply);
+
+        let reply = other.request(["SCRIPT", "KILL"]).await.unwrap();
+        assert_eq!(Frame::Simple("OK".to_string()), reply);
+        let reply = script.await.unwrap().unwrap();
+        assert!(matches!(reply, Frame::Error(err) if err.contains("killed")));
+        assert_eq!(Frame::Simple("PONG".to_string()), other.request(["PING"]).await.unwrap());
+    }
+}
@@ -0,0 +1,513 @@
+//! 服务端
+//!
+//! `bin/server` 只负责解析启动参数, 监听端口与处理连接的逻辑都在这里,
+//! 因此其他代码(包括测试)可以在进程内启动一个服务端:
+//!
+//! ```no_run
+//! # async fn run() -> rudis::Result<()> {
+//! // 端口为 0 时由操作系统分配一个空闲的端口
+//! let server = rudis::server::Server::builder().port(0).spawn().await?;
+//! let mut conn = server.connect().await?;
+//! conn.request(["SET", "foo", "bar"]).await?;
+//!
+//! // 关闭监听并断开所有的连接, Handle 被 drop 时也会关闭
+//! server.shutdown().await;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! 测试可以使用 [`isolated`], 每次调用都会启动一个数据相互隔离的服务端
+
+#[cfg(unix)]
+use std::{fs, os::unix::fs::PermissionsExt};
+use std::{io, net::SocketAddr, sync::Arc};
+
+use log::{debug, info, warn};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, TcpStream},
+    sync::watch,
+    task::JoinHandle,
+};
+
+use crate::{
+    cmd::{self, Registry, Reply},
+    config::Config,
+    db::{Db, DbDropGuard},
+    session::Session,
+    Connection, Frame, Result,
+};
+
+/// 分片的数量, 每个分片都有一个独立的 `std::sync::Mutex`
+///
+/// Tokio 提供的异步锁只应该在跨多个 `.await` 调用时使用
+/// 在 `.await` 执行期间, 任务可能会在线程间转移. 理解了这个很多时候就明白错误了
+///
+/// Example:
+///
+/// 如果我们在 `async` 中跨 `.await` 使用了 Mutex, 此时会可能导致死锁的问题
+/// 因为 `.await` 期间如果调度了另外一个 Future, 并且该 Future 也需要获取锁, 此时就导致死锁啦
+///
+/// 或者我们可以使用 Tokio 提供的锁.
+/// 最大的优点就是：它可以在 `.await` 执行期间被持有，而且不会有任何问题。但是代价就是，这种异步锁的性能开销会更高
+///
+/// rudis 中的命令在持有分片锁期间不会调用 `.await`, 所以使用 `std::sync::Mutex` 即可
+///
+/// 另一种做法是像 `lib.rs` 中的 `Command` 那样, 由专门的线程持有每个分片, 命令通过 channel 发送过去.
+/// rudis 没有采用这种方式: 跨分片的命令(MSET、脚本等)仍然需要同时锁定多个分片,
+/// 每个命令还要多一次跨线程的往返. 实测热点 key 与多 key 的负载下都比直接加锁慢 2 到 3 倍
+pub const SHARDS: usize = 3;
+
+pub struct Server {
+    tcp_listener: TcpListener,
+    #[cfg(unix)]
+    unix_listener: Option<UnixListener>,
+
+    // DbDropGuard 在服务端退出时关闭后台清理过期 key 的任务
+    db_holder: DbDropGuard,
+
+    // 命令表在所有连接之间共享
+    registry: Arc<Registry>,
+}
+
+/// 服务端的配置, 与 redis.conf 中的同名配置项含义相同
+pub struct Builder {
+    bind: String,
+    port: u16,
+    #[cfg(unix)]
+    unixsocket: Option<String>,
+
+    /// socket 文件的权限, 使用八进制表示, 不指定时由 umask 决定
+    #[cfg(unix)]
+    unixsocketperm: Option<u32>,
+
+    config: Config,
+    registry: Option<Registry>,
+}
+
+impl Builder {
+    pub fn bind(mut self, bind: impl Into<String>) -> Self {
+        self.bind = bind.into();
+        self
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    #[cfg(unix)]
+    pub fn unixsocket(mut self, path: impl Into<String>) -> Self {
+        self.unixsocket = Some(path.into());
+        self
+    }
+
+    #[cfg(unix)]
+    pub fn unixsocketperm(mut self, perm: u32) -> Self {
+        self.unixsocketperm = Some(perm);
+        self
+    }
+
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// 扩展命令需要注册到 registry 中, 不指定时使用 `Registry::default()`
+    pub fn registry(mut self, registry: Registry) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    /// 监听端口, 此时还不会接受连接
+    pub async fn build(self) -> io::Result<Server> {
+        let tcp_listener = TcpListener::bind((self.bind.as_str(), self.port)).await?;
+        #[cfg(unix)]
+        let unix_listener = match &self.unixsocket {
+            Some(path) => Some(bind_unix(path, self.unixsocketperm)?),
+            None => None,
+        };
+
+        Ok(Server {
+            tcp_listener,
+            #[cfg(unix)]
+            unix_listener,
+            db_holder: DbDropGuard::new(SHARDS, self.config),
+            registry: Arc::new(self.registry.unwrap_or_default()),
+        })
+    }
+
+    /// 在后台任务中启动服务端, 返回的 Handle 可以获取监听的地址并关闭服务端
+    pub async fn spawn(self) -> io::Result<Handle> {
+        let server = self.build().await?;
+        let addr = server.local_addr()?;
+        info!("listening on {}", addr);
+
+        let (shutdown, signal) = watch::channel(false);
+        let task = tokio::spawn(server.run(signal));
+
+        Ok(Handle {
+            addr,
+            shutdown,
+            task: Some(task),
+        })
+    }
+}
+
+impl Server {
+    pub fn builder() -> Builder {
+        Builder {
+            bind: "127.0.0.1".to_string(),
+            port: 6379,
+            #[cfg(unix)]
+            unixsocket: None,
+            #[cfg(unix)]
+            unixsocketperm: None,
+            config: Config::default(),
+            registry: None,
+        }
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.tcp_listener.local_addr()
+    }
+
+    /// 接受连接, 直到收到 shutdown 的通知. 退出时所有的连接都会被关闭
+    pub async fn run(self, mut shutdown: watch::Receiver<bool>) {
+        loop {
+            // 一个 Tokio 任务是一个异步的绿色线程, 它们通过 `tokio::spawn` 进行创建
+            // 该函数会返回一个 `JoinHandle` 类型的句柄, 调用者可以使用该句柄跟创建的任务进行交互
+            // 任务是调度器管理的执行单元. spawn生成的任务会首先提交给'调度器', 然后由它负责调度执行.
+            // 需要注意的是, 执行任务的线程未必是创建任务的线程, 任务'完全有可能运行在另一个不同的线程'上, 而且任务在生成后, 它还可能会在线程间被移动.
+            // 类似于启动一个 "Golang的协程" :)
+            let db = self.db_holder.db();
+            let registry = self.registry.clone();
+            let signal = shutdown.clone();
+
+            tokio::select! {
+                res = self.accept() => match res {
+                    Ok(Stream::Tcp(tcp_stream)) => {
+                        // 与 redis 相同关闭 Nagle 算法, 否则小的回复会被延迟发送
+                        if let Err(err) = tcp_stream.set_nodelay(true) {
+                            warn!("failed to set TCP_NODELAY: {}", err);
+                        }
+                        tokio::spawn(process(tcp_stream, registry, db, signal));
+                    }
+                    #[cfg(unix)]
+                    Ok(Stream::Unix(unix_stream)) => {
+                        tokio::spawn(process(unix_stream, registry, db, signal));
+                    }
+                    Err(err) => warn!("failed to accept connection: {}", err),
+                },
+                _ = shutdown.changed() => break,
+            }
+        }
+
+        info!("server is shutting down");
+    }
+
+    /// 同时等待 TCP 与 Unix domain socket 上的连接
+    #[cfg(unix)]
+    async fn accept(&self) -> io::Result<Stream> {
+        tokio::select! {
+            res = self.tcp_listener.accept() => res.map(|(stream, _)| Stream::Tcp(stream)),
+            res = accept_unix(self.unix_listener.as_ref()) => res.map(Stream::Unix),
+        }
+    }
+
+    #[cfg(not(unix))]
+    async fn accept(&self) -> io::Result<Stream> {
+        self.tcp_listener.accept().await.map(|(stream, _)| Stream::Tcp(stream))
+    }
+}
+
+/// 接受的连接, 只有 unix 平台上才有 Unix domain socket
+enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+/// 在后台运行的服务端, 被 drop 时关闭
+pub struct Handle {
+    addr: SocketAddr,
+    shutdown: watch::Sender<bool>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl Handle {
+    /// 实际监听的地址, 端口为 0 时可以通过它获取分配的端口
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub async fn connect(&self) -> Result<Connection> {
+        Connection::connect(self.addr).await
+    }
+
+    /// 关闭服务端, 等待监听的任务退出
+    pub async fn shutdown(mut self) {
+        let _ = self.shutdown.send(true);
+        if let Some(task) = self.task.take() {
+            let _ = task.await;
+        }
+    }
+
+    /// 等待服务端退出, 除非发生 panic, 否则不会返回
+    pub async fn wait(mut self) {
+        if let Some(task) = self.task.take() {
+            let _ = task.await;
+        }
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        let _ = self.shutdown.send(true);
+    }
+}
+
+/// 测试使用: 在随机端口上启动一个新的服务端, 不同的服务端之间数据相互隔离
+pub async fn isolated() -> Handle {
+    Server::builder()
+        .port(0)
+        .spawn()
+        .await
+        .expect("failed to spawn rudis server")
+}
+
+/// 监听 Unix domain socket
+///
+/// 与 redis 相同, 启动时删除上一次遗留的 socket 文件, 否则 bind 会失败
+#[cfg(unix)]
+fn bind_unix(path: &str, perm: Option<u32>) -> io::Result<UnixListener> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+
+    let listener = UnixListener::bind(path)?;
+    if let Some(perm) = perm {
+        fs::set_permissions(path, fs::Permissions::from_mode(perm))?;
+    }
+    info!("listening on unix socket {}", path);
+
+    Ok(listener)
+}
+
+/// 没有配置 Unix domain socket 时永远不会返回
+#[cfg(unix)]
+async fn accept_unix(listener: Option<&UnixListener>) -> io::Result<UnixStream> {
+    match listener {
+        Some(listener) => listener.accept().await.map(|(stream, _)| stream),
+        None => std::future::pending().await,
+    }
+}
+
+async fn process<S>(
+    socket: S,
+    registry: Arc<Registry>,
+    db: Db,
+    mut shutdown: watch::Receiver<bool>,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send,
+{
+    // Connection 对 redis 的读写进行了封装
+    // Frame(数据帧 = redis命令 + 数据)
+    let mut connection = Connection::new(socket);
+    connection.enable_inline();
+
+    // pushes 接收其他连接推送给当前连接的消息, 例如 pub/sub 的消息
+    let (mut session, mut pushes) = Session::new();
+
+    // 我们需要使用循环的方式在同一个客户端连接中处理多次连续的请求
+    // 同时还需要监听推送通道以及服务端的关闭信号, 因此使用 select! 同时等待
+    loop {
+        // pipeline 中已经到达的请求直接处理, 回复先留在缓冲区中.
+        // 缓冲区中没有完整的请求时才 flush, 一批请求的回复只需要写一次 socket
+        let res = match connection.buffered_frame() {
+            Ok(None) => {
+                if connection.flush().await.is_err() {
+                    break;
+                }
+                tokio::select! {
+                    res = connection.read_frame() => res,
+                    Some(push) = pushes.recv() => {
+                        // RESP3 的连接通过 `>` 区分推送消息与命令的回复
+                        let push = match push {
+                            Frame::Array(items) if session.protocol() == 3 => Frame::Push(items),
+                            push => push,
+                        };
+                        if connection.write_frame(&push).await.is_err() {
+                            break;
+                        }
+                        continue;
+                    }
+                    _ = shutdown.changed() => break,
+                }
+            }
+            res => res,
+        };
+        let frame = match res {
+            Ok(Some(frame)) => frame,
+            Ok(None) => break,
+            Err(err) => {
+                // 与 redis 相同, 出现协议错误时先将错误回复给客户端再关闭连接
+                warn!("failed to read frame: {}", err);
+                let _ = connection.write_frame(&Frame::Error(format!("ERR {}", err))).await;
+                break;
+            }
+        };
+
+        debug!("session {} received: {}", session.id(), frame);
+
+        // reply
+        let res = match cmd::execute(&registry, &db, &mut session, frame) {
+            Reply::Frame(frame) => connection.feed_frame(&frame).await,
+            Reply::Multi(frames) => connection.feed_frames(&frames).await,
+            Reply::Later(reply) => connection.feed_frame(&reply.await).await,
+        };
+        if res.is_err() {
+            break;
+        }
+    }
+
+    cmd::disconnect(&db, &mut session);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn isolated_servers() {
+        let (a, b) = (isolated().await, isolated().await);
+        assert_ne!(a.addr(), b.addr());
+
+        let mut conn = a.connect().await.unwrap();
+        let reply = conn.request(["SET", "foo", "bar"]).await.unwrap();
+        assert_eq!(Frame::Simple("OK".to_string()), reply);
+        let reply = conn.request(["GET", "foo"]).await.unwrap();
+        assert_eq!(Frame::Bulk(Bytes::from("bar")), reply);
+
+        // 两个服务端的数据相互隔离
+        let mut other = b.connect().await.unwrap();
+        assert_eq!(Frame::Null, other.request(["GET", "foo"]).await.unwrap());
+
+        // 关闭之后已有的连接被断开
+        a.shutdown().await;
+        assert!(matches!(conn.read_frame().await, Ok(None) | Err(_)));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn unix_socket() {
+        let path = std::env::temp_dir().join(format!("rudis-{}.sock", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let server = Server::builder()
+            .port(0)
+            .unixsocket(path.clone())
+            .unixsocketperm(0o700)
+            .spawn()
+            .await
+            .unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(0o700, mode & 0o777);
+        let mut conn = Connection::connect_unix(&path).await.unwrap();
+        let reply = conn.request(["PING"]).await.unwrap();
+        assert_eq!(Frame::Simple("PONG".to_string()), reply);
+
+        server.shutdown().await;
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn pipeline() {
+        let server = isolated().await;
+        let mut conn = server.connect().await.unwrap();
+
+        // 一批请求的回复在最后一起 flush, 顺序与请求相同
+        let incr = Frame::Array(vec![Frame::Bulk("INCR".into()), Frame::Bulk("n".into())]);
+        conn.write_frames(&vec![incr; 100]).await.unwrap();
+        for i in 1..=100 {
+            assert_eq!(Some(Frame::Integer(i)), conn.read_frame().await.unwrap());
+        }
+
+        // 协议错误之前的请求仍然会收到回复, 之后连接被关闭
+        let get = Frame::Array(vec![Frame::Bulk("GET".into()), Frame::Bulk("n".into())]);
+        let nested = Frame::Array(vec![Frame::Array(vec![])]);
+        conn.write_frames(&[get, nested]).await.unwrap();
+        assert_eq!(Some(Frame::Bulk("100".into())), conn.read_frame().await.unwrap());
+        let reply = conn.read_frame().await.unwrap();
+        assert!(matches!(reply, Some(Frame::Error(err)) if err.contains("expected '$'")));
+        assert!(matches!(conn.read_frame().await, Ok(None) | Err(_)));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn debug_command() {
+        let disabled = isolated().await;
+        let mut conn = disabled.connect().await.unwrap();
+        let reply = conn.request(["DEBUG", "JMAP"]).await.unwrap();
+        assert!(matches!(reply, Frame::Error(err) if err.contains("not allowed")));
+
+        let server = Server::builder()
+            .port(0)
+            .config(Config::default().with_debug_command(true))
+            .spawn()
+            .await
+            .unwrap();
+        let mut conn = server.connect().await.unwrap();
+        conn.request(["SET", "foo", "bar", "EX", "100"]).await.unwrap();
+        conn.request(["RPUSH", "list", "a", "b"]).await.unwrap();
+        conn.request(["TS.CREATE", "src"]).await.unwrap();
+        conn.request(["TS.CREATE", "dst"]).await.unwrap();
+        conn.request(["TS.CREATERULE", "src", "dst", "AGGREGATION", "avg", "10"]).await.unwrap();
+
+        // RELOAD 之后数据, 过期时间与降采样规则保持不变
+        let reply = conn.request(["DEBUG", "RELOAD"]).await.unwrap();
+        assert_eq!(Frame::Simple("OK".to_string()), reply);
+        assert_eq!(Frame::Bulk(Bytes::from("bar")), conn.request(["GET", "foo"]).await.unwrap());
+        assert!(matches!(conn.request(["TTL", "foo"]).await.unwrap(), Frame::Integer(90..=100)));
+        let reply = conn.request(["DEBUG", "OBJECT", "list"]).await.unwrap();
+        assert!(matches!(reply, Frame::Simple(info) if info.contains("encoding:listpack")));
+        let reply = conn.request(["TS.DELETERULE", "src", "dst"]).await.unwrap();
+        assert_eq!(Frame::Simple("OK".to_string()), reply);
+        conn.request(["DEL", "src", "dst"]).await.unwrap();
+
+        // 关闭主动过期之后, 过期的 key 只在被访问时删除
+        conn.request(["DEBUG", "SET-ACTIVE-EXPIRE", "0"]).await.unwrap();
+        conn.request(["PEXPIRE", "list", "1"]).await.unwrap();
+        // 服务端至少睡眠 10 毫秒, key 一定已经过期, 不依赖客户端这边的计时
+        conn.request(["DEBUG", "SLEEP", "0.01"]).await.unwrap();
+        let reply = conn.request(["DEBUG", "JMAP"]).await.unwrap();
+        assert!(matches!(reply, Frame::Bulk(histo) if !histo.windows(4).any(|w| w == b"list")));
+        assert_eq!(Frame::Integer(2), conn.request(["DBSIZE"]).await.unwrap());
+        assert_eq!(Frame::Integer(0), conn.request(["EXISTS", "list"]).await.unwrap());
+        assert_eq!(Frame::Integer(1), conn.request(["DBSIZE"]).await.unwrap());
+
+        // 无法转换为 Duration 的值回复错误, 连接仍然可用
+        for secs in ["1e30", "-1", "nan", "inf"] {
+            let reply = conn.request(["DEBUG", "SLEEP", secs]).await.unwrap();
+            assert!(matches!(reply, Frame::Error(err) if err.contains("not a valid float")));
+        }
+
+        // 两个连接同时发出请求, 无论服务端先处理哪一个, 都要得到各自的回复,
+        // 并且 DEBUG SLEEP 的回复不早于睡眠结束
+        let mut other = server.connect().await.unwrap();
+        let start = std::time::Instant::now();
+        let (slept, pinged) = tokio::join!(
+            conn.request(["DEBUG", "SLEEP", "0.05"]),
+            other.request(["PING"]),
+        );
+        assert_eq!(Frame::Simple("OK".to_string()), slept.unwrap());
+        assert_eq!(Frame::Simple("PONG".to_string()), pinged.unwrap());
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+}
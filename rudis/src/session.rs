@@ -0,0 +1,85 @@
+use std::{
+    collections::HashSet,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use crate::{tracking, Frame};
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// 服务端为每个客户端连接维护的状态
+pub struct Session {
+    id: u64,
+
+    /// 推送通道: 其他连接可以通过它向当前连接推送消息(例如 pub/sub 的消息),
+    /// 接收端由连接所在的任务持有
+    push: UnboundedSender<Frame>,
+
+    /// 当前选择的数据库, 通过 SELECT 切换
+    db: usize,
+
+    /// 已经订阅的频道
+    pub(crate) channels: HashSet<String>,
+
+    /// 已经订阅的模式
+    pub(crate) patterns: HashSet<String>,
+
+    /// 使用的 RESP 协议版本, 通过 HELLO 切换
+    protocol: u8,
+
+    /// CLIENT TRACKING 的选项, 没有开启时为 None
+    pub(crate) tracking: Option<tracking::Options>,
+
+    /// CLIENT CACHING 的设置, 只对下一条命令生效
+    pub(crate) caching: Option<bool>,
+}
+
+impl Session {
+    pub fn new() -> (Session, UnboundedReceiver<Frame>) {
+        let (push, pushes) = mpsc::unbounded_channel();
+
+        let session = Session {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            push,
+            db: 0,
+            channels: HashSet::new(),
+            patterns: HashSet::new(),
+            protocol: 2,
+            tracking: None,
+            caching: None,
+        };
+
+        (session, pushes)
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn db(&self) -> usize {
+        self.db
+    }
+
+    pub(crate) fn select(&mut self, db: usize) {
+        self.db = db;
+    }
+
+    pub fn protocol(&self) -> u8 {
+        self.protocol
+    }
+
+    pub(crate) fn set_protocol(&mut self, protocol: u8) {
+        self.protocol = protocol;
+    }
+
+    pub fn push(&self) -> UnboundedSender<Frame> {
+        self.push.clone()
+    }
+
+    /// 订阅的频道与模式的总数, 大于 0 时连接处于订阅模式
+    pub fn subscriptions(&self) -> usize {
+        self.channels.len() + self.patterns.len()
+    }
+}
@@ -0,0 +1,153 @@
+//! 集合类型的存储
+//!
+//! 与 redis 相同, 集合有两种编码:
+//! + intset: 所有成员都是整数时使用, 见 `crate::intset`
+//! + hashtable: 插入了非整数的成员, 或者成员个数超过 `set-max-intset-entries` 之后转换为 `HashSet`
+//!
+//! 转换是单向的, 删除成员之后不会再转换回 intset
+
+use std::collections::HashSet;
+
+use bytes::Bytes;
+
+use crate::intset::IntSet;
+
+/// `set-max-intset-entries` 的默认值, 与 redis 相同
+pub const DEFAULT_MAX_INTSET_ENTRIES: usize = 512;
+
+#[derive(Debug, Clone)]
+pub enum Set {
+    IntSet(IntSet),
+    Table(HashSet<Bytes>),
+}
+
+impl Default for Set {
+    fn default() -> Self {
+        Set::IntSet(IntSet::new())
+    }
+}
+
+impl PartialEq for Set {
+    /// 只比较成员, 与编码无关
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|member| other.contains(&member))
+    }
+}
+
+impl Set {
+    pub fn new() -> Set {
+        Set::default()
+    }
+
+    /// 依次插入 members, 例如 RESTORE 时按照当前的 `set-max-intset-entries` 重新选择编码
+    pub fn from_members(members: impl IntoIterator<Item = Bytes>, max_intset: usize) -> Set {
+        let mut set = Set::new();
+        for member in members {
+            set.insert(member, max_intset);
+        }
+        set
+    }
+
+    /// OBJECT ENCODING 返回的编码名称
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Set::IntSet(_) => "intset",
+            Set::Table(_) => "hashtable",
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Set::IntSet(set) => set.len(),
+            Set::Table(set) => set.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        match self {
+            Set::IntSet(set) => as_int(member).is_some_and(|val| set.contains(val)),
+            Set::Table(set) => set.contains(member),
+        }
+    }
+
+    /// 插入成员, 成员是新增的时返回 true. max_intset 为 `set-max-intset-entries`
+    pub fn insert(&mut self, member: Bytes, max_intset: usize) -> bool {
+        if let Set::IntSet(set) = self {
+            match as_int(&member) {
+                Some(val) if set.contains(val) => return false,
+                Some(val) if set.len() < max_intset => return set.insert(val),
+                _ => self.convert(),
+            }
+        }
+
+        let Set::Table(set) = self else {
+            unreachable!()
+        };
+        set.insert(member)
+    }
+
+    /// 删除成员, 成员存在时返回 true
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self {
+            Set::IntSet(set) => as_int(member).is_some_and(|val| set.remove(val)),
+            Set::Table(set) => set.remove(member),
+        }
+    }
+
+    /// intset 编码按照从小到大的顺序遍历, 整数成员会被格式化为字符串
+    pub fn iter(&self) -> Box<dyn Iterator<Item = Bytes> + '_> {
+        match self {
+            Set::IntSet(set) => Box::new(set.iter().map(|val| Bytes::from(val.to_string()))),
+            Set::Table(set) => Box::new(set.iter().cloned()),
+        }
+    }
+
+    /// intset 编码占用的字节数, hashtable 编码返回 None
+    pub fn intset_bytes(&self) -> Option<usize> {
+        match self {
+            Set::IntSet(set) => Some(set.bytes()),
+            Set::Table(_) => None,
+        }
+    }
+
+    fn convert(&mut self) {
+        let set = self.iter().collect();
+        *self = Set::Table(set);
+    }
+}
+
+/// 与 redis 相同, 只有格式化之后与原来完全相同的字符串才能作为整数保存, 例如 "01" 不是整数
+fn as_int(member: &[u8]) -> Option<i64> {
+    let val: i64 = std::str::from_utf8(member).ok()?.parse().ok()?;
+    (val.to_string().as_bytes() == member).then_some(val)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert() {
+        let mut set = Set::new();
+        for member in ["3", "1", "2", "1"] {
+            set.insert(Bytes::from(member), 3);
+        }
+        assert_eq!("intset", set.encoding());
+        assert_eq!(vec!["1", "2", "3"], set.iter().collect::<Vec<_>>());
+        assert!(set.contains(b"2") && !set.contains(b"02"));
+
+        let mut table = set.clone();
+        assert!(table.insert(Bytes::from("4"), 3));
+        assert_eq!("hashtable", table.encoding());
+        assert!(table.remove(b"4"));
+        assert_eq!(set, table);
+
+        assert!(set.insert(Bytes::from("01"), 512));
+        assert_eq!("hashtable", set.encoding());
+        assert!(set.contains(b"01") && set.contains(b"1"));
+    }
+}
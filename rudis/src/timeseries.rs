@@ -0,0 +1,677 @@
+//! 时间序列, 与 RedisTimeSeries 模块的 TS.* 命令兼容
+//!
+//! 样本按照时间顺序保存在若干个 chunk 中, 每个 chunk 使用 Facebook Gorilla 论文中的压缩方式:
+//! + 时间戳保存 delta-of-delta, 即相邻两个间隔的差值. 采样间隔固定时差值总是 0, 只占用 1 个 bit
+//! + 值保存与上一个值的 XOR, 相同的值只占用 1 个 bit, 相近的值只保存中间有意义的部分
+//!
+//! chunk 的数据超过 `chunk_size` 字节之后创建新的 chunk, 超过 retention 的 chunk 会被整个删除.
+//! 写入比最后一个样本更早的时间戳时, 需要解压对应的 chunk 再重新压缩
+
+use crate::dump::{self, Reader};
+
+/// 与 RedisTimeSeries 的默认值相同
+pub const DEFAULT_CHUNK_SIZE: usize = 4096;
+
+/// 时间戳相同时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    /// 返回错误
+    Block,
+    /// 保留原来的值
+    First,
+    /// 使用新的值
+    Last,
+    Min,
+    Max,
+    /// 两个值相加
+    Sum,
+}
+
+impl DuplicatePolicy {
+    const ALL: [DuplicatePolicy; 6] = [
+        DuplicatePolicy::Block,
+        DuplicatePolicy::First,
+        DuplicatePolicy::Last,
+        DuplicatePolicy::Min,
+        DuplicatePolicy::Max,
+        DuplicatePolicy::Sum,
+    ];
+
+    pub fn parse(name: &str) -> Option<DuplicatePolicy> {
+        Self::ALL.into_iter().find(|policy| policy.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DuplicatePolicy::Block => "block",
+            DuplicatePolicy::First => "first",
+            DuplicatePolicy::Last => "last",
+            DuplicatePolicy::Min => "min",
+            DuplicatePolicy::Max => "max",
+            DuplicatePolicy::Sum => "sum",
+        }
+    }
+
+    fn resolve(&self, old: f64, new: f64) -> Result<f64, &'static str> {
+        match self {
+            DuplicatePolicy::Block => Err(
+                "ERR TSDB: Error at upsert, update is not supported when DUPLICATE_POLICY is set to BLOCK mode",
+            ),
+            DuplicatePolicy::First => Ok(old),
+            DuplicatePolicy::Last => Ok(new),
+            DuplicatePolicy::Min => Ok(old.min(new)),
+            DuplicatePolicy::Max => Ok(old.max(new)),
+            DuplicatePolicy::Sum => Ok(old + new),
+        }
+    }
+}
+
+/// 聚合函数, 用于 TS.RANGE 的 AGGREGATION 以及降采样规则
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregation {
+    Avg,
+    Min,
+    Max,
+    Sum,
+    Count,
+}
+
+impl Aggregation {
+    const ALL: [Aggregation; 5] = [
+        Aggregation::Avg,
+        Aggregation::Min,
+        Aggregation::Max,
+        Aggregation::Sum,
+        Aggregation::Count,
+    ];
+
+    pub fn parse(name: &str) -> Option<Aggregation> {
+        Self::ALL.into_iter().find(|agg| agg.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aggregation::Avg => "avg",
+            Aggregation::Min => "min",
+            Aggregation::Max => "max",
+            Aggregation::Sum => "sum",
+            Aggregation::Count => "count",
+        }
+    }
+
+    /// 聚合一组值, 没有值时返回 None
+    pub fn apply(&self, values: impl Iterator<Item = f64>) -> Option<f64> {
+        let (mut count, mut sum) = (0usize, 0.0);
+        let (mut min, mut max) = (f64::INFINITY, f64::NEG_INFINITY);
+        for val in values {
+            count += 1;
+            sum += val;
+            min = min.min(val);
+            max = max.max(val);
+        }
+
+        if count == 0 {
+            return None;
+        }
+
+        Some(match self {
+            Aggregation::Avg => sum / count as f64,
+            Aggregation::Min => min,
+            Aggregation::Max => max,
+            Aggregation::Sum => sum,
+            Aggregation::Count => count as f64,
+        })
+    }
+}
+
+/// 降采样规则: 源时间序列的每个时间桶结束之后, 将聚合的结果写入 dest
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub dest: String,
+    pub aggregation: Aggregation,
+    /// 时间桶的长度(毫秒)
+    pub bucket: u64,
+    /// 当前还没有结束的时间桶的起始时间
+    current: Option<i64>,
+}
+
+impl Rule {
+    pub fn new(dest: String, aggregation: Aggregation, bucket: u64) -> Rule {
+        Rule {
+            dest,
+            aggregation,
+            bucket,
+            current: None,
+        }
+    }
+}
+
+/// TS.CREATE 等命令的选项
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// 样本保留的时长(毫秒), 相对于最新的样本, 0 代表永久保留
+    pub retention: u64,
+    pub chunk_size: usize,
+    pub policy: DuplicatePolicy,
+    pub labels: Vec<(String, String)>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            retention: 0,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            policy: DuplicatePolicy::Block,
+            labels: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeSeries {
+    retention: u64,
+    chunk_size: usize,
+    policy: DuplicatePolicy,
+    labels: Vec<(String, String)>,
+    chunks: Vec<Chunk>,
+    /// 作为降采样的目标时, 源时间序列的 key
+    source: Option<String>,
+    rules: Vec<Rule>,
+}
+
+impl TimeSeries {
+    pub fn new(options: Options) -> TimeSeries {
+        TimeSeries {
+            retention: options.retention,
+            chunk_size: options.chunk_size,
+            policy: options.policy,
+            labels: options.labels,
+            chunks: vec![],
+            source: None,
+            rules: vec![],
+        }
+    }
+
+    /// 样本的个数
+    pub fn len(&self) -> usize {
+        self.chunks.iter().map(|chunk| chunk.count).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    pub fn first_timestamp(&self) -> Option<i64> {
+        self.chunks.first().map(|chunk| chunk.first)
+    }
+
+    pub fn last_timestamp(&self) -> Option<i64> {
+        self.chunks.last().map(|chunk| chunk.last)
+    }
+
+    pub fn retention(&self) -> u64 {
+        self.retention
+    }
+
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn policy(&self) -> DuplicatePolicy {
+        self.policy
+    }
+
+    pub fn labels(&self) -> &[(String, String)] {
+        &self.labels
+    }
+
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    pub fn set_source(&mut self, source: Option<String>) {
+        self.source = source;
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    pub fn add_rule(&mut self, rule: Rule) {
+        self.rules.push(rule);
+    }
+
+    /// 删除目标为 dest 的规则, 规则存在时返回 true
+    pub fn remove_rule(&mut self, dest: &str) -> bool {
+        let len = self.rules.len();
+        self.rules.retain(|rule| rule.dest != dest);
+        self.rules.len() != len
+    }
+
+    /// 压缩之后的数据占用的字节数
+    pub fn size(&self) -> usize {
+        self.chunks.iter().map(|chunk| chunk.data.len()).sum()
+    }
+
+    /// 写入一个样本, policy 覆盖时间序列自身的 DUPLICATE_POLICY
+    pub fn add(
+        &mut self,
+        timestamp: i64,
+        value: f64,
+        policy: Option<DuplicatePolicy>,
+    ) -> Result<(), &'static str> {
+        let last = match self.chunks.last_mut() {
+            None => {
+                self.chunks.push(Chunk::new(timestamp, value));
+                return Ok(());
+            }
+            Some(chunk) if timestamp > chunk.last => {
+                if chunk.data.len() < self.chunk_size {
+                    chunk.push(timestamp, value);
+                } else {
+                    self.chunks.push(Chunk::new(timestamp, value));
+                }
+                self.trim(timestamp);
+                return Ok(());
+            }
+            Some(chunk) => chunk.last,
+        };
+
+        if self.retention > 0 && timestamp < last.saturating_sub(self.retention as i64) {
+            return Err("ERR TSDB: Timestamp is older than retention");
+        }
+        self.upsert(timestamp, value, policy.unwrap_or(self.policy))
+    }
+
+    /// [from, to] 之间的样本
+    pub fn range(&self, from: i64, to: i64) -> Vec<(i64, f64)> {
+        self.chunks
+            .iter()
+            .filter(|chunk| chunk.last >= from && chunk.first <= to)
+            .flat_map(|chunk| chunk.iter())
+            .filter(|(ts, _)| (from..=to).contains(ts))
+            .collect()
+    }
+
+    /// 写入 timestamp 之后需要更新的降采样结果, 依次为目标 key、时间桶的起始时间以及聚合的值
+    ///
+    /// 与 RedisTimeSeries 相同, 时间桶只有在后续时间桶的样本到达之后才算结束.
+    /// 写入已经结束的时间桶时, 重新计算该时间桶的结果
+    pub fn compactions(&mut self, timestamp: i64) -> Vec<(String, i64, f64)> {
+        let mut out = vec![];
+
+        for i in 0..self.rules.len() {
+            let rule = &self.rules[i];
+            let start = bucket_start(timestamp, rule.bucket);
+            let closed = match rule.current {
+                Some(current) if start > current => Some(current),
+                Some(current) if start < current => Some(start),
+                _ => None,
+            };
+            if rule.current.is_none_or(|current| start > current) {
+                self.rules[i].current = Some(start);
+            }
+
+            let Some(closed) = closed else {
+                continue;
+            };
+            let rule = &self.rules[i];
+            let end = closed.saturating_add(rule.bucket as i64 - 1);
+            let values = self.range(closed, end).into_iter().map(|(_, val)| val);
+            if let Some(val) = rule.aggregation.apply(values) {
+                out.push((rule.dest.clone(), closed, val));
+            }
+        }
+
+        out
+    }
+
+    /// 写入的时间戳不晚于最后一个样本, 解压所在的 chunk, 修改之后重新压缩
+    fn upsert(&mut self, timestamp: i64, value: f64, policy: DuplicatePolicy) -> Result<(), &'static str> {
+        let index = self
+            .chunks
+            .iter()
+            .rposition(|chunk| chunk.first <= timestamp)
+            .unwrap_or(0);
+        let mut samples: Vec<(i64, f64)> = self.chunks[index].iter().collect();
+
+        match samples.binary_search_by_key(&timestamp, |(ts, _)| *ts) {
+            Ok(i) => samples[i].1 = policy.resolve(samples[i].1, value)?,
+            Err(i) => samples.insert(i, (timestamp, value)),
+        }
+
+        let mut chunk = Chunk::new(samples[0].0, samples[0].1);
+        for (ts, val) in &samples[1..] {
+            chunk.push(*ts, *val);
+        }
+        self.chunks[index] = chunk;
+
+        Ok(())
+    }
+
+    /// 删除所有样本都已经超过 retention 的 chunk
+    fn trim(&mut self, newest: i64) {
+        if self.retention == 0 {
+            return;
+        }
+
+        let oldest = newest.saturating_sub(self.retention as i64);
+        let expired = self.chunks.iter().take_while(|chunk| chunk.last < oldest).count();
+        self.chunks.drain(..expired);
+    }
+
+    /// 序列化, 见 `crate::dump`. 样本解压之后保存, 恢复时重新压缩
+    pub(crate) fn encode(&self, out: &mut Vec<u8>) {
+        dump::put_u64(out, self.retention);
+        dump::put_u64(out, self.chunk_size as u64);
+        out.push(DuplicatePolicy::ALL.iter().position(|p| *p == self.policy).unwrap_or(0) as u8);
+
+        dump::put_u64(out, self.labels.len() as u64);
+        for (label, value) in &self.labels {
+            dump::put_bytes(out, label.as_bytes());
+            dump::put_bytes(out, value.as_bytes());
+        }
+
+        dump::put_u64(out, self.len() as u64);
+        for (ts, val) in self.chunks.iter().flat_map(|chunk| chunk.iter()) {
+            dump::put_u64(out, ts as u64);
+            dump::put_f64(out, val);
+        }
+    }
+
+    /// 降采样规则与 key 之间的关系不会被序列化, 与 RedisTimeSeries 的 RESTORE 相同
+    pub(crate) fn decode(reader: &mut Reader<'_>) -> Option<TimeSeries> {
+        let retention = reader.u64()?;
+        let chunk_size = usize::try_from(reader.u64()?).ok()?;
+        let policy = *DuplicatePolicy::ALL.get(reader.u8()? as usize)?;
+
+        let mut labels = vec![];
+        for _ in 0..reader.len()? {
+            let label = String::from_utf8(reader.bytes()?.to_vec()).ok()?;
+            let value = String::from_utf8(reader.bytes()?.to_vec()).ok()?;
+            labels.push((label, value));
+        }
+
+        let mut series = TimeSeries::new(Options {
+            retention,
+            chunk_size,
+            policy,
+            labels,
+        });
+        for _ in 0..reader.len()? {
+            let ts = i64::try_from(reader.u64()?).ok()?;
+            series.add(ts, reader.f64()?, Some(DuplicatePolicy::Last)).ok()?;
+        }
+
+        Some(series)
+    }
+}
+
+/// 按照时间桶聚合样本, 每个时间桶的时间戳为起始时间, 没有样本的时间桶会被跳过
+pub fn aggregate(samples: &[(i64, f64)], aggregation: Aggregation, bucket: u64) -> Vec<(i64, f64)> {
+    samples
+        .chunk_by(|a, b| bucket_start(a.0, bucket) == bucket_start(b.0, bucket))
+        .filter_map(|samples| {
+            let val = aggregation.apply(samples.iter().map(|(_, val)| *val))?;
+            Some((bucket_start(samples[0].0, bucket), val))
+        })
+        .collect()
+}
+
+fn bucket_start(timestamp: i64, bucket: u64) -> i64 {
+    timestamp - timestamp.rem_euclid(bucket.min(i64::MAX as u64) as i64)
+}
+
+/// 一段压缩的样本
+#[derive(Debug, Clone, PartialEq)]
+struct Chunk {
+    data: Vec<u8>,
+    /// data 中有效的 bit 数
+    bits: usize,
+    count: usize,
+    first: i64,
+    last: i64,
+    /// 以下为压缩下一个样本需要的状态
+    last_delta: i64,
+    last_value: u64,
+    leading: u32,
+    trailing: u32,
+}
+
+impl Chunk {
+    /// 第一个样本的时间戳保存在 first 中, 值不压缩
+    fn new(timestamp: i64, value: f64) -> Chunk {
+        let mut chunk = Chunk {
+            data: vec![],
+            bits: 0,
+            count: 1,
+            first: timestamp,
+            last: timestamp,
+            last_delta: 0,
+            last_value: value.to_bits(),
+            leading: u32::MAX,
+            trailing: 0,
+        };
+        chunk.write(value.to_bits(), 64);
+        chunk
+    }
+
+    /// 追加一个样本, timestamp 必须大于最后一个样本的时间戳
+    fn push(&mut self, timestamp: i64, value: f64) {
+        let delta = timestamp - self.last;
+        let dod = delta - self.last_delta;
+        match dod {
+            0 => self.write(0b0, 1),
+            -64..=63 => self.write_dod(0b10, 2, dod, 7),
+            -256..=255 => self.write_dod(0b110, 3, dod, 9),
+            -2048..=2047 => self.write_dod(0b1110, 4, dod, 12),
+            _ => self.write_dod(0b1111, 4, dod, 64),
+        }
+
+        let bits = value.to_bits();
+        let xor = bits ^ self.last_value;
+        if xor == 0 {
+            self.write(0b0, 1);
+        } else {
+            let (leading, trailing) = (xor.leading_zeros().min(63), xor.trailing_zeros());
+            if self.leading != u32::MAX && leading >= self.leading && trailing >= self.trailing {
+                // 有意义的部分落在上一个值的范围内, 沿用上一个值的 leading/trailing
+                self.write(0b10, 2);
+                self.write(xor >> self.trailing, 64 - self.leading - self.trailing);
+            } else {
+                let meaningful = 64 - leading - trailing;
+                self.write(0b11, 2);
+                self.write(leading as u64, 6);
+                self.write(meaningful as u64 - 1, 6);
+                self.write(xor >> trailing, meaningful);
+                (self.leading, self.trailing) = (leading, trailing);
+            }
+        }
+
+        self.count += 1;
+        self.last = timestamp;
+        self.last_delta = delta;
+        self.last_value = bits;
+    }
+
+    fn iter(&self) -> ChunkIter<'_> {
+        ChunkIter {
+            chunk: self,
+            pos: 0,
+            remaining: self.count,
+            timestamp: self.first,
+            delta: 0,
+            value: 0,
+            leading: 0,
+            trailing: 0,
+        }
+    }
+
+    fn write_dod(&mut self, prefix: u64, prefix_bits: u32, dod: i64, bits: u32) {
+        self.write(prefix, prefix_bits);
+        self.write(dod as u64, bits);
+    }
+
+    /// 按照从高到低的顺序写入 val 的低 n 位
+    fn write(&mut self, val: u64, n: u32) {
+        for i in (0..n).rev() {
+            if self.bits.is_multiple_of(8) {
+                self.data.push(0);
+            }
+            if (val >> i) & 1 == 1 {
+                self.data[self.bits / 8] |= 0x80 >> (self.bits % 8);
+            }
+            self.bits += 1;
+        }
+    }
+}
+
+struct ChunkIter<'a> {
+    chunk: &'a Chunk,
+    pos: usize,
+    remaining: usize,
+    timestamp: i64,
+    delta: i64,
+    value: u64,
+    leading: u32,
+    trailing: u32,
+}
+
+impl ChunkIter<'_> {
+    fn read(&mut self, n: u32) -> u64 {
+        let mut val = 0;
+        for _ in 0..n {
+            let bit = (self.chunk.data[self.pos / 8] >> (7 - self.pos % 8)) & 1;
+            val = (val << 1) | bit as u64;
+            self.pos += 1;
+        }
+        val
+    }
+
+    /// 读取 n 位的有符号整数
+    fn read_signed(&mut self, n: u32) -> i64 {
+        let val = self.read(n);
+        if n < 64 && val >> (n - 1) == 1 {
+            (val | (u64::MAX << n)) as i64
+        } else {
+            val as i64
+        }
+    }
+
+    /// 计算前缀中连续的 1 的个数, 最多 max 个
+    fn read_prefix(&mut self, max: u32) -> u32 {
+        let mut ones = 0;
+        while ones < max && self.read(1) == 1 {
+            ones += 1;
+        }
+        ones
+    }
+}
+
+impl Iterator for ChunkIter<'_> {
+    type Item = (i64, f64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+
+        if self.pos == 0 {
+            self.value = self.read(64);
+            return Some((self.timestamp, f64::from_bits(self.value)));
+        }
+
+        let dod = match self.read_prefix(4) {
+            0 => 0,
+            1 => self.read_signed(7),
+            2 => self.read_signed(9),
+            3 => self.read_signed(12),
+            _ => self.read_signed(64),
+        };
+        self.delta += dod;
+        self.timestamp += self.delta;
+
+        if self.read(1) == 1 {
+            if self.read(1) == 1 {
+                self.leading = self.read(6) as u32;
+                let meaningful = self.read(6) as u32 + 1;
+                self.trailing = 64 - self.leading - meaningful;
+            }
+            let meaningful = 64 - self.leading - self.trailing;
+            self.value ^= self.read(meaningful) << self.trailing;
+        }
+
+        Some((self.timestamp, f64::from_bits(self.value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compression() {
+        let samples: Vec<(i64, f64)> = (0..1000)
+            .map(|i| {
+                // 大部分间隔固定, 偶尔出现抖动与大的跳跃
+                let ts = 1_700_000_000_000 + i * 1000 + (i % 7) * 3 + (i / 500) * 10_000_000;
+                let val = if i % 10 == 0 { -(i as f64) / 3.0 } else { 20.5 + (i % 4) as f64 };
+                (ts, val)
+            })
+            .collect();
+
+        let mut series = TimeSeries::new(Options::default());
+        for (ts, val) in &samples {
+            series.add(*ts, *val, None).unwrap();
+        }
+        assert_eq!(samples, series.range(0, i64::MAX));
+        assert!(series.size() < samples.len() * 16);
+
+        // 固定间隔并且变化缓慢的样本, 每个样本只需要几个 bit
+        let mut regular = TimeSeries::new(Options::default());
+        for i in 0..1000 {
+            regular.add(i * 1000, (i / 100) as f64, None).unwrap();
+        }
+        assert!(regular.size() < 1000 * 16 / 20);
+
+        // 乱序写入与重复的时间戳
+        let (ts, _) = samples[10];
+        assert!(series.add(ts, 1.0, None).is_err());
+        series.add(ts, 1.0, Some(DuplicatePolicy::Sum)).unwrap();
+        series.add(ts + 1, 2.0, None).unwrap();
+        assert_eq!(vec![(ts, samples[10].1 + 1.0), (ts + 1, 2.0)], series.range(ts, ts + 1));
+        assert_eq!(samples.len() + 1, series.len());
+    }
+
+    #[test]
+    fn retention_and_compaction() {
+        let mut series = TimeSeries::new(Options {
+            retention: 100,
+            chunk_size: 16,
+            ..Options::default()
+        });
+        series.add_rule(Rule::new("dest".to_string(), Aggregation::Avg, 10));
+
+        let mut compacted = vec![];
+        for ts in 0..300 {
+            series.add(ts, ts as f64, None).unwrap();
+            compacted.extend(series.compactions(ts));
+        }
+        assert!(series.first_timestamp().unwrap() >= 299 - 100 - 50);
+        assert!(series.add(150, 0.0, None).is_err());
+
+        assert_eq!(29, compacted.len());
+        let samples = series.range(270, 299);
+        assert_eq!(vec![(270, 10.0), (280, 10.0), (290, 10.0)], aggregate(&samples, Aggregation::Count, 10));
+        assert_eq!(("dest".to_string(), 280, 284.5), compacted[28]);
+
+        // 写入已经结束的时间桶时重新计算
+        series.add(285, 1000.0, Some(DuplicatePolicy::Last)).unwrap();
+        assert_eq!(vec![("dest".to_string(), 280, 356.0)], series.compactions(285));
+    }
+}
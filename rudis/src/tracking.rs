@@ -0,0 +1,284 @@
+//! 客户端缓存(client-side caching)的服务端部分
+//!
+//! 连接通过 `CLIENT TRACKING ON` 开启之后, 服务端会在 key 被修改时向连接发送失效消息,
+//! 客户端收到之后删除本地缓存的值. 与 redis 相同, 有两种模式:
+//! + 默认模式: 记录连接通过只读命令读取过的 key. 每个 key 只会通知一次,
+//!   通知之后需要重新读取才会被再次记录. OPTIN/OPTOUT 配合 `CLIENT CACHING` 控制哪些读取需要记录
+//! + 广播模式(BCAST): 不记录读取过的 key, 被修改的 key 匹配连接注册的前缀时就发送通知,
+//!   没有指定前缀时匹配所有的 key
+//!
+//! 失效消息的投递方式取决于连接使用的协议:
+//! + RESP3(`HELLO 3`): 直接向连接推送 `invalidate` 消息
+//! + RESP2: 普通的回复之间不能插入推送消息, 需要通过 REDIRECT 指定另一个连接,
+//!   该连接订阅 `__redis__:invalidate` 频道, 以 pub/sub 消息的形式接收失效消息.
+//!   既不是 RESP3 也没有 REDIRECT 时, 失效消息会被丢弃
+//!
+//! 与 redis 相同, 记录的 key 不区分数据库. FLUSHDB 等命令会向所有连接发送 key 为 nil 的消息,
+//! 代表所有的缓存都已经失效
+//!
+//! 记录的 key 的个数受 `tracking-table-max-keys` 限制, 超过之后随意挑选一些 key 提前发送失效消息,
+//! 客户端删除缓存之后再次读取时会被重新记录
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+
+use bytes::Bytes;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::{pubsub::PubSub, Frame};
+
+/// RESP2 连接接收失效消息的频道
+pub const INVALIDATE_CHANNEL: &str = "__redis__:invalidate";
+
+/// 连接开启 tracking 时的选项, 保存在 `Session` 中
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// 接收失效消息的连接 id
+    pub redirect: Option<u64>,
+    pub bcast: bool,
+    /// 广播模式下注册的前缀
+    pub prefixes: Vec<String>,
+    /// 只记录 `CLIENT CACHING yes` 之后的下一条命令读取的 key
+    pub optin: bool,
+    /// 不记录 `CLIENT CACHING no` 之后的下一条命令读取的 key
+    pub optout: bool,
+}
+
+impl Options {
+    /// 是否需要记录当前命令读取的 key, caching 为上一条命令设置的 `CLIENT CACHING`
+    pub fn remember(&self, caching: Option<bool>) -> bool {
+        if self.bcast {
+            false
+        } else if self.optin {
+            caching == Some(true)
+        } else if self.optout {
+            caching != Some(false)
+        } else {
+            true
+        }
+    }
+}
+
+/// 所有开启了 tracking 的连接, 由 `Db` 持有
+#[derive(Default)]
+pub struct Tracking {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// key -> 读取过该 key 的连接 id
+    keys: HashMap<String, HashSet<u64>>,
+
+    /// 前缀 -> 广播模式下注册了该前缀的连接 id
+    prefixes: HashMap<String, HashSet<u64>>,
+
+    /// 连接 id -> 失效消息的接收者
+    clients: HashMap<u64, Target>,
+}
+
+/// 失效消息的接收者
+#[derive(Debug, Clone)]
+struct Target {
+    /// 连接自身的推送通道, 只有 RESP3 连接才会使用
+    push: UnboundedSender<Frame>,
+    redirect: Option<u64>,
+    resp3: bool,
+}
+
+impl Tracking {
+    /// 开启 tracking, 重复调用时更新接收者并追加广播模式的前缀
+    pub fn enable(&self, id: u64, push: UnboundedSender<Frame>, resp3: bool, options: &Options) {
+        let mut inner = self.inner.lock().unwrap();
+        let target = Target {
+            push,
+            redirect: options.redirect,
+            resp3,
+        };
+        inner.clients.insert(id, target);
+
+        if options.bcast {
+            let prefixes = match options.prefixes.is_empty() {
+                true => vec![String::new()],
+                false => options.prefixes.clone(),
+            };
+            for prefix in prefixes {
+                inner.prefixes.entry(prefix).or_default().insert(id);
+            }
+        }
+    }
+
+    /// 关闭 tracking, 连接断开时也需要调用
+    ///
+    /// 需要遍历所有记录的 key, 好在 key 的个数有上限, 并且只有开启过 tracking 的连接才需要清理
+    pub fn disable(&self, id: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.clients.remove(&id).is_none() {
+            return;
+        }
+
+        inner.keys.retain(|_, ids| {
+            ids.remove(&id);
+            !ids.is_empty()
+        });
+        inner.prefixes.retain(|_, ids| {
+            ids.remove(&id);
+            !ids.is_empty()
+        });
+    }
+
+    /// 连接通过 HELLO 切换协议之后, 失效消息的投递方式随之改变
+    pub fn set_resp3(&self, id: u64, resp3: bool) {
+        if let Some(target) = self.inner.lock().unwrap().clients.get_mut(&id) {
+            target.resp3 = resp3;
+        }
+    }
+
+    /// 记录连接读取过的 key, 记录的 key 超过 `max_keys` 时提前让一些 key 失效, 0 代表不限制
+    pub fn remember(&self, id: u64, keys: &[&str], max_keys: usize, pubsub: &PubSub) {
+        let mut inner = self.inner.lock().unwrap();
+        for key in keys {
+            inner.keys.entry(key.to_string()).or_default().insert(id);
+        }
+
+        while max_keys > 0 && inner.keys.len() > max_keys {
+            let Some(key) = inner.keys.keys().next().cloned() else {
+                break;
+            };
+            let ids = inner.keys.remove(&key).unwrap_or_default();
+            inner.send(ids, &key, pubsub);
+        }
+    }
+
+    /// key 被修改, 通知读取过该 key 以及注册了匹配前缀的连接
+    pub fn invalidate(&self, key: &str, pubsub: &PubSub) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.clients.is_empty() {
+            return;
+        }
+
+        let mut ids = inner.keys.remove(key).unwrap_or_default();
+        for (prefix, subscribers) in &inner.prefixes {
+            if key.starts_with(prefix.as_str()) {
+                ids.extend(subscribers);
+            }
+        }
+
+        inner.send(ids, key, pubsub);
+    }
+
+    /// 数据库被清空, 通知所有的连接, 已经记录的 key 不再需要
+    pub fn invalidate_all(&self, pubsub: &PubSub) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.keys.clear();
+
+        for target in inner.clients.values() {
+            send(target, Frame::Null, pubsub);
+        }
+    }
+}
+
+impl Inner {
+    /// 向 ids 中的连接发送 key 的失效消息
+    fn send(&self, ids: HashSet<u64>, key: &str, pubsub: &PubSub) {
+        let keys = Frame::Array(vec![Frame::Bulk(Bytes::from(key.to_string()))]);
+        for id in ids {
+            if let Some(target) = self.clients.get(&id) {
+                send(target, keys.clone(), pubsub);
+            }
+        }
+    }
+}
+
+/// 发送失效消息, keys 为由 key 组成的 Array, 为 Null 时代表所有的 key
+fn send(target: &Target, keys: Frame, pubsub: &PubSub) {
+    let invalidate = Frame::Bulk(Bytes::from_static(b"invalidate"));
+
+    match target.redirect {
+        Some(redirect) => {
+            let message = Frame::Array(vec![
+                Frame::Bulk(Bytes::from_static(b"message")),
+                Frame::Bulk(Bytes::from_static(INVALIDATE_CHANNEL.as_bytes())),
+                keys,
+            ]);
+            pubsub.send_to(INVALIDATE_CHANNEL, redirect, message);
+        }
+        None if target.resp3 => {
+            // 发送失败说明连接已经关闭, 连接断开时会自行关闭 tracking
+            let _ = target.push.send(Frame::Push(vec![invalidate, keys]));
+        }
+        None => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn invalidate_once() {
+        let tracking = Tracking::default();
+        let pubsub = PubSub::default();
+        let (push, mut pushes) = mpsc::unbounded_channel();
+
+        tracking.enable(1, push.clone(), true, &Options::default());
+        tracking.remember(1, &["foo"], 0, &pubsub);
+
+        let expected = |keys| Frame::Push(vec![Frame::Bulk(Bytes::from("invalidate")), keys]);
+        let foo = Frame::Array(vec![Frame::Bulk(Bytes::from("foo"))]);
+
+        tracking.invalidate("foo", &pubsub);
+        assert_eq!(Some(expected(foo)), pushes.try_recv().ok());
+
+        // 通知之后需要重新读取才会被再次记录
+        tracking.invalidate("foo", &pubsub);
+        tracking.invalidate("bar", &pubsub);
+        assert!(pushes.try_recv().is_err());
+
+        // 广播模式按照前缀匹配
+        let bcast = Options {
+            bcast: true,
+            prefixes: vec!["user:".to_string()],
+            ..Default::default()
+        };
+        tracking.enable(2, push, true, &bcast);
+        tracking.invalidate("user:1", &pubsub);
+        tracking.invalidate("order:1", &pubsub);
+        let user = Frame::Array(vec![Frame::Bulk(Bytes::from("user:1"))]);
+        assert_eq!(Some(expected(user)), pushes.try_recv().ok());
+        assert!(pushes.try_recv().is_err());
+
+        tracking.disable(2);
+        tracking.invalidate("user:1", &pubsub);
+        assert!(pushes.try_recv().is_err());
+    }
+
+    #[test]
+    fn prune_and_limit() {
+        let tracking = Tracking::default();
+        let pubsub = PubSub::default();
+        let (push, mut pushes) = mpsc::unbounded_channel();
+        let tracked = |tracking: &Tracking| tracking.inner.lock().unwrap().keys.len();
+
+        // 关闭 tracking(或者断开连接)之后, 连接读取过的 key 不再被记录
+        tracking.enable(1, push.clone(), true, &Options::default());
+        tracking.enable(2, push.clone(), true, &Options::default());
+        tracking.remember(1, &["a", "b"], 0, &pubsub);
+        tracking.remember(2, &["b"], 0, &pubsub);
+        tracking.disable(1);
+        assert_eq!(1, tracked(&tracking));
+        tracking.disable(2);
+        assert_eq!(0, tracked(&tracking));
+
+        // 超过上限时被挤出的 key 会提前收到失效消息
+        tracking.enable(1, push, true, &Options::default());
+        tracking.remember(1, &["a", "b", "c"], 2, &pubsub);
+        assert_eq!(2, tracked(&tracking));
+        assert!(matches!(pushes.try_recv(), Ok(Frame::Push(_))));
+        assert!(pushes.try_recv().is_err());
+    }
+}
@@ -0,0 +1,152 @@
+//! 有序集合
+//!
+//! redis 使用跳表 + 哈希表实现有序集合, 这里使用 `BTreeSet` 代替跳表:
+//! + `scores` 保存成员到分数的映射, 用于 O(1) 的 ZSCORE 以及判断成员是否存在
+//! + `ordered` 按照 (分数, 成员) 排序, 用于按照分数范围查找
+//!
+//! `BTreeSet` 不记录子树的大小, 因此按照排名查找需要 O(N) 的遍历
+
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap},
+    ops::Bound,
+};
+
+use bytes::Bytes;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SortedSet {
+    scores: HashMap<Bytes, f64>,
+    ordered: BTreeSet<(Score, Bytes)>,
+}
+
+/// 可以排序的分数, 有序集合中的分数不会是 NaN
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Score(f64);
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl SortedSet {
+    pub fn new() -> SortedSet {
+        SortedSet::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// 添加成员或者更新成员的分数, 返回原来的分数
+    pub fn insert(&mut self, member: Bytes, score: f64) -> Option<f64> {
+        // -0 与 0 视为相同的分数
+        let score = if score == 0.0 { 0.0 } else { score };
+        let old = self.scores.insert(member.clone(), score);
+        if let Some(old) = old {
+            self.ordered.remove(&(Score(old), member.clone()));
+        }
+        self.ordered.insert((Score(score), member));
+
+        old
+    }
+
+    pub fn remove(&mut self, member: &[u8]) -> Option<f64> {
+        let (member, score) = self.scores.remove_entry(member)?;
+        self.ordered.remove(&(Score(score), member));
+
+        Some(score)
+    }
+
+    /// 按照分数从小到大遍历, 分数相同时按照成员的字典序
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&Bytes, f64)> {
+        self.ordered.iter().map(|(score, member)| (member, score.0))
+    }
+
+    /// 分数在 [min, max] 范围内的成员, 两端是否包含由 Bound 决定
+    pub fn range_by_score(
+        &self,
+        min: Bound<f64>,
+        max: Bound<f64>,
+    ) -> impl Iterator<Item = (&Bytes, f64)> {
+        // 成员作为第二个排序字段, 空字符串是最小的成员, 因此 (score, "") 可以作为分数的下界.
+        // 上界没有对应的最大成员, 所以只用分数过滤
+        let start = match min {
+            Bound::Included(min) | Bound::Excluded(min) => {
+                Bound::Included((Score(min), Bytes::new()))
+            }
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let lower_excluded = match min {
+            Bound::Excluded(min) => Some(min),
+            _ => None,
+        };
+
+        self.ordered
+            .range((start, Bound::Unbounded))
+            .skip_while(move |(score, _)| Some(score.0) == lower_excluded)
+            .take_while(move |(score, _)| match max {
+                Bound::Included(max) => score.0 <= max,
+                Bound::Excluded(max) => score.0 < max,
+                Bound::Unbounded => true,
+            })
+            .map(|(score, member)| (member, score.0))
+    }
+}
+
+/// 与 redis 相同的分数格式: 整数不带小数点, 其他使用最短的可以还原的表示
+pub fn format_score(score: f64) -> String {
+    if score.is_infinite() {
+        return if score > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    if score.fract() == 0.0 && score.abs() < 1e17 {
+        return format!("{}", score as i64);
+    }
+
+    score.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn range_by_score() {
+        let mut zset = SortedSet::new();
+        for (member, score) in [("a", 1.0), ("b", 2.0), ("c", 2.0), ("d", 3.0)] {
+            zset.insert(Bytes::from(member), score);
+        }
+        assert_eq!(Some(2.0), zset.insert(Bytes::from("b"), 2.5));
+
+        let members = |min, max| -> Vec<&str> {
+            zset.range_by_score(min, max)
+                .map(|(member, _)| std::str::from_utf8(member).unwrap())
+                .collect()
+        };
+        assert_eq!(
+            vec!["c", "b", "d"],
+            members(Bound::Excluded(1.0), Bound::Unbounded)
+        );
+        assert_eq!(
+            vec!["a", "c"],
+            members(Bound::Included(1.0), Bound::Excluded(2.5))
+        );
+    }
+}